@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::{hint::black_box, path::Path};
+
+use scene_builder_lib::project::{
+    package::Package,
+    serialize::{ByteWriter, EncodeBinary},
+    NanoID,
+};
+
+const SCENES: usize = 1000;
+
+// A package of SCENES scenes, cloned from the creature fixture with unique ids and events
+fn synthetic_package() -> Package {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("project")
+        .join("tests")
+        .join("fixtures")
+        .join("creatures")
+        .join("project.slsb.json");
+    let template = Package::open(&path).unwrap();
+    let sources: Vec<_> = template.exported_scenes().collect();
+
+    let mut package = Package::new();
+    package.pack_name = "Benchmark".into();
+    for i in 0..SCENES {
+        let mut scene = sources[i % sources.len()].clone();
+        scene.id = NanoID::new_nanoid();
+        scene.name = format!("{} {}", scene.name, i);
+        for stage in &mut scene.stages {
+            for position in &mut stage.positions {
+                for event in &mut position.event {
+                    *event = format!("{}_{}", event, i);
+                }
+            }
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    package
+}
+
+fn encoding(c: &mut Criterion) {
+    let package = synthetic_package();
+    c.bench_function("get_byte_size", |b| {
+        b.iter(|| black_box(&package).get_byte_size())
+    });
+    c.bench_function("write_byte", |b| {
+        b.iter_batched(
+            || ByteWriter::with_capacity(package.get_byte_size()),
+            |mut buf| {
+                black_box(&package).write_byte(&mut buf);
+                buf.into_bytes()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn fnis_lists(c: &mut Criterion) {
+    let package = synthetic_package();
+    let dir = std::env::temp_dir().join(format!("slsb_bench_{}", NanoID::new_nanoid().0));
+    c.bench_function("write_fnis_files", |b| {
+        b.iter(|| package.write_fnis_files(&dir).unwrap())
+    });
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+criterion_group!(benches, encoding, fnis_lists);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scene_builder_lib::project::{
+    offsets::{apply_offsets, OffsetMatching},
+    package::Package,
+};
+
+const PROJECT: &str = include_str!("../../src/project/tests/fixtures/creatures/project.slsb.json");
+
+// Offset files are applied to a fixture project with every matching mode
+fuzz_target!(|data: &[u8]| {
+    let Ok(offsets) = serde_yaml::from_slice::<serde_yaml::Mapping>(data) else {
+        return;
+    };
+    let mut package: Package = serde_json::from_str(PROJECT).unwrap();
+    for matching in [
+        OffsetMatching::Id,
+        OffsetMatching::Name,
+        OffsetMatching::Events,
+    ] {
+        let _ = apply_offsets(&mut package, &offsets, matching);
+    }
+});
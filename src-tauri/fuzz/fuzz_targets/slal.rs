@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scene_builder_lib::project::package::Package;
+
+// Community SLAL files may be truncated or hand edited, conversion has to fail gracefully
+fuzz_target!(|data: &[u8]| {
+    if let Ok(slal) = serde_json::from_slice::<serde_json::Value>(data) {
+        let _ = Package::from_slal_value(&slal);
+    }
+});
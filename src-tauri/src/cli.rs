@@ -1,53 +1,272 @@
-use std::path::PathBuf;
-use crate::project::package::Package;
+use log::info;
+use std::{path::PathBuf, sync::atomic::AtomicBool};
+use crate::{
+  error::SlsbError,
+  i18n::Message,
+  project::{
+    convert::{convert_slal, slal_inputs},
+    inspect::inspect_file,
+    package::Package,
+    upgrade::upgrade_projects,
+    validate::{validate, Severity},
+  },
+  watch::{rebuild, watch, WatchBuild},
+};
 
 pub fn convert(
   args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
-) -> Result<(), String> {
-  let in_path = match &args.get("in").unwrap().value {
-      serde_json::Value::String(value) => PathBuf::from(value),
-      _ => return Err("input slal file not provided".to_string()),
-  };
-  if !in_path.exists() || !in_path.is_file() || in_path.extension().unwrap() != "json" {
-      return Err("input slal file is invalid".to_string());
+) -> Result<(), SlsbError> {
+  // -i and -d are the flags of older versions, kept next to the positional input and -o
+  let in_paths: Vec<PathBuf> = ["in", "input"]
+      .iter()
+      .flat_map(|name| match args.get(*name).map(|arg| &arg.value) {
+          Some(serde_json::Value::String(value)) => vec![PathBuf::from(value)],
+          Some(serde_json::Value::Array(values)) => values
+              .iter()
+              .filter_map(|value| value.as_str())
+              .map(PathBuf::from)
+              .collect(),
+          _ => vec![],
+      })
+      .collect();
+  if in_paths.is_empty() {
+      return Err(Message::new("cli.missing_input").into());
+  }
+  let mut files = vec![];
+  for path in &in_paths {
+      files.extend(slal_inputs(path)?);
   }
+  let merge = matches!(
+      args.get("merge").map(|arg| &arg.value),
+      Some(serde_json::Value::Bool(true))
+  );
+  let unique_names = matches!(
+      args.get("unique-names").map(|arg| &arg.value),
+      Some(serde_json::Value::Bool(true))
+  );
 
-  let mut out_path = match &args.get("out").unwrap().value {
-      serde_json::Value::String(value) => PathBuf::from(value),
-      _ => return Err("output dir not provided".to_string()),
+  let out_path = match ["out", "dir"]
+      .iter()
+      .find_map(|name| args.get(*name).and_then(|arg| arg.value.as_str()))
+  {
+      Some(value) => PathBuf::from(value),
+      None => return Err(Message::new("cli.missing_output").into()),
   };
-  if !out_path.exists() || !out_path.is_dir() {
-      return Err("output dir is invalid".to_string());
+  // a single project may be written to a file path directly, otherwise out is a folder
+  let out_is_file = !out_path.is_dir() && out_path.to_string_lossy().ends_with(".json");
+  if !out_path.is_dir() && !out_is_file {
+      return Err(Message::new("cli.invalid_output").into());
+  }
+  if out_is_file && !merge && files.len() > 1 {
+      return Err(Message::new("cli.output_not_dir").into());
   }
 
-  out_path.push(in_path.file_stem().unwrap());
-  out_path.set_extension("slsb.json");
-  println!("Converting {} to {}", in_path.display(), out_path.display());
+  let (projects, mut report) = convert_slal(&files, merge);
+  for mut project in projects {
+      let target = if out_is_file {
+          out_path.clone()
+      } else {
+          let stem = project.pack_path.file_stem().unwrap_or_default().to_string_lossy();
+          out_path.join(format!("{}.slsb.json", stem))
+      };
+      info!(target: "cli", from:? = project.pack_path, to:? = target; "Converting SLAL file");
+      if unique_names {
+          for rename in project.make_scene_names_unique() {
+              println!("Renamed {} to {}", rename.from, rename.to);
+              report.renamed.push(rename);
+          }
+      }
+      project.write(target.clone())?;
+      report.outputs.push(target);
+  }
+  for file in &report.inputs {
+      match &file.error {
+        Some(error) => println!("{}: {}", file.path.display(), error),
+        None => println!("{}: {} scenes, {} stages", file.path.display(), file.scenes, file.stages),
+      }
+  }
+  if let Some(serde_json::Value::String(value)) = args.get("report").map(|arg| &arg.value) {
+      std::fs::write(value, serde_json::to_string_pretty(&report)?)?;
+      println!("Wrote conversion report to {}", value);
+  }
 
-  let mut project = Package::from_slal(in_path)?;
-  project.write(out_path.clone())
+  let failed = report.failed();
+  if failed > 0 {
+      return Err(Message::new("cli.conversion_failed")
+          .arg("failed", failed)
+          .arg("total", report.inputs.len())
+          .into());
+  }
+  Ok(())
 }
 
 pub fn build(
   args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
-) -> Result<(), String> {
+) -> Result<(), SlsbError> {
   let in_path = match &args.get("in").unwrap().value {
       serde_json::Value::String(value) => PathBuf::from(value),
-      _ => return Err("input slal file not provided".to_string()),
+      _ => return Err(Message::new("cli.missing_input").into()),
   };
   if !in_path.exists() || !in_path.is_file() || in_path.extension().unwrap() != "json" {
-      return Err("input slal file is invalid".to_string());
+      return Err(Message::new("cli.invalid_input").into());
   }
 
   let out_dir = match &args.get("out").unwrap().value {
       serde_json::Value::String(value) => PathBuf::from(value),
-      _ => return Err("output dir not provided".to_string()),
+      _ => return Err(Message::new("cli.missing_output").into()),
   };
   if !out_dir.exists() || !out_dir.is_dir() {
-      return Err("output dir is invalid".to_string());
+      return Err(Message::new("cli.invalid_output").into());
+  }
+
+  let mut project = Package::open(&in_path)?;
+  if matches!(args.get("profile").map(|arg| &arg.value), Some(serde_json::Value::Bool(true))) {
+      project.profile_export = true;
+  }
+  let summary = project.build(out_dir)?;
+  for scene in &summary.excluded {
+      println!("Left out scene {} ({:?})", scene.name, scene.reason);
+  }
+  println!("Exported {} scenes, {} left out", summary.scenes, summary.excluded.len());
+  if let Some(profile) = &summary.profile {
+      for timing in &profile.phases {
+          println!("{:>10}: {:.1}ms", timing.phase, timing.ms);
+      }
+      println!("{:>10}: {:.1}ms", "total", profile.total_ms);
+  }
+  Ok(())
+}
+
+pub fn spec(
+  args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
+) -> Result<(), SlsbError> {
+  let spec = serde_json::to_string_pretty(&Package::format_spec())?;
+  match args.get("out").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => {
+          std::fs::write(value, spec)?;
+          println!("Wrote format specification to {}", value);
+      }
+      _ => println!("{}", spec),
   }
+  Ok(())
+}
 
-  let file = std::fs::File::open(&in_path).map_err(|e| e.to_string())?;
-  let project = Package::from_file(file)?;
-  project.build(out_dir).map_err(|e| e.to_string())
+pub fn validate_project(
+  args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
+) -> Result<(), SlsbError> {
+  let path = match args.get("project").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => PathBuf::from(value),
+      _ => return Err(Message::new("cli.missing_project").into()),
+  };
+  let format = match args.get("format").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => value.as_str(),
+      _ => "text",
+  };
+  let project = Package::open(&path)?;
+  let diagnostics = validate(&project);
+  let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+  match format {
+      "json" => println!("{}", serde_json::to_string_pretty(&diagnostics)?),
+      "text" => {
+          for diagnostic in &diagnostics {
+              println!("{}", diagnostic);
+          }
+          println!(
+              "{}: {} errors, {} warnings",
+              path.display(),
+              errors,
+              diagnostics.len() - errors
+          );
+      }
+      _ => return Err(Message::new("cli.invalid_format").arg("format", format).into()),
+  }
+  if errors > 0 {
+      return Err(Message::new("cli.validation_failed").arg("count", errors).into());
+  }
+  Ok(())
+}
+
+pub fn inspect(
+  args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
+) -> Result<(), SlsbError> {
+  let path = match args.get("file").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => PathBuf::from(value),
+      _ => return Err(Message::new("cli.missing_file").into()),
+  };
+  let format = match args.get("format").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => value.as_str(),
+      _ => "text",
+  };
+  let inspection = inspect_file(&path)?;
+  match format {
+      "json" => println!("{}", serde_json::to_string_pretty(&inspection)?),
+      "text" => print!("{}", inspection),
+      _ => return Err(Message::new("cli.invalid_format").arg("format", format).into()),
+  }
+  Ok(())
+}
+
+pub fn upgrade(
+  args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
+) -> Result<(), SlsbError> {
+  let dir = match args.get("folder").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => PathBuf::from(value),
+      _ => return Err(Message::new("cli.missing_input").into()),
+  };
+  let report = upgrade_projects(&dir)?;
+  for file in &report.files {
+      match (&file.error, file.from_version) {
+        (Some(error), _) => println!("{}: {}", file.path.display(), error),
+        (None, Some(version)) if file.upgraded => {
+            println!("{}: upgraded from version {}", file.path.display(), version)
+        }
+        _ => {}
+      }
+  }
+  println!(
+      "Upgraded {} of {} projects, {} failed",
+      report.upgraded(),
+      report.files.len(),
+      report.failed()
+  );
+  if let Some(serde_json::Value::String(value)) = args.get("report").map(|arg| &arg.value) {
+      std::fs::write(value, serde_json::to_string_pretty(&report)?)?;
+      println!("Wrote upgrade report to {}", value);
+  }
+  if report.failed() > 0 {
+      return Err(Message::new("cli.upgrade_failed")
+          .arg("failed", report.failed())
+          .arg("total", report.files.len())
+          .into());
+  }
+  Ok(())
+}
+
+pub fn watch_project(
+  args: std::collections::HashMap<String, tauri_plugin_cli::ArgData>,
+) -> Result<(), SlsbError> {
+  let in_path = match args.get("project").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => PathBuf::from(value),
+      _ => return Err(Message::new("cli.missing_project").into()),
+  };
+  if !in_path.is_file() {
+      return Err(Message::new("cli.invalid_input").into());
+  }
+  let out_dir = match args.get("out").map(|arg| &arg.value) {
+      Some(serde_json::Value::String(value)) => PathBuf::from(value),
+      _ => return Err(Message::new("cli.missing_output").into()),
+  };
+  if !out_dir.is_dir() {
+      return Err(Message::new("cli.invalid_output").into());
+  }
+
+  let report = |build: WatchBuild| match build.error {
+      Some(error) => println!("Build failed: {}", error),
+      None => println!("Built {} scenes in {:.2}s", build.scenes, build.seconds),
+  };
+  report(rebuild(&in_path, &out_dir));
+  println!("Watching {} for changes, press Ctrl+C to stop", in_path.display());
+  // runs until the process is terminated
+  watch(&in_path, &out_dir, &AtomicBool::new(false), report);
+  Ok(())
 }
@@ -0,0 +1,163 @@
+use log::{error, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    backtrace::Backtrace,
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::SlsbError, project::package::Package};
+
+pub const CRASH_FILE: &str = "crash.json";
+pub const AUTOSAVE_FILE: &str = "autosave.slsb.json";
+
+// Number of operations from the audit log to include in a report
+const RECENT_OPERATIONS: usize = 20;
+
+// Counts only, a report never contains names, events or other authored content
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ProjectStats {
+    pub scenes: usize,
+    pub stages: usize,
+    pub positions: usize,
+    pub locked_scenes: usize,
+}
+
+impl ProjectStats {
+    pub fn from_package(package: &Package) -> Self {
+        let stages = package.scenes.values().flat_map(|scene| &scene.stages);
+        Self {
+            scenes: package.scenes.len(),
+            stages: stages.clone().count(),
+            positions: stages.map(|stage| stage.positions.len()).sum(),
+            locked_scenes: package.scenes.values().filter(|scene| scene.locked).count(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrashReport {
+    // seconds since unix epoch
+    pub timestamp: u64,
+    pub app_version: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub thread: Option<String>,
+    pub backtrace: String,
+    pub project: ProjectStats,
+    pub operations: Vec<String>,
+    // whether an autosave was available at the time of the crash
+    pub autosave: bool,
+}
+
+// State captured whenever the project is autosaved. The panic hook cannot access the project
+// itself, as the panicking thread is likely to hold its lock
+#[derive(Debug, Default)]
+struct CrashContext {
+    stats: ProjectStats,
+    operations: Vec<String>,
+    autosave: bool,
+}
+
+static CONTEXT: Lazy<Mutex<CrashContext>> = Lazy::new(|| Mutex::new(CrashContext::default()));
+
+pub fn update_context(package: &Package) {
+    let mut ctx = CONTEXT.lock().unwrap();
+    ctx.stats = ProjectStats::from_package(package);
+    ctx.operations = package
+        .audit
+        .recent(RECENT_OPERATIONS)
+        .into_iter()
+        .map(|entry| entry.operation)
+        .collect();
+}
+
+// Write a snapshot of the project into the given folder
+pub fn autosave(dir: &Path, package: &Package) -> Result<(), SlsbError> {
+    fs::create_dir_all(dir)?;
+    let data = serde_json::to_string(package)?;
+    fs::write(dir.join(AUTOSAVE_FILE), data)?;
+    update_context(package);
+    CONTEXT.lock().unwrap().autosave = true;
+    Ok(())
+}
+
+pub fn make_report(message: String, location: Option<String>) -> CrashReport {
+    let (stats, operations, autosave) = match CONTEXT.try_lock() {
+        Ok(ctx) => (ctx.stats.clone(), ctx.operations.clone(), ctx.autosave),
+        Err(_) => Default::default(),
+    };
+    CrashReport {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        app_version: env!("CARGO_PKG_VERSION").into(),
+        message,
+        location,
+        thread: std::thread::current().name().map(|s| s.to_string()),
+        backtrace: Backtrace::force_capture().to_string(),
+        project: stats,
+        operations,
+        autosave,
+    }
+}
+
+// Write a crash report into the given folder whenever a thread panics
+pub fn install(dir: PathBuf) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or("Unknown panic".into());
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+        let report = make_report(message, location);
+        error!("Crashed: {} at {:?}", report.message, report.location);
+        match serde_json::to_string_pretty(&report) {
+            Ok(data) => {
+                if let Err(e) =
+                    fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(CRASH_FILE), data))
+                {
+                    error!("Unable to write crash report: {}", e);
+                }
+            }
+            Err(e) => error!("Unable to serialize crash report: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+// The report of the previous session, if it crashed. The report is archived so it is only
+// returned once
+pub fn take_report(dir: &Path) -> Option<CrashReport> {
+    let path = dir.join(CRASH_FILE);
+    let data = fs::read_to_string(&path).ok()?;
+    let report: Option<CrashReport> = serde_json::from_str(&data)
+        .map_err(|e| warn!("Discarding invalid crash report: {}", e))
+        .ok();
+    let archived = dir.join(format!(
+        "crash-{}.json",
+        report.as_ref().map_or(0, |r| r.timestamp)
+    ));
+    if let Err(e) = fs::rename(&path, archived) {
+        warn!("Unable to archive crash report: {}", e);
+        let _ = fs::remove_file(&path);
+    }
+    report
+}
+
+pub fn load_autosave(dir: &Path) -> Result<Package, SlsbError> {
+    Package::from_file(fs::File::open(dir.join(AUTOSAVE_FILE))?)
+}
+
+pub fn discard_autosave(dir: &Path) {
+    let _ = fs::remove_file(dir.join(AUTOSAVE_FILE));
+    CONTEXT.lock().unwrap().autosave = false;
+}
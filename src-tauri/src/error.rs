@@ -0,0 +1,66 @@
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::i18n::Message;
+
+#[derive(Debug, Error)]
+pub enum SlsbError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid yaml: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("{0}")]
+    Validation(Message),
+    #[error("Unrecognized race: {0}")]
+    UnknownRace(String),
+    #[error("{0}")]
+    DialogCancelled(Message),
+}
+
+impl SlsbError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SlsbError::Io(_) => "io",
+            SlsbError::Json(_) => "json",
+            SlsbError::Yaml(_) => "yaml",
+            SlsbError::Validation(_) => "validation",
+            SlsbError::UnknownRace(_) => "unknown_race",
+            SlsbError::DialogCancelled(_) => "dialog_cancelled",
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        match self {
+            SlsbError::Io(e) => Message::new("error.io").arg("details", e),
+            SlsbError::Json(e) => Message::new("error.json").arg("details", e),
+            SlsbError::Yaml(e) => Message::new("error.yaml").arg("details", e),
+            SlsbError::Validation(msg) | SlsbError::DialogCancelled(msg) => msg.clone(),
+            SlsbError::UnknownRace(race) => Message::new("error.unknown_race").arg("race", race),
+        }
+    }
+}
+
+impl From<Message> for SlsbError {
+    fn from(value: Message) -> Self {
+        SlsbError::Validation(value)
+    }
+}
+
+// Errors are passed to the frontend as { kind, key, params, message }, the message being the
+// english rendition for clients without a translation
+impl Serialize for SlsbError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let message = self.message();
+        let mut state = serializer.serialize_struct("SlsbError", 4)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("key", message.key)?;
+        state.serialize_field("params", &message.params)?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
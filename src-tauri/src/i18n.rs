@@ -0,0 +1,598 @@
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{collections::BTreeMap, fmt::Display};
+
+// English templates of all user facing backend messages. Parameters are written as {name}
+// Other languages are provided by the frontend, using the same keys
+const CATALOG: &[(&str, &str)] = &[
+    ("error.io", "IO error: {details}"),
+    ("error.json", "Invalid json: {details}"),
+    ("error.yaml", "Invalid yaml: {details}"),
+    ("error.unknown_race", "Unrecognized race: {race}"),
+    ("error.unknown_command", "Unrecognized command: {command}"),
+    ("dialog.load_project", "No path to load project from"),
+    ("dialog.save_project", "No path to save project to"),
+    ("dialog.load_slal", "No path to load slal file from"),
+    ("dialog.load_offsets", "No path to load offsets from"),
+    ("dialog.save_offsets", "No path to save offsets to"),
+    ("dialog.export", "No path to export project to"),
+    ("dialog.registry", "No registry folder to compare against"),
+    ("dialog.upgrade", "No folder selected to upgrade projects in"),
+    ("dialog.animation_root", "No animation folder selected"),
+    ("dialog.preview", "No preview clip selected"),
+    ("dialog.load_csv", "No path to load the stage sheet from"),
+    ("dialog.save_csv", "No path to save the project summary to"),
+    ("dialog.load_dot", "No path to load the stage graph from"),
+    ("dialog.save_dot", "No path to save the stage graph to"),
+    ("dialog.anim_object_forms", "No list of animation objects selected"),
+    ("project.empty_name", "Project name must not be empty"),
+    (
+        "project.invalid_version",
+        "Invalid version {version}, expected major.minor.patch",
+    ),
+    (
+        "project.invalid_file",
+        "Invalid project file at {pointer}: expected {expected}, found {found} ({count} problems in total)",
+    ),
+    ("scene.invalid_id", "Invalid Scene ID: {scene}"),
+    ("scene.locked", "Scene {scene} is locked"),
+    (
+        "scene.released",
+        "Scene {scene} was already released, saved games refer to it by its id",
+    ),
+    ("scene.empty_name", "Scenes need a name"),
+    (
+        "scene.unknown_stage",
+        "Scene {scene} refers to stage {stage}, which it does not contain",
+    ),
+    (
+        "scene.position_count",
+        "Stage {stage} has {found} positions, but the scene has {expected}",
+    ),
+    (
+        "scene.invalid_event",
+        "Event {event} of stage {stage} cannot be read by FNIS",
+    ),
+    (
+        "scene.update_failed",
+        "Failed to update scene {scene}: {details}",
+    ),
+    (
+        "scene.missing_stage",
+        "Scene {scene} has no stage with id {stage}",
+    ),
+    ("scene.no_stages", "No stages found in Scene"),
+    ("scene.self_edge", "Stage {stage} cannot lead to itself"),
+    ("dot.syntax", "Unable to read the graph file: {details}"),
+    (
+        "dot.unknown_stage",
+        "Scene {scene} has no stage with the id or name {stage}",
+    ),
+    (
+        "dot.ambiguous_stage",
+        "Several stages of scene {scene} are named {stage}, refer to them by id instead",
+    ),
+    (
+        "dot.empty_graph",
+        "The graph file has no stages, scene {scene} would lose all of its edges",
+    ),
+    (
+        "scene.missing_edge",
+        "There is no edge from stage {from} to stage {to}",
+    ),
+    (
+        "scene.chain_empty",
+        "No stages given to chain in Scene {scene}",
+    ),
+    (
+        "scene.stage_listed_twice",
+        "Stage {stage} is listed more than once",
+    ),
+    (
+        "scene.split_subset",
+        "Splitting Scene {scene} requires a non-empty subset of its stages",
+    ),
+    (
+        "scene.split_disconnected",
+        "Selected stages must form a connected graph",
+    ),
+    (
+        "export.unknown_language",
+        "{language} is not a language of the game",
+    ),
+    (
+        "scene.not_in_trash",
+        "There is no discarded scene with id {scene}",
+    ),
+    (
+        "scene.restore_conflict",
+        "Scene {scene} already uses the id of the discarded scene",
+    ),
+    (
+        "scene.invalid_language",
+        "{lang} is not a language code, e.g. de or pt-br",
+    ),
+    (
+        "scene.self_transition",
+        "Scene {scene} cannot transition into itself",
+    ),
+    (
+        "scene.transition_not_ending",
+        "Only ending stages can transition into another scene, {stage} is not an ending",
+    ),
+    (
+        "scene.missing_transition",
+        "There is no transition from stage {stage} into scene {scene}",
+    ),
+    (
+        "offset.invalid_scene_id",
+        "Not a valid offset file, expected string for scene id",
+    ),
+    (
+        "offset.expected_mapping",
+        "Expected mapping in scene {scene}",
+    ),
+    (
+        "offset.expected_stage_id",
+        "Expected Stage id for Scene {scene}",
+    ),
+    (
+        "offset.expected_sequence",
+        "Expecting sequence in scene {scene} for stage {stage}",
+    ),
+    (
+        "offset.position_count",
+        "Invalid position length, got {got} but expected {expected}",
+    ),
+    ("offset.missing_transform", "Position {position} has no transform"),
+    ("offset.location_type", "Location is not a sequence"),
+    (
+        "offset.location_length",
+        "Invalid location vector, expected length 3 but got {got}",
+    ),
+    (
+        "offset.rotation_type",
+        "Rotation is neither a float nor a sequence",
+    ),
+    (
+        "offset.rotation_length",
+        "Invalid rotation vector, expected length 3 but got {got}",
+    ),
+    (
+        "offset.unsupported_version",
+        "Unsupported offset file version {version}",
+    ),
+    ("offset.template_unnamed", "Offset templates require a name"),
+    (
+        "offset.unknown_template",
+        "No offset template named {template}",
+    ),
+    ("offset.no_match", "No matching stage in the project"),
+    ("offset.ambiguous", "Offsets match {count} stages"),
+    (
+        "export.position_count",
+        "Stage {stage} of Scene {scene} has {got} positions, expected {expected}",
+    ),
+    (
+        "export.missing_event",
+        "A position in Stage {stage} of Scene {scene} has no animation event",
+    ),
+    ("validate.unnamed_scene", "Scene has no name"),
+    (
+        "validate.illegal_event",
+        "FNIS cannot read event {event}, it could be named {suggestion}",
+    ),
+    (
+        "validate.known_event",
+        "Event {event} is also used by {source}, either may end up playing the other animation",
+    ),
+    (
+        "validate.framework_version",
+        "Scene {scene} uses {feature}, which requires framework {required} but the pack declares {declared}",
+    ),
+    (
+        "validate.missing_anim_objects",
+        "Scene {scene} uses animation objects no listed plugin provides: {objects}",
+    ),
+    (
+        "validate.behaviour_folder",
+        "Position {position} uses an invalid behaviour folder: {folder}",
+    ),
+    (
+        "export.invalid_behaviour_folder",
+        "Invalid behaviour folder {folder}, expected a folder below meshes\\actors",
+    ),
+    (
+        "export.preset_name",
+        "Export presets need a name",
+    ),
+    (
+        "export.unknown_preset",
+        "There is no export preset named {preset}",
+    ),
+    (
+        "export.read_only",
+        "{path} is read-only, clear its read-only attribute or export into another folder",
+    ),
+    (
+        "export.permission_denied",
+        "Access to {path} was denied, close programs using it or export into a folder you can write to",
+    ),
+    (
+        "export.path_too_long",
+        "{path} is {length} characters long, windows accepts {max}. Export into a folder closer to the drive root or use a shorter pack name",
+    ),
+    (
+        "export.invalid_list_name",
+        "Invalid FNIS list name {name}, expected FNIS_<name>_List.txt",
+    ),
+    (
+        "validate.flagged",
+        "Scene {scene} is flagged with warnings and will not be exported",
+    ),
+    (
+        "validate.archetype",
+        "Scene {scene} was imported as {archetype} but its actors make it {detected}",
+    ),
+    (
+        "validate.no_climax",
+        "Scene {scene} has no position climaxing in any stage",
+    ),
+    (
+        "validate.no_stages",
+        "Scene {scene} has no stages and will not be exported",
+    ),
+    (
+        "validate.invalid_root",
+        "The root of Scene {scene} is not one of its stages",
+    ),
+    (
+        "validate.unreachable",
+        "Stage {stage} cannot be reached from the root",
+    ),
+    (
+        "stage.invalid_timer",
+        "The {category} stage timer must be a positive number of seconds, got {value}",
+    ),
+    (
+        "validate.duplicate_scene_name",
+        "{count} scenes are named {scene}, the runtime cannot tell them apart",
+    ),
+    (
+        "validate.scene_name_format",
+        "The name of Scene {scene} contains line breaks, control characters or surplus spaces",
+    ),
+    (
+        "validate.no_ending",
+        "Scene {scene} has no ending reachable from its root",
+    ),
+    (
+        "validate.shortcut_root",
+        "Stage {stage} is the root and does not need to be a start shortcut",
+    ),
+    (
+        "validate.shortcut_unreachable",
+        "Start shortcut {stage} cannot be reached from the root",
+    ),
+    ("validate.unknown_dest", "Reference to unknown stage {dest}"),
+    (
+        "validate.unknown_transition",
+        "Transition into unknown scene {scene}",
+    ),
+    (
+        "validate.transition_not_ending",
+        "Stage {stage} transitions into another scene but is not an ending",
+    ),
+    (
+        "validate.transition_excluded",
+        "Transition into scene {scene}, which is left out of the build",
+    ),
+    (
+        "validate.file_name_sanitized",
+        "{name} contains characters not allowed in file names and is exported as {file}",
+    ),
+    (
+        "validate.file_name_unicode",
+        "Registry file name {file} contains non-ASCII characters, which some systems cannot load",
+    ),
+    (
+        "validate.file_extension",
+        "Invalid registry file extension {extension}",
+    ),
+    (
+        "validate.file_name_collision",
+        "More than one registry would be written to {file}",
+    ),
+    (
+        "repair.dangling_node",
+        "Graph contains removed stage {stage}",
+    ),
+    (
+        "repair.missing_node",
+        "Stage {stage} is missing from the graph",
+    ),
+    (
+        "repair.root",
+        "Root of Scene {scene} is not one of its stages, using {stage}",
+    ),
+    (
+        "orphans.root",
+        "Stage {stage} is the root of its scene and cannot be deleted",
+    ),
+    (
+        "library.unknown_stage",
+        "Stage {stage} is not in the stage library",
+    ),
+    (
+        "library.shared",
+        "Stage {stage} is already in the stage library",
+    ),
+    (
+        "library.linked",
+        "Scene {scene} already uses stage {stage}",
+    ),
+    (
+        "library.position_count",
+        "Stage {stage} has {got} positions, the scene has {expected}",
+    ),
+    (
+        "repair.transition",
+        "Transition from stage {stage} into scene {scene} has no valid endpoints",
+    ),
+    (
+        "validate.tag_creature",
+        "Tag {tag} is set but no actor is a creature",
+    ),
+    (
+        "validate.tag_composition",
+        "Tag {tag} does not match the sex of the actors",
+    ),
+    (
+        "inspect.truncated",
+        "Unexpected end of file at byte {offset}",
+    ),
+    (
+        "inspect.invalid_string",
+        "Invalid utf-8 string at byte {offset}",
+    ),
+    (
+        "inspect.unknown_string",
+        "Reference to unknown string {index}",
+    ),
+    (
+        "inspect.unknown_shared_stage",
+        "Reference to shared stage {index}, which the registry does not contain",
+    ),
+    (
+        "inspect.trailing_bytes",
+        "{count} unexpected bytes after the last scene",
+    ),
+    (
+        "inspect.unsupported_version",
+        "Unsupported registry version {version}, expected {expected}",
+    ),
+    (
+        "inspect.unsupported_capabilities",
+        "Registry uses sections this version cannot read (capabilities {bits})",
+    ),
+    (
+        "registry.scene_name",
+        "Scene {scene} is also defined by {registry}",
+    ),
+    (
+        "registry.event",
+        "Animation event {event} is also registered by {registry}",
+    ),
+    (
+        "registry.behaviour_folder",
+        "Behaviour folder {folder} is also used by {registry}",
+    ),
+    ("tags.actor_count", "Scene has {count} actors"),
+    ("tags.creature", "Scene includes a {race}"),
+    ("tags.furniture", "Scene is played on {furniture}"),
+    ("tags.climax", "Stage contains a climax"),
+    (
+        "validate.requirement_plugin",
+        "Required plugin {plugin} is not an esp, esm or esl file",
+    ),
+    (
+        "validate.requirement_keyword",
+        "Required keyword '{keyword}' is not a valid editor id",
+    ),
+    (
+        "validate.duration_mismatch",
+        "Animation {event} lasts {duration}s while {longest} lasts {expected}s",
+    ),
+    (
+        "validate.unreadable_animation",
+        "Unable to read the length of {path}: {details}",
+    ),
+    ("validate.bundle_unnamed", "A bundle has no name"),
+    (
+        "validate.bundle_duplicate",
+        "Bundle name {bundle} is used more than once",
+    ),
+    (
+        "validate.bundle_no_query",
+        "Bundle {bundle} has no tags and takes up all remaining scenes",
+    ),
+    (
+        "validate.bundle_no_scenes",
+        "No scenes are exported with bundle {bundle}",
+    ),
+    ("slal.missing_attribute", "Missing {attribute} attribute"),
+    ("slal.no_stages", "Scene has no stages"),
+    (
+        "slal.stage_count",
+        "An actor of {scene} lists {got} stages, expected {expected}",
+    ),
+    ("slal.unknown_sex", "Unrecognized gender: {sex}"),
+    ("csv.unterminated_quote", "Unterminated quote in row {row}"),
+    ("csv.missing_column", "Missing column: {column}"),
+    (
+        "csv.invalid_value",
+        "Invalid {column} in row {row}: {value}",
+    ),
+    (
+        "csv.duplicate_stage",
+        "Stage {stage} of {scene} is listed twice, again in row {row}",
+    ),
+    (
+        "csv.actor_count",
+        "Row {row} of {scene} lists {got} events, expected {expected}",
+    ),
+    (
+        "csv.conflicting_actor",
+        "Row {row} of {scene} lists {value} as {column} of actor {actor}, an earlier stage lists {expected}",
+    ),
+    ("git.failed", "git {command} failed: {details}"),
+    (
+        "git.unsaved_project",
+        "The project has to be saved before it can be put under version control",
+    ),
+    (
+        "git.no_repository",
+        "The project folder is not part of a git repository",
+    ),
+    ("git.invalid_revision", "Unknown version: {revision}"),
+    (
+        "slal.unknown_creature",
+        "No creature race is given for {scene} and none could be inferred from its name or tags",
+    ),
+    (
+        "slal.invalid_alias",
+        "Actor type {alias} must allow at least one sex",
+    ),
+    (
+        "slal.invalid_snippet",
+        "The pasted text is neither a SLAL animation nor a list of them",
+    ),
+    ("events.empty_prefix", "Event prefix must not be empty"),
+    (
+        "events.no_animation_root",
+        "No animation folder has been configured for this project",
+    ),
+    (
+        "events.invalid_animation_root",
+        "Animation folder {path} does not exist",
+    ),
+    (
+        "preview.unsaved_project",
+        "The project must be saved before previews can be attached",
+    ),
+    (
+        "preview.unsupported_format",
+        "Preview {path} must be a gif or webm file",
+    ),
+    (
+        "hkx.binary_packfile",
+        "{path} is a binary packfile, convert it to xml first",
+    ),
+    ("hkx.invalid", "{path} is not a valid packfile: {details}"),
+    (
+        "hkx.unsupported_packfile",
+        "{path} is a binary packfile of version {version}, only hk_2010 packfiles (version 8) can be read. Convert it to xml first",
+    ),
+    ("hkx.xml_syntax", "Malformed xml, {details}"),
+    (
+        "hkx.compressed",
+        "{path} holds a spline compressed animation, which cannot be previewed. Convert it to an uncompressed (interleaved) animation first",
+    ),
+    ("hkx.no_animation_data", "{path} holds no animation"),
+    ("hkx.no_skeleton_data", "{path} holds no skeleton"),
+    (
+        "hkx.no_skeleton",
+        "No skeleton found for race {race} under the animation folder",
+    ),
+    (
+        "racekeys.unknown_race",
+        "{path}: {race} is not a known race, mappings can only point at existing races",
+    ),
+    (
+        "racekeys.invalid_folder",
+        "{path}: {folder} is not a valid behaviour folder for {race}, expected a folder below meshes\\actors",
+    ),
+    ("cli.missing_project", "project file not provided"),
+    ("cli.invalid_format", "Unknown output format {format}"),
+    (
+        "cli.validation_failed",
+        "Validation failed with {count} errors",
+    ),
+    ("cli.missing_file", "file to inspect not provided"),
+    ("cli.missing_input", "input slal file not provided"),
+    ("cli.invalid_input", "input slal file is invalid"),
+    ("cli.missing_output", "output dir not provided"),
+    ("cli.invalid_output", "output dir is invalid"),
+    (
+        "cli.output_not_dir",
+        "output must be a directory when converting multiple files without --merge",
+    ),
+    (
+        "cli.conversion_failed",
+        "{failed} of {total} slal files failed to convert",
+    ),
+    (
+        "cli.upgrade_failed",
+        "{failed} of {total} project files failed to upgrade",
+    ),
+];
+
+pub fn catalog() -> BTreeMap<&'static str, &'static str> {
+    CATALOG.iter().cloned().collect()
+}
+
+pub fn template(key: &str) -> Option<&'static str> {
+    CATALOG.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+// A user facing message, identified by its key and the parameters to interpolate into it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub key: &'static str,
+    pub params: BTreeMap<&'static str, String>,
+}
+
+impl Message {
+    pub fn new(key: &'static str) -> Self {
+        Self {
+            key,
+            params: BTreeMap::new(),
+        }
+    }
+
+    pub fn arg(mut self, name: &'static str, value: impl Display) -> Self {
+        self.params.insert(name, value.to_string());
+        self
+    }
+
+    // Interpolate the parameters into the given template
+    pub fn format(&self, template: &str) -> String {
+        self.params
+            .iter()
+            .fold(template.to_string(), |acc, (name, value)| {
+                acc.replace(&format!("{{{}}}", name), value)
+            })
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match template(self.key) {
+            Some(template) => write!(f, "{}", self.format(template)),
+            None => write!(f, "{}", self.key),
+        }
+    }
+}
+
+// Serialized as { key, params, text }, text being the english rendition
+impl Serialize for Message {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Message", 3)?;
+        state.serialize_field("key", self.key)?;
+        state.serialize_field("params", &self.params)?;
+        state.serialize_field("text", &self.to_string())?;
+        state.end()
+    }
+}
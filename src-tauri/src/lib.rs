@@ -0,0 +1,11 @@
+// The project model and its export, shared by the app and the fuzz targets in fuzz/
+
+pub mod cli;
+pub mod crash;
+pub mod error;
+pub mod furniture;
+pub mod i18n;
+pub mod logging;
+pub mod project;
+pub mod racekeys;
+pub mod watch;
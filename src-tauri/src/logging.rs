@@ -0,0 +1,90 @@
+use log::{
+    kv::{Error, Key, Value, VisitSource},
+    warn, Record,
+};
+use once_cell::sync::Lazy;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    error::SlsbError,
+    project::export_path::{create_export_dir, write_export_file},
+};
+
+pub const LOG_FILE: &str = "SceneBuilder.log";
+pub const BUILD_LOG_FILE: &str = "SceneBuilder-Export.log";
+
+// Lines logged while an export is running, None if no export is in progress
+static CAPTURE: Lazy<Mutex<Option<Vec<String>>>> = Lazy::new(|| Mutex::new(None));
+static LAST_BUILD_LOG: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+struct FieldWriter<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for FieldWriter<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        write!(self.0, " {}={}", key, value)?;
+        Ok(())
+    }
+}
+
+// [LEVEL] [target] message key=value ...
+pub fn format_record(record: &Record) -> String {
+    let mut ret = format!(
+        "[{}] [{}] {}",
+        record.level(),
+        record.target(),
+        record.args()
+    );
+    let _ = record.key_values().visit(&mut FieldWriter(&mut ret));
+    ret
+}
+
+pub fn setup_logger() -> Result<(), fern::InitError> {
+    // the build log formats records itself, as formatted records lose their key/value fields
+    fern::Dispatch::new()
+        .level(log::LevelFilter::Info)
+        .chain(
+            fern::Dispatch::new()
+                .format(|out, _, record| out.finish(format_args!("{}", format_record(record))))
+                .chain(std::io::stdout())
+                .chain(fern::log_file(LOG_FILE)?),
+        )
+        .chain(fern::Output::call(capture))
+        .apply()?;
+    Ok(())
+}
+
+fn capture(record: &Record) {
+    if let Some(lines) = CAPTURE.lock().unwrap().as_mut() {
+        lines.push(format_record(record));
+    }
+}
+
+// Run an export, writing everything logged in the meantime into a log file inside the output folder.
+// The result of the export is returned as is, a log which cannot be written is only warned about
+pub fn with_build_log<T>(
+    dir: &Path,
+    f: impl FnOnce() -> Result<T, SlsbError>,
+) -> Result<T, SlsbError> {
+    *CAPTURE.lock().unwrap() = Some(vec![]);
+    let ret = f();
+    let mut lines = CAPTURE.lock().unwrap().take().unwrap_or_default();
+    if let Err(err) = &ret {
+        lines.push(format!("[ERROR] [export] Export failed: {}", err));
+    }
+    let path = dir.join(BUILD_LOG_FILE);
+    match create_export_dir(dir).and_then(|_| write_export_file(&path, lines.join("\n") + "\n")) {
+        Ok(()) => *LAST_BUILD_LOG.lock().unwrap() = Some(path),
+        Err(err) => {
+            warn!(target: "export", path:? = path; "Could not write the build log: {}", err)
+        }
+    }
+    ret
+}
+
+pub fn last_build_log() -> Option<PathBuf> {
+    LAST_BUILD_LOG.lock().unwrap().clone()
+}
@@ -2,18 +2,50 @@
     all(not(debug_assertions), target_os = "windows"),
     windows_subsystem = "windows"
 )]
-mod cli;
-mod furniture;
-mod project;
-mod racekeys;
-
-use log::{error, info};
-use once_cell::sync::Lazy;
-use project::{package::Package, position::Position, scene::Scene, stage::Stage, NanoID};
+use scene_builder_lib::{cli, crash, error, i18n, logging, project, racekeys, watch};
+
+use error::SlsbError;
+use i18n::Message;
+use log::{error, info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use project::{
+    audit::AuditEntry,
+    bundle::Bundle,
+    convert::ConversionRecord,
+    dot::{import_dot, scene_to_dot},
+    events::{EventUsageReport, PrefixReport},
+    git::{GitChange, GitCommit},
+    graph::SceneStatistics,
+    hkx::{OffsetSuggestion, SkeletonPreview},
+    library::{sync_shared_stages, LibraryEntry},
+    manifest::PackVersion,
+    metrics::{MetricsReport, UsageMetrics, METRICS_FILE},
+    offsets::{OffsetImportReport, OffsetMatching, OffsetTemplate, TemplateReport},
+    orphans::{OrphanAction, OrphanedStage},
+    package::{
+        sanitize_file_name, BuildSummary, ExportPreset, Package, ScenePage, SceneRename,
+        TrashSummary, UnsavedScenes,
+    },
+    position::Position,
+    registry::{compare_registries, RegistryComparison},
+    repair::RepairReport,
+    scene::{Archetype, MirroredStage, Scene, SuppressedWarning},
+    schematic::Schematic,
+    stage::{Stage, StageTimers},
+    tags::TagSuggestion,
+    upgrade::{upgrade_projects, UpgradeReport},
+    validate::{check_scene, scene_warnings, Diagnostic, SceneWarning},
+    IdStatistics, NanoID,
+};
 use serde::{Deserialize, Serialize};
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Mutex,
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 use tauri::{
     menu::{CheckMenuItem, Menu, MenuBuilder, MenuItem, SubmenuBuilder},
@@ -23,7 +55,7 @@ use tauri_plugin_cli::CliExt;
 use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 use tauri_plugin_opener::OpenerExt;
 
-use crate::project::position_info::PositionInfo;
+use project::position_info::PositionInfo;
 
 const DEFAULT_MAINWINDOW_TITLE: &str = "SexLab Scene Builder";
 
@@ -32,10 +64,26 @@ pub static PROJECT: Lazy<Mutex<Package>> = Lazy::new(|| {
     Mutex::new(prjct)
 });
 
+static METRICS: Lazy<Mutex<UsageMetrics>> = Lazy::new(|| Mutex::new(UsageMetrics::default()));
+
+fn update_metrics(f: impl FnOnce(&mut UsageMetrics) -> ()) -> () {
+    let mut metrics = METRICS.lock().unwrap();
+    f(&mut metrics);
+    if let Err(err) = metrics.save() {
+        error!("Failed to write usage metrics: {}", err);
+    }
+}
+
+static APP: OnceCell<AppHandle> = OnceCell::new();
+
 static EDITED: AtomicBool = AtomicBool::new(false);
-#[inline]
+// Tells the frontend whenever the project turns edited or saved
 fn set_edited(val: bool) -> () {
-    EDITED.store(val, Ordering::Relaxed)
+    if EDITED.swap(val, Ordering::Relaxed) != val {
+        if let Some(app) = APP.get() {
+            let _ = app.emit("on_edited_changed", val);
+        }
+    }
 }
 #[inline]
 fn get_edited() -> bool {
@@ -52,14 +100,12 @@ fn get_darkmode() -> bool {
     IS_DARKMODE.load(Ordering::Relaxed)
 }
 
-fn setup_logger() -> Result<(), fern::InitError> {
-    fern::Dispatch::new()
-        .format(|out, message, record| out.finish(format_args!("[{}] {}", record.level(), message)))
-        .level(log::LevelFilter::Info)
-        .chain(std::io::stdout())
-        .chain(fern::log_file("SceneBuilder.log")?)
-        .apply()?;
-    Ok(())
+// Cancelling a dialog is a regular user action and not worth an error entry
+fn log_error(context: &str, err: &SlsbError) -> () {
+    match err {
+        SlsbError::DialogCancelled(_) => info!("{}: {}", context, err),
+        _ => error!("{}: {}", context, err),
+    }
 }
 
 /// MAIN
@@ -69,9 +115,11 @@ const MAIN_WINDOW: &str = "main_window";
 const NEW_PROJECT: &str = "new_prjct";
 const OPEN_PROJECT: &str = "open_prjct";
 const DARKMODE: &str = "darkmode";
+const USAGE_METRICS: &str = "usage_metrics";
+const WATCH_PROJECT: &str = "watch_project";
 
 fn main() {
-    setup_logger().expect("Unable to initialize logger");
+    logging::setup_logger().expect("Unable to initialize logger");
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
@@ -80,23 +128,134 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             request_project_update,
             get_race_keys,
+            get_race_infos,
             create_blank_scene,
+            get_id_statistics,
             save_scene,
             delete_scene,
+            get_trash,
+            restore_scene,
+            empty_trash,
+            set_scene_locked,
+            get_scene_warnings,
+            suppress_warning,
+            relayout_scene,
+            mirror_stage,
+            export_scene_dot,
+            import_scene_dot,
+            make_linear_scene,
+            add_edge,
+            remove_edge,
+            set_root,
+            split_scene,
+            set_localized_name,
+            set_stage_order,
+            add_transition,
+            remove_transition,
+            has_unsaved_changes,
+            get_unsaved_scenes,
+            get_scene_page,
+            get_scene,
+            get_scene_statistics,
+            suggest_tags,
+            get_bundles,
+            set_bundles,
+            set_export_name,
+            get_stage_timers,
+            set_stage_timers,
+            prefix_events,
+            fix_events,
+            sanitize_event,
+            import_offsets,
+            import_csv,
+            paste_slal,
+            load_slal,
+            export_csv,
+            export_offsets,
+            get_offset_templates,
+            save_offset_template,
+            delete_offset_template,
+            apply_offset_template,
+            set_animation_root,
+            get_event_usage,
+            get_scene_preview,
+            attach_scene_preview,
+            remove_scene_preview,
+            clean_previews,
+            set_export_previews,
+            set_prefix_anim_objects,
+            set_translation_languages,
+            set_anim_object_forms,
+            import_anim_object_forms,
+            git_init,
+            git_commit,
+            get_git_status,
+            get_git_history,
+            git_restore,
+            set_git_autocommit,
+            set_pretty_save,
+            set_readable_ids,
+            set_clean_export,
+            set_merge_fnis,
+            set_profile_export,
+            set_behaviour_folder,
+            set_fnis_list_name,
+            save_export_preset,
+            remove_export_preset,
+            build_preset,
+            set_min_framework_version,
+            make_ids_readable,
+            get_stage_preview,
+            get_stage_schematic,
+            write_stage_thumbnail,
+            suggest_offsets,
             open_stage_editor,
             open_stage_editor_from,
             stage_save_and_close,
             make_position,
             mark_as_edited,
+            save_project_with_message,
+            rename_project,
+            make_scene_names_unique,
+            get_audit_log,
+            get_conversion_report,
+            get_project_schema,
+            get_latest_build_log,
+            get_problems,
+            repair_project,
+            get_stage_library,
+            share_stage,
+            link_stage,
+            detach_stage,
+            find_orphans,
+            collect_orphans,
+            compare_with_registry,
+            upgrade_project_folder,
+            get_message_catalog,
+            get_usage_metrics,
+            set_usage_metrics_enabled,
+            clear_usage_metrics,
+            track_scene_focus,
             get_in_darkmode
         ])
         .setup(|app| {
+            let race_overrides = race_override_paths(app.app_handle());
+            if let Err(err) = racekeys::load_race_overrides(&race_overrides) {
+                error!("Unable to load race key mappings: {}", err);
+            }
             let matches = app.cli().matches()?;
             if let Some(command) = matches.subcommand {
                 let res = match command.name.as_str() {
                     "convert" => cli::convert(command.matches.args),
                     "build" => cli::build(command.matches.args),
-                    _ => Err(format!("Unrecognized subcommand: {}", command.name)),
+                    "spec" => cli::spec(command.matches.args),
+                    "validate" => cli::validate_project(command.matches.args),
+                    "inspect" => cli::inspect(command.matches.args),
+                    "watch" => cli::watch_project(command.matches.args),
+                    "upgrade" => cli::upgrade(command.matches.args),
+                    _ => Err(Message::new("error.unknown_command")
+                        .arg("command", &command.name)
+                        .into()),
                 }
                 .map_err(|e| {
                     error!("Error while processing CLI command: {}", e);
@@ -105,7 +264,19 @@ fn main() {
                 app.handle().exit(res.is_err() as i32);
                 return res;
             }
+            let data_dir = app
+                .path()
+                .app_data_dir()
+                .map_err(|err| error!("Unable to locate app data directory: {}", err))
+                .ok();
+            if let Some(dir) = &data_dir {
+                *METRICS.lock().unwrap() = UsageMetrics::load(dir.join(METRICS_FILE));
+                crash::install(dir.clone());
+                start_autosave(dir.clone());
+            }
+            std::thread::spawn(move || racekeys::watch_race_overrides(&race_overrides));
             let app_handle = app.app_handle().clone();
+            let _ = APP.set(app_handle.clone());
             WebviewWindowBuilder::new(
                 app.app_handle(),
                 MAIN_WINDOW.to_string(),
@@ -119,12 +290,84 @@ fn main() {
             .expect("Failed to create main window")
             .on_window_event(move |event| window_event_listener(&app_handle, event));
             app.on_menu_event(menu_event_listener);
+            if let Some(dir) = data_dir {
+                offer_crash_recovery(app.app_handle(), dir);
+            }
             Ok(())
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
+// racekeys.yaml next to the executable, then the one in app data which takes precedence
+fn race_override_paths(app: &AppHandle) -> Vec<PathBuf> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    exe_dir
+        .into_iter()
+        .chain(app.path().app_data_dir().ok())
+        .map(|dir| dir.join(racekeys::RACEKEYS_FILE))
+        .collect()
+}
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+fn start_autosave(dir: PathBuf) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(AUTOSAVE_INTERVAL);
+        if !get_edited() {
+            continue;
+        }
+        // a panic while holding the project is what the autosave is there for
+        let prjct = PROJECT.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = crash::autosave(&dir, &prjct) {
+            error!("Failed to autosave project: {}", err);
+        }
+    });
+}
+
+// If the previous session crashed, offer to restore the project from its last autosave
+fn offer_crash_recovery(app: &AppHandle, dir: PathBuf) {
+    let Some(report) = crash::take_report(&dir) else {
+        crash::discard_autosave(&dir);
+        return;
+    };
+    info!(
+        target: "crash", version = report.app_version.as_str();
+        "Previous session crashed: {}",
+        report.message
+    );
+    if !report.autosave {
+        crash::discard_autosave(&dir);
+        return;
+    }
+    let window = app.get_webview_window(MAIN_WINDOW).unwrap();
+    app.dialog()
+        .message("Scene Builder closed unexpectedly during the last session.\nRestore the project from its last autosave?")
+        .title("Restore Project")
+        .buttons(MessageDialogButtons::YesNo)
+        .kind(MessageDialogKind::Warning)
+        .show(move |restore| {
+            if !restore {
+                crash::discard_autosave(&dir);
+                return;
+            }
+            let mut prjct = PROJECT.lock().unwrap();
+            match crash::load_autosave(&dir) {
+                Ok(package) => {
+                    *prjct = package;
+                    set_edited(true);
+                    let _ = window.set_title(
+                        format!("{} - {}*", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str(),
+                    );
+                    window.emit("on_project_update", &prjct.scenes).unwrap();
+                }
+                Err(err) => log_error("Failed to restore autosave", &err),
+            }
+        });
+}
+
 fn reload_project(reload_type: &str, window: &tauri::WebviewWindow) {
     let mut prjct = PROJECT.lock().unwrap();
     let result = match reload_type {
@@ -133,10 +376,12 @@ fn reload_project(reload_type: &str, window: &tauri::WebviewWindow) {
             Ok(())
         }
         OPEN_PROJECT => prjct.load_project(window.app_handle()),
-        _ => Err(format!("Invalid reload type: {}", reload_type)),
+        _ => Err(Message::new("error.unknown_command")
+            .arg("command", reload_type)
+            .into()),
     };
     if let Err(e) = result {
-        error!("{}", e);
+        log_error("Failed to load project", &e);
         return;
     }
     if prjct.pack_name == String::default() {
@@ -146,6 +391,66 @@ fn reload_project(reload_type: &str, window: &tauri::WebviewWindow) {
             .set_title(format!("{} - {}", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str());
     }
     window.emit("on_project_update", &prjct.scenes).unwrap();
+    stop_watch(window.app_handle());
+}
+
+static WATCH_STOP: Lazy<Mutex<Option<Arc<AtomicBool>>>> = Lazy::new(|| Mutex::new(None));
+
+// Rebuild the project into a chosen folder whenever its file changes on disk
+fn toggle_watch(app: &AppHandle) -> () {
+    if stop_watch(app) {
+        return;
+    }
+    let project = PROJECT.lock().unwrap().pack_path.clone();
+    if !project.is_file() {
+        info!("Save the project before enabling rebuild on save");
+        set_watch_checked(app, false);
+        return;
+    }
+    let out = app
+        .dialog()
+        .file()
+        .set_title("Rebuild Into")
+        .blocking_pick_folder()
+        .and_then(|path| path.into_path().ok());
+    let Some(out) = out else {
+        set_watch_checked(app, false);
+        return;
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    *WATCH_STOP.lock().unwrap() = Some(stop.clone());
+    let app = app.clone();
+    std::thread::spawn(move || {
+        watch::watch(&project, &out, &stop, |build| {
+            if let Err(err) = app.emit("on_watch_build", &build) {
+                error!("Unable to report rebuild, event failure: {}", err);
+            }
+        })
+    });
+}
+
+// Stop a running watch, returns whether one was running
+fn stop_watch(app: &AppHandle) -> bool {
+    let Some(stop) = WATCH_STOP.lock().unwrap().take() else {
+        return false;
+    };
+    stop.store(true, Ordering::Relaxed);
+    set_watch_checked(app, false);
+    true
+}
+
+fn set_watch_checked(app: &AppHandle, checked: bool) -> () {
+    let item = app
+        .get_webview_window(MAIN_WINDOW)
+        .and_then(|window| window.menu())
+        .and_then(|menu| menu.items().ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.as_submenu().and_then(|sub| sub.get(WATCH_PROJECT)))
+        .find_map(|item| item.as_check_menuitem().cloned());
+    if let Some(item) = item {
+        let _ = item.set_checked(checked);
+    }
 }
 
 fn get_menu(app: &AppHandle) -> Result<Menu<Wry>, Box<dyn std::error::Error>> {
@@ -175,6 +480,13 @@ fn get_menu(app: &AppHandle) -> Result<Menu<Wry>, Box<dyn std::error::Error>> {
                 true,
                 Option::<&str>::None,
             )?,
+            &MenuItem::with_id(
+                app,
+                "export_offset",
+                "Export Offset.yaml",
+                true,
+                Option::<&str>::None,
+            )?,
             &MenuItem::with_id(app, "save", "Save", true, "cmdOrControl+S".into())?,
             &MenuItem::with_id(
                 app,
@@ -185,6 +497,14 @@ fn get_menu(app: &AppHandle) -> Result<Menu<Wry>, Box<dyn std::error::Error>> {
             )?,
             &MenuItem::with_id(app, "build", "Export", true, "cmdOrControl+B".into())?,
         ])
+        .item(&CheckMenuItem::with_id(
+            app,
+            WATCH_PROJECT,
+            "Rebuild on Save",
+            true,
+            false,
+            Option::<&str>::None,
+        )?)
         .separator()
         .quit()
         .build()?;
@@ -197,9 +517,19 @@ fn get_menu(app: &AppHandle) -> Result<Menu<Wry>, Box<dyn std::error::Error>> {
             get_darkmode(),
             Option::<&str>::None,
         )?)
+        .separator()
+        .item(&CheckMenuItem::with_id(
+            app,
+            USAGE_METRICS,
+            "Collect Usage Metrics",
+            true,
+            METRICS.lock().unwrap().enabled,
+            Option::<&str>::None,
+        )?)
         .build()?;
     let help_menu = SubmenuBuilder::new(app, "Help")
         .text("open_docs", "Open Wiki")
+        .text("open_build_log", "Open Latest Export Log")
         .separator()
         .text("discord", "Discord")
         .text("patreon", "Patreon")
@@ -216,7 +546,7 @@ fn menu_event_listener(app: &tauri::AppHandle, event: tauri::menu::MenuEvent) {
         NEW_PROJECT | OPEN_PROJECT => {
             let event_id = event.id().0.clone();
             let window = app.get_webview_window(MAIN_WINDOW).unwrap();
-            if get_edited() {
+            if has_unsaved_changes() {
                 app.dialog()
                     .message("There are unsaved changes. Loading a new project will cause these changes to be lost.\nContinue?")
                     .title(if event_id == NEW_PROJECT {"New Project"} else {"Open Project"})
@@ -232,8 +562,8 @@ fn menu_event_listener(app: &tauri::AppHandle, event: tauri::menu::MenuEvent) {
         }
         "save" | "save_as" => {
             let mut prjct = PROJECT.lock().unwrap();
-            if let Err(err) = prjct.save_project(event.id().0 == "save_as", app) {
-                error!("Failed to save project: {}", err);
+            if let Err(err) = prjct.save_project(event.id().0 == "save_as", app, None) {
+                log_error("Failed to save project", &err);
                 return;
             }
             set_edited(false);
@@ -242,11 +572,24 @@ fn menu_event_listener(app: &tauri::AppHandle, event: tauri::menu::MenuEvent) {
                 .set_title(format!("{} - {}", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str());
         }
         "build" => {
-            let prjct = PROJECT.lock().unwrap();
-            if let Err(err) = prjct.export(app) {
-                error!("Failed to build project: {}", err);
+            let mut prjct = PROJECT.lock().unwrap();
+            let version = prjct.pack_version;
+            let summary = match prjct.export(app) {
+                Ok(summary) => summary,
+                Err(err) => {
+                    log_error("Failed to build project", &err);
+                    return;
+                }
+            };
+            if let Err(err) = app.emit("on_build_finished", &summary) {
+                error!("Unable to report build, event failure: {}", err);
+            }
+            if version != prjct.pack_version {
+                set_edited(true);
             }
+            update_metrics(|metrics| metrics.record_build(&prjct.pack_name));
         }
+        WATCH_PROJECT => toggle_watch(app),
         DARKMODE => {
             let new_darkmode = !get_darkmode();
             set_darkmode(new_darkmode);
@@ -254,6 +597,20 @@ fn menu_event_listener(app: &tauri::AppHandle, event: tauri::menu::MenuEvent) {
                 error!("Unable to toggle darkmode, event failure: {}", err);
             }
         }
+        USAGE_METRICS => {
+            update_metrics(|metrics| {
+                let enabled = !metrics.enabled;
+                metrics.set_enabled(enabled)
+            });
+        }
+        "open_build_log" => match logging::last_build_log() {
+            Some(path) => {
+                if let Err(err) = app.opener().open_path(path.to_string_lossy(), None::<&str>) {
+                    error!("Unable to open export log: {}", err);
+                }
+            }
+            None => info!("No project has been exported yet"),
+        },
         "open_docs" => {
             let _ = app.opener().open_url(
                 "https://github.com/Scrabx3/SexLab/wiki/Scene-Builder",
@@ -278,8 +635,18 @@ fn menu_event_listener(app: &tauri::AppHandle, event: tauri::menu::MenuEvent) {
         }
         "import_offset" => {
             let mut prjct = PROJECT.lock().unwrap();
-            if let Err(err) = prjct.import_offset(app) {
-                error!("{}", err);
+            match prjct.import_offset(app, OffsetMatching::Id) {
+                Ok(report) if !report.applied.is_empty() => {
+                    set_edited(true);
+                    emit_scenes_update(app, &prjct, report.scenes());
+                }
+                Ok(_) => {}
+                Err(err) => log_error("Failed to import offsets", &err),
+            }
+        }
+        "export_offset" => {
+            if let Err(err) = export_offsets_to_file(app, None) {
+                log_error("Failed to export offsets", &err);
             }
         }
         _ => {
@@ -291,7 +658,7 @@ fn menu_event_listener(app: &tauri::AppHandle, event: tauri::menu::MenuEvent) {
 fn window_event_listener(app: &AppHandle, event: &tauri::WindowEvent) {
     match event {
         tauri::WindowEvent::CloseRequested { api, .. } => {
-            if get_edited() {
+            if has_unsaved_changes() {
                 let do_close = app
                     .dialog()
                     .message("There are unsaved changes. Are you sure you want to close?")
@@ -323,8 +690,13 @@ async fn get_race_keys() -> Vec<String> {
     racekeys::get_race_keys_string()
 }
 
+// Races with their folders and example creatures, for the creature picker
 #[tauri::command]
-async fn mark_as_edited<R: Runtime>(window: tauri::Window<R>) -> () {
+async fn get_race_infos(search: Option<String>) -> Vec<racekeys::RaceInfo> {
+    racekeys::get_race_infos(search.as_deref())
+}
+
+fn set_window_edited<R: Runtime>(window: &tauri::Window<R>) -> () {
     set_edited(true);
     if let Ok(title) = window.title() {
         if !title.ends_with('*') {
@@ -333,6 +705,232 @@ async fn mark_as_edited<R: Runtime>(window: tauri::Window<R>) -> () {
     }
 }
 
+// Send the given scenes to the frontend instead of the whole project. Scenes which no longer
+// exist, such as renamed scenes under their old id, are sent as null for the frontend to drop
+fn emit_scenes_update<R: Runtime>(
+    emitter: &impl Emitter<R>,
+    prjct: &Package,
+    ids: impl IntoIterator<Item = NanoID>,
+) {
+    let scenes: BTreeMap<NanoID, Option<&Scene>> = ids
+        .into_iter()
+        .map(|id| {
+            let scene = prjct.get_scene(&id);
+            (id, scene)
+        })
+        .collect();
+    emitter.emit("on_scenes_update", scenes).unwrap();
+}
+
+// Edits of the project itself only set the edited flag, scenes are compared against the file
+#[tauri::command]
+fn has_unsaved_changes() -> bool {
+    get_edited() || !PROJECT.lock().unwrap().unsaved_scenes().is_empty()
+}
+
+#[tauri::command]
+fn get_unsaved_scenes() -> UnsavedScenes {
+    PROJECT.lock().unwrap().unsaved_scenes()
+}
+
+#[tauri::command]
+async fn mark_as_edited<R: Runtime>(window: tauri::Window<R>) -> () {
+    set_window_edited(&window);
+}
+
+// Rename the project without touching its file
+#[tauri::command]
+fn rename_project(window: tauri::Window, name: String) -> Result<(), SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.rename(&name)?;
+    let _ =
+        window.set_title(format!("{} - {}*", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str());
+    set_edited(true);
+    Ok(())
+}
+
+// Suffix scenes sharing a name with a counter, returning the renamed scenes
+#[tauri::command]
+fn make_scene_names_unique(window: tauri::Window) -> Vec<SceneRename> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let renamed = prjct.make_scene_names_unique();
+    if !renamed.is_empty() {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, renamed.iter().map(|r| r.scene.clone()));
+    }
+    renamed
+}
+
+#[tauri::command]
+fn save_project_with_message(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    message: String,
+) -> Result<(), SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.save_project(false, &app, Some(message))?;
+    set_edited(false);
+    let _ =
+        window.set_title(format!("{} - {}", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str());
+    Ok(())
+}
+
+#[tauri::command]
+fn get_audit_log(scene: Option<NanoID>) -> Vec<AuditEntry> {
+    PROJECT.lock().unwrap().get_audit_log(scene.as_ref())
+}
+
+// What the slal importer and version migrations did to the project or a single scene
+#[tauri::command]
+fn get_conversion_report(scene: Option<NanoID>) -> Vec<ConversionRecord> {
+    PROJECT.lock().unwrap().get_conversions(scene.as_ref())
+}
+
+// JSON Schema of project files, for editing them outside of the app
+#[tauri::command]
+fn get_project_schema() -> serde_json::Value {
+    project::schema::project_schema().clone()
+}
+
+// English templates of all backend messages, by key
+#[tauri::command]
+fn get_message_catalog() -> BTreeMap<&'static str, &'static str> {
+    i18n::catalog()
+}
+
+// Content of the log file written by the most recent export
+#[tauri::command]
+fn get_latest_build_log() -> Result<Option<String>, SlsbError> {
+    match logging::last_build_log() {
+        Some(path) => Ok(Some(std::fs::read_to_string(path)?)),
+        None => Ok(None),
+    }
+}
+
+// All diagnostics of the project, errors first
+#[tauri::command]
+fn get_problems() -> Vec<Diagnostic> {
+    project::validate::validate(&PROJECT.lock().unwrap())
+}
+
+// Fix dangling references between scenes, stages and graphs, or only list them on a dry run
+#[tauri::command]
+fn repair_project(window: tauri::Window, dry_run: bool) -> RepairReport {
+    let mut prjct = PROJECT.lock().unwrap();
+    let report = project::repair::repair_project(&mut prjct, dry_run);
+    if report.fixed() > 0 {
+        set_window_edited(&window);
+        let scenes = report
+            .repairs
+            .iter()
+            .filter(|r| r.fixed)
+            .map(|r| r.scene.clone());
+        emit_scenes_update(&window, &prjct, scenes);
+    }
+    report
+}
+
+#[tauri::command]
+fn get_stage_library() -> Vec<LibraryEntry> {
+    project::library::library_entries(&PROJECT.lock().unwrap())
+}
+
+#[tauri::command]
+fn share_stage(window: tauri::Window, scene: NanoID, stage: NanoID) -> Result<(), SlsbError> {
+    project::library::share_stage(&mut PROJECT.lock().unwrap(), &scene, &stage)?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn link_stage(window: tauri::Window, scene: NanoID, stage: NanoID) -> Result<Stage, SlsbError> {
+    let stage = project::library::link_stage(&mut PROJECT.lock().unwrap(), &scene, &stage)?;
+    set_window_edited(&window);
+    Ok(stage)
+}
+
+#[tauri::command]
+fn detach_stage(window: tauri::Window, scene: NanoID, stage: NanoID) -> Result<NanoID, SlsbError> {
+    let id = project::library::detach_stage(&mut PROJECT.lock().unwrap(), &scene, &stage)?;
+    set_window_edited(&window);
+    Ok(id)
+}
+
+#[tauri::command]
+fn find_orphans(scene: Option<NanoID>) -> Vec<OrphanedStage> {
+    project::orphans::find_orphans(&PROJECT.lock().unwrap(), scene.as_ref())
+}
+
+#[tauri::command]
+fn collect_orphans(
+    window: tauri::Window,
+    scene: NanoID,
+    stages: Vec<NanoID>,
+    action: OrphanAction,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    if project::orphans::collect_orphans(&mut prjct, &scene, &stages, action)? > 0 {
+        set_window_edited(&window);
+    }
+    Ok(prjct.get_scene(&scene).unwrap().clone())
+}
+
+// Conflicts between the current project and the registries in a chosen folder
+#[tauri::command]
+fn compare_with_registry(app: tauri::AppHandle) -> Result<RegistryComparison, SlsbError> {
+    let dir = app
+        .dialog()
+        .file()
+        .set_title("Select Registry Folder")
+        .blocking_pick_folder()
+        .ok_or(SlsbError::DialogCancelled(Message::new("dialog.registry")))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let prjct = PROJECT.lock().unwrap();
+    compare_registries(&prjct, &dir)
+}
+
+// Migrate all project files below a chosen folder, e.g. after a format change
+#[tauri::command]
+fn upgrade_project_folder(app: tauri::AppHandle) -> Result<UpgradeReport, SlsbError> {
+    let dir = app
+        .dialog()
+        .file()
+        .set_title("Upgrade Projects In")
+        .blocking_pick_folder()
+        .ok_or(SlsbError::DialogCancelled(Message::new("dialog.upgrade")))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    upgrade_projects(&dir)
+}
+
+#[tauri::command]
+fn get_usage_metrics(project: Option<String>) -> MetricsReport {
+    let project = project.unwrap_or_else(|| PROJECT.lock().unwrap().pack_name.clone());
+    METRICS.lock().unwrap().report(&project)
+}
+
+#[tauri::command]
+fn set_usage_metrics_enabled(enabled: bool) -> () {
+    update_metrics(|metrics| metrics.set_enabled(enabled));
+}
+
+#[tauri::command]
+fn clear_usage_metrics() -> () {
+    update_metrics(|metrics| metrics.clear());
+}
+
+// Called by the editor whenever the active scene changes
+#[tauri::command]
+fn track_scene_focus(id: Option<NanoID>) -> () {
+    let prjct = PROJECT.lock().unwrap();
+    let scene = id
+        .as_ref()
+        .and_then(|id| prjct.get_scene(id))
+        .map(|scene| (&scene.id, scene.name.as_str()));
+    update_metrics(|metrics| metrics.focus_scene(&prjct.pack_name, scene));
+}
+
 #[tauri::command]
 fn get_in_darkmode() -> bool {
     get_darkmode()
@@ -342,35 +940,942 @@ fn get_in_darkmode() -> bool {
 
 #[tauri::command]
 fn create_blank_scene() -> Scene {
-    Scene::default()
+    PROJECT.lock().unwrap().new_scene()
 }
 
+// Parameters of generated ids and the chance of a collision among the ids of the project
 #[tauri::command]
-async fn save_scene<R: Runtime>(window: tauri::Window<R>, scene: Scene) -> () {
+fn get_id_statistics() -> IdStatistics {
+    PROJECT.lock().unwrap().id_statistics()
+}
+
+#[tauri::command]
+async fn save_scene<R: Runtime>(
+    window: tauri::Window<R>,
+    scene: Scene,
+    force: Option<bool>,
+) -> Result<Scene, SlsbError> {
+    check_scene(&scene).map_err(|e| {
+        error!("Rejected scene {}: {}", scene.id.0, e);
+        e
+    })?;
+    let saved = {
+        let mut prjct = PROJECT.lock().unwrap();
+        let (id, name) = (scene.id.clone(), scene.name.clone());
+        let synced = prjct
+            .save_scene(scene, force.unwrap_or(false))
+            .map_err(|e| {
+                error!("{}", e);
+                e
+            })?;
+        update_metrics(|metrics| metrics.record_edit(&prjct.pack_name, &id, &name));
+        if !synced.is_empty() {
+            emit_scenes_update(&window, &prjct, synced);
+        }
+        prjct.get_scene(&id).unwrap().clone()
+    };
     mark_as_edited(window).await;
-    PROJECT.lock().unwrap().save_scene(scene);
+    Ok(saved)
 }
 
 #[tauri::command]
-fn delete_scene<R: Runtime>(window: tauri::Window<R>, id: NanoID) -> Result<Scene, String> {
-    let ret = PROJECT.lock().unwrap().discard_scene(&id).ok_or_else(|| {
-        let msg = format!("Invalid Scene ID: {}", id.0);
+fn delete_scene<R: Runtime>(window: tauri::Window<R>, id: NanoID) -> Result<Scene, SlsbError> {
+    let ret = PROJECT.lock().unwrap().discard_scene(&id).map_err(|msg| {
         error!("{}", msg);
         msg
     });
 
     if ret.is_ok() {
-        set_edited(true);
-        if let Ok(title) = window.title() {
-            if !title.ends_with('*') {
-                window.set_title(format!("{}*", title).as_str()).unwrap();
-            }
-        }
+        set_window_edited(&window);
     }
 
     ret
 }
 
+#[tauri::command]
+fn get_trash() -> Vec<TrashSummary> {
+    PROJECT.lock().unwrap().trashed_scenes()
+}
+
+#[tauri::command]
+fn restore_scene(window: tauri::Window, id: NanoID) -> Result<Scene, SlsbError> {
+    let ret = PROJECT.lock().unwrap().restore_scene(&id)?.clone();
+    set_window_edited(&window);
+    Ok(ret)
+}
+
+#[tauri::command]
+fn empty_trash(window: tauri::Window) -> usize {
+    let ret = PROJECT.lock().unwrap().empty_trash();
+    if ret > 0 {
+        set_window_edited(&window);
+    }
+    ret
+}
+
+#[tauri::command]
+fn set_scene_locked<R: Runtime>(
+    window: tauri::Window<R>,
+    id: NanoID,
+    locked: bool,
+) -> Result<(), SlsbError> {
+    PROJECT.lock().unwrap().set_scene_locked(&id, locked)?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_scene_warnings(id: NanoID) -> Result<Vec<SceneWarning>, SlsbError> {
+    let prjct = PROJECT.lock().unwrap();
+    let scene = prjct
+        .get_scene(&id)
+        .ok_or_else(|| Message::new("scene.invalid_id").arg("scene", &id.0))?;
+    Ok(scene_warnings(scene, Some(&prjct)))
+}
+
+#[tauri::command]
+fn suppress_warning<R: Runtime>(
+    window: tauri::Window<R>,
+    id: NanoID,
+    key: String,
+    stage: Option<NanoID>,
+    suppressed: bool,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.suppress_warning(&id, SuppressedWarning { key, stage }, suppressed)?;
+    set_window_edited(&window);
+    emit_scenes_update(&window, &prjct, [id.clone()]);
+    Ok(prjct.get_scene(&id).unwrap().clone())
+}
+
+#[tauri::command]
+fn relayout_scene<R: Runtime>(window: tauri::Window<R>, id: NanoID) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let scene = prjct.get_unlocked_scene_mut(&id)?;
+    scene.relayout();
+    let ret = scene.clone();
+    prjct
+        .audit
+        .record("relayout_scene", Some(&id), vec![], None);
+    set_window_edited(&window);
+    Ok(ret)
+}
+
+// Mirror the offsets and events of a stage, or of a copy of it
+#[tauri::command]
+fn mirror_stage<R: Runtime>(
+    window: tauri::Window<R>,
+    scene: NanoID,
+    stage: NanoID,
+    copy: bool,
+) -> Result<MirroredStage, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let ret = prjct
+        .get_unlocked_scene_mut(&scene)?
+        .mirror_stage(&stage, copy)?;
+    // a shared stage mirrored in place is mirrored for every scene using it
+    let mut changed = sync_shared_stages(&mut prjct, &scene);
+    prjct.audit.record(
+        "mirror_stage",
+        Some(&scene),
+        vec![stage, ret.stage.clone()],
+        None,
+    );
+    set_window_edited(&window);
+    changed.insert(scene);
+    emit_scenes_update(&window, &prjct, changed);
+    Ok(ret)
+}
+
+#[tauri::command]
+fn add_edge<R: Runtime>(
+    window: tauri::Window<R>,
+    scene: NanoID,
+    from: NanoID,
+    to: NanoID,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let changed = prjct.get_unlocked_scene_mut(&scene)?.add_edge(&from, &to)?;
+    if changed {
+        prjct
+            .audit
+            .record("add_edge", Some(&scene), vec![from, to], None);
+        set_window_edited(&window);
+    }
+    Ok(prjct.get_scene(&scene).unwrap().clone())
+}
+
+#[tauri::command]
+fn remove_edge<R: Runtime>(
+    window: tauri::Window<R>,
+    scene: NanoID,
+    from: NanoID,
+    to: NanoID,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct
+        .get_unlocked_scene_mut(&scene)?
+        .remove_edge(&from, &to)?;
+    prjct
+        .audit
+        .record("remove_edge", Some(&scene), vec![from, to], None);
+    set_window_edited(&window);
+    Ok(prjct.get_scene(&scene).unwrap().clone())
+}
+
+#[tauri::command]
+fn set_root<R: Runtime>(
+    window: tauri::Window<R>,
+    scene: NanoID,
+    stage: NanoID,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.get_unlocked_scene_mut(&scene)?.set_root(&stage)?;
+    prjct
+        .audit
+        .record("set_root", Some(&scene), vec![stage], None);
+    set_window_edited(&window);
+    Ok(prjct.get_scene(&scene).unwrap().clone())
+}
+
+#[tauri::command]
+fn make_linear_scene<R: Runtime>(
+    window: tauri::Window<R>,
+    scene_id: NanoID,
+    ordered_stage_ids: Vec<NanoID>,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let scene = prjct.get_unlocked_scene_mut(&scene_id)?;
+    scene.make_linear(&ordered_stage_ids)?;
+    let ret = scene.clone();
+    prjct.audit.record(
+        "make_linear_scene",
+        Some(&scene_id),
+        ordered_stage_ids,
+        None,
+    );
+    set_window_edited(&window);
+    Ok(ret)
+}
+
+#[tauri::command]
+fn split_scene<R: Runtime>(
+    window: tauri::Window<R>,
+    scene_id: NanoID,
+    stage_ids: Vec<NanoID>,
+    link_scenes: bool,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let split = prjct
+        .split_scene(&scene_id, &stage_ids, link_scenes)?
+        .clone();
+    set_window_edited(&window);
+    emit_scenes_update(&window, &prjct, [scene_id, split.id.clone()]);
+    Ok(split)
+}
+
+#[tauri::command]
+fn set_localized_name(
+    window: tauri::Window,
+    scene_id: NanoID,
+    lang: String,
+    name: Option<String>,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.set_localized_name(&scene_id, &lang, name.as_deref())?;
+    set_window_edited(&window);
+    emit_scenes_update(&window, &prjct, [scene_id.clone()]);
+    Ok(prjct.get_scene(&scene_id).unwrap().clone())
+}
+
+#[tauri::command]
+fn set_stage_order(
+    window: tauri::Window,
+    scene_id: NanoID,
+    order: Vec<NanoID>,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.set_stage_order(&scene_id, &order)?;
+    set_window_edited(&window);
+    Ok(prjct.get_scene(&scene_id).unwrap().clone())
+}
+
+#[tauri::command]
+fn add_transition(
+    window: tauri::Window,
+    scene_id: NanoID,
+    stage_id: NanoID,
+    to_scene: NanoID,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    if prjct.add_transition(&scene_id, &stage_id, &to_scene)? {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, [scene_id.clone()]);
+    }
+    Ok(prjct.get_scene(&scene_id).unwrap().clone())
+}
+
+#[tauri::command]
+fn remove_transition(
+    window: tauri::Window,
+    scene_id: NanoID,
+    stage_id: NanoID,
+    to_scene: NanoID,
+) -> Result<Scene, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.remove_transition(&scene_id, &stage_id, &to_scene)?;
+    set_window_edited(&window);
+    emit_scenes_update(&window, &prjct, [scene_id.clone()]);
+    Ok(prjct.get_scene(&scene_id).unwrap().clone())
+}
+
+// Scenes listed per page if the frontend does not ask for a different amount
+const SCENE_PAGE_SIZE: usize = 50;
+
+#[tauri::command]
+fn get_scene_page(
+    offset: Option<usize>,
+    limit: Option<usize>,
+    archetype: Option<Archetype>,
+) -> ScenePage {
+    PROJECT.lock().unwrap().scene_page(
+        offset.unwrap_or(0),
+        limit.unwrap_or(SCENE_PAGE_SIZE),
+        archetype,
+    )
+}
+
+// The full scene, for summaries of get_scene_page opened in the editor
+#[tauri::command]
+fn get_scene(id: NanoID) -> Result<Scene, SlsbError> {
+    PROJECT
+        .lock()
+        .unwrap()
+        .get_scene(&id)
+        .cloned()
+        .ok_or_else(|| Message::new("scene.invalid_id").arg("scene", &id.0).into())
+}
+
+#[tauri::command]
+fn get_scene_statistics(id: Option<NanoID>) -> Result<Vec<SceneStatistics>, SlsbError> {
+    let prjct = PROJECT.lock().unwrap();
+    match id {
+        Some(id) => prjct
+            .get_scene(&id)
+            .map(|scene| vec![scene.statistics(&prjct.stage_timers)])
+            .ok_or_else(|| Message::new("scene.invalid_id").arg("scene", &id.0).into()),
+        None => Ok(prjct
+            .scenes
+            .values()
+            .map(|s| s.statistics(&prjct.stage_timers))
+            .collect()),
+    }
+}
+
+#[tauri::command]
+fn get_bundles() -> Vec<Bundle> {
+    PROJECT.lock().unwrap().bundles.clone()
+}
+
+#[tauri::command]
+fn set_bundles(window: tauri::Window, bundles: Vec<Bundle>) -> () {
+    PROJECT.lock().unwrap().bundles = bundles;
+    set_window_edited(&window);
+}
+
+// Override the registry file name, returning the problems with the new name
+#[tauri::command]
+fn set_export_name(
+    window: tauri::Window,
+    name: Option<String>,
+    extension: Option<String>,
+) -> Vec<Diagnostic> {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.export_name = name.filter(|name| !name.trim().is_empty());
+    prjct.export_extension = extension.filter(|ext| !ext.trim().is_empty());
+    set_window_edited(&window);
+    project::validate::validate_export_name(&prjct)
+}
+
+#[tauri::command]
+fn get_stage_timers() -> StageTimers {
+    PROJECT.lock().unwrap().stage_timers
+}
+
+// Default durations of stages without a fixed length, written into the registry for the runtime
+#[tauri::command]
+fn set_stage_timers(window: tauri::Window, timers: StageTimers) -> Result<(), SlsbError> {
+    timers.validate()?;
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct.stage_timers = timers;
+    set_window_edited(&window);
+    Ok(())
+}
+
+// Tags derived from the actors, races, furniture and climaxes of a scene
+#[tauri::command]
+fn suggest_tags(id: NanoID) -> Result<Vec<TagSuggestion>, SlsbError> {
+    let prjct = PROJECT.lock().unwrap();
+    prjct
+        .get_scene(&id)
+        .map(project::tags::suggest_tags)
+        .ok_or_else(|| Message::new("scene.invalid_id").arg("scene", &id.0).into())
+}
+
+#[tauri::command]
+fn prefix_events<R: Runtime>(
+    window: tauri::Window<R>,
+    prefix: String,
+    replace: Option<String>,
+    scenes: Option<Vec<NanoID>>,
+) -> Result<PrefixReport, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let report =
+        project::events::prefix_events(&mut prjct, &prefix, replace.as_deref(), scenes.as_deref())?;
+    if report.renamed > 0 {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, report.scenes.clone());
+    }
+    Ok(report)
+}
+
+// Rename the events FNIS cannot read, of the given scenes or all of them
+#[tauri::command]
+fn fix_events<R: Runtime>(window: tauri::Window<R>, scenes: Option<Vec<NanoID>>) -> PrefixReport {
+    let mut prjct = PROJECT.lock().unwrap();
+    let report = project::events::fix_events(&mut prjct, scenes.as_deref());
+    if report.renamed > 0 {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, report.scenes.clone());
+    }
+    report
+}
+
+// Name the editor suggests for an event while it is typed
+#[tauri::command]
+fn sanitize_event(event: String) -> String {
+    project::events::sanitize_event(&event)
+}
+
+// Import an offset file, matching its entries by id and optionally by name or events
+#[tauri::command]
+fn import_offsets(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    matching: OffsetMatching,
+) -> Result<OffsetImportReport, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let report = prjct.import_offset(&app, matching)?;
+    if !report.applied.is_empty() {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, report.scenes());
+    }
+    Ok(report)
+}
+
+// Replace the project with a converted slal file. Runs off the main thread so the progress it
+// reports through on_slal_progress can be shown while converting. The project is only locked
+// once the conversion is done, to swap in its result
+#[tauri::command]
+async fn load_slal(app: AppHandle, window: tauri::Window) -> Result<(), SlsbError> {
+    if has_unsaved_changes()
+        && !app
+            .dialog()
+            .message("There are unsaved changes. Loading a new project will cause these changes to be lost.\nContinue?")
+            .title("Load SLAL File")
+            .buttons(MessageDialogButtons::YesNo)
+            .kind(MessageDialogKind::Warning)
+            .blocking_show()
+    {
+        info!("User cancelled the project reload.");
+        return Err(SlsbError::DialogCancelled(Message::new("dialog.load_slal")));
+    }
+    let package = Package::load_slal(&app)?;
+    let mut prjct = PROJECT.lock().unwrap();
+    *prjct = package;
+    set_edited(true);
+    let _ =
+        window.set_title(format!("{} - {}*", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str());
+    window.emit("on_project_update", &prjct.scenes).unwrap();
+    stop_watch(&app);
+    Ok(())
+}
+
+// Add the animations of a pasted SLAL snippet to the project
+#[tauri::command]
+fn paste_slal(window: tauri::Window, text: String) -> Result<Vec<NanoID>, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let ids = prjct.import_slal_snippet(&text)?;
+    set_window_edited(&window);
+    emit_scenes_update(&window, &prjct, ids.clone());
+    Ok(ids)
+}
+
+// Add the scenes of a csv stage sheet to the project
+#[tauri::command]
+fn import_csv(app: tauri::AppHandle, window: tauri::Window) -> Result<Vec<NanoID>, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let ids = prjct.import_csv(&app)?;
+    if !ids.is_empty() {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, ids.clone());
+    }
+    Ok(ids)
+}
+
+// Write the stage graph of a scene into a DOT file
+#[tauri::command]
+fn export_scene_dot(app: tauri::AppHandle, id: NanoID) -> Result<PathBuf, SlsbError> {
+    let (dot, name) = {
+        let prjct = PROJECT.lock().unwrap();
+        let scene = prjct
+            .get_scene(&id)
+            .ok_or(Message::new("scene.invalid_id").arg("scene", &id.0))?;
+        (scene_to_dot(scene), sanitize_file_name(&scene.name))
+    };
+    let path = app
+        .dialog()
+        .file()
+        .set_title("Export Stage Graph")
+        .set_file_name(format!("{}.dot", name))
+        .add_filter("DOT", &["dot", "gv"])
+        .blocking_save_file()
+        .ok_or(SlsbError::DialogCancelled(Message::new("dialog.save_dot")))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    std::fs::write(&path, dot)?;
+    Ok(path)
+}
+
+// Replace the edges and root of a scene by those of a DOT file
+#[tauri::command]
+fn import_scene_dot(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    id: NanoID,
+) -> Result<Scene, SlsbError> {
+    let path = app
+        .dialog()
+        .file()
+        .set_title("Import Stage Graph")
+        .add_filter("DOT", &["dot", "gv"])
+        .blocking_pick_file()
+        .ok_or(SlsbError::DialogCancelled(Message::new("dialog.load_dot")))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let text = std::fs::read_to_string(&path)?;
+    let mut prjct = PROJECT.lock().unwrap();
+    let scene = prjct.get_unlocked_scene_mut(&id)?;
+    let before = scene.graph.clone();
+    for warning in import_dot(scene, &text)? {
+        warn!(target: "project", scene = id.0.as_str(); "{}", warning);
+    }
+    let ret = scene.clone();
+    // stages whose edges the import changed
+    let stages = ret
+        .stages
+        .iter()
+        .filter(|stage| {
+            before.get(&stage.id).map(|n| &n.dest) != ret.graph.get(&stage.id).map(|n| &n.dest)
+        })
+        .map(|stage| stage.id.clone())
+        .collect();
+    prjct
+        .audit
+        .record("import_scene_dot", Some(&id), stages, None);
+    set_window_edited(&window);
+    Ok(ret)
+}
+
+// Write every position of every stage into a csv file
+#[tauri::command]
+fn export_csv(app: tauri::AppHandle) -> Result<PathBuf, SlsbError> {
+    let name = PROJECT.lock().unwrap().pack_name.clone();
+    let path = app
+        .dialog()
+        .file()
+        .set_title("Export Project Summary")
+        .set_file_name(format!("{}.csv", sanitize_file_name(&name)))
+        .add_filter("CSV", &["csv"])
+        .blocking_save_file()
+        .ok_or(SlsbError::DialogCancelled(Message::new("dialog.save_csv")))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    project::csv::write_summary(&PROJECT.lock().unwrap(), &path)?;
+    Ok(path)
+}
+
+fn export_offsets_to_file(
+    app: &AppHandle,
+    scenes: Option<&[NanoID]>,
+) -> Result<PathBuf, SlsbError> {
+    let path = app
+        .dialog()
+        .file()
+        .set_title("Export Offsets")
+        .set_file_name("Offset.yaml")
+        .add_filter("Offset File", &["yaml", "yml"])
+        .blocking_save_file()
+        .ok_or(SlsbError::DialogCancelled(Message::new(
+            "dialog.save_offsets",
+        )))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    project::offsets::write_offset_file(&PROJECT.lock().unwrap(), &path, scenes)?;
+    Ok(path)
+}
+
+#[tauri::command]
+fn export_offsets(
+    app: tauri::AppHandle,
+    scenes: Option<Vec<NanoID>>,
+) -> Result<PathBuf, SlsbError> {
+    export_offsets_to_file(&app, scenes.as_deref())
+}
+
+#[tauri::command]
+fn get_offset_templates() -> Vec<OffsetTemplate> {
+    PROJECT.lock().unwrap().offset_templates.clone()
+}
+
+#[tauri::command]
+fn save_offset_template(
+    window: tauri::Window,
+    name: String,
+    scene: NanoID,
+    stage: NanoID,
+) -> Result<OffsetTemplate, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let template = project::offsets::save_template(&mut prjct, &name, &scene, &stage)?;
+    set_window_edited(&window);
+    Ok(template)
+}
+
+#[tauri::command]
+fn delete_offset_template(window: tauri::Window, name: String) -> () {
+    let mut prjct = PROJECT.lock().unwrap();
+    prjct
+        .offset_templates
+        .retain(|template| template.name != name);
+    set_window_edited(&window);
+}
+
+// Apply a template to all stages whose actors have the races and sexes it was saved with
+#[tauri::command]
+fn apply_offset_template(window: tauri::Window, name: String) -> Result<TemplateReport, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let report = project::offsets::apply_template(&mut prjct, &name)?;
+    if !report.applied.is_empty() {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, report.scenes());
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+fn set_animation_root(app: tauri::AppHandle, window: tauri::Window) -> Result<PathBuf, SlsbError> {
+    let dir = app
+        .dialog()
+        .file()
+        .set_title("Select Animation Folder")
+        .blocking_pick_folder()
+        .ok_or(SlsbError::DialogCancelled(Message::new(
+            "dialog.animation_root",
+        )))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    PROJECT.lock().unwrap().animation_root = Some(dir.clone());
+    set_window_edited(&window);
+    Ok(dir)
+}
+
+// Unused animation files and events without a file under the animation root
+#[tauri::command]
+fn get_event_usage() -> Result<EventUsageReport, SlsbError> {
+    let prjct = PROJECT.lock().unwrap();
+    project::events::event_usage(&prjct)
+}
+
+#[tauri::command]
+fn get_scene_preview(id: NanoID) -> Option<PathBuf> {
+    project::preview::find_preview(&PROJECT.lock().unwrap(), &id)
+}
+
+// Copy a gif or webm clip into the preview folder of the project
+#[tauri::command]
+fn attach_scene_preview(app: tauri::AppHandle, id: NanoID) -> Result<PathBuf, SlsbError> {
+    let source = app
+        .dialog()
+        .file()
+        .set_title("Select Preview")
+        .add_filter("Preview", &project::preview::PREVIEW_EXTENSIONS)
+        .blocking_pick_file()
+        .ok_or(SlsbError::DialogCancelled(Message::new("dialog.preview")))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let prjct = PROJECT.lock().unwrap();
+    project::preview::attach_preview(&prjct, &id, &source)
+}
+
+#[tauri::command]
+fn remove_scene_preview(id: NanoID) -> Result<bool, SlsbError> {
+    project::preview::remove_preview(&PROJECT.lock().unwrap(), &id)
+}
+
+// Delete previews of scenes which no longer exist
+#[tauri::command]
+fn clean_previews(dry_run: bool) -> Result<Vec<PathBuf>, SlsbError> {
+    project::preview::clean_previews(&PROJECT.lock().unwrap(), dry_run)
+}
+
+#[tauri::command]
+fn set_export_previews(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().export_previews = enabled;
+    set_window_edited(&window);
+}
+
+#[tauri::command]
+fn set_anim_object_forms(window: tauri::Window, plugin: String, forms: Vec<String>) -> () {
+    PROJECT
+        .lock()
+        .unwrap()
+        .set_anim_object_forms(&plugin, &forms);
+    set_window_edited(&window);
+}
+
+// Read the animation objects of a plugin from a text file, returning how many were listed
+#[tauri::command]
+fn import_anim_object_forms(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    plugin: String,
+) -> Result<usize, SlsbError> {
+    let path = app
+        .dialog()
+        .file()
+        .set_title("Select Animation Objects")
+        .add_filter("Text", &["txt"])
+        .blocking_pick_file()
+        .ok_or(SlsbError::DialogCancelled(Message::new(
+            "dialog.anim_object_forms",
+        )))?
+        .into_path()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let ret = PROJECT
+        .lock()
+        .unwrap()
+        .import_anim_object_forms(&plugin, &path)?;
+    set_window_edited(&window);
+    Ok(ret)
+}
+
+#[tauri::command]
+fn set_translation_languages(
+    window: tauri::Window,
+    languages: Vec<String>,
+) -> Result<(), SlsbError> {
+    PROJECT
+        .lock()
+        .unwrap()
+        .set_translation_languages(&languages)?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_prefix_anim_objects(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().prefix_anim_objects = enabled;
+    set_window_edited(&window);
+}
+
+/* Version control */
+
+#[tauri::command]
+fn git_init() -> Result<PathBuf, SlsbError> {
+    project::git::init_repository(&PROJECT.lock().unwrap())
+}
+
+#[tauri::command]
+fn git_commit(message: String) -> Result<Option<String>, SlsbError> {
+    project::git::commit_project(&PROJECT.lock().unwrap(), &message)
+}
+
+#[tauri::command]
+fn get_git_status() -> Result<Vec<GitChange>, SlsbError> {
+    project::git::uncommitted_changes(&PROJECT.lock().unwrap())
+}
+
+#[tauri::command]
+fn get_git_history() -> Result<Vec<GitCommit>, SlsbError> {
+    project::git::project_history(&PROJECT.lock().unwrap())
+}
+
+// Load the project as of an earlier commit, discarding unsaved changes
+#[tauri::command]
+fn git_restore(window: tauri::Window, revision: String) -> Result<(), SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    *prjct = project::git::restore_project(&prjct, &revision)?;
+    set_edited(false);
+    let _ =
+        window.set_title(format!("{} - {}", DEFAULT_MAINWINDOW_TITLE, prjct.pack_name).as_str());
+    window.emit("on_project_update", &prjct.scenes).unwrap();
+    Ok(())
+}
+
+#[tauri::command]
+fn set_pretty_save(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().pretty_save = enabled;
+    set_window_edited(&window);
+}
+
+#[tauri::command]
+fn set_clean_export(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().clean_export = enabled;
+    set_window_edited(&window);
+}
+
+#[tauri::command]
+fn set_merge_fnis(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().merge_fnis = enabled;
+    set_window_edited(&window);
+}
+
+#[tauri::command]
+fn set_profile_export(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().profile_export = enabled;
+    set_window_edited(&window);
+}
+
+// Declare the oldest framework version the pack supports, none to not declare any
+#[tauri::command]
+fn set_min_framework_version(
+    window: tauri::Window,
+    version: Option<String>,
+) -> Result<(), SlsbError> {
+    let version = match version.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(version) => Some(
+            version
+                .parse::<PackVersion>()
+                .map_err(|_| Message::new("project.invalid_version").arg("version", version))?,
+        ),
+        None => None,
+    };
+    PROJECT.lock().unwrap().min_framework_version = version;
+    set_window_edited(&window);
+    Ok(())
+}
+
+// Override the behaviour folder of a race, an empty folder restores its default
+#[tauri::command]
+fn set_behaviour_folder(
+    window: tauri::Window,
+    race: String,
+    folder: Option<String>,
+) -> Result<(), SlsbError> {
+    PROJECT
+        .lock()
+        .unwrap()
+        .set_behaviour_folder(&race, folder.as_deref())?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn save_export_preset(window: tauri::Window, preset: ExportPreset) -> Result<(), SlsbError> {
+    PROJECT.lock().unwrap().save_export_preset(preset)?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn remove_export_preset(window: tauri::Window, name: String) -> Result<(), SlsbError> {
+    PROJECT.lock().unwrap().remove_export_preset(&name)?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn build_preset(window: tauri::Window, name: String) -> Result<BuildSummary, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let version = prjct.pack_version;
+    let summary = prjct.build_preset(&name)?;
+    if version != prjct.pack_version {
+        set_window_edited(&window);
+    }
+    update_metrics(|metrics| metrics.record_build(&prjct.pack_name));
+    Ok(summary)
+}
+
+#[tauri::command]
+fn set_fnis_list_name(
+    window: tauri::Window,
+    race: String,
+    pattern: Option<String>,
+) -> Result<(), SlsbError> {
+    PROJECT
+        .lock()
+        .unwrap()
+        .set_fnis_list_name(&race, pattern.as_deref())?;
+    set_window_edited(&window);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_readable_ids(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().readable_ids = enabled;
+    set_window_edited(&window);
+}
+
+// Derive the ids of the given scenes, or of all unlocked and unreleased ones, from their names.
+// Locked and released scenes are only changed if forced
+#[tauri::command]
+fn make_ids_readable(
+    window: tauri::Window,
+    scenes: Option<Vec<NanoID>>,
+    force: Option<bool>,
+) -> Result<usize, SlsbError> {
+    let mut prjct = PROJECT.lock().unwrap();
+    let readable = prjct.make_ids_readable(scenes.as_deref(), force.unwrap_or(false))?;
+    if readable.changed > 0 {
+        set_window_edited(&window);
+        emit_scenes_update(&window, &prjct, readable.scenes);
+    }
+    Ok(readable.changed)
+}
+
+#[tauri::command]
+fn set_git_autocommit(window: tauri::Window, enabled: bool) -> () {
+    PROJECT.lock().unwrap().git_autocommit = enabled;
+    set_window_edited(&window);
+}
+
+// Top down drawing of the actors of a stage, with their skeletons if an animation root is set
+#[tauri::command]
+fn get_stage_schematic(scene: NanoID, stage: NanoID) -> Result<Schematic, SlsbError> {
+    project::schematic::stage_schematic(&PROJECT.lock().unwrap(), &scene, &stage)
+}
+
+// Save the schematic of a stage as the thumbnail of its scene, returning the written file
+#[tauri::command]
+fn write_stage_thumbnail(scene: NanoID, stage: NanoID) -> Result<PathBuf, SlsbError> {
+    project::schematic::write_thumbnail(&PROJECT.lock().unwrap(), &scene, &stage)
+}
+
+// Stick figure poses of the animations of a stage, keyed by their events
+#[tauri::command]
+fn get_stage_preview(
+    scene: NanoID,
+    stage: NanoID,
+) -> Result<BTreeMap<String, SkeletonPreview>, SlsbError> {
+    project::hkx::stage_preview(&PROJECT.lock().unwrap(), &scene, &stage)
+}
+
+// Offsets placing the positions of a stage where the root motion of their animations puts them
+#[tauri::command]
+fn suggest_offsets(scene: NanoID, stage: NanoID) -> Result<Vec<OffsetSuggestion>, SlsbError> {
+    project::hkx::suggest_offsets(&PROJECT.lock().unwrap(), &scene, &stage)
+}
+
 /* Stage */
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -422,7 +1927,7 @@ async fn open_stage_editor<R: Runtime>(
         &app,
         EditorPayload {
             scene: active_scene.id.clone(),
-            stage: stage.unwrap_or(Stage::new(&active_scene)),
+            stage: stage.unwrap_or_else(|| PROJECT.lock().unwrap().new_stage(&active_scene)),
             positions: active_scene.positions.clone(),
         },
     );
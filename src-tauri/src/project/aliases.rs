@@ -0,0 +1,124 @@
+use log::info;
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::define::Sex;
+
+// Extra actor types, read from the folder of the converted slal file
+pub const ALIAS_FILE: &str = "slal_aliases.yaml";
+
+// Actor types written by SLAL itself, anything else is an alias
+const SLAL_TYPES: [&str; 4] = ["male", "female", "creaturemale", "creaturefemale"];
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct ActorType {
+    #[serde(default)]
+    pub male: bool,
+    #[serde(default)]
+    pub female: bool,
+    #[serde(default)]
+    pub futa: bool,
+    // the race is taken from the actor or the creature_race of the animation
+    #[serde(default)]
+    pub creature: bool,
+}
+
+impl ActorType {
+    const fn new(male: bool, female: bool, futa: bool, creature: bool) -> Self {
+        Self {
+            male,
+            female,
+            futa,
+            creature,
+        }
+    }
+
+    // "male", "female, futa"
+    pub fn describe(&self) -> String {
+        [
+            ("male", self.male),
+            ("female", self.female),
+            ("futa", self.futa),
+        ]
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    pub fn sex(&self) -> Sex {
+        Sex {
+            male: self.male,
+            female: self.female,
+            futa: self.futa,
+        }
+    }
+}
+
+// Actor types of slal files, by their trimmed lowercase name
+#[derive(Debug, Clone)]
+pub struct SexAliases(HashMap<String, ActorType>);
+
+impl Default for SexAliases {
+    fn default() -> Self {
+        Self(
+            [
+                ("male", ActorType::new(true, false, false, false)),
+                ("female", ActorType::new(false, true, false, false)),
+                ("creaturemale", ActorType::new(true, false, false, true)),
+                ("creaturefemale", ActorType::new(false, true, false, true)),
+                // placeholder left behind by some slal generators
+                ("type", ActorType::new(true, false, false, false)),
+                ("futa", ActorType::new(false, false, true, false)),
+                ("futanari", ActorType::new(false, false, true, false)),
+                ("transmale", ActorType::new(true, false, true, false)),
+                ("transfemale", ActorType::new(false, true, true, false)),
+                ("creaturefuta", ActorType::new(false, false, true, true)),
+            ]
+            .into_iter()
+            .map(|(name, actor)| (name.to_string(), actor))
+            .collect(),
+        )
+    }
+}
+
+impl SexAliases {
+    fn key(name: &str) -> String {
+        name.trim().to_lowercase()
+    }
+
+    // Built-in aliases, extended or overridden by the alias file inside dir
+    pub fn load(dir: &Path) -> Result<Self, SlsbError> {
+        let mut ret = Self::default();
+        let path = dir.join(ALIAS_FILE);
+        if !path.is_file() {
+            return Ok(ret);
+        }
+        let file: HashMap<String, ActorType> = serde_yaml::from_str(&fs::read_to_string(&path)?)?;
+        for (name, actor) in file {
+            ret.insert(&name, actor)?;
+        }
+        info!(target: "slal", path:? = path; "Loaded actor type aliases");
+        Ok(ret)
+    }
+
+    pub fn insert(&mut self, name: &str, actor: ActorType) -> Result<(), SlsbError> {
+        if !actor.male && !actor.female && !actor.futa {
+            return Err(Message::new("slal.invalid_alias").arg("alias", name).into());
+        }
+        self.0.insert(Self::key(name), actor);
+        Ok(())
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<ActorType> {
+        self.0.get(&Self::key(name)).copied()
+    }
+
+    // Whether the type is one of those SLAL writes, ignoring case and surrounding whitespace
+    pub fn is_native(name: &str) -> bool {
+        SLAL_TYPES.contains(&Self::key(name).as_str())
+    }
+}
@@ -0,0 +1,149 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::NanoID;
+use crate::error::SlsbError;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    // seconds since unix epoch
+    pub timestamp: u64,
+    pub user: String,
+    pub operation: String,
+    #[serde(default)]
+    pub scene: Option<NanoID>,
+    #[serde(default)]
+    pub stages: Vec<NanoID>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl AuditEntry {
+    fn rename(&mut self, renamed: &HashMap<NanoID, NanoID>) {
+        for id in self.scene.iter_mut().chain(self.stages.iter_mut()) {
+            if let Some(new) = renamed.get(id) {
+                *id = new.clone();
+            }
+        }
+    }
+}
+
+// Modifications made since the project was last saved. Entries are appended to a
+// json-lines file next to the project file whenever the project is saved
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    pending: Vec<AuditEntry>,
+    // ids changed since the last save, applied to the stored entries once the project is saved
+    renamed: HashMap<NanoID, NanoID>,
+}
+
+impl AuditLog {
+    pub fn record(
+        &mut self,
+        operation: &str,
+        scene: Option<&NanoID>,
+        stages: Vec<NanoID>,
+        message: Option<String>,
+    ) -> () {
+        self.pending.push(AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            user: std::env::var("USERNAME")
+                .or_else(|_| std::env::var("USER"))
+                .unwrap_or("Unknown".into()),
+            operation: operation.into(),
+            scene: scene.cloned(),
+            stages,
+            message,
+        });
+    }
+
+    // Let all entries refer to a scene or stage by its new id
+    pub fn rename(&mut self, old: &NanoID, new: &NanoID) {
+        for id in self.renamed.values_mut().filter(|id| *id == old) {
+            *id = new.clone();
+        }
+        self.renamed.insert(old.clone(), new.clone());
+        let renamed = HashMap::from([(old.clone(), new.clone())]);
+        for entry in &mut self.pending {
+            entry.rename(&renamed);
+        }
+    }
+
+    pub fn flush(&mut self, path: &Path) -> Result<(), SlsbError> {
+        if !self.renamed.is_empty() && path.is_file() {
+            let mut content = String::new();
+            for entry in self.stored(path) {
+                content.push_str(&serde_json::to_string(&entry)?);
+                content.push('\n');
+            }
+            fs::write(path, content)?;
+        }
+        self.renamed.clear();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        for entry in &self.pending {
+            let line = serde_json::to_string(entry)?;
+            writeln!(file, "{}", line)?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    // The most recent, unsaved entries, oldest first
+    pub fn recent(&self, n: usize) -> Vec<AuditEntry> {
+        let skip = self.pending.len().saturating_sub(n);
+        self.pending[skip..].to_vec()
+    }
+
+    // Entries stored at the given path, with the ids changed since they were written
+    fn stored(&self, path: &Path) -> Vec<AuditEntry> {
+        let Ok(file) = fs::File::open(path) else {
+            return vec![];
+        };
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str::<AuditEntry>(&line)
+                    .map_err(|e| warn!("Skipping invalid audit entry: {}", e))
+                    .ok()
+            })
+            .map(|mut entry| {
+                entry.rename(&self.renamed);
+                entry
+            })
+            .collect()
+    }
+
+    // All entries stored at the given path followed by the unsaved ones
+    pub fn entries(&self, path: Option<&Path>) -> Vec<AuditEntry> {
+        let mut ret = path.map(|path| self.stored(path)).unwrap_or_default();
+        ret.extend(self.pending.iter().cloned());
+        ret
+    }
+}
+
+// {project}.slsb.json -> {project}.slsb.log
+pub fn log_path(project_path: &Path) -> PathBuf {
+    let name = project_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let stem = name.strip_suffix(".slsb.json").unwrap_or(name);
+    project_path.with_file_name(format!("{}.slsb.log", stem))
+}
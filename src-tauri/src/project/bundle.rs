@@ -0,0 +1,75 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::{package::sanitize_file_name, scene::Scene, stage::encode_tag};
+
+// Tag query selecting the scenes of a bundle. A scene matches if it carries all tags of `all`,
+// at least one tag of `any` (if any are given) and none of the tags of `none`. If `sources` are
+// given, the scene also has to originate from one of these packs
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq)]
+pub struct TagQuery {
+    #[serde(default)]
+    pub all: Vec<String>,
+    #[serde(default)]
+    pub any: Vec<String>,
+    #[serde(default)]
+    pub none: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+impl TagQuery {
+    pub fn is_empty(&self) -> bool {
+        self.all.is_empty()
+            && self.any.is_empty()
+            && self.none.is_empty()
+            && self.sources.is_empty()
+    }
+
+    pub fn matches(&self, scene: &Scene) -> bool {
+        let tags = scene_tags(scene);
+        let has = |tag: &String| tags.contains(&encode_tag(tag));
+        self.all.iter().all(has)
+            && (self.any.is_empty() || self.any.iter().any(has))
+            && !self.none.iter().any(has)
+            && (self.sources.is_empty()
+                || scene.source_pack.as_deref().is_some_and(|source| {
+                    self.sources
+                        .iter()
+                        .any(|s| s.trim().eq_ignore_ascii_case(source.trim()))
+                }))
+    }
+}
+
+// A named subset of the project exported into its own registry file, so users can install only
+// the content they want
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq)]
+pub struct Bundle {
+    pub name: String,
+    pub query: TagQuery,
+}
+
+impl Bundle {
+    pub fn file_name(&self, stem: &str, extension: &str) -> String {
+        format!("{}_{}.{}", stem, sanitize_file_name(&self.name), extension)
+    }
+}
+
+// The tags of a scene and all of its stages
+fn scene_tags(scene: &Scene) -> HashSet<String> {
+    scene
+        .tags
+        .iter()
+        .chain(scene.stages.iter().flat_map(|stage| &stage.tags))
+        .map(|tag| encode_tag(tag))
+        .collect()
+}
+
+// Index of the bundle the scene is exported with. Scenes matching several bundles belong to the
+// first of them, scenes matching none stay in the main registry
+pub fn bundle_of(bundles: &[Bundle], scene: &Scene) -> Option<usize> {
+    bundles
+        .iter()
+        .position(|bundle| bundle.query.matches(scene))
+}
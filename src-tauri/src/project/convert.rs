@@ -0,0 +1,190 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    package::{Package, SceneRename},
+    NanoID,
+};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ConvertedFile {
+    pub path: PathBuf,
+    pub pack_name: Option<String>,
+    pub scenes: usize,
+    pub stages: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ConversionReport {
+    pub inputs: Vec<ConvertedFile>,
+    pub outputs: Vec<PathBuf>,
+    // scenes renamed to keep names unique, if requested
+    pub renamed: Vec<SceneRename>,
+}
+
+impl ConversionReport {
+    pub fn failed(&self) -> usize {
+        self.inputs
+            .iter()
+            .filter(|file| file.error.is_some())
+            .count()
+    }
+}
+
+// A single slal file, or every json file inside the given folder
+pub fn slal_inputs(path: &PathBuf) -> Result<Vec<PathBuf>, SlsbError> {
+    if path.is_file() {
+        return Ok(vec![path.clone()]);
+    }
+    if !path.is_dir() {
+        return Err(Message::new("cli.invalid_input").into());
+    }
+    let mut ret: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    ret.sort();
+    if ret.is_empty() {
+        return Err(Message::new("cli.invalid_input").into());
+    }
+    Ok(ret)
+}
+
+// Convert every input into its own project, or into a single project if merge is set. Inputs
+// which fail to convert are listed in the report and otherwise skipped. The pack path of each
+// project is set to the file it was converted from
+pub fn convert_slal(inputs: &[PathBuf], merge: bool) -> (Vec<Package>, ConversionReport) {
+    let mut report = ConversionReport::default();
+    let mut ret: Vec<Package> = vec![];
+    for path in inputs {
+        match Package::from_slal(path.clone()) {
+            Ok(mut package) => {
+                package.pack_path = path.clone();
+                report.inputs.push(ConvertedFile {
+                    path: path.clone(),
+                    pack_name: Some(package.pack_name.clone()),
+                    scenes: package.scenes.len(),
+                    stages: package.scenes.values().map(|s| s.stages.len()).sum(),
+                    error: None,
+                });
+                match ret.first_mut().filter(|_| merge) {
+                    Some(merged) => merged.merge(package),
+                    None => ret.push(package),
+                }
+            }
+            Err(err) => report.inputs.push(ConvertedFile {
+                path: path.clone(),
+                pack_name: None,
+                scenes: 0,
+                stages: 0,
+                error: Some(err.to_string()),
+            }),
+        }
+    }
+    (ret, report)
+}
+
+// Animations of a slal file converted so far, sent while the file is converted
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct SlalProgress {
+    pub converted: usize,
+    // number of animations, unknown while a file is streamed
+    pub total: Option<usize>,
+    // bytes of a streamed file read so far, out of its size
+    pub bytes_read: u64,
+    pub file_size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionKind {
+    Slal,
+    Migration,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    High,
+    Low,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct RaceMapping {
+    pub position: usize,
+    // race as written in the slal file, None for human positions and inferred races
+    pub legacy: Option<String>,
+    pub race: String,
+    // set if the race was inferred from the name and tags of the animation
+    #[serde(default)]
+    pub confidence: Option<Confidence>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct GenderGuess {
+    pub position: usize,
+    pub sex: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct SceneConversion {
+    pub scene: NanoID,
+    pub name: String,
+    // id of the animation in the slal file
+    #[serde(default)]
+    pub source_name: Option<String>,
+    #[serde(default)]
+    pub races: Vec<RaceMapping>,
+    // attributes of the source which have no counterpart in the project
+    #[serde(default)]
+    pub dropped: Vec<String>,
+    #[serde(default)]
+    pub guessed_genders: Vec<GenderGuess>,
+}
+
+// What the importer or a version migration did to the scenes of a project. Records are stored
+// in the project file so they can be audited long after the conversion
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ConversionRecord {
+    // seconds since unix epoch
+    pub timestamp: u64,
+    pub kind: ConversionKind,
+    // file the scenes were converted from
+    #[serde(default)]
+    pub source: Option<String>,
+    pub from_version: u8,
+    pub to_version: u8,
+    #[serde(default)]
+    pub migrations: Vec<String>,
+    #[serde(default)]
+    pub scenes: Vec<SceneConversion>,
+}
+
+impl ConversionRecord {
+    pub fn new(kind: ConversionKind, from_version: u8, to_version: u8) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            kind,
+            source: None,
+            from_version,
+            to_version,
+            migrations: vec![],
+            scenes: vec![],
+        }
+    }
+
+    pub fn scene(&self, id: &NanoID) -> Option<&SceneConversion> {
+        self.scenes.iter().find(|scene| &scene.scene == id)
+    }
+}
@@ -0,0 +1,164 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use crate::error::SlsbError;
+
+use super::{
+    export_path::{create_export_dir, write_export_file},
+    package::Package,
+};
+
+pub const CREDITS_FILE: &str = "Credits.txt";
+
+// "1 scene", "2 scenes"
+pub(crate) fn count(n: usize, noun: &str) -> String {
+    format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" })
+}
+
+// A group with the distinct animation events of its scenes
+type Group<'a> = (CreditGroup, BTreeSet<&'a str>);
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CreditGroup {
+    pub author: String,
+    pub source_pack: Option<String>,
+    pub scenes: Vec<String>,
+    pub stages: usize,
+    pub animations: usize,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AnimObjectCredit {
+    pub name: String,
+    pub scenes: usize,
+}
+
+// Exported scenes grouped by their author and the pack they were taken from
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Credits {
+    pub pack_name: String,
+    pub pack_author: String,
+    pub pack_version: String,
+    pub groups: Vec<CreditGroup>,
+    pub anim_objects: Vec<AnimObjectCredit>,
+}
+
+impl Credits {
+    pub fn from_package(package: &Package) -> Self {
+        let mut groups: BTreeMap<(String, Option<String>), Group> = BTreeMap::new();
+        let mut anim_objects: BTreeMap<&str, usize> = BTreeMap::new();
+        for scene in package.exported_scenes() {
+            let author = scene
+                .author
+                .as_deref()
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .unwrap_or(&package.pack_author)
+                .to_string();
+            let source_pack = scene
+                .source_pack
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string);
+            let (group, events) = groups
+                .entry((author.clone(), source_pack.clone()))
+                .or_insert_with(|| {
+                    let group = CreditGroup {
+                        author,
+                        source_pack,
+                        ..Default::default()
+                    };
+                    (group, BTreeSet::new())
+                });
+            group.scenes.push(scene.name.clone());
+            group.stages += scene.stages.len();
+
+            let mut objects = BTreeSet::new();
+            for position in scene.stages.iter().flat_map(|s| &s.positions) {
+                for event in &position.event {
+                    if event != "__BLANK__" && event != "__DEFAULT__" {
+                        events.insert(event);
+                    }
+                }
+                objects.extend(position.anim_objects());
+            }
+            for obj in objects {
+                *anim_objects.entry(obj).or_default() += 1;
+            }
+        }
+
+        Self {
+            pack_name: package.pack_name.clone(),
+            pack_author: package.pack_author.clone(),
+            pack_version: package.pack_version.to_string(),
+            groups: groups
+                .into_values()
+                .map(|(mut group, events)| {
+                    group.scenes.sort();
+                    group.animations = events.len();
+                    group
+                })
+                .collect(),
+            anim_objects: anim_objects
+                .into_iter()
+                .map(|(name, scenes)| AnimObjectCredit {
+                    name: name.into(),
+                    scenes,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut ret = format!(
+            "{} {} by {}\n",
+            self.pack_name, self.pack_version, self.pack_author
+        );
+        let scenes: usize = self.groups.iter().map(|g| g.scenes.len()).sum();
+        let animations: usize = self.groups.iter().map(|g| g.animations).sum();
+        ret.push_str(&format!(
+            "{}, {}\n",
+            count(scenes, "scene"),
+            count(animations, "animation")
+        ));
+
+        ret.push_str("\nCredits\n");
+        for group in &self.groups {
+            ret.push_str(&format!("\n{}", group.author));
+            if let Some(source) = &group.source_pack {
+                ret.push_str(&format!(", from {}", source));
+            }
+            ret.push_str(&format!(
+                " ({}, {}, {})\n",
+                count(group.scenes.len(), "scene"),
+                count(group.stages, "stage"),
+                count(group.animations, "animation")
+            ));
+            for name in &group.scenes {
+                ret.push_str(&format!("- {}\n", name));
+            }
+        }
+
+        if !self.anim_objects.is_empty() {
+            ret.push_str("\nRequired animation objects\n");
+            for obj in &self.anim_objects {
+                ret.push_str(&format!(
+                    "- {} ({})\n",
+                    obj.name,
+                    count(obj.scenes, "scene")
+                ));
+            }
+        }
+        ret
+    }
+
+    // Written on every export, replacing the previous file
+    pub fn write(&self, root_dir: &Path) -> Result<(), SlsbError> {
+        create_export_dir(root_dir)?;
+        write_export_file(&root_dir.join(CREDITS_FILE), self.to_text())
+    }
+}
@@ -0,0 +1,349 @@
+use log::info;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    error::SlsbError,
+    i18n::Message,
+    racekeys::{get_race_keys_string, map_legacy_to_racekey},
+};
+
+use super::{
+    aliases::SexAliases, define::Sex, package::Package, position::Position,
+    position_info::PositionInfo, scene::Scene, stage::Stage, NanoID,
+};
+
+// Columns of a stage sheet. Scene, stage and events are required
+pub const IMPORT_COLUMNS: [&str; 7] = [
+    "scene", "stage", "events", "races", "sexes", "duration", "tags",
+];
+
+// Columns of the project summary, one row per position of every stage. Events and variants
+// are listed like the events of a stage sheet
+pub const SUMMARY_COLUMNS: [&str; 15] = [
+    "scene",
+    "scene_id",
+    "stage",
+    "stage_id",
+    "position",
+    "events",
+    "variants",
+    "race",
+    "male",
+    "female",
+    "futa",
+    "fixed_len",
+    "tags",
+    "position_tags",
+    "climax",
+];
+
+// Separates the values of the actors inside a cell, e.g. "A1;B1"
+const LIST_SEPARATOR: char = ';';
+
+// Quote fields which would otherwise be split or misread
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) || value.trim() != value {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.into()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    fields.join(",") + "\r\n"
+}
+
+// Rows of comma separated values. Fields may be quoted, doubling quotes inside of them
+pub fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, SlsbError> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, _) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(Message::new("csv.unterminated_quote")
+            .arg("row", rows.len() + 1)
+            .into());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    // spreadsheets like to append empty rows
+    rows.retain(|row| row.iter().any(|field| !field.trim().is_empty()));
+    Ok(rows)
+}
+
+fn list_of(cell: &str) -> Vec<String> {
+    cell.split(LIST_SEPARATOR)
+        .map(|value| value.trim().to_string())
+        .collect()
+}
+
+// Race keys as shown in the editor, or the legacy keys of SLAL
+fn resolve_race(race: &str) -> Result<String, SlsbError> {
+    match get_race_keys_string()
+        .into_iter()
+        .find(|key| key.eq_ignore_ascii_case(race.trim()))
+    {
+        Some(key) => Ok(key),
+        None => map_legacy_to_racekey(race.trim()),
+    }
+}
+
+struct StageRow {
+    row: usize,
+    index: i64,
+    events: Vec<String>,
+    races: Vec<String>,
+    sexes: Vec<String>,
+    duration: f32,
+    tags: Vec<String>,
+}
+
+fn conflict(
+    scene: &str,
+    row: usize,
+    actor: usize,
+    column: &str,
+    value: &str,
+    expected: &str,
+) -> SlsbError {
+    Message::new("csv.conflicting_actor")
+        .arg("scene", scene)
+        .arg("row", row)
+        .arg("actor", actor + 1)
+        .arg("column", column)
+        .arg("value", value)
+        .arg("expected", expected)
+        .into()
+}
+
+// Scenes of a stage sheet with one row per stage, in the order they first appear. Stages are
+// ordered by their index and linked one after another
+pub fn import_scenes(text: &str) -> Result<Vec<Scene>, SlsbError> {
+    let rows = parse_csv(text)?;
+    let Some((header, rows)) = rows.split_first() else {
+        return Ok(vec![]);
+    };
+    let columns: HashMap<String, usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.trim().to_lowercase(), i))
+        .collect();
+    for column in &IMPORT_COLUMNS[..3] {
+        if !columns.contains_key(*column) {
+            return Err(Message::new("csv.missing_column")
+                .arg("column", column)
+                .into());
+        }
+    }
+
+    let mut order: Vec<String> = vec![];
+    let mut scenes: HashMap<String, Vec<StageRow>> = HashMap::new();
+    for (i, fields) in rows.iter().enumerate() {
+        // header is the first row, rows are counted from 1 like in spreadsheets
+        let row = i + 2;
+        let cell = |column: &str| {
+            columns
+                .get(column)
+                .and_then(|&i| fields.get(i))
+                .map(|field| field.trim())
+                .unwrap_or_default()
+        };
+        let invalid = |column: &str| -> SlsbError {
+            Message::new("csv.invalid_value")
+                .arg("row", row)
+                .arg("column", column)
+                .arg("value", cell(column))
+                .into()
+        };
+        let name = cell("scene");
+        if name.is_empty() {
+            return Err(invalid("scene"));
+        }
+        let events = list_of(cell("events"));
+        if events.iter().any(|event| event.is_empty()) {
+            return Err(invalid("events"));
+        }
+        let duration = match cell("duration") {
+            "" => 0.0,
+            value => value
+                .parse::<f32>()
+                .ok()
+                .filter(|d| d.is_finite() && *d >= 0.0)
+                .ok_or_else(|| invalid("duration"))?,
+        };
+        let stage = StageRow {
+            row,
+            index: cell("stage").parse().map_err(|_| invalid("stage"))?,
+            events,
+            races: list_of(cell("races")),
+            sexes: list_of(cell("sexes")),
+            duration,
+            tags: cell("tags")
+                .split([',', LIST_SEPARATOR])
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        };
+        if !scenes.contains_key(name) {
+            order.push(name.to_string());
+        }
+        scenes.entry(name.to_string()).or_default().push(stage);
+    }
+
+    let aliases = SexAliases::default();
+    let mut ret = vec![];
+    for name in order {
+        let mut rows = scenes.remove(&name).unwrap();
+        rows.sort_by_key(|row| row.index);
+        if let Some(pair) = rows.windows(2).find(|pair| pair[0].index == pair[1].index) {
+            return Err(Message::new("csv.duplicate_stage")
+                .arg("scene", &name)
+                .arg("stage", pair[1].index)
+                .arg("row", pair[1].row)
+                .into());
+        }
+        let actors = rows[0].events.len();
+        if let Some(row) = rows.iter().find(|row| row.events.len() != actors) {
+            return Err(Message::new("csv.actor_count")
+                .arg("scene", &name)
+                .arg("row", row.row)
+                .arg("got", row.events.len())
+                .arg("expected", actors)
+                .into());
+        }
+
+        let mut scene = Scene {
+            name,
+            positions: vec![],
+            ..Default::default()
+        };
+        // races and sexes are shared by all stages of a scene. Every stage may list them, but
+        // has to agree with the stages before it. Missing values default to humans
+        for n in 0..actors {
+            let mut race: Option<String> = None;
+            let mut sex: Option<(&str, Sex)> = None;
+            for row in &rows {
+                if let Some(value) = row.races.get(n).filter(|race| !race.is_empty()) {
+                    let value = resolve_race(value)?;
+                    match &race {
+                        Some(race) if *race != value => {
+                            return Err(conflict(&scene.name, row.row, n, "races", &value, race))
+                        }
+                        Some(_) => {}
+                        None => race = Some(value),
+                    }
+                }
+                if let Some(value) = row.sexes.get(n).filter(|sex| !sex.is_empty()) {
+                    let resolved = aliases
+                        .resolve(value)
+                        .ok_or(Message::new("slal.unknown_sex").arg("sex", value))?
+                        .sex();
+                    match &sex {
+                        Some((given, sex)) if *sex != resolved => {
+                            return Err(conflict(&scene.name, row.row, n, "sexes", value, given))
+                        }
+                        Some(_) => {}
+                        None => sex = Some((value, resolved)),
+                    }
+                }
+            }
+            let mut info = PositionInfo::default();
+            if let Some(race) = race {
+                info.race = race;
+            }
+            if let Some((_, sex)) = sex {
+                info.sex = sex;
+            }
+            scene.positions.push(info);
+        }
+        for row in &rows {
+            let mut stage = Stage::new(&scene);
+            stage.positions = row
+                .events
+                .iter()
+                .zip(&scene.positions)
+                .map(|(event, info)| {
+                    let mut position = Position::new(None);
+                    position.event = vec![event.clone()];
+                    position.sex = info.sex.clone();
+                    position.race = info.race.clone();
+                    position
+                })
+                .collect();
+            stage.extra.fixed_len = row.duration;
+            stage.tags = row.tags.clone();
+            scene.stages.push(stage);
+        }
+        for position in &mut scene.stages.last_mut().unwrap().positions {
+            position.climax = true;
+        }
+        let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+        scene.make_linear(&order)?;
+        ret.push(scene);
+    }
+    info!(target: "project", "Imported {} scenes from csv", ret.len());
+    Ok(ret)
+}
+
+// All positions of all stages, scenes ordered by name and stages in the order of the scene
+pub fn project_summary(package: &Package) -> String {
+    let mut scenes: Vec<&Scene> = package.scenes.values().collect();
+    scenes.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    let header: Vec<String> = SUMMARY_COLUMNS.iter().map(|c| c.to_string()).collect();
+    let mut ret = csv_row(&header);
+    let separator = LIST_SEPARATOR.to_string();
+    for scene in scenes {
+        for (i, stage) in scene.stages.iter().enumerate() {
+            for (n, position) in stage.positions.iter().enumerate() {
+                let info = scene.positions.get(n).cloned().unwrap_or_default();
+                let flag = |set: bool| if set { "1" } else { "0" }.to_string();
+                ret.push_str(&csv_row(&[
+                    scene.name.clone(),
+                    scene.id.0.clone(),
+                    (i + 1).to_string(),
+                    stage.id.0.clone(),
+                    (n + 1).to_string(),
+                    position.event.join(&separator),
+                    position.variants.join(&separator),
+                    info.race,
+                    flag(info.sex.male),
+                    flag(info.sex.female),
+                    flag(info.sex.futa),
+                    stage.extra.fixed_len.to_string(),
+                    stage.tags.join(", "),
+                    position.tags.join(", "),
+                    flag(position.climax),
+                ]));
+            }
+        }
+    }
+    ret
+}
+
+pub fn write_summary(package: &Package, path: &Path) -> Result<(), SlsbError> {
+    fs::write(path, project_summary(package))?;
+    info!(target: "project", path:? = path; "Wrote project summary");
+    Ok(())
+}
@@ -1,7 +1,15 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::project::{define::Offset, serialize::EncodeBinary};
+use std::mem::size_of;
+use crate::{
+    furniture::Furniture,
+    project::{
+        define::Offset,
+        serialize::{ByteWriter, EncodeBinary, Layout},
+    },
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct FurnitureData {
     pub furni_types: Vec<String>,
     pub allow_bed: bool,
@@ -18,17 +26,46 @@ impl Default for FurnitureData {
   }
 }
 
+impl FurnitureData {
+  // Whether the data differs from the default, which registries without furniture imply
+  pub fn is_default(&self) -> bool {
+    crate::furniture::as_furnitre(&self.furni_types) == Furniture::None
+      && !self.allow_bed
+      && self.offset.is_zero()
+  }
+}
+
 impl EncodeBinary for FurnitureData {
   fn get_byte_size(&self) -> usize {
-    self.furni_types.get_byte_size() +
+    size_of::<u32>() + // furniture bitflags
     self.allow_bed.get_byte_size() +
     self.offset.get_byte_size()
   }
 
-  fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+  fn write_byte(&self, buf: &mut ByteWriter) -> () {
     let furni_bytes = crate::furniture::as_furnitre(&self.furni_types);
     buf.extend_from_slice(&furni_bytes.bits().to_be_bytes());
     self.allow_bed.write_byte(buf);
     self.offset.write_byte(buf);
   }
+
+  fn layout() -> Layout {
+    Layout::structure(
+      "FurnitureData",
+      vec![
+        (
+          "furni_types",
+          Layout::Flags {
+            width: size_of::<u32>(),
+            flags: Furniture::all()
+              .iter_names()
+              .map(|(name, flag)| (name.to_string(), flag.bits() as u64))
+              .collect(),
+          },
+        ),
+        ("allow_bed", bool::layout()),
+        ("offset", Offset::layout()),
+      ],
+    )
+  }
 }
@@ -12,3 +12,9 @@ pub use furniture_data::FurnitureData;
 
 mod node;
 pub use node::Node;
+
+mod transition;
+pub use transition::Transition;
+
+mod requirements;
+pub use requirements::{plugin_of_race, Requirements};
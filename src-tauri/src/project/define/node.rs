@@ -1,9 +1,13 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::project::{serialize::EncodeBinary, NanoID};
+use crate::project::{
+    serialize::{ByteWriter, EncodeBinary, Layout},
+    NanoID,
+};
 
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct Node {
     pub dest: Vec<NanoID>,
     pub x: f32,
@@ -25,7 +29,11 @@ impl EncodeBinary for Node {
       self.dest.get_byte_size()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) {
+    fn write_byte(&self, buf: &mut ByteWriter) {
       self.dest.write_byte(buf);
     }
+
+    fn layout() -> Layout {
+      Layout::structure("Node", vec![("dest", Vec::<NanoID>::layout())])
+    }
 }
@@ -1,21 +1,54 @@
 use std::mem::size_of;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::project::serialize::EncodeBinary;
+use crate::project::serialize::{ByteWriter, Capabilities, EncodeBinary, Layout};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq)]
 pub struct Offset {
     pub x: f32,
     pub y: f32,
     pub z: f32,
     pub r: f32,
+    // pitch and roll, r being the rotation around the z axis
+    #[serde(default)] // addition 2.2
+    pub rx: f32,
+    #[serde(default)] // addition 2.2
+    pub ry: f32,
+}
+
+impl Offset {
+    // Whether the offset pitches or rolls, registries only carry rotations if an offset does
+    pub fn is_tilted(&self) -> bool {
+        self.rx != 0.0 || self.ry != 0.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        [self.x, self.y, self.z, self.r].iter().all(|v| *v == 0.0) && !self.is_tilted()
+    }
+
+    // The offset reflected onto the other side of the partner, negating x and the rotations
+    // which turn left or right. The heading stays within -180 and 180 degrees
+    pub fn mirrored(&self) -> Offset {
+        let negate = |v: f32| if v == 0.0 { 0.0 } else { -v };
+        let heading = (180.0 - self.r).rem_euclid(360.0) - 180.0;
+        Offset {
+            x: negate(self.x),
+            y: self.y,
+            z: self.z,
+            r: if heading == -180.0 { 180.0 } else { heading },
+            rx: self.rx,
+            ry: negate(self.ry),
+        }
+    }
 }
 
 impl EncodeBinary for Offset {
+    // Without rotations, the registry accounts for their size if it announces them
     fn get_byte_size(&self) -> usize {
-        size_of::<Offset>()
+        4 * size_of::<i32>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         let x_ = (self.x * 1000.0).round() as i32;
         buf.extend_from_slice(&x_.to_be_bytes());
         let y_ = (self.y * 1000.0).round() as i32;
@@ -24,5 +57,26 @@ impl EncodeBinary for Offset {
         buf.extend_from_slice(&z_.to_be_bytes());
         let r_ = (self.r * 1000.0).round() as i32;
         buf.extend_from_slice(&r_.to_be_bytes());
+        if !buf.capabilities().contains(Capabilities::OFFSETS_V2) {
+            return;
+        }
+        let rx_ = (self.rx * 1000.0).round() as i32;
+        buf.extend_from_slice(&rx_.to_be_bytes());
+        let ry_ = (self.ry * 1000.0).round() as i32;
+        buf.extend_from_slice(&ry_.to_be_bytes());
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Offset",
+            vec![
+                ("x", f32::layout()),
+                ("y", f32::layout()),
+                ("z", f32::layout()),
+                ("r", f32::layout()),
+                ("rx", Layout::gated(Capabilities::OFFSETS_V2, f32::layout())),
+                ("ry", Layout::gated(Capabilities::OFFSETS_V2, f32::layout())),
+            ],
+        )
     }
 }
@@ -0,0 +1,83 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    project::serialize::{interned_list_size, ByteWriter, EncodeBinary, Layout, StringTable},
+    racekeys::map_race_to_folder,
+};
+
+// Mods a scene depends on, the runtime hides scenes whose requirements are not installed
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq)]
+pub struct Requirements {
+    // plugin files, e.g. Dawnguard.esm or ZaZAnimationPack.esm
+    #[serde(default)]
+    pub plugins: Vec<String>,
+    // editor ids of keywords which have to be loaded, e.g. of furniture
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+// The plugin adding the behaviour folder of a race, if it is not part of the base game
+pub fn plugin_of_race(race: &str) -> Option<&'static str> {
+    let folder = map_race_to_folder(race).ok()?;
+    match folder.split('\\').next() {
+        Some("dlc01") => Some("Dawnguard.esm"),
+        Some("dlc02") => Some("Dragonborn.esm"),
+        _ => None,
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: &str) {
+    let value = value.trim();
+    if !value.is_empty() && !list.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        list.push(value.to_string());
+    }
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty() && self.keywords.is_empty()
+    }
+
+    // These requirements with the plugins of the given races added, trimmed and without duplicates
+    pub fn with_races<'a>(&self, races: impl Iterator<Item = &'a str>) -> Requirements {
+        let mut ret = Requirements::default();
+        for plugin in &self.plugins {
+            push_unique(&mut ret.plugins, plugin);
+        }
+        for plugin in races.filter_map(plugin_of_race) {
+            push_unique(&mut ret.plugins, plugin);
+        }
+        for keyword in &self.keywords {
+            push_unique(&mut ret.keywords, keyword);
+        }
+        ret
+    }
+
+    pub fn intern_strings(&self, table: &mut StringTable) -> () {
+        for value in self.plugins.iter().chain(&self.keywords) {
+            table.intern(value);
+        }
+    }
+}
+
+impl EncodeBinary for Requirements {
+    fn get_byte_size(&self) -> usize {
+        interned_list_size(self.plugins.len()) + interned_list_size(self.keywords.len())
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        buf.write_interned_list(&self.plugins);
+        buf.write_interned_list(&self.keywords);
+    }
+
+    fn layout() -> Layout {
+        let list = || Layout::List {
+            item: Box::new(Layout::Interned),
+        };
+        Layout::structure(
+            "Requirements",
+            vec![("plugins", list()), ("keywords", list())],
+        )
+    }
+}
@@ -1,7 +1,8 @@
-use crate::project::serialize::EncodeBinary;
+use crate::project::serialize::{ByteWriter, EncodeBinary, Layout};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Sex {
     pub male: bool,
     pub female: bool,
@@ -13,7 +14,7 @@ impl EncodeBinary for Sex {
         size_of::<u8>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         if !self.male && !self.female && !self.futa {
             panic!("Empty Sex definition");
         }
@@ -21,6 +22,13 @@ impl EncodeBinary for Sex {
             (1 << 0) * self.male as u8 + (1 << 1) * self.female as u8 + (1 << 2) * self.futa as u8,
         );
     }
+
+    fn layout() -> Layout {
+        Layout::flags(
+            size_of::<u8>(),
+            &[("male", 1 << 0), ("female", 1 << 1), ("futa", 1 << 2)],
+        )
+    }
 }
 
 impl Default for Sex {
@@ -1,8 +1,9 @@
-use crate::project::serialize::EncodeBinary;
+use crate::project::serialize::{ByteWriter, EncodeBinary, Layout};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::mem::size_of;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct Stripping {
     default: bool,
 
@@ -18,7 +19,7 @@ impl EncodeBinary for Stripping {
         size_of::<u8>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         if self.default {
             buf.push(1 << 7);
         } else if self.everything {
@@ -29,6 +30,19 @@ impl EncodeBinary for Stripping {
             buf.push(self.helmet as u8 + 2 * self.gloves as u8 + 4 * self.boots as u8);
         }
     }
+
+    // 0xFF strips everything, 0x00 nothing, otherwise the individual flags apply
+    fn layout() -> Layout {
+        Layout::flags(
+            size_of::<u8>(),
+            &[
+                ("helmet", 1 << 0),
+                ("gloves", 1 << 1),
+                ("boots", 1 << 2),
+                ("default", 1 << 7),
+            ],
+        )
+    }
 }
 
 impl Default for Stripping {
@@ -0,0 +1,35 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::project::{
+    serialize::{ByteWriter, EncodeBinary, Layout},
+    NanoID,
+};
+
+// Continuation from a stage of one scene into the root of another scene
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct Transition {
+    pub from_stage: NanoID,
+    pub to_scene: NanoID,
+}
+
+impl EncodeBinary for Transition {
+    fn get_byte_size(&self) -> usize {
+        self.from_stage.get_byte_size() + self.to_scene.get_byte_size()
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        self.from_stage.write_byte(buf);
+        self.to_scene.write_byte(buf);
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Transition",
+            vec![
+                ("from_stage", NanoID::layout()),
+                ("to_scene", NanoID::layout()),
+            ],
+        )
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use crate::error::SlsbError;
+
+use super::{
+    credits::count,
+    export_path::{create_export_dir, write_export_file},
+    package::Package,
+};
+
+pub const DEPENDENCIES_FILE: &str = "dependencies.json";
+pub const DEPENDENCIES_LIST_FILE: &str = "Requirements.txt";
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    // names of the exported scenes depending on it
+    pub scenes: Vec<String>,
+}
+
+// Everything the exported scenes need installed next to the pack
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Dependencies {
+    pub pack_name: String,
+    pub pack_version: String,
+    pub plugins: Vec<Dependency>,
+    pub keywords: Vec<Dependency>,
+    pub anim_objects: Vec<Dependency>,
+}
+
+type Usage<'a> = BTreeMap<String, (String, BTreeSet<&'a str>)>;
+
+// Entries are compared case insensitive, keeping the spelling of their first use
+fn add<'a>(usage: &mut Usage<'a>, name: &str, scene: &'a str) {
+    usage
+        .entry(name.to_lowercase())
+        .or_insert_with(|| (name.to_string(), BTreeSet::new()))
+        .1
+        .insert(scene);
+}
+
+fn collect(usage: Usage) -> Vec<Dependency> {
+    usage
+        .into_values()
+        .map(|(name, scenes)| Dependency {
+            name,
+            scenes: scenes.into_iter().map(str::to_string).collect(),
+        })
+        .collect()
+}
+
+impl Dependencies {
+    pub fn from_package(package: &Package) -> Self {
+        let mut plugins = Usage::new();
+        let mut keywords = Usage::new();
+        let mut anim_objects = Usage::new();
+        for scene in package.exported_scenes() {
+            let requirements = scene.encoded_requirements();
+            for plugin in &requirements.plugins {
+                add(&mut plugins, plugin, &scene.name);
+            }
+            for keyword in &requirements.keywords {
+                add(&mut keywords, keyword, &scene.name);
+            }
+            for position in scene.stages.iter().flat_map(|s| &s.positions) {
+                for obj in position.anim_objects() {
+                    add(
+                        &mut anim_objects,
+                        &package.exported_anim_object(obj),
+                        &scene.name,
+                    );
+                }
+            }
+        }
+        Self {
+            pack_name: package.pack_name.clone(),
+            pack_version: package.pack_version.to_string(),
+            plugins: collect(plugins),
+            keywords: collect(keywords),
+            anim_objects: collect(anim_objects),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty() && self.keywords.is_empty() && self.anim_objects.is_empty()
+    }
+
+    // A list to paste into the requirements section of a mod page
+    pub fn to_text(&self) -> String {
+        let mut ret = format!("Requirements of {} {}\n", self.pack_name, self.pack_version);
+        if self.is_empty() {
+            ret.push_str("\nNo requirements besides SexLab\n");
+        }
+        for (title, list) in [
+            ("Plugins", &self.plugins),
+            ("Keywords", &self.keywords),
+            ("Animation objects", &self.anim_objects),
+        ] {
+            if list.is_empty() {
+                continue;
+            }
+            ret.push_str(&format!("\n{}\n", title));
+            for dependency in list {
+                ret.push_str(&format!(
+                    "- {} ({})\n",
+                    dependency.name,
+                    count(dependency.scenes.len(), "scene")
+                ));
+            }
+        }
+        ret
+    }
+
+    pub fn write(&self, root_dir: &Path) -> Result<(), SlsbError> {
+        create_export_dir(root_dir)?;
+        let json = serde_json::to_string_pretty(self)?;
+        write_export_file(&root_dir.join(DEPENDENCIES_FILE), json)?;
+        write_export_file(&root_dir.join(DEPENDENCIES_LIST_FILE), self.to_text())
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{graph::depths, scene::Scene, NanoID};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Id(String),
+    Edge,
+    Open,
+    Close,
+    OpenBrace,
+    CloseBrace,
+    Equals,
+    Separator,
+}
+
+// Nodes and edges of a DOT file, in order of appearance. Node and edge attributes other than
+// root are ignored
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DotGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<(String, String)>,
+    pub root: Option<String>,
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// The stage graph of a scene, nodes named by stage id and labelled with the stage name
+pub fn scene_to_dot(scene: &Scene) -> String {
+    let mut ret = format!("digraph {} {{\n", quoted(&scene.name));
+    for stage in &scene.stages {
+        ret.push_str(&format!(
+            "  {} [label={}",
+            quoted(&stage.id.0),
+            quoted(&stage.name)
+        ));
+        if stage.id == scene.root {
+            ret.push_str(", root=true");
+        }
+        ret.push_str("];\n");
+    }
+    for stage in &scene.stages {
+        for dest in scene
+            .graph
+            .get(&stage.id)
+            .iter()
+            .flat_map(|node| &node.dest)
+        {
+            ret.push_str(&format!(
+                "  {} -> {};\n",
+                quoted(&stage.id.0),
+                quoted(&dest.0)
+            ));
+        }
+    }
+    ret.push_str("}\n");
+    ret
+}
+
+fn syntax_error(details: &str) -> SlsbError {
+    Message::new("dot.syntax").arg("details", details).into()
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SlsbError> {
+    let mut ret = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            // preprocessor lines
+            '#' if line_start => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => prev = c,
+                        None => return Err(syntax_error("unterminated comment")),
+                    }
+                }
+            }
+            '-' if chars.next_if(|&c| c == '>' || c == '-').is_some() => ret.push(Token::Edge),
+            '[' => ret.push(Token::Open),
+            ']' => ret.push(Token::Close),
+            '{' => ret.push(Token::OpenBrace),
+            '}' => ret.push(Token::CloseBrace),
+            '=' => ret.push(Token::Equals),
+            ';' | ',' => ret.push(Token::Separator),
+            '"' => {
+                let mut id = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('"') => id.push('"'),
+                            Some('\\') => id.push('\\'),
+                            // line continuation
+                            Some('\n') => {}
+                            Some(c) => {
+                                id.push('\\');
+                                id.push(c);
+                            }
+                            None => return Err(syntax_error("unterminated string")),
+                        },
+                        Some(c) => id.push(c),
+                        None => return Err(syntax_error("unterminated string")),
+                    }
+                }
+                ret.push(Token::Id(id));
+            }
+            // html strings, kept with their markup
+            '<' => {
+                let mut id = String::new();
+                let mut depth = 1;
+                loop {
+                    match chars.next() {
+                        Some('>') if depth == 1 => break,
+                        Some(c) => {
+                            depth += (c == '<') as i32 - (c == '>') as i32;
+                            id.push(c);
+                        }
+                        None => return Err(syntax_error("unterminated html string")),
+                    }
+                }
+                ret.push(Token::Id(id));
+            }
+            c if c.is_whitespace() => continue,
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let mut id = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_' || c == '.')
+                {
+                    id.push(c);
+                }
+                ret.push(Token::Id(id));
+            }
+            c => return Err(syntax_error(&format!("unexpected character '{}'", c))),
+        }
+        line_start = false;
+    }
+    Ok(ret)
+}
+
+// Parse the attribute lists following a statement, returning whether root is set
+fn attributes(tokens: &[Token], i: &mut usize) -> Result<bool, SlsbError> {
+    let mut root = false;
+    while tokens.get(*i) == Some(&Token::Open) {
+        *i += 1;
+        loop {
+            match (tokens.get(*i), tokens.get(*i + 1), tokens.get(*i + 2)) {
+                (Some(Token::Close), ..) => break,
+                (Some(Token::Separator), ..) => *i += 1,
+                (Some(Token::Id(key)), Some(Token::Equals), Some(Token::Id(value))) => {
+                    root |= key.eq_ignore_ascii_case("root") && value.eq_ignore_ascii_case("true");
+                    *i += 3;
+                }
+                (Some(Token::Id(_)), ..) => *i += 1,
+                _ => return Err(syntax_error("unterminated attribute list")),
+            }
+        }
+        *i += 1;
+    }
+    Ok(root)
+}
+
+pub fn parse_dot(text: &str) -> Result<DotGraph, SlsbError> {
+    let tokens = tokenize(text)?;
+    let mut ret = DotGraph::default();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let Token::Id(id) = &tokens[i] else {
+            match tokens[i] {
+                Token::OpenBrace => depth += 1,
+                Token::CloseBrace if depth == 0 => return Err(syntax_error("unmatched '}'")),
+                Token::CloseBrace => depth -= 1,
+                Token::Separator => {}
+                _ => return Err(syntax_error("expected a node")),
+            }
+            i += 1;
+            continue;
+        };
+        let keyword = id.to_lowercase();
+        let next = tokens.get(i + 1);
+        if (depth == 0 && matches!(keyword.as_str(), "strict" | "digraph" | "graph"))
+            || keyword == "subgraph"
+            || next == Some(&Token::OpenBrace)
+        {
+            // graph headers and names
+            i += 1;
+        } else if matches!(keyword.as_str(), "node" | "edge" | "graph") {
+            i += 1;
+            attributes(&tokens, &mut i)?;
+        } else if next == Some(&Token::Equals) {
+            // graph attributes
+            i += 3;
+        } else {
+            let mut chain = vec![id.clone()];
+            i += 1;
+            while tokens.get(i) == Some(&Token::Edge) {
+                match tokens.get(i + 1) {
+                    Some(Token::Id(dest)) => chain.push(dest.clone()),
+                    _ => return Err(syntax_error("edge without a target")),
+                }
+                i += 2;
+            }
+            if attributes(&tokens, &mut i)? && chain.len() == 1 {
+                ret.root = Some(id.clone());
+            }
+            for node in &chain {
+                if !ret.nodes.contains(node) {
+                    ret.nodes.push(node.clone());
+                }
+            }
+            for pair in chain.windows(2) {
+                ret.edges.push((pair[0].clone(), pair[1].clone()));
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(syntax_error("unmatched '{'"));
+    }
+    Ok(ret)
+}
+
+// Stage a DOT node refers to, by id or else by a name no other stage of the scene has
+fn resolve_node(scene: &Scene, node: &str) -> Result<NanoID, SlsbError> {
+    if let Some(stage) = scene.stages.iter().find(|stage| stage.id.0 == node) {
+        return Ok(stage.id.clone());
+    }
+    let mut named = scene
+        .stages
+        .iter()
+        .filter(|stage| stage.name.trim().eq_ignore_ascii_case(node.trim()));
+    match (named.next(), named.next()) {
+        (Some(stage), None) => Ok(stage.id.clone()),
+        (Some(_), Some(_)) => Err(Message::new("dot.ambiguous_stage")
+            .arg("scene", &scene.name)
+            .arg("stage", node)
+            .into()),
+        (None, _) => Err(Message::new("dot.unknown_stage")
+            .arg("scene", &scene.name)
+            .arg("stage", node)
+            .into()),
+    }
+}
+
+// Replace the edges and root of a scene by those of a DOT file, keeping the editor coordinates
+// of its stages. Without a node marked as root the root is kept if it is still part of the graph,
+// otherwise the first node nothing leads to becomes the root. Returns a warning for every stage
+// the new graph no longer reaches, e.g. because the file leaves it out
+pub fn import_dot(scene: &mut Scene, text: &str) -> Result<Vec<Message>, SlsbError> {
+    let graph = parse_dot(text)?;
+    if graph.nodes.is_empty() {
+        return Err(Message::new("dot.empty_graph")
+            .arg("scene", &scene.name)
+            .into());
+    }
+    let ids: HashMap<&String, NanoID> = graph
+        .nodes
+        .iter()
+        .map(|node| resolve_node(scene, node).map(|id| (node, id)))
+        .collect::<Result<_, _>>()?;
+    let edges: Vec<(NanoID, NanoID)> = graph
+        .edges
+        .iter()
+        .map(|(from, to)| (ids[from].clone(), ids[to].clone()))
+        .collect();
+    if let Some((from, _)) = edges.iter().find(|(from, to)| from == to) {
+        return Err(Message::new("scene.self_edge").arg("stage", &from.0).into());
+    }
+    let root = match &graph.root {
+        Some(root) => ids[root].clone(),
+        None if ids.values().any(|id| *id == scene.root) => scene.root.clone(),
+        None => graph
+            .nodes
+            .iter()
+            .map(|node| &ids[node])
+            .find(|id| !edges.iter().any(|(_, to)| to == *id))
+            .or_else(|| graph.nodes.first().map(|node| &ids[node]))
+            .cloned()
+            .unwrap_or_else(|| scene.root.clone()),
+    };
+
+    let mut nodes = scene.graph.clone();
+    for node in nodes.values_mut() {
+        node.dest.clear();
+    }
+    for (from, to) in edges {
+        let node = nodes.entry(from).or_default();
+        if !node.dest.contains(&to) {
+            node.dest.push(to);
+        }
+    }
+    let stages: HashSet<&NanoID> = scene.stages.iter().map(|stage| &stage.id).collect();
+    let reachable = depths(&root, &nodes, &stages);
+    let ret = scene
+        .stages
+        .iter()
+        .filter(|stage| !reachable.contains_key(&stage.id))
+        .map(|stage| Message::new("validate.unreachable").arg("stage", &stage.name))
+        .collect();
+    scene.graph = nodes;
+    scene.root = root;
+    Ok(ret)
+}
@@ -0,0 +1,392 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{package::Package, NanoID};
+use crate::{error::SlsbError, i18n::Message};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct EventCollision {
+    pub event: String,
+    pub sources: Vec<String>,
+}
+
+// Outcome of renaming events in bulk
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PrefixReport {
+    pub applied: bool,
+    pub renamed: usize,
+    pub collisions: Vec<EventCollision>,
+    // scenes with renamed events
+    pub scenes: Vec<NanoID>,
+}
+
+// Line types of FNIS lists, which FNIS would read an event of the same name as
+pub const RESERVED_FNIS_TOKENS: [&str; 14] = [
+    "b", "s", "so", "fu", "fuo", "ofa", "pa", "km", "aa", "md", "rd", "ch", "sg", "+",
+];
+
+// Events the game or a framework already sends, with where they come from. Names ending with a
+// star match every event starting with them. Behaviour graphs compare events ignoring case
+pub const KNOWN_EVENTS: &[(&str, &str)] = &[
+    // vanilla behaviour graphs
+    ("IdleForceDefaultState", "Skyrim"),
+    ("IdleStop", "Skyrim"),
+    ("IdleStopInstant", "Skyrim"),
+    ("IdleStop_Loose", "Skyrim"),
+    ("IdleChairSitting", "Skyrim"),
+    ("IdleLayDownEnter", "Skyrim"),
+    ("IdleSitCrossLeggedEnter", "Skyrim"),
+    ("IdleGreybeardMeditateEnter", "Skyrim"),
+    ("IdlePray", "Skyrim"),
+    ("IdleWipeBrow", "Skyrim"),
+    ("IdleLaugh", "Skyrim"),
+    ("IdleSalute", "Skyrim"),
+    ("IdleWave", "Skyrim"),
+    ("IdleApplaud2", "Skyrim"),
+    ("PickNewIdle", "Skyrim"),
+    ("moveStart", "Skyrim"),
+    ("moveStop", "Skyrim"),
+    ("turnStop", "Skyrim"),
+    ("sprintStart", "Skyrim"),
+    ("sneakStart", "Skyrim"),
+    ("JumpUp", "Skyrim"),
+    ("attackStart", "Skyrim"),
+    ("attackStop", "Skyrim"),
+    ("bowAttackStart", "Skyrim"),
+    ("blockStart", "Skyrim"),
+    ("staggerStart", "Skyrim"),
+    ("BleedOutStart", "Skyrim"),
+    ("BleedOutStop", "Skyrim"),
+    ("GetUpBegin", "Skyrim"),
+    ("Ragdoll", "Skyrim"),
+    ("KillMoveStart", "Skyrim"),
+    ("AnimObjDraw", "Skyrim"),
+    ("AnimObjUnequip", "Skyrim"),
+    ("HitFrame", "Skyrim"),
+    ("SoundPlay", "Skyrim"),
+    ("FootLeft", "Skyrim"),
+    ("FootRight", "Skyrim"),
+    // schlongs of skyrim, sent by SexLab during scenes
+    ("SOSFastErect", "SOS"),
+    ("SOSFlaccid", "SOS"),
+    ("SOSBend*", "SOS"),
+    // FNIS generated events
+    ("FNIS_*", "FNIS"),
+];
+
+// Prefixes of the animations bundled with SexLab. The standalone packs of their authors use them
+// as well and are what most SLAL conversions start from, so scenes converted from SLAL may use them
+pub const SEXLAB_PREFIXES: [&str; 3] = ["Arrok_", "Leito_", "Zyn_"];
+
+// Source of the known event an event collides with
+pub fn known_event_source(event: &str, converted: bool) -> Option<&'static str> {
+    let event = event.to_lowercase();
+    if !converted
+        && SEXLAB_PREFIXES
+            .iter()
+            .any(|prefix| event.starts_with(&prefix.to_lowercase()))
+    {
+        return Some("SexLab");
+    }
+    KNOWN_EVENTS
+        .iter()
+        .find(|(known, _)| match known.strip_suffix('*') {
+            Some(prefix) => event.starts_with(&prefix.to_lowercase()),
+            None => event == known.to_lowercase(),
+        })
+        .map(|(_, source)| *source)
+}
+
+// FNIS splits list lines at whitespace and commas, only reads ascii and takes words starting
+// with a dash for options and those starting with an apostrophe for comments
+pub fn is_fnis_legal(event: &str) -> bool {
+    !event.is_empty()
+        && event.chars().all(|c| c.is_ascii_graphic() && c != ',')
+        && !event.starts_with(['-', '\''])
+        && !RESERVED_FNIS_TOKENS.contains(&event.to_lowercase().as_str())
+}
+
+// Closest name FNIS accepts, replacing illegal characters with underscores
+pub fn sanitize_event(event: &str) -> String {
+    let mut ret: String = event
+        .trim()
+        .chars()
+        .map(|c| match c.is_ascii_graphic() && c != ',' {
+            true => c,
+            false => '_',
+        })
+        .collect::<String>()
+        .trim_start_matches(['-', '\''])
+        .to_string();
+    if ret.is_empty() || RESERVED_FNIS_TOKENS.contains(&ret.to_lowercase().as_str()) {
+        ret.push('_');
+    }
+    ret
+}
+
+// The event of the animation for the other side, swapping side markers separated by underscores
+// such as "L" and "R" or "Left" and "Right". None if the event names no side
+pub fn mirror_event(event: &str) -> Option<String> {
+    let mut mirrored = false;
+    let ret: Vec<String> = event
+        .split('_')
+        .map(|token| {
+            let other = match token.to_lowercase().as_str() {
+                "l" => "r",
+                "r" => "l",
+                "left" => "right",
+                "right" => "left",
+                _ => return token.to_string(),
+            };
+            mirrored = true;
+            if token.chars().all(char::is_uppercase) {
+                other.to_uppercase()
+            } else if token.starts_with(char::is_uppercase) {
+                other[..1].to_uppercase() + &other[1..]
+            } else {
+                other.to_string()
+            }
+        })
+        .collect();
+    mirrored.then(|| ret.join("_"))
+}
+
+// A prefix followed by its separator, so "Pack" and "Pack_" name the same namespace
+fn namespace(prefix: &str) -> String {
+    match prefix.ends_with('_') {
+        true => prefix.to_string(),
+        false => format!("{}_", prefix),
+    }
+}
+
+fn prefixed(event: &str, prefix: &str, replace: Option<&str>) -> String {
+    let prefix = namespace(prefix);
+    let base = replace
+        .filter(|old| !old.is_empty())
+        .and_then(|old| event.strip_prefix(namespace(old).as_str()))
+        .unwrap_or(event);
+    if base.starts_with(&prefix) {
+        base.to_string()
+    } else {
+        format!("{}{}", prefix, base)
+    }
+}
+
+// Prepend the prefix to every event of the given scenes (or all scenes), replacing an old prefix
+// if specified. Locked scenes are left untouched. Nothing is renamed if two distinct events would end up with the same name
+pub fn prefix_events(
+    package: &mut Package,
+    prefix: &str,
+    replace: Option<&str>,
+    scenes: Option<&[NanoID]>,
+) -> Result<PrefixReport, SlsbError> {
+    if prefix.is_empty() {
+        return Err(Message::new("events.empty_prefix").into());
+    }
+    let report = rename_events(package, scenes, |event| prefixed(event, prefix, replace));
+    if report.applied {
+        package.audit.record(
+            "prefix_events",
+            None,
+            vec![],
+            Some(format!(
+                "Prefixed {} events with {}",
+                report.renamed, prefix
+            )),
+        );
+    }
+    Ok(report)
+}
+
+// Replace events FNIS cannot read by their sanitized name in the given scenes (or all scenes).
+// Locked scenes are left untouched, nothing is renamed if two distinct events would collide
+pub fn fix_events(package: &mut Package, scenes: Option<&[NanoID]>) -> PrefixReport {
+    let report = rename_events(package, scenes, |event| match is_fnis_legal(event) {
+        true => event.to_string(),
+        false => sanitize_event(event),
+    });
+    if report.renamed > 0 {
+        package.audit.record(
+            "fix_events",
+            None,
+            vec![],
+            Some(format!("Renamed {} illegal events", report.renamed)),
+        );
+    }
+    report
+}
+
+fn rename_events(
+    package: &mut Package,
+    scenes: Option<&[NanoID]>,
+    rename: impl Fn(&str) -> String,
+) -> PrefixReport {
+    let locked: Vec<NanoID> = package
+        .scenes
+        .values()
+        .filter(|scene| scene.locked)
+        .map(|scene| scene.id.clone())
+        .collect();
+    let in_scope =
+        |id: &NanoID| !locked.contains(id) && scenes.map_or(true, |list| list.contains(id));
+
+    let mut mapping: HashMap<String, String> = HashMap::new();
+    let mut sources: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (id, scene) in &package.scenes {
+        for stage in &scene.stages {
+            for position in &stage.positions {
+                for event in position.event.iter().chain(&position.variants) {
+                    let name = if in_scope(id) {
+                        rename(event)
+                    } else {
+                        event.clone()
+                    };
+                    if &name != event {
+                        mapping.insert(event.clone(), name.clone());
+                    }
+                    sources.entry(name).or_default().insert(event.clone());
+                }
+            }
+        }
+    }
+    let collisions: Vec<EventCollision> = sources
+        .into_iter()
+        .filter(|(_, from)| from.len() > 1)
+        .map(|(event, from)| EventCollision {
+            event,
+            sources: from.into_iter().collect(),
+        })
+        .collect();
+    if !collisions.is_empty() {
+        return PrefixReport {
+            collisions,
+            ..Default::default()
+        };
+    }
+
+    let mut renamed = 0;
+    let mut scenes = vec![];
+    for (id, scene) in package.scenes.iter_mut().filter(|(id, _)| in_scope(id)) {
+        let before = renamed;
+        for stage in &mut scene.stages {
+            for position in &mut stage.positions {
+                for event in position.event.iter_mut().chain(&mut position.variants) {
+                    if let Some(name) = mapping.get(event) {
+                        *event = name.clone();
+                        renamed += 1;
+                    }
+                }
+            }
+        }
+        if renamed > before {
+            scenes.push(id.clone());
+        }
+    }
+    PrefixReport {
+        applied: true,
+        renamed,
+        collisions,
+        scenes,
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MissingAnimation {
+    pub event: String,
+    pub scenes: Vec<NanoID>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct EventUsageReport {
+    pub root: PathBuf,
+    pub files: usize,
+    // animation files no exported event refers to
+    pub unused_files: Vec<PathBuf>,
+    pub unused_bytes: u64,
+    // events without an animation file
+    pub missing_files: Vec<MissingAnimation>,
+}
+
+// Files below dir with one of the given extensions, compared case insensitive
+pub(crate) fn collect_files(
+    dir: &Path,
+    extensions: &[&str],
+    out: &mut Vec<PathBuf>,
+) -> Result<(), SlsbError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, extensions, out)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Cross reference the .hkx files under the animation root with the events of all exported scenes.
+// Events and file names are compared case insensitive, control events are ignored
+pub fn event_usage(package: &Package) -> Result<EventUsageReport, SlsbError> {
+    let root = package
+        .animation_root
+        .clone()
+        .ok_or(Message::new("events.no_animation_root"))?;
+    if !root.is_dir() {
+        return Err(Message::new("events.invalid_animation_root")
+            .arg("path", root.display())
+            .into());
+    }
+    let mut files = vec![];
+    collect_files(&root, &["hkx"], &mut files)?;
+    files.sort();
+
+    let mut events: BTreeMap<String, (String, Vec<NanoID>)> = BTreeMap::new();
+    for scene in package.exported_scenes() {
+        for position in scene.stages.iter().flat_map(|stage| &stage.positions) {
+            for event in position.event.iter().chain(&position.variants) {
+                if event == "__BLANK__" || event == "__DEFAULT__" {
+                    continue;
+                }
+                let (_, scenes) = events
+                    .entry(event.to_lowercase())
+                    .or_insert_with(|| (event.clone(), vec![]));
+                if !scenes.contains(&scene.id) {
+                    scenes.push(scene.id.clone());
+                }
+            }
+        }
+    }
+
+    let mut ret = EventUsageReport {
+        root,
+        files: files.len(),
+        ..Default::default()
+    };
+    let mut found = BTreeSet::new();
+    for file in files {
+        let stem = file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if events.contains_key(&stem) {
+            found.insert(stem);
+        } else {
+            ret.unused_bytes += fs::metadata(&file).map(|m| m.len()).unwrap_or(0);
+            ret.unused_files.push(file);
+        }
+    }
+    ret.missing_files = events
+        .into_iter()
+        .filter(|(key, _)| !found.contains(key))
+        .map(|(_, (event, scenes))| MissingAnimation { event, scenes })
+        .collect();
+    Ok(ret)
+}
@@ -0,0 +1,92 @@
+use log::warn;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+// Longest path windows accepts without the extended-length prefix, and the longest FNIS reads
+pub const MAX_PATH: usize = 260;
+// "The filename or extension is too long" and "The filename, directory name, or volume label
+// syntax is incorrect"
+const ERROR_FILENAME_EXCED_RANGE: i32 = 206;
+const ERROR_INVALID_NAME: i32 = 123;
+
+// The extended-length form of an absolute windows path, which lifts the MAX_PATH limit
+pub fn extended_path(path: &str) -> String {
+    let path = path.replace('/', "\\");
+    if path.starts_with(r"\\?\") {
+        path
+    } else if let Some(share) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", path)
+    }
+}
+
+// The path to hand to the file system. On windows absolute paths beyond MAX_PATH are extended,
+// other platforms have no such limit
+pub fn long_path(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if cfg!(windows) && path.is_absolute() && text.len() >= MAX_PATH {
+        PathBuf::from(extended_path(&text))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+// Turn the io errors of writing path into messages naming the file and what to do about it
+pub fn export_error(err: std::io::Error, path: &Path) -> SlsbError {
+    let length = path.to_string_lossy().chars().count();
+    match (err.kind(), err.raw_os_error()) {
+        (ErrorKind::PermissionDenied, _) => Message::new("export.permission_denied")
+            .arg("path", path.display())
+            .into(),
+        (_, Some(ERROR_FILENAME_EXCED_RANGE | ERROR_INVALID_NAME)) if length >= MAX_PATH => {
+            Message::new("export.path_too_long")
+                .arg("path", path.display())
+                .arg("length", length)
+                .arg("max", MAX_PATH)
+                .into()
+        }
+        _ => err.into(),
+    }
+}
+
+// Check the files an export is about to overwrite before anything is written, so a read-only
+// file does not leave the export half done. Paths too long for FNIS are only warned about
+pub fn check_export_targets(targets: &[PathBuf]) -> Result<(), SlsbError> {
+    for path in targets {
+        let length = path.to_string_lossy().chars().count();
+        if length >= MAX_PATH {
+            warn!(
+                target: "export", path:? = path, length = length;
+                "Path is longer than {} characters, which FNIS cannot read",
+                MAX_PATH
+            );
+        }
+        let Ok(metadata) = fs::metadata(long_path(path)) else {
+            continue;
+        };
+        if metadata.is_file() && metadata.permissions().readonly() {
+            return Err(Message::new("export.read_only")
+                .arg("path", path.display())
+                .into());
+        }
+    }
+    Ok(())
+}
+
+pub fn create_export_dir(dir: &Path) -> Result<(), SlsbError> {
+    fs::create_dir_all(long_path(dir)).map_err(|err| export_error(err, dir))
+}
+
+pub fn create_export_file(path: &Path) -> Result<fs::File, SlsbError> {
+    fs::File::create(long_path(path)).map_err(|err| export_error(err, path))
+}
+
+pub fn write_export_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), SlsbError> {
+    fs::write(long_path(path), contents).map_err(|err| export_error(err, path))
+}
@@ -0,0 +1,347 @@
+use git2::{Index, IndexAddOption, Oid, Repository, Signature, Status, StatusOptions, Tree};
+use log::info;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    audit::{log_path, AuditEntry},
+    package::Package,
+    preview::PREVIEW_DIR,
+};
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct GitChange {
+    // two letter status as printed by git status --porcelain, e.g. " M" or "??"
+    pub status: String,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GitCommit {
+    pub hash: String,
+    // seconds since unix epoch
+    pub timestamp: u64,
+    pub author: String,
+    pub message: String,
+}
+
+// Report a failed libgit2 call under the name of the git command it stands in for
+fn git_error(command: &str) -> impl Fn(git2::Error) -> SlsbError + '_ {
+    move |e| {
+        Message::new("git.failed")
+            .arg("command", command)
+            .arg("details", e.message())
+            .into()
+    }
+}
+
+fn project_dir(package: &Package) -> Result<&Path, SlsbError> {
+    if !package.pack_path.is_file() {
+        return Err(Message::new("git.unsaved_project").into());
+    }
+    Ok(package
+        .pack_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new(".")))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Files of the project put under version control, relative to the project folder
+fn tracked_files(package: &Package) -> Result<Vec<String>, SlsbError> {
+    let dir = project_dir(package)?;
+    let mut ret = vec![file_name(&package.pack_path)];
+    let log = log_path(&package.pack_path);
+    if log.is_file() {
+        ret.push(file_name(&log));
+    }
+    if dir.join(PREVIEW_DIR).is_dir() {
+        ret.push(PREVIEW_DIR.into());
+    }
+    Ok(ret)
+}
+
+// The repository with a working tree the folder is in, if any
+fn open_repository(dir: &Path) -> Option<Repository> {
+    Repository::discover(dir)
+        .ok()
+        .filter(|repo| repo.workdir().is_some())
+}
+
+// Top level folder of the repository the project is in, if any
+pub fn repository_root(package: &Package) -> Option<PathBuf> {
+    let dir = project_dir(package).ok()?;
+    let repo = open_repository(dir)?;
+    repo.workdir().map(|root| root.components().collect())
+}
+
+fn require_repository(package: &Package) -> Result<(Repository, &Path), SlsbError> {
+    let dir = project_dir(package)?;
+    let repo = open_repository(dir).ok_or(Message::new("git.no_repository"))?;
+    Ok((repo, dir))
+}
+
+// Path of a file of the project folder relative to the top level folder, as git stores it
+fn repository_path(repo: &Repository, dir: &Path, file: &str) -> Result<String, SlsbError> {
+    let root = fs::canonicalize(repo.workdir().unwrap_or(Path::new(".")))?;
+    let dir = fs::canonicalize(dir)?;
+    let relative = dir.strip_prefix(&root).unwrap_or(Path::new(""));
+    let ret: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .chain([file.to_string()])
+        .filter(|part| !part.is_empty())
+        .collect();
+    Ok(ret.join("/"))
+}
+
+fn head_tree(repo: &Repository) -> Option<Tree<'_>> {
+    repo.head().ok()?.peel_to_tree().ok()
+}
+
+// Turn the project folder into a repository and commit the current state of the project
+pub fn init_repository(package: &Package) -> Result<PathBuf, SlsbError> {
+    let dir = project_dir(package)?;
+    if repository_root(package).is_none() {
+        Repository::init(dir).map_err(git_error("init"))?;
+        info!(target: "git", path:? = dir; "Initialized repository");
+    }
+    commit_project(package, &format!("Add {}", package.pack_name))?;
+    Ok(repository_root(package).unwrap_or(dir.to_path_buf()))
+}
+
+// Commit the project files, returning the new commit or None if nothing changed. Other changes
+// of the repository are left as they are. Commits by users without a configured git identity
+// are attributed to their account name
+pub fn commit_project(package: &Package, message: &str) -> Result<Option<String>, SlsbError> {
+    let (repo, dir) = require_repository(package)?;
+    let mut paths = vec![];
+    for file in tracked_files(package)? {
+        paths.push(repository_path(&repo, dir, &file)?);
+    }
+
+    // stage the project files, then commit them on top of HEAD without anything else staged
+    let mut index = repo.index().map_err(git_error("add"))?;
+    index
+        .add_all(&paths, IndexAddOption::DEFAULT, None)
+        .and_then(|_| index.update_all(&paths, None))
+        .and_then(|_| index.write())
+        .map_err(git_error("add"))?;
+    let head = head_tree(&repo);
+    let tree = committed_tree(&repo, &index, head.as_ref(), &paths).map_err(git_error("commit"))?;
+    let unchanged = match &head {
+        Some(head) => head.id() == tree,
+        None => repo.find_tree(tree).is_ok_and(|tree| tree.is_empty()),
+    };
+    if unchanged {
+        return Ok(None);
+    }
+
+    let signature = repo.signature().or_else(|_| {
+        let user = std::env::var("USERNAME")
+            .or_else(|_| std::env::var("USER"))
+            .unwrap_or("Unknown".into());
+        Signature::now(&user, &format!("{}@localhost", user))
+    });
+    let hash = signature
+        .and_then(|signature| {
+            let tree = repo.find_tree(tree)?;
+            let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+            let parents: Vec<_> = parent.iter().collect();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+        })
+        .map_err(git_error("commit"))?
+        .to_string();
+    info!(target: "git", commit = hash.as_str(); "Committed project {}", package.pack_name);
+    Ok(Some(hash))
+}
+
+// The tree of HEAD with the given paths as they are staged
+fn committed_tree(
+    repo: &Repository,
+    index: &Index,
+    head: Option<&Tree>,
+    paths: &[String],
+) -> Result<Oid, git2::Error> {
+    let mut ret = Index::new()?;
+    if let Some(head) = head {
+        ret.read_tree(head)?;
+    }
+    ret.remove_all(paths, None)?;
+    let staged = index.iter().filter(|entry| {
+        let path = String::from_utf8_lossy(&entry.path);
+        paths
+            .iter()
+            .any(|p| path == p.as_str() || path.starts_with(&format!("{}/", p)))
+    });
+    for entry in staged {
+        ret.add(&entry)?;
+    }
+    ret.write_tree_to(repo)
+}
+
+// "Update pack" followed by the operations recorded since the last save
+pub fn commit_message(package: &Package, entries: &[AuditEntry], message: Option<&str>) -> String {
+    let mut ret = message
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Update {}", package.pack_name));
+    let mut lines: Vec<String> = vec![];
+    for entry in entries {
+        let scene = entry
+            .scene
+            .as_ref()
+            .map(|id| match package.scenes.get(id) {
+                Some(scene) => format!(" {}", scene.name),
+                None => format!(" {}", id.0),
+            })
+            .unwrap_or_default();
+        let line = format!("- {}{}", entry.operation, scene);
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+    if !lines.is_empty() {
+        ret.push_str("\n\n");
+        ret.push_str(&lines.join("\n"));
+    }
+    ret
+}
+
+// Two letter porcelain code of a status, the staged change followed by the unstaged one
+fn porcelain_status(status: Status) -> String {
+    if status.contains(Status::WT_NEW) && !status.intersects(Status::INDEX_NEW) {
+        return "??".into();
+    }
+    let code = |flags: [(Status, char); 5]| {
+        flags
+            .iter()
+            .find(|(flag, _)| status.contains(*flag))
+            .map_or(' ', |(_, code)| *code)
+    };
+    let staged = code([
+        (Status::INDEX_NEW, 'A'),
+        (Status::INDEX_MODIFIED, 'M'),
+        (Status::INDEX_DELETED, 'D'),
+        (Status::INDEX_RENAMED, 'R'),
+        (Status::INDEX_TYPECHANGE, 'T'),
+    ]);
+    let unstaged = code([
+        (Status::WT_NEW, '?'),
+        (Status::WT_MODIFIED, 'M'),
+        (Status::WT_DELETED, 'D'),
+        (Status::WT_RENAMED, 'R'),
+        (Status::WT_TYPECHANGE, 'T'),
+    ]);
+    format!("{}{}", staged, unstaged)
+}
+
+// Changes of the project folder which have not been committed yet
+pub fn uncommitted_changes(package: &Package) -> Result<Vec<GitChange>, SlsbError> {
+    let (repo, dir) = require_repository(package)?;
+    let folder = repository_path(&repo, dir, "")?;
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    if !folder.is_empty() {
+        options.pathspec(format!("{}/", folder));
+    }
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .map_err(git_error("status"))?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status() != Status::CURRENT)
+        .filter_map(|entry| {
+            Some(GitChange {
+                status: porcelain_status(entry.status()),
+                path: entry.path()?.into(),
+            })
+        })
+        .collect())
+}
+
+// Commits touching the project file, newest first. Merges count if they differ from every parent
+pub fn project_history(package: &Package) -> Result<Vec<GitCommit>, SlsbError> {
+    let (repo, dir) = require_repository(package)?;
+    let file = repository_path(&repo, dir, &file_name(&package.pack_path))?;
+    // a repository without any commits yet
+    if repo.head().is_err() {
+        return Ok(vec![]);
+    }
+    let blob = |tree: Option<Tree>| -> Option<Oid> {
+        tree?
+            .get_path(Path::new(&file))
+            .ok()
+            .map(|entry| entry.id())
+    };
+    let mut walk = repo.revwalk().map_err(git_error("log"))?;
+    walk.set_sorting(git2::Sort::TIME)
+        .and_then(|_| walk.push_head())
+        .map_err(git_error("log"))?;
+    let mut ret = vec![];
+    for id in walk {
+        let commit = id
+            .and_then(|id| repo.find_commit(id))
+            .map_err(git_error("log"))?;
+        let own = blob(commit.tree().ok());
+        let touched = if commit.parent_count() == 0 {
+            own.is_some()
+        } else {
+            commit
+                .parents()
+                .all(|parent| blob(parent.tree().ok()) != own)
+        };
+        if touched {
+            ret.push(GitCommit {
+                hash: commit.id().to_string(),
+                timestamp: commit.time().seconds().max(0) as u64,
+                author: commit.author().name().unwrap_or_default().into(),
+                message: commit.summary().unwrap_or_default().into(),
+            });
+        }
+    }
+    Ok(ret)
+}
+
+// Replace the project file with its version of the given commit and load it. The restored
+// file shows up as an uncommitted change until it is committed again
+pub fn restore_project(package: &Package, revision: &str) -> Result<Package, SlsbError> {
+    let (repo, dir) = require_repository(package)?;
+    let invalid = || Message::new("git.invalid_revision").arg("revision", revision);
+    if revision.starts_with('-') {
+        return Err(invalid().into());
+    }
+    let commit = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|_| invalid())?;
+    let file = repository_path(&repo, dir, &file_name(&package.pack_path))?;
+    let content = commit
+        .tree()
+        .and_then(|tree| tree.get_path(Path::new(&file)))
+        .and_then(|entry| repo.find_blob(entry.id()))
+        .map_err(git_error("checkout"))?;
+    fs::write(&package.pack_path, content.content())?;
+    info!(target: "git", commit = revision; "Restored project {}", package.pack_name);
+    let mut restored = Package::open(&package.pack_path)?;
+    restored.pack_path = package.pack_path.clone();
+    Ok(restored)
+}
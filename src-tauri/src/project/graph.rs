@@ -0,0 +1,240 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::{define::Node, NanoID};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PathStatistics {
+    pub ending: NanoID,
+    pub shortest: Vec<NanoID>,
+    pub longest: Vec<NanoID>,
+    pub shortest_duration: f32,
+    pub longest_duration: f32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SceneStatistics {
+    pub scene: NanoID,
+    pub paths: Vec<PathStatistics>,
+    // stages which are not part of any path from the root to an ending
+    pub dead_stages: Vec<NanoID>,
+}
+
+const LAYOUT_MARGIN: f32 = 40.0;
+const LAYOUT_SPACING: f32 = 200.0;
+
+// Distance of every node reachable from start, ignoring destinations outside of the given set
+pub fn depths(
+    start: &NanoID,
+    graph: &HashMap<NanoID, Node>,
+    nodes: &HashSet<&NanoID>,
+) -> HashMap<NanoID, usize> {
+    let mut ret = HashMap::new();
+    if !nodes.contains(start) {
+        return ret;
+    }
+    let mut queue = VecDeque::from([(start.clone(), 0)]);
+    ret.insert(start.clone(), 0);
+    while let Some((id, depth)) = queue.pop_front() {
+        let Some(node) = graph.get(&id) else {
+            continue;
+        };
+        for dest in &node.dest {
+            if !nodes.contains(dest) || ret.contains_key(dest) {
+                continue;
+            }
+            ret.insert(dest.clone(), depth + 1);
+            queue.push_back((dest.clone(), depth + 1));
+        }
+    }
+    ret
+}
+
+// Shortest path from start to every reachable node
+pub fn shortest_paths(
+    start: &NanoID,
+    graph: &HashMap<NanoID, Node>,
+    nodes: &HashSet<&NanoID>,
+) -> HashMap<NanoID, Vec<NanoID>> {
+    let mut parents: HashMap<NanoID, Option<NanoID>> = HashMap::new();
+    if !nodes.contains(start) {
+        return HashMap::new();
+    }
+    let mut queue = VecDeque::from([start.clone()]);
+    parents.insert(start.clone(), None);
+    while let Some(id) = queue.pop_front() {
+        for dest in graph.get(&id).into_iter().flat_map(|node| &node.dest) {
+            if nodes.contains(dest) && !parents.contains_key(dest) {
+                parents.insert(dest.clone(), Some(id.clone()));
+                queue.push_back(dest.clone());
+            }
+        }
+    }
+    collect_paths(&parents)
+}
+
+// Longest path from start to every reachable node. Edges closing a cycle are ignored
+pub fn longest_paths(
+    start: &NanoID,
+    graph: &HashMap<NanoID, Node>,
+    nodes: &HashSet<&NanoID>,
+) -> HashMap<NanoID, Vec<NanoID>> {
+    fn visit(
+        id: &NanoID,
+        graph: &HashMap<NanoID, Node>,
+        nodes: &HashSet<&NanoID>,
+        on_stack: &mut HashSet<NanoID>,
+        done: &mut HashSet<NanoID>,
+        post_order: &mut Vec<NanoID>,
+        back_edges: &mut HashSet<(NanoID, NanoID)>,
+    ) {
+        on_stack.insert(id.clone());
+        for dest in graph.get(id).into_iter().flat_map(|node| &node.dest) {
+            if !nodes.contains(dest) {
+                continue;
+            }
+            if on_stack.contains(dest) {
+                back_edges.insert((id.clone(), dest.clone()));
+            } else if !done.contains(dest) {
+                visit(dest, graph, nodes, on_stack, done, post_order, back_edges);
+            }
+        }
+        on_stack.remove(id);
+        done.insert(id.clone());
+        post_order.push(id.clone());
+    }
+
+    if !nodes.contains(start) {
+        return HashMap::new();
+    }
+    let mut post_order = vec![];
+    let mut back_edges = HashSet::new();
+    visit(
+        start,
+        graph,
+        nodes,
+        &mut HashSet::new(),
+        &mut HashSet::new(),
+        &mut post_order,
+        &mut back_edges,
+    );
+    let mut distance: HashMap<&NanoID, usize> = HashMap::from([(start, 0)]);
+    let mut parents: HashMap<NanoID, Option<NanoID>> = HashMap::from([(start.clone(), None)]);
+    for id in post_order.iter().rev() {
+        let Some(&dist) = distance.get(id) else {
+            continue;
+        };
+        for dest in graph.get(id).into_iter().flat_map(|node| &node.dest) {
+            if !nodes.contains(dest) || back_edges.contains(&(id.clone(), dest.clone())) {
+                continue;
+            }
+            if distance.get(dest).map_or(true, |d| dist + 1 > *d) {
+                distance.insert(dest, dist + 1);
+                parents.insert(dest.clone(), Some(id.clone()));
+            }
+        }
+    }
+    collect_paths(&parents)
+}
+
+fn collect_paths(parents: &HashMap<NanoID, Option<NanoID>>) -> HashMap<NanoID, Vec<NanoID>> {
+    parents
+        .keys()
+        .map(|id| {
+            let mut path = vec![id.clone()];
+            while let Some(Some(parent)) = parents.get(path.last().unwrap()) {
+                path.push(parent.clone());
+            }
+            path.reverse();
+            (id.clone(), path)
+        })
+        .collect()
+}
+
+// All nodes from which any of the targets can be reached
+pub fn reaching(
+    targets: &[NanoID],
+    graph: &HashMap<NanoID, Node>,
+    nodes: &HashSet<&NanoID>,
+) -> HashSet<NanoID> {
+    let mut ret: HashSet<NanoID> = targets.iter().cloned().collect();
+    let mut queue: VecDeque<NanoID> = targets.iter().cloned().collect();
+    while let Some(id) = queue.pop_front() {
+        for (from, node) in graph {
+            if nodes.contains(from) && node.dest.contains(&id) && ret.insert(from.clone()) {
+                queue.push_back(from.clone());
+            }
+        }
+    }
+    ret
+}
+
+// Layered layout: every stage is placed into a column by its distance from the root and
+// columns are ordered by the rows of their predecessors to reduce edge crossings.
+// Stages which cannot be reached from the root are laid out from the first of their stages.
+pub fn layered_layout(
+    order: &[NanoID],
+    root: &NanoID,
+    graph: &HashMap<NanoID, Node>,
+) -> HashMap<NanoID, (f32, f32)> {
+    let nodes: HashSet<&NanoID> = order.iter().collect();
+    let mut layer_of: HashMap<NanoID, usize> = HashMap::new();
+    for start in std::iter::once(root).chain(order.iter()) {
+        if layer_of.contains_key(start) {
+            continue;
+        }
+        for (id, depth) in depths(start, graph, &nodes) {
+            layer_of.entry(id).or_insert(depth);
+        }
+    }
+
+    let layer_count = layer_of.values().max().map_or(0, |max| max + 1);
+    let mut layers: Vec<Vec<NanoID>> = vec![vec![]; layer_count];
+    for id in order {
+        if let Some(layer) = layer_of.get(id) {
+            layers[*layer].push(id.clone());
+        }
+    }
+
+    let mut row_of: HashMap<NanoID, usize> = HashMap::new();
+    for layer in &mut layers {
+        let barycenter = |id: &NanoID, fallback: usize| -> f32 {
+            let rows: Vec<usize> = graph
+                .iter()
+                .filter(|(_, node)| node.dest.contains(id))
+                .filter_map(|(pred, _)| row_of.get(pred).copied())
+                .collect();
+            if rows.is_empty() {
+                fallback as f32
+            } else {
+                rows.iter().sum::<usize>() as f32 / rows.len() as f32
+            }
+        };
+        let mut keyed: Vec<(f32, NanoID)> = layer
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (barycenter(id, i), id.clone()))
+            .collect();
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        *layer = keyed.into_iter().map(|(_, id)| id).collect();
+        for (row, id) in layer.iter().enumerate() {
+            row_of.insert(id.clone(), row);
+        }
+    }
+
+    layers
+        .iter()
+        .enumerate()
+        .flat_map(|(column, layer)| {
+            layer.iter().enumerate().map(move |(row, id)| {
+                (
+                    id.clone(),
+                    (
+                        LAYOUT_MARGIN + column as f32 * LAYOUT_SPACING,
+                        LAYOUT_MARGIN + row as f32 * LAYOUT_SPACING,
+                    ),
+                )
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,910 @@
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message, racekeys::map_race_to_folder};
+
+use super::{
+    define::Offset, events::collect_files, package::Package, scene::Scene, stage::Stage, NanoID,
+};
+
+// Magic of binary packfiles. Only their length is read, previews need them converted into xml
+// (e.g. with hkxcmd) first
+const PACKFILE_MAGIC: [u8; 8] = [0x57, 0xE0, 0xE0, 0x57, 0x10, 0xC0, 0xC0, 0x10];
+// Layout of the binary packfiles of hk_2010, as shipped with Skyrim and Skyrim SE
+const PACKFILE_VERSION: u32 = 8;
+const PACKFILE_HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 48;
+// Longer animations are sampled down to keep the previews small
+pub const MAX_FRAMES: usize = 60;
+// Length of a havok unit in game units
+pub const HAVOK_TO_GAME: f32 = 69.991_25;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PreviewBone {
+    pub name: String,
+    pub parent: i32,
+}
+
+// Model space positions of the bones of a skeleton, sampled over the duration of an animation
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SkeletonPreview {
+    pub event: String,
+    pub duration: f32,
+    pub bones: Vec<PreviewBone>,
+    pub frames: Vec<Vec<[f32; 3]>>,
+    // translation and heading of the actor per frame, not applied to the frames
+    pub root_motion: Vec<[f32; 4]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: 1.0,
+        }
+    }
+}
+
+fn rotate(q: [f32; 4], v: [f32; 3]) -> [f32; 3] {
+    let [x, y, z, w] = q;
+    // v + 2w(q x v) + 2(q x (q x v))
+    let t = [
+        2.0 * (y * v[2] - z * v[1]),
+        2.0 * (z * v[0] - x * v[2]),
+        2.0 * (x * v[1] - y * v[0]),
+    ];
+    [
+        v[0] + w * t[0] + (y * t[2] - z * t[1]),
+        v[1] + w * t[1] + (z * t[0] - x * t[2]),
+        v[2] + w * t[2] + (x * t[1] - y * t[0]),
+    ]
+}
+
+fn multiply(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    let [ax, ay, az, aw] = a;
+    let [bx, by, bz, bw] = b;
+    [
+        aw * bx + ax * bw + ay * bz - az * by,
+        aw * by - ax * bz + ay * bw + az * bx,
+        aw * bz + ax * by - ay * bx + az * bw,
+        aw * bw - ax * bx - ay * by - az * bz,
+    ]
+}
+
+impl Transform {
+    // This transform applied on top of its parent
+    pub fn compose(&self, parent: &Transform) -> Transform {
+        let scaled = self.translation.map(|v| v * parent.scale);
+        let moved = rotate(parent.rotation, scaled);
+        Transform {
+            translation: [
+                parent.translation[0] + moved[0],
+                parent.translation[1] + moved[1],
+                parent.translation[2] + moved[2],
+            ],
+            rotation: multiply(parent.rotation, self.rotation),
+            scale: parent.scale * self.scale,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub bones: Vec<PreviewBone>,
+    pub reference_pose: Vec<Transform>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Animation {
+    pub duration: f32,
+    pub track_names: Vec<String>,
+    // frames of transforms, one per track
+    pub frames: Vec<Vec<Transform>>,
+    pub track_to_bone: Option<Vec<i32>>,
+    pub root_motion: Vec<[f32; 4]>,
+}
+
+/* Xml */
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn param(&self, name: &str) -> Option<&Element> {
+        self.children
+            .iter()
+            .find(|c| c.name == "hkparam" && c.attribute("name") == Some(name))
+    }
+
+    fn descendants<'a>(&'a self, out: &mut Vec<&'a Element>) {
+        for child in &self.children {
+            out.push(child);
+            child.descendants(out);
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn xml_error(details: &str) -> SlsbError {
+    Message::new("hkx.xml_syntax")
+        .arg("details", details)
+        .into()
+}
+
+// Just enough of xml to read packfiles: elements, attributes, text, comments and declarations
+fn parse_xml(src: &str) -> Result<Element, SlsbError> {
+    let mut stack = vec![Element::default()];
+    let mut rest = src;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            stack.last_mut().unwrap().text.push_str(&unescape(rest));
+            break;
+        };
+        stack
+            .last_mut()
+            .unwrap()
+            .text
+            .push_str(&unescape(&rest[..start]));
+        rest = &rest[start..];
+        if let Some(body) = rest.strip_prefix("<!--") {
+            let end = body
+                .find("-->")
+                .ok_or_else(|| xml_error("unterminated comment"))?;
+            rest = &body[end + 3..];
+            continue;
+        }
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest
+                .find('>')
+                .ok_or_else(|| xml_error("unterminated declaration"))?;
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let end = rest
+            .find('>')
+            .ok_or_else(|| xml_error("unterminated tag"))?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let element = stack.pop().filter(|_| !stack.is_empty());
+            match element {
+                Some(element) if element.name == name.trim() => {
+                    stack.last_mut().unwrap().children.push(element)
+                }
+                _ => {
+                    let details = format!("unexpected closing tag {}", name.trim());
+                    return Err(xml_error(&details));
+                }
+            }
+            continue;
+        }
+        let closed = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut element = Element {
+            name: tag[..name_end].to_string(),
+            ..Default::default()
+        };
+        let mut attributes = &tag[name_end..];
+        while let Some(eq) = attributes.find('=') {
+            let key = attributes[..eq].trim().to_string();
+            let value = attributes[eq + 1..].trim_start();
+            let quote = value
+                .chars()
+                .next()
+                .ok_or_else(|| xml_error("missing attribute value"))?;
+            let value = &value[quote.len_utf8()..];
+            let close = value
+                .find(quote)
+                .ok_or_else(|| xml_error("unterminated attribute"))?;
+            element.attributes.push((key, unescape(&value[..close])));
+            attributes = &value[close + 1..];
+        }
+        if closed {
+            stack.last_mut().unwrap().children.push(element);
+        } else {
+            stack.push(element);
+        }
+    }
+    if stack.len() != 1 {
+        let details = format!("unclosed tag {}", stack.last().unwrap().name);
+        return Err(xml_error(&details));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+fn numbers(text: &str) -> Vec<f32> {
+    text.split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',')
+        .filter_map(|v| v.parse::<f32>().ok())
+        .collect()
+}
+
+fn transforms(text: &str) -> Vec<Transform> {
+    numbers(text)
+        .chunks_exact(10)
+        .map(|c| Transform {
+            translation: [c[0], c[1], c[2]],
+            rotation: [c[3], c[4], c[5], c[6]],
+            scale: c[7],
+        })
+        .collect()
+}
+
+/* Packfiles */
+
+struct Packfile {
+    root: Element,
+}
+
+impl Packfile {
+    fn read(path: &Path) -> Result<Self, SlsbError> {
+        Self::parse(path, &fs::read(path)?)
+    }
+
+    fn parse(path: &Path, bytes: &[u8]) -> Result<Self, SlsbError> {
+        if bytes.starts_with(&PACKFILE_MAGIC) {
+            return Err(Message::new("hkx.binary_packfile")
+                .arg("path", path.display())
+                .into());
+        }
+        let root = parse_xml(&String::from_utf8_lossy(bytes)).map_err(|e| {
+            Message::new("hkx.invalid")
+                .arg("path", path.display())
+                .arg("details", e)
+        })?;
+        Ok(Self { root })
+    }
+
+    fn objects(&self) -> Vec<&Element> {
+        let mut ret = vec![];
+        self.root.descendants(&mut ret);
+        ret.retain(|e| e.name == "hkobject" && e.attribute("class").is_some());
+        ret
+    }
+
+    fn object(&self, class: &str) -> Option<&Element> {
+        self.objects()
+            .into_iter()
+            .find(|e| e.attribute("class") == Some(class))
+    }
+
+    fn named(&self, name: &str) -> Option<&Element> {
+        self.objects()
+            .into_iter()
+            .find(|e| e.attribute("name") == Some(name))
+    }
+}
+
+fn packfile_error(path: &Path, details: &str) -> SlsbError {
+    Message::new("hkx.invalid")
+        .arg("path", path.display())
+        .arg("details", details)
+        .into()
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    let word = bytes.get(at..at.checked_add(4)?)?;
+    Some(u32::from_le_bytes(word.try_into().unwrap()))
+}
+
+// Start of a section's data and its virtual fixups, which pair objects with their class names
+struct BinarySection {
+    start: usize,
+    virtual_fixups: (usize, usize),
+}
+
+// Duration of the first animation of a binary packfile. Only the header, the section table and
+// the classes of the objects are read: the duration is a member of hkaAnimation, so the track data
+// of compressed animations does not need to be decoded
+fn binary_duration(path: &Path, bytes: &[u8]) -> Result<Option<f32>, SlsbError> {
+    let truncated = || packfile_error(path, "the file ends before the data it refers to");
+    let version = read_u32(bytes, 12).ok_or_else(truncated)?;
+    let pointer_size = bytes.get(16).copied().ok_or_else(truncated)?;
+    let little_endian = bytes.get(17).copied().ok_or_else(truncated)?;
+    if version != PACKFILE_VERSION || little_endian != 1 || ![4, 8].contains(&pointer_size) {
+        return Err(Message::new("hkx.unsupported_packfile")
+            .arg("path", path.display())
+            .arg("version", version)
+            .into());
+    }
+    let count = read_u32(bytes, 20).ok_or_else(truncated)? as usize;
+    let mut sections = HashMap::new();
+    for i in 0..count {
+        let at = PACKFILE_HEADER_SIZE + i * SECTION_HEADER_SIZE;
+        let tag = bytes.get(at..at + 20).ok_or_else(truncated)?;
+        let tag = String::from_utf8_lossy(tag.split(|&b| b == 0).next().unwrap_or_default());
+        let field = |n: usize| read_u32(bytes, at + 20 + 4 * n).map(|v| v as usize);
+        let (Some(start), Some(fixups), Some(exports)) = (field(0), field(3), field(4)) else {
+            return Err(truncated());
+        };
+        sections.insert(
+            tag.into_owned(),
+            BinarySection {
+                start,
+                virtual_fixups: (start + fixups, start + exports),
+            },
+        );
+    }
+    let (Some(names), Some(data)) = (sections.get("__classnames__"), sections.get("__data__"))
+    else {
+        return Err(packfile_error(path, "no data or class name section"));
+    };
+    // hkReferencedObject precedes the type and then the duration of the animation
+    let duration_at = if pointer_size == 8 { 20 } else { 12 };
+    let (from, to) = data.virtual_fixups;
+    for fixup in (from..to).step_by(12) {
+        let object = read_u32(bytes, fixup).ok_or_else(truncated)?;
+        let name = read_u32(bytes, fixup + 8).ok_or_else(truncated)? as usize;
+        if object == u32::MAX {
+            continue;
+        }
+        let class = bytes.get(names.start + name..).ok_or_else(truncated)?;
+        let class = class.split(|&b| b == 0).next().unwrap_or_default();
+        if class.starts_with(b"hka") && class.ends_with(b"Animation") {
+            let at = data.start + object as usize + duration_at;
+            let duration = read_u32(bytes, at).ok_or_else(truncated)?;
+            return Ok(Some(f32::from_bits(duration)).filter(|d| d.is_finite() && *d >= 0.0));
+        }
+    }
+    Ok(None)
+}
+
+fn param_text<'a>(object: &'a Element, name: &str) -> &'a str {
+    object.param(name).map_or("", |p| p.text.as_str())
+}
+
+pub fn read_skeleton(path: &Path) -> Result<Skeleton, SlsbError> {
+    let packfile = Packfile::read(path)?;
+    let Some(skeleton) = packfile.object("hkaSkeleton") else {
+        return Err(Message::new("hkx.no_skeleton_data")
+            .arg("path", path.display())
+            .into());
+    };
+    let names: Vec<String> = skeleton
+        .param("bones")
+        .map(|bones| {
+            bones
+                .children
+                .iter()
+                .map(|bone| param_text(bone, "name").trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let parents = numbers(param_text(skeleton, "parentIndices"));
+    let mut reference_pose = transforms(param_text(skeleton, "referencePose"));
+    reference_pose.resize(names.len(), Transform::default());
+    Ok(Skeleton {
+        bones: names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| PreviewBone {
+                name,
+                parent: parents.get(i).map_or(-1, |&p| p as i32),
+            })
+            .collect(),
+        reference_pose,
+    })
+}
+
+// Length of an animation in seconds, from a packfile or an hkanno dump of one. Binary packfiles
+// are read if they are hk_2010 packfiles, other versions fail as unsupported
+pub fn read_duration(path: &Path) -> Result<Option<f32>, SlsbError> {
+    if has_extension(path, "txt") {
+        let text = fs::read_to_string(path)?;
+        return Ok(text.lines().find_map(|line| {
+            let value = line
+                .trim()
+                .strip_prefix('#')?
+                .trim()
+                .strip_prefix("duration:")?;
+            value.trim().parse::<f32>().ok()
+        }));
+    }
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(&PACKFILE_MAGIC) {
+        return binary_duration(path, &bytes);
+    }
+    let packfile = Packfile::parse(path, &bytes)?;
+    Ok(packfile
+        .objects()
+        .into_iter()
+        .filter(|o| {
+            o.attribute("class")
+                .is_some_and(|c| c.ends_with("Animation"))
+        })
+        .find_map(|o| numbers(param_text(o, "duration")).first().copied()))
+}
+
+// Frames of an uncompressed animation. Spline compressed animations, which is how most animations
+// are exported, are not decoded and have to be converted to an interleaved uncompressed one first
+pub fn read_animation(path: &Path) -> Result<Animation, SlsbError> {
+    let packfile = Packfile::read(path)?;
+    let Some(animation) = packfile.object("hkaInterleavedUncompressedAnimation") else {
+        let key = if packfile.object("hkaSplineCompressedAnimation").is_some() {
+            "hkx.compressed"
+        } else {
+            "hkx.no_animation_data"
+        };
+        return Err(Message::new(key).arg("path", path.display()).into());
+    };
+    let tracks = numbers(param_text(animation, "numberOfTransformTracks"))
+        .first()
+        .map_or(0, |&n| n as usize);
+    let all = transforms(param_text(animation, "transforms"));
+    let frames = if tracks == 0 {
+        vec![]
+    } else {
+        all.chunks_exact(tracks).map(|c| c.to_vec()).collect()
+    };
+    let track_names = animation
+        .param("annotationTracks")
+        .map(|list| {
+            list.children
+                .iter()
+                .map(|track| param_text(track, "trackName").trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let root_motion = packfile
+        .named(param_text(animation, "extractedMotion").trim())
+        .map(|motion| {
+            numbers(param_text(motion, "referenceFrameSamples"))
+                .chunks_exact(4)
+                .map(|c| [c[0], c[1], c[2], c[3]])
+                .collect()
+        })
+        .unwrap_or_default();
+    let track_to_bone = packfile.object("hkaAnimationBinding").map(|binding| {
+        numbers(param_text(binding, "transformTrackToBoneIndices"))
+            .into_iter()
+            .map(|i| i as i32)
+            .collect::<Vec<_>>()
+    });
+    Ok(Animation {
+        duration: numbers(param_text(animation, "duration"))
+            .first()
+            .copied()
+            .unwrap_or_default(),
+        track_names,
+        frames,
+        track_to_bone: track_to_bone.filter(|list| !list.is_empty()),
+        root_motion,
+    })
+}
+
+// Tracks are bound to bones by the binding of the animation, by their name or, lacking both, by
+// their index
+fn bind_tracks(skeleton: &Skeleton, animation: &Animation) -> Vec<Option<usize>> {
+    (0..animation.frames.first().map_or(0, |f| f.len()))
+        .map(|track| {
+            if let Some(binding) = &animation.track_to_bone {
+                return binding.get(track).and_then(|&b| usize::try_from(b).ok());
+            }
+            match animation.track_names.get(track) {
+                Some(name) if !name.is_empty() => {
+                    skeleton.bones.iter().position(|bone| &bone.name == name)
+                }
+                _ => Some(track),
+            }
+        })
+        .collect()
+}
+
+// Model space transforms of all bones of the skeleton in the given frame
+fn pose(skeleton: &Skeleton, bone_of: &[Option<usize>], frame: &[Transform]) -> Vec<Transform> {
+    let mut local = skeleton.reference_pose.clone();
+    for (track, transform) in frame.iter().enumerate() {
+        if let Some(bone) = bone_of[track].filter(|&b| b < local.len()) {
+            local[bone] = *transform;
+        }
+    }
+    let mut world: Vec<Transform> = Vec::with_capacity(local.len());
+    for (i, transform) in local.iter().enumerate() {
+        let parent = usize::try_from(skeleton.bones[i].parent)
+            .ok()
+            .filter(|&p| p < i);
+        world.push(match parent {
+            Some(p) => transform.compose(&world[p]),
+            None => *transform,
+        });
+    }
+    world
+}
+
+// Pose the skeleton with each frame of the animation
+pub fn make_preview(event: &str, skeleton: &Skeleton, animation: &Animation) -> SkeletonPreview {
+    let bone_of = bind_tracks(skeleton, animation);
+    let step = animation.frames.len().div_ceil(MAX_FRAMES).max(1);
+    let frames = animation
+        .frames
+        .iter()
+        .step_by(step)
+        .map(|frame| {
+            pose(skeleton, &bone_of, frame)
+                .into_iter()
+                .map(|t| t.translation)
+                .collect()
+        })
+        .collect();
+    SkeletonPreview {
+        event: event.to_string(),
+        duration: animation.duration,
+        bones: skeleton.bones.clone(),
+        frames,
+        root_motion: animation
+            .root_motion
+            .iter()
+            .step_by(step)
+            .copied()
+            .collect(),
+    }
+}
+
+// The skeleton of the behaviour folder used by the race: actors/<folder>/character assets/skeleton
+// An xml conversion is preferred over the binary packfile next to it, which cannot be read
+fn find_skeleton(files: &[PathBuf], race: &str) -> Option<PathBuf> {
+    let folder = map_race_to_folder(race).ok()?;
+    let mut suffix: Vec<String> = ["actors"]
+        .into_iter()
+        .chain(folder.split('\\'))
+        .chain(["character assets"])
+        .map(|s| s.to_lowercase())
+        .collect();
+    suffix.reverse();
+    files
+        .iter()
+        .filter(|path| {
+            path.file_stem()
+                .is_some_and(|stem| stem.eq_ignore_ascii_case("skeleton"))
+        })
+        .filter(|path| {
+            let dirs: Vec<String> = path
+                .parent()
+                .into_iter()
+                .flat_map(|p| p.components().rev())
+                .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+                .collect();
+            dirs.starts_with(&suffix)
+        })
+        .min_by_key(|path| {
+            !path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"))
+        })
+        .cloned()
+}
+
+// Animation files and skeletons below the animation root of a project
+struct AnimationSource {
+    files: Vec<PathBuf>,
+    // all files named after an event, including hkanno dumps
+    animations: HashMap<String, Vec<PathBuf>>,
+    skeletons: HashMap<String, Skeleton>,
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+impl AnimationSource {
+    fn open(package: &Package) -> Result<Self, SlsbError> {
+        let root = package
+            .animation_root
+            .clone()
+            .ok_or(Message::new("events.no_animation_root"))?;
+        if !root.is_dir() {
+            return Err(Message::new("events.invalid_animation_root")
+                .arg("path", root.display())
+                .into());
+        }
+        let mut files = vec![];
+        collect_files(&root, &["hkx", "xml", "txt"], &mut files)?;
+        files.sort();
+        let mut animations: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for file in &files {
+            let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+            animations
+                .entry(stem.to_lowercase())
+                .or_default()
+                .push(file.clone());
+        }
+        files.retain(|file| !has_extension(file, "txt"));
+        Ok(Self {
+            files,
+            animations,
+            skeletons: HashMap::new(),
+        })
+    }
+
+    // The packfile of an event, preferring xml over binary packfiles
+    fn animation(&self, event: &str) -> Option<&PathBuf> {
+        let files = self.animations.get(&event.to_lowercase());
+        let ret = files.and_then(|files| {
+            files
+                .iter()
+                .find(|f| has_extension(f, "xml"))
+                .or_else(|| files.iter().find(|f| has_extension(f, "hkx")))
+        });
+        if ret.is_none() {
+            warn!(target: "project", event = event; "No animation file for event");
+        }
+        ret
+    }
+
+    // Length of the first file of an event it is known from. Files failing to read are returned
+    // alongside, unless another file of the event tells the length
+    fn duration(&self, event: &str) -> (Option<f32>, Vec<Message>) {
+        let mut errors = vec![];
+        let files = self.animations.get(&event.to_lowercase());
+        for file in files.into_iter().flatten() {
+            match read_duration(file) {
+                Ok(Some(duration)) => return (Some(duration), vec![]),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(target: "project", path:? = file; "Unable to read animation length: {}", e);
+                    errors.push(
+                        Message::new("validate.unreadable_animation")
+                            .arg("path", file.display())
+                            .arg("details", &e),
+                    );
+                }
+            }
+        }
+        (None, errors)
+    }
+
+    fn skeleton(&mut self, race: &str) -> Result<&Skeleton, SlsbError> {
+        if !self.skeletons.contains_key(race) {
+            let path = find_skeleton(&self.files, race)
+                .ok_or(Message::new("hkx.no_skeleton").arg("race", race))?;
+            self.skeletons.insert(race.into(), read_skeleton(&path)?);
+        }
+        Ok(&self.skeletons[race])
+    }
+}
+
+pub(crate) fn find_stage<'a>(
+    package: &'a Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<(&'a Scene, &'a Stage), SlsbError> {
+    let scene = package
+        .get_scene(scene)
+        .ok_or(Message::new("scene.invalid_id").arg("scene", &scene.0))?;
+    let stage = scene.get_stage(stage).ok_or(
+        Message::new("scene.missing_stage")
+            .arg("scene", &scene.name)
+            .arg("stage", &stage.0),
+    )?;
+    Ok((scene, stage))
+}
+
+fn is_control_event(event: &str) -> bool {
+    event == "__BLANK__" || event == "__DEFAULT__"
+}
+
+// Previews of all positions of a stage, keyed by their events. Animations and skeletons are read
+// from the animation root, events without an animation file are skipped
+pub fn stage_preview(
+    package: &Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<BTreeMap<String, SkeletonPreview>, SlsbError> {
+    let mut source = AnimationSource::open(package)?;
+    let (scene, stage) = find_stage(package, scene, stage)?;
+    let mut ret = BTreeMap::new();
+    for (position, info) in stage.positions.iter().zip(&scene.positions) {
+        for event in position.event.iter().filter(|e| !is_control_event(e)) {
+            let Some(path) = source.animation(event) else {
+                continue;
+            };
+            let animation = read_animation(path)?;
+            let preview = make_preview(event, source.skeleton(&info.race)?, &animation);
+            ret.insert(event.clone(), preview);
+        }
+    }
+    Ok(ret)
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RootMotion {
+    pub event: String,
+    // model space position of the root bone per frame, in game units
+    pub path: Vec<[f32; 3]>,
+    pub average: [f32; 3],
+    // rotation of the root bone around the z axis in the first frame, in degrees
+    pub heading: f32,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct OffsetSuggestion {
+    pub position: usize,
+    pub motion: RootMotion,
+    // placement relative to the first position
+    pub offset: Offset,
+    pub distance: f32,
+    pub angle: f32,
+}
+
+fn heading(q: [f32; 4]) -> f32 {
+    let [x, y, z, w] = q;
+    (2.0 * (w * z + x * y))
+        .atan2(1.0 - 2.0 * (y * y + z * z))
+        .to_degrees()
+}
+
+fn normalize_angle(degrees: f32) -> f32 {
+    let ret = (degrees + 180.0).rem_euclid(360.0) - 180.0;
+    if ret == -180.0 {
+        180.0
+    } else {
+        ret
+    }
+}
+
+// Root bone of the skeleton moved by the extracted motion of the animation, over all frames
+pub fn root_motion(event: &str, skeleton: &Skeleton, animation: &Animation) -> RootMotion {
+    let bone_of = bind_tracks(skeleton, animation);
+    let root = skeleton
+        .bones
+        .iter()
+        .position(|b| b.parent < 0)
+        .unwrap_or(0);
+    let step = animation.frames.len().div_ceil(MAX_FRAMES).max(1);
+    let mut path = vec![];
+    let mut heading_of_root = None;
+    for (i, frame) in animation.frames.iter().enumerate() {
+        let Some(transform) = pose(skeleton, &bone_of, frame).get(root).copied() else {
+            break;
+        };
+        let extracted = animation.root_motion.get(i).copied().unwrap_or([0.0; 4]);
+        heading_of_root.get_or_insert(heading(transform.rotation) + extracted[3].to_degrees());
+        let position: [f32; 3] =
+            std::array::from_fn(|k| (transform.translation[k] + extracted[k]) * HAVOK_TO_GAME);
+        path.push(position);
+    }
+    let count = path.len().max(1) as f32;
+    let average = std::array::from_fn(|k| path.iter().map(|p| p[k]).sum::<f32>() / count);
+    RootMotion {
+        event: event.to_string(),
+        path: path.into_iter().step_by(step).collect(),
+        average,
+        heading: normalize_angle(heading_of_root.unwrap_or_default()),
+    }
+}
+
+// Suggest offsets for the positions of a stage from where the roots of their animations are placed
+// relative to the first position. Positions without an animation file are left out, compressed
+// animations fail the suggestion like they fail the preview
+pub fn suggest_offsets(
+    package: &Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<Vec<OffsetSuggestion>, SlsbError> {
+    let mut source = AnimationSource::open(package)?;
+    let (scene, stage) = find_stage(package, scene, stage)?;
+    let mut motions = vec![];
+    for (i, (position, info)) in stage.positions.iter().zip(&scene.positions).enumerate() {
+        let Some(event) = position.event.iter().find(|e| !is_control_event(e)) else {
+            continue;
+        };
+        let Some(path) = source.animation(event) else {
+            continue;
+        };
+        let animation = read_animation(path)?;
+        motions.push((
+            i,
+            root_motion(event, source.skeleton(&info.race)?, &animation),
+        ));
+    }
+    let Some((_, reference)) = motions.first().cloned() else {
+        return Ok(vec![]);
+    };
+    let (sin, cos) = (-reference.heading).to_radians().sin_cos();
+    Ok(motions
+        .into_iter()
+        .map(|(position, motion)| {
+            let d: [f32; 3] = std::array::from_fn(|k| motion.average[k] - reference.average[k]);
+            let offset = Offset {
+                x: d[0] * cos - d[1] * sin,
+                y: d[0] * sin + d[1] * cos,
+                z: d[2],
+                r: normalize_angle(motion.heading - reference.heading),
+                ..Default::default()
+            };
+            OffsetSuggestion {
+                position,
+                distance: (d[0] * d[0] + d[1] * d[1]).sqrt(),
+                angle: offset.y.atan2(offset.x).to_degrees(),
+                offset,
+                motion,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StageDurations {
+    pub scene: NanoID,
+    pub stage: NanoID,
+    // events of each position with the sum of their lengths, None if any of them is unknown
+    pub positions: Vec<(Vec<String>, Option<f32>)>,
+    // files of the stage's events which could not be read, reported for the first stage using them
+    pub errors: Vec<Message>,
+}
+
+// Lengths of the animations of all exported stages, as far as they are known from the packfiles
+// or hkanno dumps below the animation root
+pub fn stage_durations(package: &Package) -> Result<Vec<StageDurations>, SlsbError> {
+    let source = AnimationSource::open(package)?;
+    let mut known: HashMap<String, Option<f32>> = HashMap::new();
+    let mut ret = vec![];
+    for scene in package.exported_scenes() {
+        for stage in &scene.stages {
+            let mut positions = vec![];
+            let mut errors = vec![];
+            for position in &stage.positions {
+                let events: Vec<String> = position
+                    .event
+                    .iter()
+                    .filter(|e| !is_control_event(e))
+                    .cloned()
+                    .collect();
+                let mut total = Some(0.0);
+                for event in &events {
+                    let duration = match known.get(&event.to_lowercase()) {
+                        Some(duration) => *duration,
+                        None => {
+                            let (duration, unreadable) = source.duration(event);
+                            errors.extend(unreadable);
+                            known.insert(event.to_lowercase(), duration);
+                            duration
+                        }
+                    };
+                    total = total.zip(duration).map(|(a, b)| a + b);
+                }
+                positions.push((events.clone(), total.filter(|_| !events.is_empty())));
+            }
+            ret.push(StageDurations {
+                scene: scene.id.clone(),
+                stage: stage.id.clone(),
+                positions,
+                errors,
+            });
+        }
+    }
+    Ok(ret)
+}
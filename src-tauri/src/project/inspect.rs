@@ -0,0 +1,444 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::BTreeSet, fmt::Display, fs, path::PathBuf};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    define::Requirements,
+    manifest::PackVersion,
+    package::{Package, VERSION},
+    scene::Scene,
+    serialize::{Capabilities, EncodeBinary, Layout},
+    stage::{Stage, StageTimers},
+    NanoID,
+};
+
+// Reads the binary produced by Package::write_byte, driven by the layout descriptions of the
+// encoded types
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    strings: Vec<String>,
+    capabilities: Capabilities,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            strings: vec![],
+            capabilities: Capabilities::all(),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SlsbError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Message::new("inspect.truncated").arg("offset", self.pos))?;
+        let ret = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(ret)
+    }
+
+    fn uint(&mut self, width: usize) -> Result<u64, SlsbError> {
+        Ok(self
+            .take(width)?
+            .iter()
+            .fold(0, |acc, byte| acc << 8 | *byte as u64))
+    }
+
+    fn string(&mut self) -> Result<String, SlsbError> {
+        let len = self.uint(4)? as usize;
+        let offset = self.pos;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| {
+            Message::new("inspect.invalid_string")
+                .arg("offset", offset)
+                .into()
+        })
+    }
+
+    fn has(&self, capability: &str) -> bool {
+        Capabilities::from_name(&capability.to_uppercase())
+            .is_some_and(|flag| self.capabilities.contains(flag))
+    }
+
+    fn decode(&mut self, layout: &Layout) -> Result<Value, SlsbError> {
+        let ret = match layout {
+            Layout::U8 => Value::from(self.uint(1)?),
+            Layout::U32 => Value::from(self.uint(4)?),
+            Layout::U64 => Value::from(self.uint(8)?),
+            Layout::Bool => Value::from(self.uint(1)? != 0),
+            Layout::Fixed { scale } => {
+                Value::from(self.uint(4)? as u32 as i32 as f64 / *scale as f64)
+            }
+            Layout::String => Value::from(self.string()?),
+            Layout::Interned => {
+                let index = self.uint(4)? as usize;
+                let value = self
+                    .strings
+                    .get(index)
+                    .ok_or(Message::new("inspect.unknown_string").arg("index", index))?;
+                Value::from(value.as_str())
+            }
+            Layout::Id { len } => Value::from(String::from_utf8_lossy(self.take(*len)?)),
+            Layout::List { item } => {
+                let len = self.uint(4)?;
+                let items = (0..len)
+                    .map(|_| self.decode(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Value::from(items)
+            }
+            Layout::Map { key, value } => {
+                let len = self.uint(4)?;
+                let mut map = Map::new();
+                for _ in 0..len {
+                    let key = match self.decode(key)? {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    map.insert(key, self.decode(value)?);
+                }
+                Value::from(map)
+            }
+            Layout::Flags { width, flags } => {
+                let bits = self.uint(*width)?;
+                let set: Vec<&str> = flags
+                    .iter()
+                    .filter(|(_, flag)| bits & flag != 0)
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+                Value::from(set)
+            }
+            Layout::Enum { width, variants } => {
+                let value = self.uint(*width)?;
+                variants
+                    .iter()
+                    .find(|(_, variant)| *variant == value)
+                    .map_or(Value::from(value), |(name, _)| Value::from(name.as_str()))
+            }
+            Layout::Struct { fields, .. } => {
+                let mut map = Map::new();
+                for field in fields {
+                    if let Layout::Gated { capability, .. } = &field.layout {
+                        if !self.has(capability) {
+                            continue;
+                        }
+                    }
+                    map.insert(field.name.clone(), self.decode(&field.layout)?);
+                }
+                Value::from(map)
+            }
+            Layout::Gated { layout, .. } => self.decode(layout)?,
+        };
+        Ok(ret)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InspectedActor {
+    pub race: Value,
+    pub sex: Vec<String>,
+    pub scale: f64,
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InspectedPosition {
+    pub event: String,
+    pub climax: bool,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub variants: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InspectedStage {
+    pub id: String,
+    pub positions: Vec<InspectedPosition>,
+    pub fixed_len: f64,
+    pub nav_text: String,
+    pub tags: Vec<String>,
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InspectedFurniture {
+    pub furni_types: Vec<String>,
+    pub allow_bed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InspectedTransition {
+    pub from_stage: String,
+    pub to_scene: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InspectedScene {
+    pub id: String,
+    pub name: String,
+    #[serde(rename(deserialize = "positions"))]
+    pub actors: Vec<InspectedActor>,
+    pub stages: Vec<InspectedStage>,
+    #[serde(default)]
+    pub furniture: InspectedFurniture,
+    pub private: bool,
+    pub tags: Vec<String>,
+    pub author: String,
+    pub source_pack: String,
+    #[serde(default)]
+    pub requirements: Requirements,
+    #[serde(default)]
+    pub transitions: Vec<InspectedTransition>,
+    // references into the shared stage table, resolved into stages once the scene is read
+    #[serde(default, skip_serializing)]
+    shared_stages: Vec<InspectedReference>,
+    #[serde(default)]
+    pub bytes: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct InspectedReference {
+    index: usize,
+    stage: usize,
+}
+
+impl InspectedScene {
+    // Union of scene and stage tags, in order of first appearance
+    pub fn all_tags(&self) -> Vec<&str> {
+        let mut seen = BTreeSet::new();
+        self.tags
+            .iter()
+            .chain(self.stages.iter().flat_map(|stage| &stage.tags))
+            .filter(|tag| seen.insert(tag.as_str()))
+            .map(|tag| tag.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct ByteSizes {
+    pub total: usize,
+    pub header: usize,
+    pub strings: usize,
+    pub shared_stages: usize,
+    pub scenes: usize,
+}
+
+// Human and machine readable summary of a registry file
+#[derive(Debug, Serialize, Clone)]
+pub struct Inspection {
+    pub version: u8,
+    pub capabilities: Vec<String>,
+    pub pack_name: String,
+    pub pack_author: String,
+    pub prefix_hash: String,
+    pub stage_timers: StageTimers,
+    pub min_framework_version: Option<PackVersion>,
+    pub strings: usize,
+    pub sizes: ByteSizes,
+    pub scenes: Vec<InspectedScene>,
+    // scenes of a project file which are not part of the registry
+    pub excluded: usize,
+}
+
+pub fn inspect_bytes(bytes: &[u8]) -> Result<Inspection, SlsbError> {
+    let mut reader = Reader::new(bytes);
+    let version = reader.uint(1)? as u8;
+    if version != VERSION {
+        return Err(Message::new("inspect.unsupported_version")
+            .arg("version", version)
+            .arg("expected", VERSION)
+            .into());
+    }
+    let bits = reader.uint(4)? as u32;
+    reader.capabilities = Capabilities::from_bits(bits)
+        .filter(|capabilities| !capabilities.contains(Capabilities::COMPRESSION))
+        .ok_or(
+            Message::new("inspect.unsupported_capabilities").arg("bits", format!("{:#x}", bits)),
+        )?;
+    let capabilities: Vec<String> = reader
+        .capabilities
+        .iter_names()
+        .map(|(name, _)| name.to_lowercase())
+        .collect();
+    let pack_name = reader.string()?;
+    let pack_author = reader.string()?;
+    let prefix_hash = String::from_utf8_lossy(reader.take(NanoID::PREFIX_HASH_LEN)?).into_owned();
+    let stage_timers = serde_json::from_value(reader.decode(&StageTimers::layout())?)?;
+    let min_framework_version = PackVersion::new(
+        reader.uint(4)? as u32,
+        reader.uint(4)? as u32,
+        reader.uint(4)? as u32,
+    );
+    let min_framework_version =
+        Some(min_framework_version).filter(|v| *v != PackVersion::new(0, 0, 0));
+    let header = reader.pos;
+
+    if reader.capabilities.contains(Capabilities::STRING_TABLE) {
+        let count = reader.uint(4)?;
+        for _ in 0..count {
+            let value = reader.string()?;
+            reader.strings.push(value);
+        }
+    }
+    let strings = reader.pos - header;
+
+    let shared_stages: Vec<InspectedStage> =
+        if reader.capabilities.contains(Capabilities::SHARED_STAGES) {
+            serde_json::from_value(reader.decode(&Vec::<Stage>::layout())?)?
+        } else {
+            vec![]
+        };
+    let shared = reader.pos - header - strings;
+
+    let count = reader.uint(4)?;
+    let mut scenes = vec![];
+    for _ in 0..count {
+        let start = reader.pos;
+        let value = reader.decode(&Scene::layout())?;
+        let mut scene: InspectedScene = serde_json::from_value(value)?;
+        scene.bytes = reader.pos - start;
+        let mut references = std::mem::take(&mut scene.shared_stages);
+        references.sort_by_key(|reference| reference.index);
+        for reference in references {
+            let stage = shared_stages.get(reference.stage).ok_or(
+                Message::new("inspect.unknown_shared_stage").arg("index", reference.stage),
+            )?;
+            let index = reference.index.min(scene.stages.len());
+            scene.stages.insert(index, stage.clone());
+        }
+        scenes.push(scene);
+    }
+    if reader.pos != bytes.len() {
+        return Err(Message::new("inspect.trailing_bytes")
+            .arg("count", bytes.len() - reader.pos)
+            .into());
+    }
+
+    Ok(Inspection {
+        version,
+        capabilities,
+        pack_name,
+        pack_author,
+        prefix_hash,
+        stage_timers,
+        min_framework_version,
+        strings: reader.strings.len(),
+        sizes: ByteSizes {
+            total: bytes.len(),
+            header,
+            strings,
+            shared_stages: shared,
+            scenes: bytes.len() - header - strings - shared,
+        },
+        scenes,
+        excluded: 0,
+    })
+}
+
+// Inspect either a registry (.slr) or a project, the latter as it would be exported
+pub fn inspect_file(path: &PathBuf) -> Result<Inspection, SlsbError> {
+    if path.extension().is_some_and(|ext| ext == "slr") {
+        return inspect_bytes(&fs::read(path)?);
+    }
+    let package = Package::open(path)?;
+    let mut ret = inspect_bytes(&package.encode())?;
+    ret.excluded = package.scenes.len() - ret.scenes.len();
+    Ok(ret)
+}
+
+impl Display for Inspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} by {} (format {}, prefix {})",
+            self.pack_name, self.pack_author, self.version, self.prefix_hash
+        )?;
+        writeln!(
+            f,
+            "{} bytes: header {}, strings {} ({} strings), shared stages {}, scenes {}",
+            self.sizes.total,
+            self.sizes.header,
+            self.sizes.strings,
+            self.strings,
+            self.sizes.shared_stages,
+            self.sizes.scenes
+        )?;
+        writeln!(f, "capabilities: {}", self.capabilities.join(", "))?;
+        if self.stage_timers != StageTimers::default() {
+            writeln!(
+                f,
+                "stage timers: foreplay {}s, main {}s, climax {}s",
+                self.stage_timers.foreplay, self.stage_timers.main, self.stage_timers.climax
+            )?;
+        }
+        if let Some(version) = self.min_framework_version {
+            writeln!(f, "requires framework {} or newer", version)?;
+        }
+        write!(f, "{} scenes", self.scenes.len())?;
+        if self.excluded > 0 {
+            write!(f, ", {} excluded from export", self.excluded)?;
+        }
+        writeln!(f)?;
+        for scene in &self.scenes {
+            writeln!(f)?;
+            write!(f, "{} [{}] {} bytes", scene.name, scene.id, scene.bytes)?;
+            if scene.private {
+                write!(f, ", private")?;
+            }
+            if !scene.author.is_empty() {
+                write!(f, ", by {}", scene.author)?;
+            }
+            if !scene.source_pack.is_empty() {
+                write!(f, ", from {}", scene.source_pack)?;
+            }
+            writeln!(f)?;
+            let actors: Vec<String> = scene
+                .actors
+                .iter()
+                .map(|actor| {
+                    let race = match &actor.race {
+                        Value::String(race) => race.clone(),
+                        race => race.to_string(),
+                    };
+                    format!("{} ({})", race, actor.sex.join("/"))
+                })
+                .collect();
+            writeln!(f, "  actors: {}", actors.join(", "))?;
+            if !scene.requirements.is_empty() {
+                let required: Vec<&str> = scene
+                    .requirements
+                    .plugins
+                    .iter()
+                    .chain(&scene.requirements.keywords)
+                    .map(|r| r.as_str())
+                    .collect();
+                writeln!(f, "  requires: {}", required.join(", "))?;
+            }
+            if !scene.furniture.furni_types.is_empty() {
+                writeln!(f, "  furniture: {}", scene.furniture.furni_types.join(", "))?;
+            }
+            writeln!(f, "  tags: {}", scene.all_tags().join(", "))?;
+            for stage in &scene.stages {
+                // variants are listed after the event they stand in for, separated by bars
+                let events: Vec<String> = stage
+                    .positions
+                    .iter()
+                    .map(|p| std::iter::once(&p.event).chain(&p.variants).cloned())
+                    .map(|pool| pool.collect::<Vec<String>>().join("|"))
+                    .collect();
+                write!(f, "  stage {}: {}", stage.id, events.join(", "))?;
+                if stage.fixed_len > 0.0 {
+                    write!(f, " ({}s)", stage.fixed_len)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    mem::size_of,
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    package::Package,
+    serialize::{ByteWriter, EncodeBinary, Layout},
+    stage::Stage,
+    NanoID,
+};
+
+// Place of a stage of the library within the stages of a scene, as stored in the project file
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct SharedStage {
+    pub index: usize,
+    pub id: NanoID,
+}
+
+// Place of a stage of the shared stage table within the stages of a scene, as written into a
+// registry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StageReference {
+    pub index: u32,
+    pub stage: u32,
+}
+
+impl EncodeBinary for StageReference {
+    fn get_byte_size(&self) -> usize {
+        2 * size_of::<u32>()
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) {
+        self.index.write_byte(buf);
+        self.stage.write_byte(buf);
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "StageReference",
+            vec![("index", u32::layout()), ("stage", u32::layout())],
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LibraryEntry {
+    pub stage: Stage,
+    // number of scenes using the stage
+    pub references: usize,
+}
+
+pub fn same_stage(a: &Stage, b: &Stage) -> bool {
+    // Stage compares ids only, shared stages need to match in content
+    a.id == b.id
+        && a.name == b.name
+        && a.positions == b.positions
+        && a.tags == b.tags
+        && a.extra == b.extra
+        && a.shortcut == b.shortcut
+        && a.ending == b.ending
+}
+
+fn library_stage(package: &Package, id: &NanoID) -> Result<Stage, SlsbError> {
+    package.stage_library.get(id).cloned().ok_or_else(|| {
+        Message::new("library.unknown_stage")
+            .arg("stage", &id.0)
+            .into()
+    })
+}
+
+// Number of scenes using each stage of the library
+pub fn stage_references(package: &Package) -> BTreeMap<NanoID, usize> {
+    let mut ret: BTreeMap<NanoID, usize> = package
+        .stage_library
+        .keys()
+        .map(|id| (id.clone(), 0))
+        .collect();
+    for scene in package.scenes.values() {
+        let ids: HashSet<&NanoID> = scene.stages.iter().map(|s| &s.id).collect();
+        for id in ids {
+            if let Some(count) = ret.get_mut(id) {
+                *count += 1;
+            }
+        }
+    }
+    ret
+}
+
+// The stages of the library, ordered by name
+pub fn library_entries(package: &Package) -> Vec<LibraryEntry> {
+    let references = stage_references(package);
+    let mut ret: Vec<LibraryEntry> = package
+        .stage_library
+        .values()
+        .map(|stage| LibraryEntry {
+            stage: stage.clone(),
+            references: references[&stage.id],
+        })
+        .collect();
+    ret.sort_by_key(|entry| entry.stage.name.to_lowercase());
+    ret
+}
+
+// Add a stage of the scene to the library, so other scenes may use it as well
+pub fn share_stage(package: &mut Package, scene: &NanoID, stage: &NanoID) -> Result<(), SlsbError> {
+    if package.stage_library.contains_key(stage) {
+        return Err(Message::new("library.shared").arg("stage", &stage.0).into());
+    }
+    let shared = package
+        .get_unlocked_scene_mut(scene)?
+        .get_stage(stage)
+        .cloned()
+        .ok_or_else(|| {
+            Message::new("scene.missing_stage")
+                .arg("scene", &scene.0)
+                .arg("stage", &stage.0)
+        })?;
+    package.stage_library.insert(stage.clone(), shared);
+    package
+        .audit
+        .record("share_stage", Some(scene), vec![stage.clone()], None);
+    Ok(())
+}
+
+// Add a stage of the library to the scene, unconnected to its other stages
+pub fn link_stage(
+    package: &mut Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<Stage, SlsbError> {
+    let shared = library_stage(package, stage)?;
+    let target = package.get_unlocked_scene_mut(scene)?;
+    if target.get_stage(stage).is_some() {
+        return Err(Message::new("library.linked")
+            .arg("scene", &target.name)
+            .arg("stage", &stage.0)
+            .into());
+    }
+    if shared.positions.len() != target.positions.len() {
+        return Err(Message::new("library.position_count")
+            .arg("stage", &shared.name)
+            .arg("got", shared.positions.len())
+            .arg("expected", target.positions.len())
+            .into());
+    }
+    target.stages.push(shared.clone());
+    target.graph.entry(stage.clone()).or_default();
+    package
+        .audit
+        .record("link_stage", Some(scene), vec![stage.clone()], None);
+    Ok(shared)
+}
+
+// Give the scene a copy of a shared stage of its own, returning the id of the copy
+pub fn detach_stage(
+    package: &mut Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<NanoID, SlsbError> {
+    library_stage(package, stage)?;
+    let mut ids = package.ids();
+    let target = package.get_unlocked_scene_mut(scene)?;
+    if target.get_stage(stage).is_none() {
+        return Err(Message::new("scene.missing_stage")
+            .arg("scene", &scene.0)
+            .arg("stage", &stage.0)
+            .into());
+    }
+    ids.extend(target.stages.iter().map(|s| s.id.clone()));
+    let id = NanoID::new_unique(|id| ids.contains(id));
+    target.rename_stage(stage, id.clone());
+    package.audit.record(
+        "detach_stage",
+        Some(scene),
+        vec![stage.clone(), id.clone()],
+        None,
+    );
+    Ok(id)
+}
+
+// Carry edits of shared stages made in the scene over to the library and every other unlocked
+// scene using them, returning the other scenes updated. A stage whose number of positions
+// changed no longer fits the other scenes, the scene gets a copy of its own instead
+pub fn sync_shared_stages(package: &mut Package, scene: &NanoID) -> BTreeSet<NanoID> {
+    let mut ret = BTreeSet::new();
+    let Some(source) = package.scenes.get(scene) else {
+        return ret;
+    };
+    let mut changed: Vec<Stage> = vec![];
+    let mut detached: Vec<NanoID> = vec![];
+    for stage in &source.stages {
+        match package.stage_library.get(&stage.id) {
+            Some(shared) if same_stage(shared, stage) => {}
+            Some(shared) if shared.positions.len() == stage.positions.len() => {
+                changed.push(stage.clone())
+            }
+            Some(_) => detached.push(stage.id.clone()),
+            None => {}
+        }
+    }
+    if !detached.is_empty() {
+        let mut ids = package.ids();
+        let source = package.scenes.get_mut(scene).unwrap();
+        for stage in detached {
+            let id = NanoID::new_unique(|id| ids.contains(id));
+            ids.insert(id.clone());
+            source.rename_stage(&stage, id.clone());
+            warn!(
+                target: "project", scene = scene.0.as_str(), stage = stage.0.as_str();
+                "Shared stage no longer fits the scenes using it, detached it as {}",
+                id.0
+            );
+            package
+                .audit
+                .record("detach_stage", Some(scene), vec![stage, id], None);
+        }
+    }
+    for stage in &changed {
+        for other in package
+            .scenes
+            .values_mut()
+            .filter(|s| &s.id != scene && !s.locked)
+        {
+            for copy in other
+                .stages
+                .iter_mut()
+                .filter(|s| s.id == stage.id && s.positions.len() == stage.positions.len())
+            {
+                *copy = stage.clone();
+                ret.insert(other.id.clone());
+            }
+        }
+        info!(
+            target: "project", stage = stage.id.0.as_str();
+            "Updated shared stage {}",
+            stage.name
+        );
+        package
+            .stage_library
+            .insert(stage.id.clone(), stage.clone());
+    }
+    for id in &ret {
+        package.scenes.get_mut(id).unwrap().update_fingerprint();
+    }
+    ret
+}
+
+// Replace the copies of shared stages in the scene by the stages of the library, e.g. for a
+// scene restored from the trash which missed edits made since. Returns the number of stages
+// replaced
+pub fn refresh_shared_stages(package: &mut Package, scene: &NanoID) -> usize {
+    let library = &package.stage_library;
+    let Some(target) = package.scenes.get_mut(scene) else {
+        return 0;
+    };
+    let mut ret = 0;
+    for stage in &mut target.stages {
+        match library.get(&stage.id) {
+            Some(shared)
+                if shared.positions.len() == stage.positions.len()
+                    && !same_stage(shared, stage) =>
+            {
+                *stage = shared.clone();
+                ret += 1;
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+// Remove stages of the library no scene uses anymore, returning how many were removed. Stages
+// of discarded scenes are kept so restoring them finds their stages
+pub fn prune_library(package: &mut Package) -> usize {
+    let trashed: HashSet<&NanoID> = package
+        .trash
+        .iter()
+        .flat_map(|trashed| trashed.scene.stages.iter().map(|s| &s.id))
+        .collect();
+    let unused: Vec<NanoID> = stage_references(package)
+        .into_iter()
+        .filter(|(id, count)| *count == 0 && !trashed.contains(id))
+        .map(|(id, _)| id)
+        .collect();
+    for id in &unused {
+        package.stage_library.remove(id);
+    }
+    unused.len()
+}
+
+// Replace shared stages within the scenes of a serialized project by their place in the scene.
+// Copies which differ from the library, e.g. those of locked scenes, stay within their scene
+pub fn compact_shared_stages(value: &mut serde_json::Value, package: &Package) {
+    let Some(scenes) = value["scenes"].as_object_mut() else {
+        return;
+    };
+    // compared the way the project was serialized, through a string
+    let library: BTreeMap<&NanoID, serde_json::Value> = package
+        .stage_library
+        .iter()
+        .filter_map(|(id, stage)| {
+            serde_json::to_string(stage)
+                .and_then(|json| serde_json::from_str(&json))
+                .ok()
+                .map(|value| (id, value))
+        })
+        .collect();
+    for scene in scenes.values_mut() {
+        let Some(stages) = scene["stages"].as_array_mut() else {
+            continue;
+        };
+        let mut shared = vec![];
+        let mut index = 0;
+        stages.retain(|stage| {
+            let id = NanoID(stage["id"].as_str().unwrap_or_default().to_string());
+            let keep = library.get(&id) != Some(stage);
+            if !keep {
+                shared.push(SharedStage { index, id });
+            }
+            index += 1;
+            keep
+        });
+        scene["shared_stages"] = serde_json::to_value(shared).unwrap_or_default();
+    }
+}
+
+// Put the stages of the library back into the scenes of a loaded project
+pub fn expand_shared_stages(package: &mut Package) {
+    let library = &package.stage_library;
+    for scene in package.scenes.values_mut() {
+        let mut shared = std::mem::take(&mut scene.shared_stages);
+        shared.sort_by_key(|shared| shared.index);
+        for SharedStage { index, id } in shared {
+            match library.get(&id) {
+                Some(stage) => scene
+                    .stages
+                    .insert(index.min(scene.stages.len()), stage.clone()),
+                None => warn!(
+                    target: "project", scene = scene.id.0.as_str(), stage = id.0.as_str();
+                    "Scene uses a stage missing from the library"
+                ),
+            }
+        }
+    }
+}
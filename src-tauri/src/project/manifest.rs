@@ -0,0 +1,430 @@
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    io::{BufReader, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::error::SlsbError;
+
+use super::{
+    export_path::{create_export_file, export_error, long_path, write_export_file},
+    package::Package,
+    scene::Scene,
+    serialize::{ByteWriter, EncodeBinary, Layout},
+    NanoID,
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const CHANGELOG_FILE: &str = "CHANGELOG.md";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct PackVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Default for PackVersion {
+    fn default() -> Self {
+        Self {
+            major: 1,
+            minor: 0,
+            patch: 0,
+        }
+    }
+}
+
+impl PackVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+// Stored as the string it converts into
+impl JsonSchema for PackVersion {
+    fn schema_name() -> String {
+        "PackVersion".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl EncodeBinary for PackVersion {
+    fn get_byte_size(&self) -> usize {
+        self.major.get_byte_size() + self.minor.get_byte_size() + self.patch.get_byte_size()
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        self.major.write_byte(buf);
+        self.minor.write_byte(buf);
+        self.patch.write_byte(buf);
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Version",
+            vec![
+                ("major", u32::layout()),
+                ("minor", u32::layout()),
+                ("patch", u32::layout()),
+            ],
+        )
+    }
+}
+
+impl fmt::Display for PackVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for PackVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|p| p.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid version {}: {}", s, e))?;
+        match parts[..] {
+            [major, minor, patch] => Ok(Self {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(format!("Invalid version {}, expected major.minor.patch", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for PackVersion {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PackVersion> for String {
+    fn from(value: PackVersion) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SceneManifest {
+    pub name: String,
+    pub revision: u32,
+    pub fingerprint: String,
+}
+
+// Summary of an export, written into the export folder to compare against on the next build
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub pack_name: String,
+    pub pack_version: PackVersion,
+    pub format_version: u8,
+    pub scenes: BTreeMap<String, SceneManifest>,
+    // files written into the folder by exports and still present, relative to it
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub distribution: Distribution,
+}
+
+// How the exported scenes spread over tags and races, to check the balance of a release
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Distribution {
+    // scenes carrying the tag themselves or on one of their stages, compared case insensitive
+    pub tags: BTreeMap<String, usize>,
+    // scenes with at least one actor of the race
+    pub races: BTreeMap<String, usize>,
+}
+
+impl Distribution {
+    pub fn from_package(package: &Package) -> Self {
+        let mut ret = Self::default();
+        for scene in package.exported_scenes() {
+            let tags: BTreeSet<String> = scene
+                .tags
+                .iter()
+                .chain(scene.stages.iter().flat_map(|stage| &stage.tags))
+                .map(|tag| tag.trim().to_lowercase())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            for tag in tags {
+                *ret.tags.entry(tag).or_default() += 1;
+            }
+            let races: BTreeSet<&String> = scene.positions.iter().map(|info| &info.race).collect();
+            for race in races {
+                *ret.races.entry(race.clone()).or_default() += 1;
+            }
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Changelog {
+    pub version: PackVersion,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+// 64 bit FNV-1a, for fingerprints and ids which have to stay the same between versions
+pub fn fnv_hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn hash_value(value: &serde_json::Value) -> String {
+    format!("{:016x}", fnv_hash(value.to_string().bytes()))
+}
+
+// FNV-1a hash over everything stored about the scene, to tell whether it differs from disk.
+// The stored fingerprint follows from the rest of the scene and is left out
+pub fn content_hash(scene: &Scene) -> String {
+    let mut value = serde_json::to_value(scene).unwrap_or_default();
+    if let Some(obj) = value.as_object_mut() {
+        obj.remove("fingerprint");
+    }
+    hash_value(&value)
+}
+
+// FNV-1a hash over the scene content, ignoring editor-only data such as node coordinates
+pub fn fingerprint(scene: &Scene) -> String {
+    let mut value = serde_json::to_value(scene).unwrap_or_default();
+    if let Some(obj) = value.as_object_mut() {
+        for key in ["revision", "locked", "has_warnings", "fingerprint"] {
+            obj.remove(key);
+        }
+        if let Some(graph) = obj.get_mut("graph").and_then(|g| g.as_object_mut()) {
+            for (_, node) in graph.iter_mut() {
+                if let Some(node) = node.as_object_mut() {
+                    node.remove("x");
+                    node.remove("y");
+                }
+            }
+        }
+    }
+    hash_value(&value)
+}
+
+impl Manifest {
+    pub fn from_package(package: &Package) -> Self {
+        Self {
+            pack_name: package.pack_name.clone(),
+            pack_version: package.pack_version,
+            format_version: package.version,
+            scenes: package
+                .exported_scenes()
+                .map(|scene| {
+                    (
+                        scene.id.0.clone(),
+                        SceneManifest {
+                            name: scene.name.clone(),
+                            revision: scene.revision,
+                            fingerprint: fingerprint(scene),
+                        },
+                    )
+                })
+                .collect(),
+            files: vec![],
+            distribution: Distribution::from_package(package),
+        }
+    }
+
+    // Manifest of the package as of the release, before the release is applied to it
+    pub fn from_release(package: &Package, release: &Release) -> Self {
+        let mut ret = Self::from_package(package);
+        ret.pack_version = release.changelog.version;
+        for (id, revision) in &release.revisions {
+            if let Some(scene) = ret.scenes.get_mut(&id.0) {
+                scene.revision = *revision;
+            }
+        }
+        ret
+    }
+
+    pub fn read(root_dir: &Path) -> Option<Self> {
+        let file = fs::File::open(long_path(&root_dir.join(MANIFEST_FILE))).ok()?;
+        serde_json::from_reader(BufReader::new(file)).ok()
+    }
+
+    pub fn write(&self, root_dir: &Path) -> Result<(), SlsbError> {
+        let path = root_dir.join(MANIFEST_FILE);
+        let mut file = BufWriter::new(create_export_file(&path)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.flush().map_err(|err| export_error(err, &path))
+    }
+}
+
+// A file inside root_dir as listed in the manifest, e.g. "SKSE/SexLab/Registry/Pack.slr"
+pub fn manifest_path(path: &Path, root_dir: &Path) -> Option<String> {
+    let parts: Vec<String> = path
+        .strip_prefix(root_dir)
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some(parts.join("/"))
+}
+
+// Files listed by the previous manifest which the current export did not write. Entries leading
+// out of root_dir are ignored, so an edited manifest cannot point at unrelated files
+pub fn stale_files(previous: &Manifest, current: &Manifest, root_dir: &Path) -> Vec<PathBuf> {
+    let files: BTreeSet<&String> = previous.files.iter().collect();
+    files
+        .into_iter()
+        .filter(|file| !current.files.contains(file))
+        .map(|file| file.split('/').collect::<PathBuf>())
+        .filter(|file| file.components().all(|c| matches!(c, Component::Normal(_))))
+        .map(|file| root_dir.join(file))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+impl Changelog {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut ret = format!("## {}\n", self.version);
+        for (title, list) in [
+            ("Added", &self.added),
+            ("Changed", &self.changed),
+            ("Removed", &self.removed),
+        ] {
+            if list.is_empty() {
+                continue;
+            }
+            ret.push_str(&format!("\n### {}\n", title));
+            for name in list {
+                ret.push_str(&format!("- {}\n", name));
+            }
+        }
+        ret
+    }
+
+    // Prepend this release to the changelog in the export folder
+    pub fn write(&self, root_dir: &Path) -> Result<(), SlsbError> {
+        let path = root_dir.join(CHANGELOG_FILE);
+        let previous = fs::read_to_string(long_path(&path)).unwrap_or_default();
+        let content = if previous.is_empty() {
+            format!("# Changelog\n\n{}", self.to_markdown())
+        } else {
+            let body = previous
+                .strip_prefix("# Changelog\n\n")
+                .unwrap_or(&previous);
+            format!("# Changelog\n\n{}\n{}", self.to_markdown(), body)
+        };
+        write_export_file(&path, content)
+    }
+}
+
+// Version and scene revisions of the next release. They are only applied to the package once
+// the export is written, so a failed export does not skip a version
+#[derive(Debug, Clone, Default)]
+pub struct Release {
+    pub changelog: Changelog,
+    pub revisions: Vec<(NanoID, u32)>,
+}
+
+impl Release {
+    pub fn apply(&self, package: &mut Package) {
+        package.pack_version = self.changelog.version;
+        for (id, revision) in &self.revisions {
+            if let Some(scene) = package.scenes.get_mut(id) {
+                scene.revision = *revision;
+            }
+        }
+    }
+}
+
+// Compare the package against the manifest of the previous export, bumping scene revisions
+// and the pack version: removed scenes are a major, added scenes a minor, and changed
+// scenes a patch release
+pub fn make_release(package: &Package, previous: Option<&Manifest>) -> Release {
+    let Some(previous) = previous else {
+        return Release {
+            changelog: Changelog {
+                version: package.pack_version,
+                added: package.exported_scenes().map(|s| s.name.clone()).collect(),
+                ..Default::default()
+            },
+            revisions: vec![],
+        };
+    };
+    let mut changelog = Changelog::default();
+    let mut revisions = vec![];
+    let mut exported = vec![];
+    for scene in package.exported_scenes() {
+        exported.push(scene.id.0.clone());
+        match previous.scenes.get(&scene.id.0) {
+            None => changelog.added.push(scene.name.clone()),
+            Some(old) if old.fingerprint != fingerprint(scene) => {
+                revisions.push((scene.id.clone(), scene.revision.max(old.revision) + 1));
+                changelog.changed.push(if old.name == scene.name {
+                    scene.name.clone()
+                } else {
+                    format!("{} (renamed from {})", scene.name, old.name)
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    changelog.removed = previous
+        .scenes
+        .iter()
+        .filter(|(id, _)| !exported.contains(id))
+        .map(|(_, scene)| scene.name.clone())
+        .collect();
+    for list in [
+        &mut changelog.added,
+        &mut changelog.changed,
+        &mut changelog.removed,
+    ] {
+        list.sort();
+    }
+
+    let mut version = package.pack_version.max(previous.pack_version);
+    if !changelog.removed.is_empty() {
+        version = PackVersion {
+            major: version.major + 1,
+            minor: 0,
+            patch: 0,
+        };
+    } else if !changelog.added.is_empty() {
+        version = PackVersion {
+            minor: version.minor + 1,
+            patch: 0,
+            ..version
+        };
+    } else if !changelog.changed.is_empty() {
+        version.patch += 1;
+    }
+    changelog.version = version;
+    Release {
+        changelog,
+        revisions,
+    }
+}
@@ -0,0 +1,185 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::NanoID;
+use crate::error::SlsbError;
+
+pub const METRICS_FILE: &str = "metrics.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SceneMetrics {
+    pub name: String,
+    pub edits: u32,
+    // seconds the scene has been open in the editor
+    pub seconds: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProjectMetrics {
+    pub builds: u32,
+    #[serde(default)]
+    pub last_build: Option<u64>,
+    #[serde(default)]
+    pub scenes: HashMap<NanoID, SceneMetrics>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SceneUsage {
+    pub id: NanoID,
+    #[serde(flatten)]
+    pub metrics: SceneMetrics,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct MetricsReport {
+    pub project: String,
+    pub enabled: bool,
+    pub builds: u32,
+    pub last_build: Option<u64>,
+    pub total_seconds: u64,
+    // most edited first
+    pub scenes: Vec<SceneUsage>,
+}
+
+// Opt-in usage statistics, kept locally in the app data directory and never sent anywhere
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct UsageMetrics {
+    pub enabled: bool,
+    #[serde(default)]
+    pub projects: BTreeMap<String, ProjectMetrics>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(skip)]
+    focus: Option<(String, NanoID, Instant)>,
+}
+
+impl UsageMetrics {
+    pub fn load(path: PathBuf) -> Self {
+        let mut ret: UsageMetrics = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| {
+                serde_json::from_str(&data)
+                    .map_err(|e| warn!("Discarding invalid metrics file: {}", e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        ret.path = Some(path);
+        ret
+    }
+
+    pub fn save(&self) -> Result<(), SlsbError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> () {
+        if !enabled {
+            self.focus = None;
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn clear(&mut self) -> () {
+        self.projects.clear();
+        self.focus = None;
+    }
+
+    pub fn record_edit(&mut self, project: &str, scene: &NanoID, name: &str) -> () {
+        if !self.enabled {
+            return;
+        }
+        let entry = self.scene_mut(project, scene, name);
+        entry.edits += 1;
+    }
+
+    pub fn record_build(&mut self, project: &str) -> () {
+        if !self.enabled {
+            return;
+        }
+        let entry = self.projects.entry(project.into()).or_default();
+        entry.builds += 1;
+        entry.last_build = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+    }
+
+    // Set the scene currently open in the editor, crediting the time spent on the previous one
+    pub fn focus_scene(&mut self, project: &str, scene: Option<(&NanoID, &str)>) -> () {
+        self.focus_scene_at(project, scene, Instant::now())
+    }
+
+    pub fn focus_scene_at(
+        &mut self,
+        project: &str,
+        scene: Option<(&NanoID, &str)>,
+        now: Instant,
+    ) -> () {
+        if let Some((prev_project, prev_scene, since)) = self.focus.take() {
+            let seconds = now.saturating_duration_since(since).as_secs();
+            if let Some(entry) = self
+                .projects
+                .get_mut(&prev_project)
+                .and_then(|p| p.scenes.get_mut(&prev_scene))
+            {
+                entry.seconds += seconds;
+            }
+        }
+        if !self.enabled {
+            return;
+        }
+        if let Some((id, name)) = scene {
+            self.scene_mut(project, id, name);
+            self.focus = Some((project.into(), id.clone(), now));
+        }
+    }
+
+    pub fn report(&self, project: &str) -> MetricsReport {
+        let metrics = self.projects.get(project).cloned().unwrap_or_default();
+        let mut scenes: Vec<SceneUsage> = metrics
+            .scenes
+            .into_iter()
+            .map(|(id, metrics)| SceneUsage { id, metrics })
+            .collect();
+        scenes.sort_by(|a, b| {
+            b.metrics
+                .edits
+                .cmp(&a.metrics.edits)
+                .then(b.metrics.seconds.cmp(&a.metrics.seconds))
+                .then(a.metrics.name.cmp(&b.metrics.name))
+        });
+        MetricsReport {
+            project: project.into(),
+            enabled: self.enabled,
+            builds: metrics.builds,
+            last_build: metrics.last_build,
+            total_seconds: scenes.iter().map(|s| s.metrics.seconds).sum(),
+            scenes,
+        }
+    }
+
+    fn scene_mut(&mut self, project: &str, scene: &NanoID, name: &str) -> &mut SceneMetrics {
+        let entry = self
+            .projects
+            .entry(project.into())
+            .or_default()
+            .scenes
+            .entry(scene.clone())
+            .or_default();
+        entry.name = name.into();
+        entry
+    }
+}
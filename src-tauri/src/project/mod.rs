@@ -1,4 +1,8 @@
-use crate::project::serialize::EncodeBinary;
+use crate::project::{
+    manifest::fnv_hash,
+    serialize::{ByteWriter, EncodeBinary, Layout},
+};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // A collection of scenes and various meta data, such as author of the project
@@ -17,18 +21,112 @@ pub mod position_info;
 
 pub mod define;
 
-mod serialize;
+// Algorithms operating on the stage graph of a scene
+pub mod graph;
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+// Stage graphs written to and read from DOT files, for external graph editors
+pub mod dot;
+
+// Project wide tools for animation events
+pub mod events;
+
+// Matching of offset files against the scenes of a project
+pub mod offsets;
+
+// Subsets of a project exported into their own registry files
+pub mod bundle;
+
+// Tags derived from the content of a scene
+pub mod tags;
+
+// Consistency checks run before export and from the command line
+pub mod validate;
+
+// Headless conversion of legacy SLAL packs
+pub mod convert;
+
+// Migration of every project file below a folder to the current format
+pub mod upgrade;
+
+// Actor types of SLAL packs and their sexes
+pub mod aliases;
+
+// Version control of the project folder through libgit2, without a git installation
+pub mod git;
+
+// Structure of project files, checked before they are deserialized
+pub mod schema;
+
+// Stage sheets, one row per stage, for planning packs in spreadsheets
+pub mod csv;
+
+// Repair of dangling references left behind by hand edits and merges
+pub mod repair;
+
+// Stages cut off from the graph of their scene, which would still be exported
+pub mod orphans;
+
+// Stages shared between scenes, stored once in the project file
+pub mod library;
+
+// Decoding and summary of exported registry files
+pub mod inspect;
+
+// Conflicts between a project and the registries installed alongside it
+pub mod registry;
+
+// Log of modifications made to a project
+pub mod audit;
+
+// Opt-in local statistics on where authoring time goes
+pub mod metrics;
+
+// Summary of an export and the changes between two exports
+pub mod manifest;
+
+// Credits of the exported scenes, written next to the changelog
+pub mod credits;
+
+// Plugins, keywords and animation objects required by the exported scenes
+pub mod dependencies;
+
+// Localized display names of the exported scenes
+pub mod translations;
+
+// Checks and errors of the files an export writes, for long paths and read-only targets
+pub mod export_path;
+
+// Preview clips of scenes, kept in a folder next to the project
+pub mod preview;
+
+// Skeleton previews decoded from the animation files of a stage
+pub mod hkx;
+
+// Top-down drawings of the actors of a stage, used as thumbnails
+pub mod schematic;
+
+// Binary encoding of the registry, public for the benchmarks
+pub mod serialize;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(
+    Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
 pub struct NanoID(pub String);
 
 impl NanoID {
-    const NANOID_ALPHABET: [char; 36] = [
+    // Parameters of generated ids. Ids are written into the binary with a fixed width, so
+    // changing the length changes the format
+    pub const NANOID_ALPHABET: [char; 36] = [
         'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r',
         's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
     ];
-    const PREFIX_HASH_LEN: usize = 4;
-    const NANOID_LENGTH: usize = 8;
+    pub const PREFIX_HASH_LEN: usize = 4;
+    pub const NANOID_LENGTH: usize = 8;
+    // characters of a readable id taken from the name, leaving at least 3 for the hash
+    const READABLE_LABEL_LEN: usize = 5;
 
     pub fn new_prefix() -> Self {
         Self::new(Self::PREFIX_HASH_LEN)
@@ -38,6 +136,46 @@ impl NanoID {
         Self::new(Self::NANOID_LENGTH)
     }
 
+    // A new id for which is_taken is false, regenerating on collision
+    pub fn new_unique(is_taken: impl Fn(&NanoID) -> bool) -> Self {
+        loop {
+            let id = Self::new_nanoid();
+            if !is_taken(&id) {
+                return id;
+            }
+        }
+    }
+
+    // Lowercase letters and digits of a name, the part of it readable ids are made of
+    pub fn readable_label(name: &str) -> String {
+        name.to_lowercase()
+            .chars()
+            .filter(|c| Self::NANOID_ALPHABET.contains(c))
+            .collect()
+    }
+
+    // An id starting with the first characters of label, filled up with a hash of seed. The same
+    // label and seed give the same id, salting the hash until one is found which is not taken
+    pub fn new_readable(label: &str, seed: &str, is_taken: impl Fn(&NanoID) -> bool) -> Self {
+        let label: String = Self::readable_label(label)
+            .chars()
+            .take(Self::READABLE_LABEL_LEN)
+            .collect();
+        (0u64..)
+            .map(|salt| {
+                let mut hash = fnv_hash(seed.bytes().chain(salt.to_le_bytes()));
+                let mut id = label.clone();
+                let base = Self::NANOID_ALPHABET.len() as u64;
+                while id.len() < Self::NANOID_LENGTH {
+                    id.push(Self::NANOID_ALPHABET[(hash % base) as usize]);
+                    hash /= base;
+                }
+                NanoID(id)
+            })
+            .find(|id| !is_taken(id))
+            .unwrap()
+    }
+
     fn new(len: usize) -> Self {
         assert!(
             len == Self::NANOID_LENGTH || len == Self::PREFIX_HASH_LEN,
@@ -47,6 +185,24 @@ impl NanoID {
         );
         NanoID(nanoid::nanoid!(len, &Self::NANOID_ALPHABET))
     }
+
+    // Chance of at least two out of count random ids of length len being equal, approximated
+    // by the birthday bound 1 - e^(-n(n-1)/2N)
+    pub fn collision_probability(count: usize, len: usize) -> f64 {
+        let space = (Self::NANOID_ALPHABET.len() as f64).powi(len as i32);
+        let n = count as f64;
+        -(-n * (n - 1.0).max(0.0) / (2.0 * space)).exp_m1()
+    }
+}
+
+// Parameters of generated ids and how likely ids of a project would collide without checks
+#[derive(Debug, Serialize, Clone)]
+pub struct IdStatistics {
+    pub alphabet: String,
+    pub length: usize,
+    pub prefix_length: usize,
+    pub ids: usize,
+    pub collision_probability: f64,
 }
 
 impl EncodeBinary for NanoID {
@@ -60,7 +216,13 @@ impl EncodeBinary for NanoID {
         self.0.len()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         buf.extend_from_slice(self.0.as_bytes());
     }
+
+    fn layout() -> Layout {
+        Layout::Id {
+            len: Self::NANOID_LENGTH,
+        }
+    }
 }
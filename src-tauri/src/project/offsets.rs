@@ -0,0 +1,526 @@
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fs, io::BufReader, path::Path};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    define::{Offset, Sex},
+    package::Package,
+    scene::Scene,
+    stage::Stage,
+    NanoID,
+};
+
+// Version of the offset files written by the builder. Version 1 files map scene ids to their
+// stages directly, later versions list them under "scenes"
+pub const OFFSET_VERSION: u64 = 2;
+
+// How entries of an offset file are matched against the scenes of the project. Each mode falls
+// back to the previous ones: ids are always tried first
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetMatching {
+    #[default]
+    Id,
+    // scene and stage names, case insensitive
+    Name,
+    // the animation events listed with the positions of a stage
+    Events,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OffsetEntry {
+    // scene and stage keys as given in the offset file
+    pub scene: String,
+    pub stage: String,
+    // the stages the entry was matched to
+    pub targets: Vec<(NanoID, NanoID)>,
+    pub reason: Option<Message>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct OffsetImportReport {
+    pub applied: Vec<OffsetEntry>,
+    pub skipped: Vec<OffsetEntry>,
+    pub ambiguous: Vec<OffsetEntry>,
+}
+
+impl OffsetImportReport {
+    // Scenes whose offsets were changed
+    pub fn scenes(&self) -> BTreeSet<NanoID> {
+        self.applied
+            .iter()
+            .flat_map(|entry| entry.targets.iter().map(|(scene, _)| scene.clone()))
+            .collect()
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim().eq_ignore_ascii_case(b.trim())
+}
+
+// The events an offset entry was recorded for, if the file lists them
+fn entry_events(positions: &serde_yaml::Sequence) -> Option<Vec<String>> {
+    positions
+        .iter()
+        .map(|position| {
+            position
+                .as_mapping()
+                .and_then(|mapping| mapping.get(&"event".into()))
+                .and_then(|event| event.as_str())
+                .map(|event| event.to_lowercase())
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|events| !events.is_empty())
+}
+
+// Actor scales stored along with the positions of a stage
+fn entry_scales(positions: &serde_yaml::Sequence) -> Vec<Option<f32>> {
+    positions
+        .iter()
+        .map(|position| {
+            position
+                .as_mapping()
+                .and_then(|mapping| mapping.get(&"scale".into()))
+                .and_then(|scale| scale.as_f64())
+                .filter(|scale| scale.is_finite() && *scale > 0.0)
+                .map(|scale| scale as f32)
+        })
+        .collect()
+}
+
+fn offset_scenes(offsets: &serde_yaml::Mapping) -> Result<&serde_yaml::Mapping, SlsbError> {
+    let Some(version) = offsets.get(&"version".into()) else {
+        return Ok(offsets);
+    };
+    match version.as_u64() {
+        Some(2) => offsets
+            .get(&"scenes".into())
+            .and_then(|scenes| scenes.as_mapping())
+            .ok_or(
+                Message::new("offset.expected_mapping")
+                    .arg("scene", "scenes")
+                    .into(),
+            ),
+        v => Err(Message::new("offset.unsupported_version")
+            .arg("version", v.map_or("?".into(), |v| v.to_string()))
+            .into()),
+    }
+}
+
+fn stage_events(stage: &Stage) -> Vec<String> {
+    stage
+        .positions
+        .iter()
+        .map(|p| p.event.first().map_or(String::new(), |e| e.to_lowercase()))
+        .collect()
+}
+
+fn find_targets(
+    package: &Package,
+    scene_key: &str,
+    stage_key: &str,
+    positions: &serde_yaml::Sequence,
+    matching: OffsetMatching,
+) -> Vec<(NanoID, NanoID)> {
+    let stage_in = |scene: &Scene| -> Vec<(NanoID, NanoID)> {
+        let by_id = scene.get_stage(&NanoID(stage_key.to_string()));
+        let stages: Vec<&Stage> = match by_id {
+            Some(stage) => vec![stage],
+            None if matching >= OffsetMatching::Name => scene
+                .stages
+                .iter()
+                .filter(|stage| !stage.name.trim().is_empty() && same_name(&stage.name, stage_key))
+                .collect(),
+            None => vec![],
+        };
+        stages
+            .into_iter()
+            .map(|stage| (scene.id.clone(), stage.id.clone()))
+            .collect()
+    };
+
+    let mut scenes: Vec<&Scene> = package
+        .get_scene(&NanoID(scene_key.to_string()))
+        .into_iter()
+        .collect();
+    if scenes.is_empty() && matching >= OffsetMatching::Name {
+        scenes = package
+            .scenes
+            .values()
+            .filter(|scene| same_name(&scene.name, scene_key))
+            .collect();
+    }
+    let mut ret: Vec<(NanoID, NanoID)> = scenes.iter().flat_map(|scene| stage_in(scene)).collect();
+    if ret.is_empty() && matching >= OffsetMatching::Events {
+        if let Some(events) = entry_events(positions) {
+            // restrict the search to the matched scenes, if any
+            let candidates: Vec<&Scene> = if scenes.is_empty() {
+                package.scenes.values().collect()
+            } else {
+                scenes
+            };
+            ret = candidates
+                .into_iter()
+                .flat_map(|scene| {
+                    scene
+                        .stages
+                        .iter()
+                        .filter(|stage| stage_events(stage) == events)
+                        .map(|stage| (scene.id.clone(), stage.id.clone()))
+                })
+                .collect();
+        }
+    }
+    ret.sort_by(|a, b| (&a.0 .0, &a.1 .0).cmp(&(&b.0 .0, &b.1 .0)));
+    ret
+}
+
+// A malformed entry of an offset file, which is skipped
+fn invalid_entry(scene: &str, stage: &str, reason: Message) -> OffsetEntry {
+    warn!(
+        target: "offsets", scene = scene, stage = stage;
+        "Skipping malformed offsets: {}",
+        reason
+    );
+    OffsetEntry {
+        scene: scene.to_string(),
+        stage: stage.to_string(),
+        targets: vec![],
+        reason: Some(reason),
+    }
+}
+
+// Apply the offsets of a parsed offset file. Entries which cannot be matched to exactly one stage,
+// or which are malformed, are reported rather than applied
+pub fn apply_offsets(
+    package: &mut Package,
+    offsets: &serde_yaml::Mapping,
+    matching: OffsetMatching,
+) -> Result<OffsetImportReport, SlsbError> {
+    let mut report = OffsetImportReport::default();
+    for (scene_key, stages) in offset_scenes(offsets)? {
+        let scene_key = scene_key
+            .as_str()
+            .ok_or(Message::new("offset.invalid_scene_id"))?;
+        let Some(stages) = stages.as_mapping() else {
+            let reason = Message::new("offset.expected_mapping").arg("scene", scene_key);
+            report.skipped.push(invalid_entry(scene_key, "", reason));
+            continue;
+        };
+        for (stage_key, positions) in stages {
+            let Some(stage_key) = stage_key.as_str() else {
+                let reason = Message::new("offset.expected_stage_id").arg("scene", scene_key);
+                report.skipped.push(invalid_entry(scene_key, "", reason));
+                continue;
+            };
+            let Some(positions) = positions.as_sequence() else {
+                let reason = Message::new("offset.expected_sequence")
+                    .arg("scene", scene_key)
+                    .arg("stage", stage_key);
+                report
+                    .skipped
+                    .push(invalid_entry(scene_key, stage_key, reason));
+                continue;
+            };
+            let targets = find_targets(package, scene_key, stage_key, positions, matching);
+            let mut entry = OffsetEntry {
+                scene: scene_key.to_string(),
+                stage: stage_key.to_string(),
+                targets: targets.clone(),
+                reason: None,
+            };
+            let (scene_id, stage_id) = match targets.as_slice() {
+                [target] => target.clone(),
+                [] => {
+                    warn!(
+                        target: "offsets", scene = scene_key, stage = stage_key;
+                        "No matching stage, skipping offsets"
+                    );
+                    entry.reason = Some(Message::new("offset.no_match"));
+                    report.skipped.push(entry);
+                    continue;
+                }
+                _ => {
+                    warn!(
+                        target: "offsets", scene = scene_key, stage = stage_key;
+                        "Offsets match {} stages, skipping",
+                        targets.len()
+                    );
+                    entry.reason =
+                        Some(Message::new("offset.ambiguous").arg("count", targets.len()));
+                    report.ambiguous.push(entry);
+                    continue;
+                }
+            };
+            let scene = package.get_scene_mut(&scene_id).unwrap();
+            if scene.locked {
+                warn!(
+                    target: "offsets", scene = scene_id.0.as_str();
+                    "Scene is locked, skipping its offsets"
+                );
+                entry.reason = Some(Message::new("scene.locked").arg("scene", &scene.name));
+                report.skipped.push(entry);
+                continue;
+            }
+            let stage = scene.get_stage_mut(&stage_id).unwrap();
+            match stage.import_offset(positions) {
+                Ok(()) => {
+                    for (info, scale) in scene.positions.iter_mut().zip(entry_scales(positions)) {
+                        if let Some(scale) = scale {
+                            info.scale = scale;
+                        }
+                    }
+                    package
+                        .audit
+                        .record("import_offset", Some(&scene_id), vec![stage_id], None);
+                    report.applied.push(entry);
+                }
+                Err(err) => {
+                    entry.reason = Some(err.message());
+                    report.skipped.push(entry);
+                }
+            }
+        }
+    }
+    info!(
+        target: "offsets", skipped = report.skipped.len(), ambiguous = report.ambiguous.len();
+        "Applied offsets to {} stages",
+        report.applied.len()
+    );
+    Ok(report)
+}
+
+pub fn import_offset_file(
+    package: &mut Package,
+    path: &Path,
+    matching: OffsetMatching,
+) -> Result<OffsetImportReport, SlsbError> {
+    let file = fs::File::open(path)?;
+    let offsets: serde_yaml::Mapping = serde_yaml::from_reader(BufReader::new(file))?;
+    apply_offsets(package, &offsets, matching)
+}
+
+// The offsets of the given scenes, or all scenes, in the current offset file format
+pub fn export_offsets(package: &Package, scenes: Option<&[NanoID]>) -> serde_yaml::Mapping {
+    let mut list: Vec<&Scene> = package
+        .scenes
+        .values()
+        .filter(|scene| scenes.map_or(true, |ids| ids.contains(&scene.id)))
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.0.cmp(&b.id.0)));
+
+    let mut ret = serde_yaml::Mapping::new();
+    for scene in list {
+        let mut stages = serde_yaml::Mapping::new();
+        for stage in &scene.stages {
+            let positions: serde_yaml::Sequence = stage
+                .positions
+                .iter()
+                .zip(&scene.positions)
+                .map(|(position, info)| {
+                    let mut entry = serde_yaml::Mapping::new();
+                    if let Some(event) = position.event.first() {
+                        entry.insert("event".into(), event.as_str().into());
+                    }
+                    entry.insert("scale".into(), info.scale.into());
+                    entry.insert("transform".into(), position.export_offset().into());
+                    entry.into()
+                })
+                .collect();
+            stages.insert(stage.id.0.as_str().into(), positions.into());
+        }
+        ret.insert(scene.id.0.as_str().into(), stages.into());
+    }
+    let mut file = serde_yaml::Mapping::new();
+    file.insert("version".into(), OFFSET_VERSION.into());
+    file.insert("scenes".into(), ret.into());
+    file
+}
+
+pub fn write_offset_file(
+    package: &Package,
+    path: &Path,
+    scenes: Option<&[NanoID]>,
+) -> Result<(), SlsbError> {
+    let offsets = export_offsets(package, scenes);
+    fs::write(path, serde_yaml::to_string(&offsets)?)?;
+    info!(
+        target: "offsets", path:? = path;
+        "Exported offsets of {} scenes",
+        offsets[&"scenes".into()].as_mapping().map_or(0, |m| m.len())
+    );
+    Ok(())
+}
+
+// The race and sex of an actor, which decide whether offsets can be transferred between stages
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct ActorSignature {
+    pub race: String,
+    pub sex: Sex,
+}
+
+// The offsets of one stage, saved to be applied to all stages with the same actors
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct OffsetTemplate {
+    pub name: String,
+    pub actors: Vec<ActorSignature>,
+    pub offsets: Vec<Offset>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct TemplateReport {
+    pub applied: Vec<(NanoID, NanoID)>,
+    // matching scenes which are locked
+    pub locked: Vec<NanoID>,
+}
+
+impl TemplateReport {
+    // Scenes whose offsets were changed
+    pub fn scenes(&self) -> BTreeSet<NanoID> {
+        self.applied
+            .iter()
+            .map(|(scene, _)| scene.clone())
+            .collect()
+    }
+}
+
+fn signature(scene: &Scene) -> Vec<ActorSignature> {
+    scene
+        .positions
+        .iter()
+        .map(|info| ActorSignature {
+            race: info.race.clone(),
+            sex: info.sex.clone(),
+        })
+        .collect()
+}
+
+impl OffsetTemplate {
+    pub fn from_stage(name: &str, scene: &Scene, stage: &Stage) -> Self {
+        Self {
+            name: name.to_string(),
+            actors: signature(scene),
+            offsets: stage.positions.iter().map(|p| p.offset.clone()).collect(),
+        }
+    }
+
+    // For each actor of the scene the index of the template actor it corresponds to, if the
+    // scene has the same actors as the template in any order. Keeps the order where possible
+    pub fn assignment(&self, scene: &Scene) -> Option<Vec<usize>> {
+        fn assign(
+            template: &[ActorSignature],
+            actors: &[ActorSignature],
+            used: &mut [bool],
+            ret: &mut Vec<usize>,
+        ) -> bool {
+            let Some(actor) = actors.get(ret.len()) else {
+                return true;
+            };
+            for (i, candidate) in template.iter().enumerate() {
+                if used[i] || candidate != actor {
+                    continue;
+                }
+                used[i] = true;
+                ret.push(i);
+                if assign(template, actors, used, ret) {
+                    return true;
+                }
+                used[i] = false;
+                ret.pop();
+            }
+            false
+        }
+        let actors = signature(scene);
+        if actors.len() != self.actors.len() || self.offsets.len() != self.actors.len() {
+            return None;
+        }
+        let mut ret = vec![];
+        assign(
+            &self.actors,
+            &actors,
+            &mut vec![false; actors.len()],
+            &mut ret,
+        )
+        .then_some(ret)
+    }
+}
+
+// Save the offsets of a stage as a template, replacing a template of the same name
+pub fn save_template(
+    package: &mut Package,
+    name: &str,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<OffsetTemplate, SlsbError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Message::new("offset.template_unnamed").into());
+    }
+    let scene = package
+        .get_scene(scene)
+        .ok_or(Message::new("scene.invalid_id").arg("scene", &scene.0))?;
+    let stage = scene.get_stage(stage).ok_or(
+        Message::new("scene.missing_stage")
+            .arg("scene", &scene.id.0)
+            .arg("stage", &stage.0),
+    )?;
+    let template = OffsetTemplate::from_stage(name, scene, stage);
+    package
+        .offset_templates
+        .retain(|t| !same_name(&t.name, name));
+    package.offset_templates.push(template.clone());
+    Ok(template)
+}
+
+// Apply a template to every stage of every scene with the template's actors
+pub fn apply_template(package: &mut Package, name: &str) -> Result<TemplateReport, SlsbError> {
+    let template = package
+        .offset_templates
+        .iter()
+        .find(|t| same_name(&t.name, name))
+        .cloned()
+        .ok_or(Message::new("offset.unknown_template").arg("template", name))?;
+    let mut report = TemplateReport::default();
+    for scene in package.scenes.values_mut() {
+        let Some(assignment) = template.assignment(scene) else {
+            continue;
+        };
+        if scene.locked {
+            report.locked.push(scene.id.clone());
+            continue;
+        }
+        let mut stages = vec![];
+        for stage in &mut scene.stages {
+            if stage.positions.len() != assignment.len() {
+                continue;
+            }
+            for (position, i) in stage.positions.iter_mut().zip(&assignment) {
+                position.offset = template.offsets[*i].clone();
+            }
+            stages.push(stage.id.clone());
+            report.applied.push((scene.id.clone(), stage.id.clone()));
+        }
+        if stages.is_empty() {
+            continue;
+        }
+        package.audit.record(
+            "apply_offset_template",
+            Some(&scene.id),
+            stages,
+            Some(format!("Applied offset template {}", template.name)),
+        );
+    }
+    report
+        .applied
+        .sort_by(|a, b| (&a.0 .0, &a.1 .0).cmp(&(&b.0 .0, &b.1 .0)));
+    report.locked.sort_by(|a, b| a.0.cmp(&b.0));
+    info!(
+        target: "offsets", template = template.name.as_str(), locked = report.locked.len();
+        "Applied offset template to {} stages",
+        report.applied.len()
+    );
+    Ok(report)
+}
@@ -0,0 +1,166 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{define::Node, graph::depths, package::Package, scene::Scene, NanoID};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanKind {
+    // stage without a node in the graph
+    MissingNode,
+    // node of the graph without a stage
+    DanglingNode,
+    // stage no path from the root leads to
+    Unreachable,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct OrphanedStage {
+    pub scene: NanoID,
+    pub stage: NanoID,
+    // None for nodes without a stage
+    pub name: Option<String>,
+    pub kind: OrphanKind,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanAction {
+    Delete,
+    // connect the stage to the root, nodes without a stage are deleted either way
+    Reattach,
+}
+
+fn scene_orphans(scene: &Scene) -> Vec<OrphanedStage> {
+    let stages: HashSet<&NanoID> = scene.stages.iter().map(|s| &s.id).collect();
+    let reachable = depths(&scene.root, &scene.graph, &stages);
+    let orphan = |stage: &NanoID, name: Option<&String>, kind| OrphanedStage {
+        scene: scene.id.clone(),
+        stage: stage.clone(),
+        name: name.cloned(),
+        kind,
+    };
+
+    let mut dangling: Vec<OrphanedStage> = scene
+        .graph
+        .keys()
+        .filter(|id| !stages.contains(id))
+        .map(|id| orphan(id, None, OrphanKind::DanglingNode))
+        .collect();
+    dangling.sort_by(|a, b| a.stage.0.cmp(&b.stage.0));
+    let mut ret: Vec<OrphanedStage> = scene
+        .stages
+        .iter()
+        .filter_map(|stage| {
+            if !scene.graph.contains_key(&stage.id) {
+                Some(orphan(
+                    &stage.id,
+                    Some(&stage.name),
+                    OrphanKind::MissingNode,
+                ))
+            } else if !reachable.contains_key(&stage.id) {
+                Some(orphan(
+                    &stage.id,
+                    Some(&stage.name),
+                    OrphanKind::Unreachable,
+                ))
+            } else {
+                None
+            }
+        })
+        .collect();
+    ret.extend(dangling);
+    ret
+}
+
+// Stages of the given scene (or all scenes) missing from the graph or cut off from the root,
+// and graph nodes without a stage
+pub fn find_orphans(package: &Package, scene: Option<&NanoID>) -> Vec<OrphanedStage> {
+    let mut scenes: Vec<&Scene> = package
+        .scenes
+        .values()
+        .filter(|s| scene.is_none() || scene == Some(&s.id))
+        .collect();
+    scenes.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+    scenes.into_iter().flat_map(scene_orphans).collect()
+}
+
+// Delete or reattach the given orphans of a scene, returning how many were resolved. Stages
+// which are no longer orphaned are left alone
+pub fn collect_orphans(
+    package: &mut Package,
+    id: &NanoID,
+    stages: &[NanoID],
+    action: OrphanAction,
+) -> Result<usize, SlsbError> {
+    let scene = package.get_unlocked_scene_mut(id)?;
+    let orphans: Vec<OrphanedStage> = scene_orphans(scene)
+        .into_iter()
+        .filter(|orphan| stages.contains(&orphan.stage))
+        .collect();
+    let deletes_root = |orphan: &OrphanedStage| {
+        action == OrphanAction::Delete
+            && orphan.kind != OrphanKind::DanglingNode
+            && orphan.stage == scene.root
+    };
+    if let Some(orphan) = orphans.iter().find(|orphan| deletes_root(orphan)) {
+        return Err(Message::new("orphans.root")
+            .arg("stage", &orphan.stage.0)
+            .into());
+    }
+    // every edge is checked before the scene is changed, so a failure leaves it as it was
+    let root = scene.root.clone();
+    let reattached: Vec<&NanoID> = orphans
+        .iter()
+        .filter(|orphan| {
+            action == OrphanAction::Reattach && orphan.kind != OrphanKind::DanglingNode
+        })
+        .map(|orphan| &orphan.stage)
+        .filter(|stage| **stage != root)
+        .collect();
+    for stage in &reattached {
+        scene.require_stage(&root)?;
+        scene.require_stage(stage)?;
+    }
+    for orphan in &orphans {
+        match (orphan.kind, action) {
+            (OrphanKind::DanglingNode, _) | (_, OrphanAction::Delete) => {
+                scene.stages.retain(|stage| stage.id != orphan.stage);
+                scene.graph.remove(&orphan.stage);
+                for node in scene.graph.values_mut() {
+                    node.dest.retain(|dest| dest != &orphan.stage);
+                }
+                scene.transitions.retain(|t| t.from_stage != orphan.stage);
+                scene
+                    .suppressed_warnings
+                    .retain(|w| w.stage.as_ref() != Some(&orphan.stage));
+            }
+            (_, OrphanAction::Reattach) => {
+                scene
+                    .graph
+                    .entry(orphan.stage.clone())
+                    .or_insert_with(Node::default);
+            }
+        }
+    }
+    for stage in reattached {
+        scene.add_edge(&root, stage)?;
+    }
+    if !orphans.is_empty() {
+        let operation = match action {
+            OrphanAction::Delete => "delete_orphans",
+            OrphanAction::Reattach => "reattach_orphans",
+        };
+        let stages = orphans.iter().map(|orphan| orphan.stage.clone()).collect();
+        package.audit.record(operation, Some(id), stages, None);
+        info!(
+            target: "project", scene = id.0.as_str(), action:? = action;
+            "Resolved {} orphaned stages",
+            orphans.len()
+        );
+    }
+    Ok(orphans.len())
+}
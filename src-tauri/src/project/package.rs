@@ -1,39 +1,708 @@
-use log::info;
-use serde::{Deserialize, Serialize};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{
+    de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use std::{
-    collections::{HashMap, HashSet},
-    fs,
-    io::{BufReader, BufWriter, ErrorKind, Write},
+    borrow::Cow,
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    marker::PhantomData,
     mem::size_of,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Instant, SystemTime, UNIX_EPOCH},
     vec,
 };
+use tauri::Emitter;
 use tauri_plugin_dialog::DialogExt;
 
 use crate::{
+    error::SlsbError,
+    i18n::Message,
+    logging::with_build_log,
     project::{
-        define::{Node, Sex},
+        aliases::SexAliases,
+        audit::{log_path, AuditEntry, AuditLog},
+        convert::{
+            Confidence, ConversionKind, ConversionRecord, GenderGuess, RaceMapping,
+            SceneConversion, SlalProgress,
+        },
+        credits::Credits,
+        csv::import_scenes,
+        define::Offset,
+        dependencies::Dependencies,
+        export_path::{
+            check_export_targets, create_export_dir, create_export_file, export_error, long_path,
+        },
+        git,
+        library::{
+            compact_shared_stages, expand_shared_stages, prune_library, refresh_shared_stages,
+            same_stage, sync_shared_stages,
+        },
+        manifest::{
+            content_hash, make_release, manifest_path, stale_files, Distribution, Manifest,
+            PackVersion,
+        },
+        offsets::{import_offset_file, OffsetImportReport, OffsetMatching, OffsetTemplate},
         position::Position,
-        serialize::{make_fnis_lines, map_race_to_folder},
+        position_info::PositionInfo,
+        preview::{rename_preview, write_previews},
+        schema::{project_schema, validate},
+        serialize::{make_fnis_lines, merge_fnis_lines},
+        translations::{
+            normalize_language, write_mcm_translations, SceneTranslations, GAME_LANGUAGES,
+        },
+    },
+    racekeys::{
+        get_race_keys_string, get_race_variant_base, guess_racekeys, map_legacy_to_racekey,
+        map_race_to_folder, normalize_behaviour_folder,
+    },
+};
+
+use super::{
+    bundle::{bundle_of, Bundle},
+    scene::{display_name, Archetype, Scene, SceneSummary, SuppressedWarning},
+    serialize::{interned_list_size, ByteWriter, Capabilities, EncodeBinary, Layout, StringTable},
+    stage::{Stage, StageTimers},
+    validate::{
+        validate_anim_objects, validate_bundles, validate_export_name, validate_scene, Severity,
     },
-    racekeys::map_legacy_to_racekey,
+    IdStatistics, NanoID,
 };
 
-use super::{scene::Scene, serialize::EncodeBinary, stage::Stage, NanoID};
+pub const VERSION: u8 = 8; // current version
+
+// Strings of a slal file borrow from the input where it allows, serde only borrows a Cow which
+// is not wrapped into an Option by itself
+fn borrow_str<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Cow<'a, str>>, D::Error> {
+    #[derive(Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+    Ok(Option::<Borrowed>::deserialize(deserializer)?.map(|s| s.0))
+}
+
+// An animation of a slal file. Attributes the importer does not read are kept by name, to list
+// them as dropped
+#[derive(Debug, Deserialize)]
+struct SlalAnimation<'a> {
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    id: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    name: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    creature_race: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    actors: Option<Vec<SlalActor<'a>>>,
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    tags: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    stage: Option<Vec<SlalStage<'a>>>,
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    sound: Option<Cow<'a, str>>,
+    #[serde(flatten, borrow)]
+    dropped: BTreeMap<Cow<'a, str>, IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlalActor<'a> {
+    #[serde(rename = "type", default, borrow, deserialize_with = "borrow_str")]
+    sex: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    race: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    stages: Option<Vec<SlalEvent<'a>>>,
+    #[serde(flatten, borrow)]
+    dropped: BTreeMap<Cow<'a, str>, IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlalEvent<'a> {
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    id: Option<Cow<'a, str>>,
+    #[serde(default)]
+    open_mouth: Option<SlalFlag>,
+    #[serde(default)]
+    silent: Option<SlalFlag>,
+    sos: Option<i64>,
+    // older packs name the schlong bend up_down
+    up_down: Option<i64>,
+    #[serde(flatten, borrow)]
+    dropped: BTreeMap<Cow<'a, str>, IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlalStage<'a> {
+    number: Option<i64>,
+    timer: Option<f64>,
+    #[serde(default, borrow, deserialize_with = "borrow_str")]
+    sound: Option<Cow<'a, str>>,
+    #[serde(flatten, borrow)]
+    dropped: BTreeMap<Cow<'a, str>, IgnoredAny>,
+}
+
+// Flags are written as either booleans or numbers, anything else counts as unset
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SlalFlag {
+    Bool(bool),
+    Number(i64),
+    Other(IgnoredAny),
+}
+
+fn slal_flag(flag: &Option<SlalFlag>) -> bool {
+    match flag {
+        Some(SlalFlag::Bool(value)) => *value,
+        Some(SlalFlag::Number(value)) => *value != 0,
+        Some(SlalFlag::Other(_)) | None => false,
+    }
+}
+
+// Race of a creature animation without creature_race, inferred from its tags and name. Only
+// a single race named by the tags is trusted
+fn infer_creature_race(animation: &SlalAnimation) -> Option<(String, Confidence)> {
+    let tags = guess_racekeys(animation.tags.as_deref().unwrap_or_default());
+    if let [race] = tags.as_slice() {
+        return Some((race.clone(), Confidence::High));
+    }
+    let name = guess_racekeys(animation.name.as_deref().unwrap_or_default());
+    tags.into_iter()
+        .chain(name)
+        .next()
+        .map(|race| (race, Confidence::Low))
+}
+
+fn dropped_keys<T>(dropped: &BTreeMap<Cow<str>, T>, prefix: &str, out: &mut Vec<String>) {
+    for key in dropped.keys() {
+        let name = format!("{}{}", prefix, key);
+        if !out.contains(&name) {
+            out.push(name);
+        }
+    }
+}
+
+// Regenerate the ids of a scene colliding with the given ids, adding the ids of the scene to
+// them. Returns the number of ids replaced
+fn claim_ids(scene: &mut Scene, ids: &mut HashSet<NanoID>) -> usize {
+    let mut ret = 0;
+    if ids.contains(&scene.id) {
+        scene.id = NanoID::new_unique(|id| ids.contains(id));
+        ret += 1;
+    }
+    ids.insert(scene.id.clone());
+    let stages: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    for (i, old) in stages.iter().enumerate() {
+        if !ids.contains(old) {
+            ids.insert(old.clone());
+            continue;
+        }
+        let new = NanoID::new_unique(|id| ids.contains(id) || stages.contains(id));
+        if stages[..i].contains(old) {
+            // a duplicate within the scene, references stay with the first stage
+            scene.stages[i].id = new.clone();
+        } else {
+            scene.rename_stage(old, new.clone());
+        }
+        ids.insert(new);
+        ret += 1;
+    }
+    if ret > 0 {
+        info!(
+            target: "project", scene = scene.id.0.as_str();
+            "Regenerated {} colliding ids of Scene {}",
+            ret,
+            scene.name
+        );
+    }
+    ret
+}
+
+// Derive the ids of a scene and its stages from their names, avoiding the given ids and adding
+// the new ones to them. A scene named Doggy gets an id like "doggy3k1", its stages "dog1a8x0",
+// "dog2u5ma" and so on. Stages of the library keep their ids, other scenes refer to them by it.
+// Returns the number of ids changed
+fn readable_ids(
+    scene: &mut Scene,
+    ids: &mut HashSet<NanoID>,
+    library: &BTreeMap<NanoID, Stage>,
+) -> usize {
+    let mut ret = 0;
+    let id = NanoID::new_readable(&scene.name, &scene.name, |id| ids.contains(id));
+    if id != scene.id {
+        scene.id = id;
+        ret += 1;
+    }
+    ids.insert(scene.id.clone());
+    let prefix: String = NanoID::readable_label(&scene.name)
+        .chars()
+        .take(3)
+        .collect();
+    let stages: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    for (i, old) in stages.iter().enumerate() {
+        if library.contains_key(old) {
+            continue;
+        }
+        let label = format!("{}{}", prefix, i + 1);
+        let seed = format!("{}/{}/{}", scene.name, i + 1, scene.stages[i].name);
+        // stages not renamed yet keep their ids until it is their turn
+        let new = NanoID::new_readable(&label, &seed, |id| {
+            ids.contains(id) || (stages[i + 1..].contains(id) && id != old)
+        });
+        if &new != old {
+            if stages[..i].contains(old) {
+                scene.stages[i].id = new.clone();
+            } else {
+                scene.rename_stage(old, new.clone());
+            }
+            ret += 1;
+        }
+        ids.insert(new);
+    }
+    ret
+}
+
+// Convert a single animation of a slal file into a scene
+fn slal_scene(
+    animation: &SlalAnimation,
+    aliases: &SexAliases,
+) -> Result<(Scene, SceneConversion), SlsbError> {
+    let mut scene = Scene::default();
+    scene.name = animation
+        .name
+        .as_deref()
+        .ok_or(Message::new("slal.missing_attribute").arg("attribute", "name"))?
+        .into();
+    let mut conversion = SceneConversion {
+        scene: scene.id.clone(),
+        name: scene.name.clone(),
+        source_name: animation.id.as_deref().map(str::to_string),
+        ..Default::default()
+    };
+    dropped_keys(&animation.dropped, "", &mut conversion.dropped);
+    let crt_race = animation.creature_race.as_deref().unwrap_or_default();
+    let actors = animation
+        .actors
+        .as_ref()
+        .ok_or(Message::new("slal.missing_attribute").arg("attribute", "actors"))?;
+    // stages are allocated for all actors at once, so every actor has to list all of them
+    let mut stage_count = None;
+    for position in actors {
+        let count = position
+            .stages
+            .as_ref()
+            .ok_or(Message::new("slal.missing_attribute").arg("attribute", "stages"))?
+            .len();
+        let expected = *stage_count.get_or_insert(count);
+        if count != expected {
+            return Err(Message::new("slal.stage_count")
+                .arg("scene", &scene.name)
+                .arg("got", count)
+                .arg("expected", expected)
+                .into());
+        }
+    }
+    if stage_count.unwrap_or_default() == 0 {
+        return Err(Message::new("slal.no_stages").into());
+    }
+
+    // initialize stages and copy information for every position into the respective stage
+    for (n, position) in actors.iter().enumerate() {
+        let sex_type = position.sex.as_deref();
+        let sex = sex_type.unwrap_or("male");
+        let actor = aliases
+            .resolve(sex)
+            .ok_or(Message::new("slal.unknown_sex").arg("sex", sex))?;
+        let reason = match sex_type {
+            None => Some("missing type".to_string()),
+            Some(_) if sex.trim().eq_ignore_ascii_case("type") => Some("placeholder type".into()),
+            Some(_) if !SexAliases::is_native(sex) => Some(format!("alias {}", sex.trim())),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            conversion.guessed_genders.push(GenderGuess {
+                position: n,
+                sex: actor.describe(),
+                reason,
+            });
+        }
+        let prefix = format!("actors[{}].", n);
+        dropped_keys(&position.dropped, &prefix, &mut conversion.dropped);
+        let legacy = position
+            .race
+            .as_deref()
+            .unwrap_or(crt_race)
+            .trim()
+            .to_string();
+        let (race, confidence) = match actor.creature {
+            false => ("Human".to_string(), None),
+            true if !legacy.is_empty() => (map_legacy_to_racekey(&legacy)?, None),
+            true => {
+                let (race, confidence) = infer_creature_race(animation)
+                    .ok_or(Message::new("slal.unknown_creature").arg("scene", &scene.name))?;
+                (race, Some(confidence))
+            }
+        };
+        let events = position
+            .stages
+            .as_ref()
+            .ok_or(Message::new("slal.missing_attribute").arg("attribute", "stages"))?;
+
+        if scene.stages.is_empty() {
+            for _ in 0..events.len() {
+                scene.stages.push(Stage::new(&scene));
+            }
+            for stage in &mut scene.stages {
+                stage.positions = vec![Position::new(None); actors.len()];
+            }
+        }
+        for (i, evt) in events.iter().enumerate() {
+            let prefix = format!("actors[{}].stages.", n);
+            dropped_keys(&evt.dropped, &prefix, &mut conversion.dropped);
+            let edit_position = &mut scene.stages[i].positions[n];
+            edit_position.event = vec![evt
+                .id
+                .as_deref()
+                .ok_or(Message::new("slal.missing_attribute").arg("attribute", "id"))?
+                .into()];
+            edit_position.open_mouth = slal_flag(&evt.open_mouth);
+            edit_position.silent = slal_flag(&evt.silent);
+            edit_position.schlong = evt.sos.or(evt.up_down).unwrap_or_default().clamp(-9, 9) as i8;
+            edit_position.sex = actor.sex();
+            edit_position.race = race.clone();
+        }
+        conversion.races.push(RaceMapping {
+            position: n,
+            legacy: (actor.creature && !legacy.is_empty()).then_some(legacy),
+            race,
+            confidence,
+        });
+    }
+    // finalize stage data, adding climax to last positions
+    let tags = animation
+        .tags
+        .as_deref()
+        .map(|tags| {
+            tags.to_lowercase()
+                .split(',')
+                .map(|str| str.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let stage_extra = animation.stage.as_deref().unwrap_or_default();
+    for extra in stage_extra {
+        dropped_keys(&extra.dropped, "stage.", &mut conversion.dropped);
+    }
+    let sound = animation.sound.as_deref();
+    for (i, stage) in scene.stages.iter_mut().enumerate() {
+        stage.tags = tags.clone();
+        stage.extra.sound = sound.map(str::to_string);
+        for extra in stage_extra {
+            let n = extra.number.unwrap_or(-1);
+            if n == -1 || n as usize != i {
+                continue;
+            }
+            if let Some(timer) = extra.timer {
+                stage.extra.fixed_len = timer as f32;
+            }
+            if let Some(sound) = &extra.sound {
+                stage.extra.sound = Some(sound.to_string());
+            }
+        }
+    }
+    let last = scene.stages.last_mut().unwrap();
+    for position in &mut last.positions {
+        position.extra.climax = true;
+    }
+    // build graph
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order)?;
+    Ok((scene, conversion))
+}
+
+// Convert the animations of a slal file on all cores, calling on_progress whenever one is done.
+// Results keep the order of the animations. converted counts the animations of the file
+// converted so far, progress is reported as the given one with that count
+fn slal_scenes(
+    anims: &[SlalAnimation],
+    aliases: &SexAliases,
+    converted: &AtomicUsize,
+    progress: SlalProgress,
+    on_progress: &(dyn Fn(SlalProgress) + Sync),
+) -> Result<Vec<(Scene, SceneConversion)>, SlsbError> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = anims.len().div_ceil(threads).max(1);
+    let results: Vec<Vec<Result<(Scene, SceneConversion), SlsbError>>> = std::thread::scope(|s| {
+        let workers: Vec<_> = anims
+            .chunks(chunk)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|animation| {
+                            let ret = slal_scene(animation, aliases);
+                            on_progress(SlalProgress {
+                                converted: converted.fetch_add(1, Ordering::Relaxed) + 1,
+                                ..progress
+                            });
+                            ret
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.into_iter().flatten().collect()
+}
+
+// Animations of a streamed slal file converted at once
+const SLAL_BATCH: usize = 256;
+
+// Reads the top level of a slal file, handing its animations to on_animation one at a time so
+// the file is never held in memory as a whole. Returns the name of the pack, if it is a string,
+// and whether the file lists animations
+struct SlalReader<T, F> {
+    on_animation: F,
+    animation: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>, F: FnMut(T)> Visitor<'de> for SlalReader<T, F> {
+    type Value = (Option<String>, bool);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a slal file")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut name, mut animations) = (None, false);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "name" => {
+                    name = match map.next_value()? {
+                        serde_json::Value::String(name) => Some(name),
+                        _ => None,
+                    }
+                }
+                "animations" => {
+                    map.next_value_seed(SlalAnimations {
+                        on_animation: &mut self.on_animation,
+                        animation: PhantomData,
+                    })?;
+                    animations = true;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok((name, animations))
+    }
+}
+
+struct SlalAnimations<'a, T, F> {
+    on_animation: &'a mut F,
+    animation: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>, F: FnMut(T)> DeserializeSeed<'de> for SlalAnimations<'_, T, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>, F: FnMut(T)> Visitor<'de> for SlalAnimations<'_, T, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of animations")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(animation) = seq.next_element()? {
+            (self.on_animation)(animation);
+        }
+        Ok(())
+    }
+}
+
+// Counts the bytes read from a streamed slal file, progress is reported by them
+struct CountingReader<R> {
+    inner: R,
+    read: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.set(self.read.get() + n as u64);
+        Ok(n)
+    }
+}
+
+// Stream the animations of a slal file, handing them to on_animation along with the number of
+// bytes read from the file so far. Strings of a stream cannot borrow from it and are owned
+fn read_slal<F: FnMut(SlalAnimation<'static>, u64)>(
+    path: &Path,
+    mut on_animation: F,
+) -> Result<(Option<String>, bool), SlsbError> {
+    let read = Rc::new(Cell::new(0));
+    let file = CountingReader {
+        inner: fs::File::open(path)?,
+        read: read.clone(),
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+    let ret = deserializer.deserialize_map(SlalReader {
+        on_animation: |animation| on_animation(animation, read.get()),
+        animation: PhantomData,
+    })?;
+    deserializer.end()?;
+    Ok(ret)
+}
+
+// Converts the animations of a streamed slal file a batch at a time, keeping the first error
+struct SlalBatches<'a> {
+    aliases: &'a SexAliases,
+    on_progress: &'a (dyn Fn(SlalProgress) + Sync),
+    converted: AtomicUsize,
+    bytes_read: u64,
+    file_size: u64,
+    batch: Vec<SlalAnimation<'static>>,
+    scenes: Result<Vec<(Scene, SceneConversion)>, SlsbError>,
+}
+
+impl SlalBatches<'_> {
+    fn push(&mut self, animation: SlalAnimation<'static>, bytes_read: u64) {
+        self.batch.push(animation);
+        self.bytes_read = bytes_read;
+        if self.batch.len() >= SLAL_BATCH {
+            self.convert();
+        }
+    }
+
+    fn convert(&mut self) {
+        let batch = std::mem::take(&mut self.batch);
+        if batch.is_empty() || self.scenes.is_err() {
+            return;
+        }
+        // the number of animations is unknown until the whole file is read
+        let progress = SlalProgress {
+            converted: 0,
+            total: None,
+            bytes_read: self.bytes_read,
+            file_size: self.file_size,
+        };
+        let converted = slal_scenes(
+            &batch,
+            self.aliases,
+            &self.converted,
+            progress,
+            self.on_progress,
+        );
+        match (&mut self.scenes, converted) {
+            (Ok(scenes), Ok(converted)) => scenes.extend(converted),
+            (_, Err(err)) => self.scenes = Err(err),
+            _ => (),
+        }
+    }
 
-const VERSION: u8 = 4; // current version
+    fn finish(mut self) -> Result<Vec<(Scene, SceneConversion)>, SlsbError> {
+        self.convert();
+        self.scenes
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Package {
     #[serde(default)]
     pub version: u8,
     #[serde(skip)]
     pub pack_path: PathBuf,
+    #[serde(skip)]
+    pub audit: AuditLog,
+    // content hashes of the scenes as last read from or written to the project file
+    #[serde(skip)]
+    saved_scenes: HashMap<NanoID, String>,
 
     pub pack_name: String,
     pub pack_author: String,
+    #[serde(default)] // addition 2.1
+    pub pack_version: PackVersion,
     pub prefix_hash: NanoID,
     pub scenes: HashMap<NanoID, Scene>,
+    #[serde(default)] // addition 2.2
+    pub bundles: Vec<Bundle>,
+    #[serde(default)] // addition 2.2
+    pub animation_root: Option<PathBuf>,
+    // overrides of the registry file name, which defaults to the pack name and "slr"
+    #[serde(default)] // addition 2.2
+    pub export_name: Option<String>,
+    #[serde(default)] // addition 2.2
+    pub export_extension: Option<String>,
+    #[serde(default)] // addition 2.2
+    pub offset_templates: Vec<OffsetTemplate>,
+    #[serde(default)] // addition 2.2
+    pub stage_timers: StageTimers,
+    // copy scene previews into the export
+    #[serde(default)] // addition 2.2
+    pub export_previews: bool,
+    // what the slal importer and version migrations did to the scenes, oldest first
+    #[serde(default)] // addition 2.2
+    pub conversions: Vec<ConversionRecord>,
+    // commit the project to its git repository whenever it is saved
+    #[serde(default)] // addition 2.2
+    pub git_autocommit: bool,
+    // save indented json with sorted keys, so diffs only show actual edits
+    #[serde(default)] // addition 2.2
+    pub pretty_save: bool,
+    // derive the ids of imported scenes and new stages from their names
+    #[serde(default)] // addition 2.2
+    pub readable_ids: bool,
+    // delete files of earlier exports into the same folder which are no longer written
+    #[serde(default)] // addition 2.2
+    pub clean_export: bool,
+    // add to FNIS lists of other packs in the export folder instead of overwriting them
+    #[serde(default)] // addition 2.2
+    pub merge_fnis: bool,
+    // time the phases of an export and list them in the build summary
+    #[serde(default)] // addition 2.2
+    pub profile_export: bool,
+    // behaviour folders of races which differ from the default, e.g. of custom creatures
+    #[serde(default)] // addition 2.2
+    pub behaviour_folders: BTreeMap<String, String>,
+    // oldest framework version the pack is made for, written into the registry header
+    #[serde(default)] // addition 2.2
+    pub min_framework_version: Option<PackVersion>,
+    // namespace animation objects with the prefix of the pack when exporting
+    #[serde(default)] // addition 2.2
+    pub prefix_anim_objects: bool,
+    // file name patterns of the FNIS lists of races, see fnis_list_name
+    #[serde(default)] // addition 2.2
+    pub fnis_list_names: BTreeMap<String, String>,
+    #[serde(default)] // addition 2.2
+    pub export_presets: Vec<ExportPreset>,
+    // stages used by several scenes, stored once in the project file
+    #[serde(default)] // addition 2.2
+    pub stage_library: BTreeMap<NanoID, Stage>,
+    // game languages to write translation files for besides english, e.g. GERMAN
+    #[serde(default)] // addition 2.2
+    pub translation_languages: Vec<String>,
+    // editor ids of the animation objects of each plugin, by plugin name. Once any plugin is
+    // listed, exports fail on animation objects none of them provides
+    #[serde(default)] // addition 2.2
+    pub anim_object_forms: BTreeMap<String, Vec<String>>,
+    // discarded scenes, kept until the trash is emptied
+    #[serde(default)] // addition 2.2
+    pub trash: Vec<TrashedScene>,
 }
 
 impl Package {
@@ -41,33 +710,156 @@ impl Package {
         Self {
             version: VERSION, // current version
             pack_path: Default::default(),
+            audit: Default::default(),
+            saved_scenes: Default::default(),
             pack_name: Default::default(),
             pack_author: "Unknown".into(),
+            pack_version: Default::default(),
             prefix_hash: NanoID::new_prefix(),
             scenes: HashMap::new(),
+            bundles: Default::default(),
+            animation_root: None,
+            export_name: None,
+            export_extension: None,
+            offset_templates: Default::default(),
+            stage_timers: Default::default(),
+            export_previews: false,
+            conversions: Default::default(),
+            git_autocommit: false,
+            pretty_save: false,
+            readable_ids: false,
+            clean_export: false,
+            merge_fnis: false,
+            profile_export: false,
+            behaviour_folders: Default::default(),
+            prefix_anim_objects: false,
+            fnis_list_names: Default::default(),
+            export_presets: Default::default(),
+            stage_library: Default::default(),
+            translation_languages: Default::default(),
+            anim_object_forms: Default::default(),
+            trash: Default::default(),
+            min_framework_version: None,
         }
     }
 
-    pub fn from_file(file: std::fs::File) -> Result<Package, String> {
-        serde_json::from_reader(BufReader::new(file))
-            .map_err(|e| e.to_string())
+    // Files are checked against the project schema first, pointing out the first invalid value
+    // rather than the position serde gave up at
+    pub fn from_file(file: std::fs::File) -> Result<Package, SlsbError> {
+        let value: serde_json::Value = serde_json::from_reader(BufReader::new(file))?;
+        let errors = validate(&value, project_schema());
+        for error in &errors {
+            warn!(
+                target: "project", pointer = error.pointer.as_str();
+                "Expected {}, found {}",
+                error.expected, error.found
+            );
+        }
+        if let Some(error) = errors.first() {
+            return Err(Message::new("project.invalid_file")
+                .arg("pointer", &error.pointer)
+                .arg("expected", &error.expected)
+                .arg("found", &error.found)
+                .arg("count", errors.len())
+                .into());
+        }
+        serde_json::from_value(value)
+            .map_err(SlsbError::from)
             .and_then(|mut package: Package| {
+                expand_shared_stages(&mut package);
                 if package.version < VERSION {
                     package.update_to_latest_version()?;
                 }
-                info!("Loaded project {}", package.pack_name);
+                info!(target: "project", "Loaded project {}", package.pack_name);
                 Ok(package)
             })
     }
 
-    fn update_to_latest_version(&mut self) -> Result<(), String> {
-        for (_, scene) in &mut self.scenes {
+    // Load a project file, resolving the paths stored relative to it
+    pub fn open(path: &Path) -> Result<Package, SlsbError> {
+        let mut package = Package::from_file(fs::File::open(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(root) = package
+            .animation_root
+            .as_mut()
+            .filter(|root| root.is_relative())
+        {
+            *root = dir.join(&root);
+        }
+        package.mark_saved();
+        Ok(package)
+    }
+
+    fn mark_saved(&mut self) -> () {
+        self.saved_scenes = self
+            .scenes
+            .iter()
+            .map(|(id, scene)| (id.clone(), content_hash(scene)))
+            .collect();
+    }
+
+    // Scenes which differ from the project file, as of its last save or load
+    pub fn unsaved_scenes(&self) -> UnsavedScenes {
+        let mut ret = UnsavedScenes::default();
+        for (id, scene) in &self.scenes {
+            match self.saved_scenes.get(id) {
+                None => ret.added.push(id.clone()),
+                Some(hash) if *hash != content_hash(scene) => ret.changed.push(id.clone()),
+                Some(_) => {}
+            }
+        }
+        ret.removed = self
+            .saved_scenes
+            .keys()
+            .filter(|id| !self.scenes.contains_key(id))
+            .cloned()
+            .collect();
+        ret.added.sort_by(|a, b| a.0.cmp(&b.0));
+        ret.changed.sort_by(|a, b| a.0.cmp(&b.0));
+        ret.removed.sort_by(|a, b| a.0.cmp(&b.0));
+        ret
+    }
+
+    fn update_to_latest_version(&mut self) -> Result<(), SlsbError> {
+        let mut record = ConversionRecord::new(ConversionKind::Migration, self.version, VERSION);
+        record.migrations = self.migrate()?;
+        record.scenes = self
+            .scenes
+            .values()
+            .map(|scene| SceneConversion {
+                scene: scene.id.clone(),
+                name: scene.name.clone(),
+                ..Default::default()
+            })
+            .collect();
+        self.conversions.push(record);
+        Ok(())
+    }
+
+    // Bring all scenes to the current version, returning the steps applied
+    fn migrate(&mut self) -> Result<Vec<String>, SlsbError> {
+        // discarded scenes are migrated as well, they may be restored later on
+        let trashed = self.trash.iter_mut().map(|trashed| &mut trashed.scene);
+        for scene in self.scenes.values_mut().chain(trashed) {
             if let Err(e) = scene.update_to_latest_version(self.version) {
-                return Err(format!("Failed to update scene {}: {}", scene.id.0, e));
+                return Err(Message::new("scene.update_failed")
+                    .arg("scene", &scene.id.0)
+                    .arg("details", e)
+                    .into());
+            }
+        }
+        // shared stages are migrated like the copies the scenes were given on load
+        for stage in self.stage_library.values_mut() {
+            if let Err(e) = stage.update_to_latest_version(self.version) {
+                return Err(Message::new("scene.update_failed")
+                    .arg("scene", &stage.id.0)
+                    .arg("details", e)
+                    .into());
             }
         }
+        let notes = Scene::migration_notes(self.version);
         self.version = VERSION;
-        Ok(())
+        Ok(notes)
     }
 
     pub fn reset(&mut self) -> &Self {
@@ -75,28 +867,333 @@ impl Package {
         self
     }
 
-    pub fn save_scene(&mut self, scene: Scene) -> &Scene {
+    // Insert or overwrite a scene. Locked scenes are only overwritten if forced
+    // Save the scene and carry edits of its shared stages over to the other scenes using them,
+    // returning the other scenes which were updated. A scene whose fingerprint no longer matches
+    // the stored one was edited from an outdated copy, its shared stages are then taken from the
+    // library so a stale copy does not revert edits saved through another scene
+    pub fn save_scene(&mut self, scene: Scene, force: bool) -> Result<BTreeSet<NanoID>, SlsbError> {
         let id = scene.id.clone();
-        info!("Saving or inserting Scene: {} / {}", id.0, scene.name);
+        if !force {
+            if let Some(old) = self.scenes.get(&id).filter(|old| old.locked) {
+                return Err(Message::new("scene.locked").arg("scene", &old.name).into());
+            }
+        }
+        info!(target: "project", scene = id.0.as_str(); "Saving or inserting Scene {}", scene.name);
+        let stale = self.scenes.get(&id).is_some_and(|old| {
+            !scene.fingerprint.is_empty() && old.fingerprint != scene.fingerprint
+        });
+        let stages = scene.stages.iter().map(|s| s.id.clone()).collect();
+        self.audit.record("save_scene", Some(&id), stages, None);
         self.scenes.insert(id.clone(), scene);
-        self.scenes.get(&id).unwrap()
+        if stale {
+            let refreshed = refresh_shared_stages(self, &id);
+            if refreshed > 0 {
+                warn!(
+                    target: "project", scene = id.0.as_str();
+                    "Scene was saved from an outdated copy, kept {} shared stages of the library",
+                    refreshed
+                );
+            }
+        }
+        let ret = sync_shared_stages(self, &id);
+        self.scenes.get_mut(&id).unwrap().update_fingerprint();
+        Ok(ret)
     }
 
-    pub fn discard_scene(&mut self, id: &NanoID) -> Option<Scene> {
-        self.scenes.remove(id).map(|s| {
-            info!("Deleting Scene: {} / {}", id.0, s.name);
-            s
-        })
+    // Move the scene into the trash, from where it can be restored until the trash is emptied
+    pub fn discard_scene(&mut self, id: &NanoID) -> Result<Scene, SlsbError> {
+        self.get_unlocked_scene_mut(id)?;
+        let s = self.scenes.remove(id).unwrap();
+        info!(target: "project", scene = id.0.as_str(); "Deleting Scene {}", s.name);
+        self.audit.record("delete_scene", Some(id), vec![], None);
+        self.trash.push(TrashedScene {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            scene: s.clone(),
+        });
+        Ok(s)
+    }
+
+    // Discarded scenes, most recently discarded first
+    pub fn trashed_scenes(&self) -> Vec<TrashSummary> {
+        self.trash
+            .iter()
+            .rev()
+            .map(|trashed| TrashSummary {
+                timestamp: trashed.timestamp,
+                scene: trashed.scene.summary(),
+            })
+            .collect()
+    }
+
+    // Move a discarded scene back into the project. A scene discarded more than once is restored
+    // as last discarded
+    pub fn restore_scene(&mut self, id: &NanoID) -> Result<&Scene, SlsbError> {
+        let i = self
+            .trash
+            .iter()
+            .rposition(|trashed| trashed.scene.id == *id)
+            .ok_or(Message::new("scene.not_in_trash").arg("scene", &id.0))?;
+        if let Some(scene) = self.scenes.get(id) {
+            return Err(Message::new("scene.restore_conflict")
+                .arg("scene", &scene.name)
+                .into());
+        }
+        let scene = self.trash.remove(i).scene;
+        info!(target: "project", scene = id.0.as_str(); "Restoring Scene {}", scene.name);
+        self.audit.record("restore_scene", Some(id), vec![], None);
+        self.scenes.insert(id.clone(), scene);
+        refresh_shared_stages(self, id);
+        Ok(self.scenes.get(id).unwrap())
+    }
+
+    // Delete the discarded scenes for good, returning how many there were
+    pub fn empty_trash(&mut self) -> usize {
+        let ret = self.trash.len();
+        if ret > 0 {
+            info!(target: "project", scenes = ret; "Emptying trash");
+            self.audit.record("empty_trash", None, vec![], None);
+        }
+        self.trash.clear();
+        ret
+    }
+
+    pub fn set_scene_locked(&mut self, id: &NanoID, locked: bool) -> Result<(), SlsbError> {
+        let scene = self
+            .get_scene_mut(id)
+            .ok_or(Message::new("scene.invalid_id").arg("scene", &id.0))?;
+        info!(
+            target: "project", scene = id.0.as_str();
+            "{} Scene {}",
+            if locked { "Locking" } else { "Unlocking" },
+            scene.name
+        );
+        scene.locked = locked;
+        let operation = if locked { "lock_scene" } else { "unlock_scene" };
+        self.audit.record(operation, Some(id), vec![], None);
+        Ok(())
+    }
+
+    // Set the animation objects a plugin provides, an empty list forgets the plugin
+    pub fn set_anim_object_forms(&mut self, plugin: &str, forms: &[String]) {
+        let plugin = plugin.trim();
+        let mut ret: Vec<String> = vec![];
+        for form in forms.iter().map(|form| form.trim()) {
+            if !form.is_empty() && !ret.iter().any(|f| f.eq_ignore_ascii_case(form)) {
+                ret.push(form.to_string());
+            }
+        }
+        info!(target: "project", plugin = plugin; "Listing {} animation objects", ret.len());
+        if ret.is_empty() {
+            self.anim_object_forms.remove(plugin);
+        } else {
+            self.anim_object_forms.insert(plugin.to_string(), ret);
+        }
+    }
+
+    // Read the animation objects of a plugin from a text file with one editor id per line.
+    // Only the first word of a line is read, so lists exported from xEdit with the form id
+    // after the editor id can be used as they are. Lines starting with ; or # are comments
+    pub fn import_anim_object_forms(
+        &mut self,
+        plugin: &str,
+        path: &Path,
+    ) -> Result<usize, SlsbError> {
+        let text = fs::read_to_string(path)?;
+        let forms: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with(';') && !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect();
+        self.set_anim_object_forms(plugin, &forms);
+        Ok(self
+            .anim_object_forms
+            .get(plugin.trim())
+            .map_or(0, Vec::len))
+    }
+
+    // Game languages to write translation files for, english is always written
+    pub fn set_translation_languages(&mut self, languages: &[String]) -> Result<(), SlsbError> {
+        let mut ret = vec![];
+        for language in languages {
+            let language = language.trim().to_uppercase();
+            if !GAME_LANGUAGES.iter().any(|(name, _)| *name == language) {
+                return Err(Message::new("export.unknown_language")
+                    .arg("language", language)
+                    .into());
+            }
+            if language != "ENGLISH" && !ret.contains(&language) {
+                ret.push(language);
+            }
+        }
+        self.translation_languages = ret;
+        Ok(())
+    }
+
+    // Set the display name of the scene in the given language, or remove it if name is None or empty
+    pub fn set_localized_name(
+        &mut self,
+        id: &NanoID,
+        lang: &str,
+        name: Option<&str>,
+    ) -> Result<(), SlsbError> {
+        let lang = normalize_language(lang)?;
+        let scene = self.get_unlocked_scene_mut(id)?;
+        match name.map(str::trim).filter(|name| !name.is_empty()) {
+            Some(name) => scene.localized_names.insert(lang.clone(), name.to_string()),
+            None => scene.localized_names.remove(&lang),
+        };
+        self.audit
+            .record("localize_name", Some(id), vec![], Some(lang));
+        Ok(())
     }
 
     pub fn get_scene(&self, id: &NanoID) -> Option<&Scene> {
         self.scenes.get(id)
     }
 
+    // Summaries of the scenes ordered by name, limit of them starting at offset
+    pub fn scene_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        archetype: Option<Archetype>,
+    ) -> ScenePage {
+        let mut scenes: Vec<&Scene> = self
+            .scenes
+            .values()
+            .filter(|scene| archetype.is_none() || archetype == Some(scene.effective_archetype()))
+            .collect();
+        scenes.sort_by(|a, b| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then(a.id.0.cmp(&b.id.0))
+        });
+        ScenePage {
+            total: scenes.len(),
+            offset,
+            scenes: scenes
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .map(Scene::summary)
+                .collect(),
+        }
+    }
+
     pub fn get_scene_mut(&mut self, id: &NanoID) -> Option<&mut Scene> {
         self.scenes.get_mut(id)
     }
 
+    // Accept or reject again a warning of the scene, for one of its stages or all of them
+    pub fn suppress_warning(
+        &mut self,
+        id: &NanoID,
+        warning: SuppressedWarning,
+        suppressed: bool,
+    ) -> Result<(), SlsbError> {
+        let scene = self.get_unlocked_scene_mut(id)?;
+        scene.suppressed_warnings.retain(|w| *w != warning);
+        if suppressed {
+            scene.suppressed_warnings.push(warning.clone());
+        }
+        let operation = match suppressed {
+            true => "suppress_warning",
+            false => "unsuppress_warning",
+        };
+        let stages = warning.stage.into_iter().collect();
+        self.audit
+            .record(operation, Some(id), stages, Some(warning.key));
+        Ok(())
+    }
+
+    // Mutable access to a scene which is not locked against editing
+    pub fn get_unlocked_scene_mut(&mut self, id: &NanoID) -> Result<&mut Scene, SlsbError> {
+        let scene = self
+            .get_scene_mut(id)
+            .ok_or(Message::new("scene.invalid_id").arg("scene", &id.0))?;
+        if scene.locked {
+            return Err(Message::new("scene.locked")
+                .arg("scene", &scene.name)
+                .into());
+        }
+        Ok(scene)
+    }
+
+    pub fn set_stage_order(&mut self, id: &NanoID, order: &[NanoID]) -> Result<(), SlsbError> {
+        self.get_unlocked_scene_mut(id)?.set_stage_order(order)?;
+        self.audit
+            .record("order_stages", Some(id), order.to_vec(), None);
+        Ok(())
+    }
+
+    pub fn add_transition(
+        &mut self,
+        id: &NanoID,
+        from: &NanoID,
+        to_scene: &NanoID,
+    ) -> Result<bool, SlsbError> {
+        if self.get_scene(to_scene).is_none() {
+            return Err(Message::new("scene.invalid_id")
+                .arg("scene", &to_scene.0)
+                .into());
+        }
+        let added = self
+            .get_unlocked_scene_mut(id)?
+            .add_transition(from, to_scene)?;
+        if added {
+            self.audit.record(
+                "add_transition",
+                Some(id),
+                vec![from.clone(), to_scene.clone()],
+                None,
+            );
+        }
+        Ok(added)
+    }
+
+    pub fn remove_transition(
+        &mut self,
+        id: &NanoID,
+        from: &NanoID,
+        to_scene: &NanoID,
+    ) -> Result<(), SlsbError> {
+        self.get_unlocked_scene_mut(id)?
+            .remove_transition(from, to_scene)?;
+        self.audit.record(
+            "remove_transition",
+            Some(id),
+            vec![from.clone(), to_scene.clone()],
+            None,
+        );
+        Ok(())
+    }
+
+    pub fn split_scene(
+        &mut self,
+        id: &NanoID,
+        stage_ids: &[NanoID],
+        link: bool,
+    ) -> Result<&Scene, SlsbError> {
+        let scene = self.get_unlocked_scene_mut(id)?;
+        let split = scene.split(stage_ids, link)?;
+        self.audit
+            .record("split_scene", Some(id), stage_ids.to_vec(), None);
+        info!(
+            target: "project", scene = id.0.as_str(), split = split.id.0.as_str();
+            "Split {} stages into a new Scene",
+            split.stages.len()
+        );
+        let split_id = split.id.clone();
+        self.save_scene(split, false)?;
+        Ok(self.scenes.get(&split_id).unwrap())
+    }
+
     pub fn get_stage(&self, id: &NanoID) -> Option<&Stage> {
         for (_, scene) in &self.scenes {
             let stage = scene.get_stage(id);
@@ -107,434 +1204,1739 @@ impl Package {
         None
     }
 
-    pub fn load_project(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
-        let path = app
-            .dialog()
-            .file()
-            .add_filter("SexLab Project", &["slsb.json"])
-            .blocking_pick_file()
-            .ok_or("No path to load project from".to_string())?
-            .into_path()
-            .map_err(|e| e.to_string())?;
-        *self = Package::from_file(fs::File::open(&path).map_err(|e| e.to_string())?)?;
-        self.set_project_name_from_path(&path);
-        self.pack_path = path.into();
-        Ok(())
+    // Ids of all scenes and stages of the project
+    pub fn ids(&self) -> HashSet<NanoID> {
+        let mut ret = HashSet::new();
+        for scene in self.scenes.values() {
+            ret.insert(scene.id.clone());
+            ret.extend(scene.stages.iter().map(|s| s.id.clone()));
+        }
+        // ids of discarded scenes stay taken, so restoring them never collides
+        for trashed in &self.trash {
+            ret.insert(trashed.scene.id.clone());
+            ret.extend(trashed.scene.stages.iter().map(|s| s.id.clone()));
+        }
+        ret.extend(self.stage_library.keys().cloned());
+        ret
     }
 
-    pub fn save_project(&mut self, save_as: bool, app: &tauri::AppHandle) -> Result<(), String> {
-        let path = if save_as || !self.pack_path.exists() || self.pack_path.is_dir() {
-            app.dialog()
-                .file()
-                .set_title("Save Project")
-                .set_file_name(&self.pack_name)
-                .add_filter("SexLab Project", &["slsb.json"])
-                .blocking_save_file()
-                .ok_or("No path to save project to".to_string())?
-                .into_path()
-                .map_err(|e| e.to_string())?
-        } else {
-            self.pack_path.clone()
-        };
-
-        self.set_project_name_from_path(&path);
-        self.write(path)
+    pub fn id_statistics(&self) -> IdStatistics {
+        let ids = self.ids().len();
+        IdStatistics {
+            alphabet: NanoID::NANOID_ALPHABET.iter().collect(),
+            length: NanoID::NANOID_LENGTH,
+            prefix_length: NanoID::PREFIX_HASH_LEN,
+            ids,
+            collision_probability: NanoID::collision_probability(ids, NanoID::NANOID_LENGTH),
+        }
     }
 
-    pub fn write(&mut self, path: PathBuf) -> Result<(), String> {
-        let file = fs::File::create(&path).map_err(|e| e.to_string())?;
-        serde_json::to_writer(file, self).map_err(|e| e.to_string())?;
-        println!("Saved project {}", self.pack_name);
-        Ok(())
+    // A blank scene whose ids are not used anywhere in the project
+    pub fn new_scene(&self) -> Scene {
+        let mut scene = Scene::default();
+        self.claim_ids(&mut scene);
+        scene
     }
 
-    pub fn load_slal(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
-        let path = app
-            .dialog()
-            .file()
-            .set_title("Load SLAL File")
-            .add_filter("SLAL.json", &["json"])
-            .blocking_pick_file()
-            .ok_or("No path to load slal file from".to_string())?
-            .into_path()
-            .map_err(|e| e.to_string())?;
+    // A new stage of the given scene, its id unused by the project and the scene
+    pub fn new_stage(&self, scene: &Scene) -> Stage {
+        let mut ids = self.ids();
+        ids.extend(scene.stages.iter().map(|s| s.id.clone()));
+        let mut stage = Stage::new(scene);
+        if self.readable_ids {
+            let n = scene.stages.len() + 1;
+            let label: String = NanoID::readable_label(&scene.name)
+                .chars()
+                .take(3)
+                .collect();
+            let seed = format!("{}/{}/", scene.name, n);
+            stage.id =
+                NanoID::new_readable(&format!("{}{}", label, n), &seed, |id| ids.contains(id));
+        } else if ids.contains(&stage.id) {
+            stage.id = NanoID::new_unique(|id| ids.contains(id));
+        }
+        stage
+    }
 
-        Package::from_slal(path).map(|prjct| *self = prjct)
+    // Regenerate the ids of a scene about to be added which collide with ids of the project,
+    // returning the number of ids replaced
+    pub fn claim_ids(&self, scene: &mut Scene) -> usize {
+        claim_ids(scene, &mut self.ids())
     }
 
-    pub fn from_slal(path: PathBuf) -> Result<Package, String> {
-        let file = fs::File::open(&path).map_err(|e| e.to_string())?;
+    // Ids for a scene about to be added, derived from its name if readable ids are enabled
+    fn assign_ids(&self, scene: &mut Scene) -> usize {
+        match self.readable_ids {
+            true => readable_ids(scene, &mut self.ids(), &self.stage_library),
+            false => self.claim_ids(scene),
+        }
+    }
 
-        let slal: serde_json::Value =
-            serde_json::from_reader(BufReader::new(file)).map_err(|e| e.to_string())?;
+    // Scenes listed in the manifest of an export preset's folder. Saved games refer to them by
+    // their ids
+    pub fn released_scenes(&self) -> HashSet<NanoID> {
+        self.export_presets
+            .iter()
+            .filter_map(|preset| Manifest::read(&preset.out))
+            .flat_map(|manifest| manifest.scenes.into_keys())
+            .map(NanoID)
+            .collect()
+    }
 
-        let mut prjct = Package::new();
-        prjct.version = 0; // SLAL files are always version 0
-        prjct.pack_name = slal["name"]
-            .as_str()
-            .ok_or("Missing name attribute")?
-            .into();
-
-        let anims = slal["animations"]
-            .as_array()
-            .ok_or("Missing animations attribute")?;
-        for animation in anims {
-            let mut scene = Scene::default();
-            scene.name = animation["name"]
-                .as_str()
-                .ok_or("Missing name attribute")?
-                .into();
-            let crt_race = animation["creature_race"].as_str().unwrap_or_default();
-            let actors = animation["actors"]
-                .as_array()
-                .ok_or("Missing actors attribute")?;
-
-            // initialize stages and copy information for every position into the respective stage
-            for (n, position) in actors.iter().enumerate() {
-                let sex = position["type"].as_str().unwrap_or("male").to_lowercase();
-                let events = position["stages"]
-                    .as_array()
-                    .ok_or("Missing stages attribute")?;
-
-                if scene.stages.is_empty() {
-                    for _ in 0..events.len() {
-                        scene.stages.push(Stage::new(&scene));
+    // Derive the ids of the given scenes, or all unlocked and unreleased scenes, from their names.
+    // Locked and released scenes are only changed if forced. Transitions, previews, conversion
+    // records and the audit log follow renamed scenes
+    pub fn make_ids_readable(
+        &mut self,
+        scenes: Option<&[NanoID]>,
+        force: bool,
+    ) -> Result<ReadableIds, SlsbError> {
+        let released = self.released_scenes();
+        let mut targets: Vec<NanoID> = match scenes {
+            Some(scenes) => {
+                for id in scenes {
+                    let scene = self
+                        .get_scene(id)
+                        .ok_or(Message::new("scene.invalid_id").arg("scene", &id.0))?;
+                    if force {
+                        continue;
                     }
-                    if scene.stages.is_empty() {
-                        return Err("Scene has no stages".into());
+                    if scene.locked {
+                        return Err(Message::new("scene.locked")
+                            .arg("scene", &scene.name)
+                            .into());
                     }
-                    for stage in &mut scene.stages {
-                        stage.positions = vec![Position::new(None); actors.len()];
+                    if released.contains(id) {
+                        return Err(Message::new("scene.released")
+                            .arg("scene", &scene.name)
+                            .into());
                     }
                 }
-                for (i, evt) in events.iter().enumerate() {
-                    let edit_position = &mut scene.stages[i].positions[n];
-                    edit_position.event =
-                        vec![evt["id"].as_str().ok_or("Missing id attribute")?.into()];
-                    match sex.as_str() {
-                        "male" | "type" => {
-                            edit_position.sex = Sex {
-                                male: true,
-                                female: false,
-                                futa: false,
-                            };
-                            edit_position.race = "Human".into();
-                        }
-                        "female" => {
-                            edit_position.sex = Sex {
-                                male: false,
-                                female: true,
-                                futa: false,
-                            };
-                            edit_position.race = "Human".into();
-                        }
-                        "creaturemale" => {
-                            edit_position.sex = Sex {
-                                male: true,
-                                female: false,
-                                futa: false,
-                            };
-                            edit_position.race = map_legacy_to_racekey(
-                                position["race"].as_str().unwrap_or(crt_race),
-                            )?;
-                        }
-                        "creaturefemale" => {
-                            edit_position.sex = Sex {
-                                male: false,
-                                female: true,
-                                futa: false,
-                            };
-                            edit_position.race = map_legacy_to_racekey(
-                                position["race"].as_str().unwrap_or(crt_race),
-                            )?;
-                        }
-                        _ => {
-                            return Err(format!("Unrecognized gender: {}", sex));
-                        }
+                scenes.to_vec()
+            }
+            None => self
+                .scenes
+                .values()
+                .filter(|scene| force || (!scene.locked && !released.contains(&scene.id)))
+                .map(|scene| scene.id.clone())
+                .collect(),
+        };
+        // ids of earlier scenes take precedence on collisions, so keep the order stable
+        targets.sort_by(|a, b| self.scenes[a].name.cmp(&self.scenes[b].name).then(a.cmp(b)));
+        targets.dedup();
+        let mut ret = ReadableIds::default();
+        for old in targets {
+            let mut scene = self.scenes.remove(&old).unwrap();
+            let mut ids = self.ids();
+            let stages: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+            let changed = readable_ids(&mut scene, &mut ids, &self.stage_library);
+            for (old, stage) in stages.iter().zip(&scene.stages) {
+                if *old != stage.id {
+                    self.audit.rename(old, &stage.id);
+                }
+            }
+            let new = scene.id.clone();
+            if new != old {
+                self.audit.rename(&old, &new);
+                for conversion in self
+                    .conversions
+                    .iter_mut()
+                    .flat_map(|record| record.scenes.iter_mut())
+                    .filter(|conversion| conversion.scene == old)
+                {
+                    conversion.scene = new.clone();
+                }
+                for other in self.scenes.values_mut().chain([&mut scene]) {
+                    for transition in other.transitions.iter_mut().filter(|t| t.to_scene == old) {
+                        transition.to_scene = new.clone();
+                        ret.scenes.insert(other.id.clone());
+                    }
+                }
+                ret.scenes.insert(old.clone());
+                if self.pack_path.is_file() {
+                    if let Err(err) = rename_preview(self, &old, &new) {
+                        warn!(target: "project", scene = old.0.as_str(); "Unable to move preview: {}", err);
                     }
                 }
             }
-            // finalize stage data, adding climax to last positions
-            let tags = animation["tags"]
-                .as_str()
-                .and_then(|tags| {
-                    let list = tags
-                        .to_lowercase()
-                        .split(',')
-                        .map(|str| str.trim().to_string())
-                        .collect::<Vec<_>>();
-                    Some(list)
+            if changed > 0 {
+                let stages = scene.stages.iter().map(|s| s.id.clone()).collect();
+                self.audit
+                    .record("make_ids_readable", Some(&scene.id), stages, None);
+                ret.scenes.insert(scene.id.clone());
+            }
+            ret.changed += changed;
+            self.scenes.insert(scene.id.clone(), scene);
+        }
+        info!(target: "project", "Derived {} ids from names", ret.changed);
+        Ok(ret)
+    }
+
+    pub fn load_project(&mut self, app: &tauri::AppHandle) -> Result<(), SlsbError> {
+        let path = app
+            .dialog()
+            .file()
+            .add_filter("SexLab Project", &["slsb.json"])
+            .blocking_pick_file()
+            .ok_or(SlsbError::DialogCancelled(Message::new(
+                "dialog.load_project",
+            )))?
+            .into_path()
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        *self = Package::open(&path)?;
+        self.set_project_name_from_path(&path);
+        self.pack_path = path.into();
+        Ok(())
+    }
+
+    pub fn save_project(
+        &mut self,
+        save_as: bool,
+        app: &tauri::AppHandle,
+        message: Option<String>,
+    ) -> Result<(), SlsbError> {
+        let path = if save_as || !self.pack_path.exists() || self.pack_path.is_dir() {
+            app.dialog()
+                .file()
+                .set_title("Save Project")
+                .set_file_name(sanitize_file_name(&self.pack_name))
+                .add_filter("SexLab Project", &["slsb.json"])
+                .blocking_save_file()
+                .ok_or(SlsbError::DialogCancelled(Message::new(
+                    "dialog.save_project",
+                )))?
+                .into_path()
+                .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?
+        } else {
+            self.pack_path.clone()
+        };
+
+        self.set_project_name_from_path(&path);
+        self.write(path.clone())?;
+        self.pack_path = path;
+        let changes = self.audit.recent(usize::MAX);
+        self.audit
+            .record("save_project", None, vec![], message.clone());
+        self.audit.flush(&log_path(&self.pack_path))?;
+        if self.git_autocommit {
+            // the project has been saved either way, failing to commit it is not fatal
+            let message = git::commit_message(self, &changes, message.as_deref());
+            if let Err(e) = git::commit_project(self, &message) {
+                warn!(target: "git", "Failed to commit project: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_audit_log(&self, scene: Option<&NanoID>) -> Vec<AuditEntry> {
+        let path = log_path(&self.pack_path);
+        self.audit
+            .entries(self.pack_path.is_file().then_some(path.as_path()))
+            .into_iter()
+            .filter(|entry| scene.map_or(true, |id| entry.scene.as_ref() == Some(id)))
+            .collect()
+    }
+
+    // Conversion records, narrowed down to the given scene if any
+    pub fn get_conversions(&self, scene: Option<&NanoID>) -> Vec<ConversionRecord> {
+        let Some(id) = scene else {
+            return self.conversions.clone();
+        };
+        self.conversions
+            .iter()
+            .filter_map(|record| {
+                let conversion = record.scene(id)?.clone();
+                Some(ConversionRecord {
+                    scenes: vec![conversion],
+                    ..record.clone()
                 })
-                .unwrap_or_default();
-            let stage_extra = animation["stage"].as_array();
-            for (i, stage) in scene.stages.iter_mut().enumerate() {
-                stage.tags = tags.clone();
-                if let Some(extra_vec) = stage_extra {
-                    for extra in extra_vec {
-                        let n = extra["number"].as_i64().unwrap_or(-1);
-                        if n == -1 || n as usize != i {
-                            continue;
-                        }
-                        stage.extra.fixed_len = extra["timer"].as_f64().unwrap_or_default() as f32;
+            })
+            .collect()
+    }
+
+    // Paths are stored relative to the project file, so projects can be shared between machines
+    // The file is replaced only once the whole project is serialized, a failing save leaves the
+    // previous version in place
+    pub fn write(&mut self, path: PathBuf) -> Result<(), SlsbError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let root = self.animation_root.take();
+        self.animation_root = root.as_ref().map(|root| portable_path(root, dir));
+        prune_library(self);
+        // scenes edited outside of save_scene, e.g. by localizing their name, are caught up here
+        for scene in self.scenes.values_mut() {
+            scene.update_fingerprint();
+        }
+        let result = match (self.pretty_save, self.stage_library.is_empty()) {
+            (false, true) => serde_json::to_vec(self),
+            // maps of a json value are sorted by key, which orders scenes by their id. Going
+            // through a string keeps floats as short as they are in the compact format
+            (pretty, _) => serde_json::to_string(self)
+                .and_then(|json| serde_json::from_str::<serde_json::Value>(&json))
+                .and_then(|mut value| {
+                    compact_shared_stages(&mut value, self);
+                    match pretty {
+                        true => serde_json::to_vec_pretty(&value),
+                        false => serde_json::to_vec(&value),
                     }
-                }
+                }),
+        };
+        self.animation_root = root;
+        let bytes = result?;
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        if let Err(e) = fs::write(&temp, bytes).and_then(|_| fs::rename(&temp, &path)) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        self.mark_saved();
+        info!(target: "project", path:? = path; "Saved project {}", self.pack_name);
+        Ok(())
+    }
+
+    // Move all scenes of other into this project, keeping this project's meta data
+    pub fn merge(&mut self, other: Package) -> () {
+        for (id, scene) in other.scenes {
+            if self.scenes.contains_key(&id) {
+                warn!(
+                    target: "project", scene = id.0.as_str();
+                    "Skipping duplicate Scene {} in merge",
+                    scene.name
+                );
+                continue;
             }
-            let last = scene.stages.last_mut().unwrap();
-            for position in &mut last.positions {
-                position.extra.climax = true;
-            }
-            // build graph
-            scene.root = scene.stages[0].id.clone();
-            let mut prev_id: Option<NanoID> = None;
-            for stage in scene.stages.iter_mut().rev() {
-                let mut value = Node::default();
-                if let Some(id) = prev_id {
-                    value.dest = vec![id];
-                }
-                scene.graph.insert(stage.id.clone(), value);
-                prev_id = Some(stage.id.clone());
+            self.scenes.insert(id, scene);
+        }
+        self.conversions.extend(other.conversions);
+    }
+
+    // Convert a slal file picked by the user. The current project is left alone, so the caller
+    // decides when to replace it
+    pub fn load_slal(app: &tauri::AppHandle) -> Result<Package, SlsbError> {
+        let path = app
+            .dialog()
+            .file()
+            .set_title("Load SLAL File")
+            .add_filter("SLAL.json", &["json"])
+            .blocking_pick_file()
+            .ok_or(SlsbError::DialogCancelled(Message::new("dialog.load_slal")))?
+            .into_path()
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+
+        let on_progress = |progress: SlalProgress| {
+            if let Err(err) = app.emit("on_slal_progress", progress) {
+                warn!(target: "slal", "Unable to report progress, event failure: {}", err);
             }
-            // add to prjct
-            prjct.scenes.insert(scene.id.clone(), scene);
+        };
+        Package::from_slal_with_progress(path, &on_progress)
+    }
+
+    pub fn from_slal(path: PathBuf) -> Result<Package, SlsbError> {
+        Package::from_slal_with_progress(path, &|_| {})
+    }
+
+    pub fn from_slal_with_progress(
+        path: PathBuf,
+        on_progress: &(dyn Fn(SlalProgress) + Sync),
+    ) -> Result<Package, SlsbError> {
+        let aliases = SexAliases::load(path.parent().unwrap_or(Path::new("")))?;
+        let mut batches = SlalBatches {
+            aliases: &aliases,
+            on_progress,
+            converted: AtomicUsize::new(0),
+            bytes_read: 0,
+            file_size: fs::metadata(&path)?.len(),
+            batch: Vec::with_capacity(SLAL_BATCH),
+            scenes: Ok(vec![]),
+        };
+        let (name, has_animations) = read_slal(&path, |animation, bytes_read| {
+            batches.push(animation, bytes_read)
+        })?;
+        let name = name.ok_or(Message::new("slal.missing_attribute").arg("attribute", "name"))?;
+        if !has_animations {
+            return Err(Message::new("slal.missing_attribute")
+                .arg("attribute", "animations")
+                .into());
+        }
+        let mut prjct = Package::from_slal_scenes(name, batches.finish()?)?;
+        // credit the file the scenes were converted from
+        let source = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        for scene in prjct.scenes.values_mut() {
+            scene.source_pack = source.clone();
         }
-        println!(
-            "Loaded {} Animations from {}",
-            prjct.scenes.len(),
-            path.to_str().unwrap_or_default()
+        for record in &mut prjct.conversions {
+            record.source = path.file_name().map(|f| f.to_string_lossy().into_owned());
+        }
+        info!(
+            target: "slal", path:? = path;
+            "Loaded {} Animations",
+            prjct.scenes.len()
         );
-        prjct.update_to_latest_version()?;
         Ok(prjct)
     }
 
-    pub fn export(&self, app: &tauri::AppHandle) -> Result<(), std::io::Error> {
+    // Add the animations of a pasted SLAL snippet, either a single entry copied out of the
+    // animations of a pack or a list of them, returning the ids of the new scenes
+    pub fn import_slal_snippet(&mut self, text: &str) -> Result<Vec<NanoID>, SlsbError> {
+        // entries copied out of a list tend to take their trailing comma along
+        let text = text.trim().trim_end_matches(',').trim_end();
+        let animations = match serde_json::from_str(text)? {
+            serde_json::Value::Array(animations) => animations,
+            animation @ serde_json::Value::Object(_) => vec![animation],
+            _ => vec![],
+        };
+        if animations.is_empty() || animations.iter().any(|a| !a["actors"].is_array()) {
+            return Err(Message::new("slal.invalid_snippet").into());
+        }
+        let slal = serde_json::json!({ "name": self.pack_name, "animations": animations });
+        let mut imported = Package::from_slal_value(&slal)?;
+        let mut record = imported.conversions.pop().unwrap();
+        record.source = Some("clipboard".into());
+        let mut ret = vec![];
+        for conversion in &mut record.scenes {
+            let mut scene = imported.scenes.remove(&conversion.scene).unwrap();
+            self.assign_ids(&mut scene);
+            conversion.scene = scene.id.clone();
+            ret.push(scene.id.clone());
+            self.save_scene(scene, false)?;
+        }
+        info!(target: "slal", "Pasted {} Animations", ret.len());
+        self.conversions.push(record);
+        Ok(ret)
+    }
+
+    pub fn from_slal_value(slal: &serde_json::Value) -> Result<Package, SlsbError> {
+        Package::from_slal_aliased(slal, &SexAliases::default(), &|_| {})
+    }
+
+    // Convert a parsed SLAL file. Malformed files are rejected as a whole
+    pub fn from_slal_aliased(
+        slal: &serde_json::Value,
+        aliases: &SexAliases,
+        on_progress: &(dyn Fn(SlalProgress) + Sync),
+    ) -> Result<Package, SlsbError> {
+        let name = slal["name"]
+            .as_str()
+            .ok_or(Message::new("slal.missing_attribute").arg("attribute", "name"))?;
+        let anims = slal["animations"]
+            .as_array()
+            .ok_or(Message::new("slal.missing_attribute").arg("attribute", "animations"))?;
+        let anims = anims
+            .iter()
+            .map(SlalAnimation::deserialize)
+            .collect::<Result<Vec<_>, _>>()?;
+        let progress = SlalProgress {
+            converted: 0,
+            total: Some(anims.len()),
+            bytes_read: 0,
+            file_size: 0,
+        };
+        let converted = AtomicUsize::new(0);
+        let scenes = slal_scenes(&anims, aliases, &converted, progress, on_progress)?;
+        Package::from_slal_scenes(name.into(), scenes)
+    }
+
+    fn from_slal_scenes(
+        pack_name: String,
+        scenes: Vec<(Scene, SceneConversion)>,
+    ) -> Result<Package, SlsbError> {
+        let mut prjct = Package::new();
+        prjct.version = 0; // SLAL files are always version 0
+        let mut record = ConversionRecord::new(ConversionKind::Slal, 0, VERSION);
+        prjct.pack_name = pack_name;
+        let mut ids = HashSet::new();
+        for (mut scene, mut conversion) in scenes {
+            claim_ids(&mut scene, &mut ids);
+            conversion.scene = scene.id.clone();
+            record.scenes.push(conversion);
+            prjct.scenes.insert(scene.id.clone(), scene);
+        }
+        record.migrations = prjct.migrate()?;
+        // actors are only known once the scenes are migrated
+        for scene in prjct.scenes.values_mut() {
+            scene.archetype = Some(scene.detect_archetype());
+        }
+        prjct.conversions.push(record);
+        Ok(prjct)
+    }
+
+    pub fn export(&mut self, app: &tauri::AppHandle) -> Result<BuildSummary, SlsbError> {
         let path = app
             .dialog()
             .file()
             .set_title("Export Project")
             .set_file_name(&self.pack_name)
             .blocking_pick_folder()
-            .ok_or(std::io::Error::from(ErrorKind::NotFound))?
+            .ok_or(SlsbError::DialogCancelled(Message::new("dialog.export")))?
             .into_path()
             .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
 
         self.build(path)
     }
 
-    pub fn build(&self, root_dir: PathBuf) -> Result<(), std::io::Error> {
-        println!("Compiling project {}", self.pack_name);
-        self.write_binary_file(&root_dir)?;
-        self.write_fnis_files(&root_dir)?;
+    // Compile the project into the given folder, logging the process into a file next to it
+    pub fn build(&mut self, root_dir: PathBuf) -> Result<BuildSummary, SlsbError> {
+        let dir = root_dir.clone();
+        with_build_log(&dir, || self.compile(root_dir))
+    }
+
+    fn compile(&mut self, root_dir: PathBuf) -> Result<BuildSummary, SlsbError> {
+        info!(target: "export", path:? = root_dir; "Compiling project {}", self.pack_name);
+        let mut profile = BuildProfile::default();
+        let started = Instant::now();
+        self.validate_export()?;
+        profile.record("validate", started);
+        let excluded = self.excluded_scenes();
+        for scene in &excluded {
+            warn!(
+                target: "export", scene = scene.name.as_str(), reason:? = scene.reason;
+                "Scene is left out of the export"
+            );
+        }
+        let previous = Manifest::read(&root_dir);
+        let release = make_release(self, previous.as_ref());
+        let start = Instant::now();
+        let lists = self.fnis_lists(&root_dir)?;
+        profile.record("fnis", start);
+        // files the export overwrites, checked before any of them is written
+        let mut targets: Vec<PathBuf> = self
+            .registries()
+            .into_iter()
+            .map(|(file_name, _)| registry_dir(&root_dir).join(file_name))
+            .chain(lists.keys().cloned())
+            .collect();
+        for file in previous.iter().flat_map(|previous| &previous.files) {
+            targets.push(root_dir.join(file.split('/').collect::<PathBuf>()));
+        }
+        check_export_targets(&targets)?;
+        let mut files = self.write_binary_file(&root_dir, &mut profile)?;
+        let (lists, shared_lists) = self.write_fnis_lists(lists, &mut profile)?;
+        files.extend(lists);
+        let start = Instant::now();
+        if previous.is_none() || !release.changelog.is_empty() {
+            release.changelog.write(&root_dir)?;
+        }
+        let version = release.changelog.version.to_string();
+        Credits {
+            pack_version: version.clone(),
+            ..Credits::from_package(self)
+        }
+        .write(&root_dir)?;
+        Dependencies {
+            pack_version: version,
+            ..Dependencies::from_package(self)
+        }
+        .write(&root_dir)?;
+        let translations = SceneTranslations::file_path(self, &root_dir);
+        files.extend(SceneTranslations::from_package(self).write(&translations)?);
+        files.extend(write_mcm_translations(self, &root_dir)?);
+        if self.export_previews {
+            files.extend(write_previews(self, &root_dir)?);
+        }
+        let mut manifest = Manifest::from_release(self, &release);
+        manifest.files = files
+            .iter()
+            .filter_map(|file| manifest_path(file, &root_dir))
+            .collect();
+        // lists of races sharing a folder may be written more than once
+        manifest.files.sort();
+        manifest.files.dedup();
+        if let Some(previous) = &previous {
+            self.clean_stale_files(previous, &mut manifest, &shared_lists, &root_dir)?;
+        }
+        manifest.files.sort();
+        manifest.write(&root_dir)?;
+        release.apply(self);
+        profile.record("io", start);
+        profile.total_ms = millis(started);
+        let summary = BuildSummary {
+            scenes: self.exported_scenes().count(),
+            excluded,
+            distribution: manifest.distribution.clone(),
+            profile: self.profile_export.then_some(profile),
+        };
+        for (race, scenes) in &summary.distribution.races {
+            info!(target: "export", race = race.as_str(), scenes = *scenes; "Scenes per race");
+        }
+        for (tag, scenes) in &summary.distribution.tags {
+            info!(target: "export", tag = tag.as_str(), scenes = *scenes; "Scenes per tag");
+        }
+        for timing in summary.profile.iter().flat_map(|profile| &profile.phases) {
+            info!(
+                target: "export", phase = timing.phase.as_str(), ms = timing.ms;
+                "Phase took {:.1}ms",
+                timing.ms
+            );
+        }
+        if !summary.excluded.is_empty() {
+            warn!(
+                target: "export", excluded = summary.excluded.len();
+                "Left out {} of {} scenes",
+                summary.excluded.len(),
+                self.scenes.len()
+            );
+        }
         info!(
+            target: "export", version:% = self.pack_version;
             "Successfully compiled {}",
-            root_dir.to_str().unwrap_or_default()
+            self.pack_name
         );
+        Ok(summary)
+    }
+
+    // Add the preset, replacing the one of the same name
+    pub fn save_export_preset(&mut self, mut preset: ExportPreset) -> Result<(), SlsbError> {
+        preset.name = preset.name.trim().to_string();
+        if preset.name.is_empty() {
+            return Err(Message::new("export.preset_name").into());
+        }
+        match self
+            .export_presets
+            .iter_mut()
+            .find(|p| p.name == preset.name)
+        {
+            Some(existing) => *existing = preset,
+            None => self.export_presets.push(preset),
+        }
+        Ok(())
+    }
+
+    pub fn remove_export_preset(&mut self, name: &str) -> Result<(), SlsbError> {
+        let count = self.export_presets.len();
+        self.export_presets.retain(|preset| preset.name != name);
+        if self.export_presets.len() == count {
+            return Err(Message::new("export.unknown_preset")
+                .arg("preset", name)
+                .into());
+        }
+        Ok(())
+    }
+
+    // Build with the settings of the named preset, leaving those of the project untouched
+    pub fn build_preset(&mut self, name: &str) -> Result<BuildSummary, SlsbError> {
+        let preset = self
+            .export_presets
+            .iter()
+            .find(|preset| preset.name == name)
+            .cloned()
+            .ok_or_else(|| Message::new("export.unknown_preset").arg("preset", name))?;
+        let bundles = self.bundles.clone();
+        let settings = (self.export_previews, self.clean_export, self.merge_fnis);
+        if let Some(selected) = &preset.bundles {
+            self.bundles
+                .retain(|bundle| selected.contains(&bundle.name));
+        }
+        self.export_previews = preset.export_previews;
+        self.clean_export = preset.clean_export;
+        self.merge_fnis = preset.merge_fnis;
+        info!(target: "export", preset = name; "Building preset");
+        let ret = self.build(preset.out);
+        self.bundles = bundles;
+        (self.export_previews, self.clean_export, self.merge_fnis) = settings;
+        ret
+    }
+
+    // Remove files of the previous export which are no longer written, if enabled. Otherwise
+    // they are kept in the manifest to be reported again on the next export. FNIS lists shared
+    // with other packs are still written but no longer owned, so they are never stale
+    fn clean_stale_files(
+        &self,
+        previous: &Manifest,
+        manifest: &mut Manifest,
+        shared_lists: &[PathBuf],
+        root_dir: &Path,
+    ) -> Result<(), SlsbError> {
+        for path in stale_files(previous, manifest, root_dir) {
+            if shared_lists.contains(&path) {
+                continue;
+            }
+            if self.clean_export {
+                fs::remove_file(long_path(&path)).map_err(|err| export_error(err, &path))?;
+                info!(target: "export", path:? = path; "Removed stale file of a previous export");
+            } else {
+                warn!(
+                    target: "export", path:? = path;
+                    "Stale file of a previous export, it may register animations twice"
+                );
+                manifest.files.extend(manifest_path(&path, root_dir));
+            }
+        }
         Ok(())
     }
 
-    pub fn import_offset(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
+    pub fn import_offset(
+        &mut self,
+        app: &tauri::AppHandle,
+        matching: OffsetMatching,
+    ) -> Result<OffsetImportReport, SlsbError> {
         let path = app
             .dialog()
             .file()
             .set_title("Import Offsets")
             .add_filter("Offset File", &["yaml", "yml"])
             .blocking_pick_file()
-            .ok_or("No path to load offsets from".to_string())?
+            .ok_or(SlsbError::DialogCancelled(Message::new(
+                "dialog.load_offsets",
+            )))?
+            .into_path()
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        import_offset_file(self, &path, matching)
+    }
+
+    // Add the scenes of a stage sheet, returning their ids
+    pub fn import_csv(&mut self, app: &tauri::AppHandle) -> Result<Vec<NanoID>, SlsbError> {
+        let path = app
+            .dialog()
+            .file()
+            .set_title("Import Stage Sheet")
+            .add_filter("CSV", &["csv"])
+            .blocking_pick_file()
+            .ok_or(SlsbError::DialogCancelled(Message::new("dialog.load_csv")))?
             .into_path()
-            .map_err(|e| e.to_string())?;
-        let file = fs::File::open(&path).map_err(|e| e.to_string())?;
-        let offsetfile: serde_yaml::Mapping =
-            serde_yaml::from_reader(BufReader::new(file)).map_err(|e| e.to_string())?;
+            .map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        let scenes = import_scenes(&fs::read_to_string(&path)?)?;
+        let mut ret = vec![];
+        for mut scene in scenes {
+            self.assign_ids(&mut scene);
+            ret.push(scene.id.clone());
+            self.save_scene(scene, false)?;
+        }
+        Ok(ret)
+    }
+
+    // Scenes which are written into the binary, ordered by id so exports are reproducible
+    pub fn exported_scenes(&self) -> impl Iterator<Item = &Scene> {
+        let mut scenes: Vec<&Scene> = self
+            .scenes
+            .values()
+            .filter(|scene| exclusion_reason(scene).is_none())
+            .collect();
+        scenes.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        scenes.into_iter()
+    }
+
+    // Scenes which are not written into the binary, ordered by name
+    pub fn excluded_scenes(&self) -> Vec<ExcludedScene> {
+        let mut ret: Vec<ExcludedScene> = self
+            .scenes
+            .values()
+            .filter_map(|scene| {
+                exclusion_reason(scene).map(|reason| ExcludedScene {
+                    id: scene.id.clone(),
+                    name: scene.name.clone(),
+                    reason,
+                })
+            })
+            .collect();
+        ret.sort_by_key(|scene| scene.name.to_lowercase());
+        ret
+    }
+
+    fn registry(&self) -> Registry<'_> {
+        Registry {
+            package: self,
+            scenes: self.exported_scenes().collect(),
+        }
+    }
 
-        for (scene_id_v, stages_v) in offsetfile {
-            if !stages_v.is_mapping() {
+    // Name of the main registry file without extension, bundles append their name to it
+    pub fn export_stem(&self) -> String {
+        let stem = [self.export_name.as_deref(), Some(self.pack_name.as_str())]
+            .into_iter()
+            .flatten()
+            .find(|name| !name.trim().is_empty())
+            .unwrap_or(&self.prefix_hash.0);
+        sanitize_file_name(stem)
+    }
+
+    // Name of the behaviour folders of the pack, which its FNIS lists are named after as well
+    pub fn behaviour_name(&self) -> String {
+        let name = sanitize_file_name(&self.pack_name);
+        match name.is_empty() {
+            true => self.prefix_hash.0.clone(),
+            false => name,
+        }
+    }
+
+    pub fn export_extension(&self) -> String {
+        self.export_extension
+            .as_deref()
+            .map(|ext| sanitize_file_name(ext.trim_start_matches('.')))
+            .filter(|ext| !ext.is_empty())
+            .unwrap_or_else(|| "slr".into())
+    }
+
+    // The registry files written on export, by file name: one per bundle and one for all scenes
+    // not contained in a bundle. The latter is omitted if bundles take up all scenes
+    pub fn registries(&self) -> Vec<(String, Registry<'_>)> {
+        let stem = self.export_stem();
+        let extension = self.export_extension();
+        let main_name = format!("{}.{}", stem, extension);
+        let mut main = vec![];
+        let mut bundles = vec![vec![]; self.bundles.len()];
+        for scene in self.exported_scenes() {
+            match bundle_of(&self.bundles, scene) {
+                Some(i) => bundles[i].push(scene),
+                None => main.push(scene),
+            }
+        }
+        let mut ret = vec![];
+        if !main.is_empty() || bundles.iter().all(|scenes| scenes.is_empty()) {
+            ret.push((main_name, Registry::new(self, main)));
+        }
+        for (bundle, scenes) in self.bundles.iter().zip(bundles) {
+            if !scenes.is_empty() {
+                ret.push((
+                    bundle.file_name(&stem, &extension),
+                    Registry::new(self, scenes),
+                ));
+            }
+        }
+        ret
+    }
+
+    // The .slr binary of all exported scenes
+    pub fn encode(&self) -> Vec<u8> {
+        self.registry().encode()
+    }
+
+    // Machine readable description of the .slr layout produced by build()
+    pub fn format_spec() -> serde_json::Value {
+        serde_json::json!({
+            "format": "slr",
+            "version": VERSION,
+            "byte_order": "big_endian",
+            "root": Package::layout(),
+        })
+    }
+
+    // Projects without a name are named after their file
+    fn set_project_name_from_path(&mut self, path: &PathBuf) -> () {
+        if self.pack_name.trim().is_empty() {
+            self.pack_name = project_name_from_path(path).unwrap_or_default();
+        }
+    }
+
+    // Clean up scene names for display and suffix duplicates with a counter. The first scene of a
+    // name keeps it, locked scenes taking precedence and never being renamed
+    pub fn make_scene_names_unique(&mut self) -> Vec<SceneRename> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for scene in self.scenes.values() {
+            *counts
+                .entry(display_name(&scene.name).to_lowercase())
+                .or_default() += 1;
+        }
+        let mut taken: HashSet<String> = counts
+            .iter()
+            .filter(|(_, count)| **count == 1)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut ids: Vec<NanoID> = self.scenes.keys().cloned().collect();
+        ids.sort_by_key(|id| (!self.scenes[id].locked, id.clone()));
+
+        let mut ret = vec![];
+        for id in ids {
+            let scene = self.scenes.get_mut(&id).unwrap();
+            let base = display_name(&scene.name);
+            if scene.locked || base.is_empty() {
+                taken.insert(base.to_lowercase());
                 continue;
             }
-            let scene_id = scene_id_v
-                .as_str()
-                .ok_or("Not a valid offset file, expected string for scene id".to_string())?
-                .to_string();
-            if let Some(scene) = self.get_scene_mut(&NanoID(scene_id.clone())) {
-                scene.import_offset(
-                    stages_v
-                        .as_mapping()
-                        .ok_or(format!("Expected mapping in scene {}", scene_id))?,
-                )?;
+            let mut name = base.clone();
+            if counts[&base.to_lowercase()] > 1 {
+                let mut n = 2;
+                while !taken.insert(name.to_lowercase()) {
+                    name = format!("{} ({})", base, n);
+                    n += 1;
+                }
+            }
+            if name == scene.name {
+                continue;
             }
+            let from = std::mem::replace(&mut scene.name, name.clone());
+            self.audit.record(
+                "rename_scene",
+                Some(&id),
+                vec![],
+                Some(format!("Renamed scene from {} to {}", from, name)),
+            );
+            ret.push(SceneRename {
+                scene: id,
+                from,
+                to: name,
+            });
         }
+        if !ret.is_empty() {
+            info!(target: "project", "Renamed {} scenes to unique names", ret.len());
+        }
+        ret
+    }
 
+    pub fn rename(&mut self, name: &str) -> Result<(), SlsbError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(Message::new("project.empty_name").into());
+        }
+        if name != self.pack_name {
+            let details = format!("Renamed project from {} to {}", self.pack_name, name);
+            self.pack_name = name.to_string();
+            self.audit
+                .record("rename_project", None, vec![], Some(details));
+        }
         Ok(())
     }
 
-    fn set_project_name_from_path(&mut self, path: &PathBuf) -> () {
-        self.pack_name = String::from(
-            path.file_name() // ...\\{project.slsb.json}
-                .and_then(|name| name.to_str())
-                .and_then(|str| {
-                    let ret = &str[0..str.find(".slsb.json").unwrap_or(str.len())];
-                    Some(ret)
-                })
-                .unwrap_or_default(),
-        );
+    // Catch inconsistencies the frontend does not flag, which would otherwise abort the export halfway
+    fn validate_export(&self) -> Result<(), SlsbError> {
+        self.exported_scenes()
+            .flat_map(|scene| validate_scene(scene, Some(self)))
+            .chain(validate_bundles(self))
+            .chain(validate_export_name(self))
+            .chain(validate_anim_objects(self))
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+            .map_or(Ok(()), |diagnostic| Err(diagnostic.message.into()))
     }
 
-    fn write_binary_file(&self, root_dir: &PathBuf) -> Result<(), std::io::Error> {
-        let target_dir = root_dir.join("SKSE\\SexLab\\Registry\\");
-        let project_name = format!(
-            "{}.slr",
-            if self.pack_name.is_empty() {
-                &self.prefix_hash.0
-            } else {
-                &self.pack_name
+    fn write_binary_file(
+        &self,
+        root_dir: &Path,
+        profile: &mut BuildProfile,
+    ) -> Result<Vec<PathBuf>, SlsbError> {
+        let target_dir = registry_dir(root_dir);
+        create_export_dir(&target_dir)?;
+        let mut ret = vec![];
+        for (file_name, registry) in self.registries() {
+            let start = Instant::now();
+            let size = registry.get_byte_size();
+            profile.record("byte_size", start);
+            let start = Instant::now();
+            let bytes = registry.encode_sized(size);
+            profile.record("encode", start);
+            info!(
+                target: "export", path:? = target_dir.join(&file_name), size = bytes.len();
+                "Writing binary file with {} scenes",
+                registry.scenes.len()
+            );
+            let start = Instant::now();
+            let path = target_dir.join(file_name);
+            create_export_file(&path)?
+                .write_all(&bytes)
+                .map_err(|err| export_error(err, &path))?;
+            profile.record("io", start);
+            ret.push(path);
+        }
+        Ok(ret)
+    }
+
+    // Behaviour folder of a race, overridden by the project or the given scene position
+    pub fn behaviour_folder(
+        &self,
+        race: &str,
+        info: Option<&PositionInfo>,
+    ) -> Result<String, SlsbError> {
+        if let Some(folder) = info.and_then(|info| info.behaviour_folder.as_deref()) {
+            return normalize_behaviour_folder(folder);
+        }
+        let folder = self.behaviour_folders.get(race).or_else(|| {
+            get_race_variant_base(race).and_then(|base| self.behaviour_folders.get(base))
+        });
+        match folder {
+            Some(folder) => Ok(folder.clone()),
+            None => map_race_to_folder(race).map_err(|_| SlsbError::UnknownRace(race.into())),
+        }
+    }
+
+    // Override the behaviour folder of a race, or restore its default if folder is None or empty
+    pub fn set_behaviour_folder(
+        &mut self,
+        race: &str,
+        folder: Option<&str>,
+    ) -> Result<(), SlsbError> {
+        if !get_race_keys_string().iter().any(|key| key == race) {
+            return Err(SlsbError::UnknownRace(race.into()));
+        }
+        match folder.map(str::trim).filter(|folder| !folder.is_empty()) {
+            Some(folder) => {
+                let folder = normalize_behaviour_folder(folder)?;
+                info!(target: "project", race = race; "Behaviour folder set to {}", folder);
+                self.behaviour_folders.insert(race.into(), folder);
             }
-        );
-        let mut buf: Vec<u8> = Vec::new();
-        buf.reserve(self.get_byte_size());
-        info!(
-            "Writing binary file for project {} with size {} at {}",
-            project_name,
-            buf.capacity(),
-            target_dir.to_str().unwrap_or("Unknown path")
-        );
-        self.write_byte(&mut buf);
-        fs::create_dir_all(&target_dir)?;
-        fs::File::create(target_dir.join(project_name))?.write(&buf)?;
+            None => {
+                self.behaviour_folders.remove(race);
+            }
+        }
         Ok(())
     }
 
-    fn write_fnis_files(&self, root_dir: &PathBuf) -> Result<(), std::io::Error> {
-        let mut events: HashMap<&str, Vec<String>> = HashMap::new(); // map<RaceKey, Lines[]>
-        let mut control: HashSet<&str> = HashSet::from(["__BLANK__", "__DEFAULT__"]);
-        for (_, scene) in &self.scenes {
-            if scene.has_warnings {
-                continue;
+    // File name of the FNIS list of the race in the given behaviour folder. Patterns set per race
+    // may use {pack}, {race} and {folder}, e.g. "FNIS_{pack}_{race}_List.txt"
+    pub fn fnis_list_name(&self, race: &str, folder: &str) -> Result<String, SlsbError> {
+        let pattern = match self.fnis_list_names.get(race) {
+            Some(pattern) => pattern.as_str(),
+            None => default_list_pattern(race, folder),
+        };
+        render_list_name(pattern, &self.behaviour_name(), race, folder)
+    }
+
+    pub fn set_fnis_list_name(
+        &mut self,
+        race: &str,
+        pattern: Option<&str>,
+    ) -> Result<(), SlsbError> {
+        if !get_race_keys_string().iter().any(|key| key == race) {
+            return Err(SlsbError::UnknownRace(race.into()));
+        }
+        match pattern.map(str::trim).filter(|pattern| !pattern.is_empty()) {
+            Some(pattern) => {
+                render_list_name(pattern, "Pack", race, "folder")?;
+                info!(target: "project", race = race; "FNIS list name set to {}", pattern);
+                self.fnis_list_names.insert(race.into(), pattern.into());
             }
-            assert_eq!(
-                scene
-                    .stages
-                    .first()
-                    .expect(&format!("Scene {} has 0 Stages", scene.id.0))
-                    .positions
-                    .len(),
-                scene.positions.len()
-            );
+            None => {
+                self.fnis_list_names.remove(race);
+            }
+        }
+        Ok(())
+    }
+
+    // Name an animation object of the project is exported with, e.g. 1a2b_AnimObjLute if objects
+    // are prefixed with the pack prefix
+    pub fn exported_anim_object(&self, obj: &str) -> String {
+        if self.prefix_anim_objects {
+            format!("{}_{}", self.prefix_hash.0, obj)
+        } else {
+            obj.to_string()
+        }
+    }
+
+    // Write the FNIS lists of every race, returning the files written
+    pub fn write_fnis_files(&self, root_dir: &Path) -> Result<Vec<PathBuf>, SlsbError> {
+        let lists = self.fnis_lists(root_dir)?;
+        Ok(self
+            .write_fnis_lists(lists, &mut BuildProfile::default())?
+            .0)
+    }
+
+    // Lines of the FNIS list of every race by the path of the list
+    fn fnis_lists(
+        &self,
+        root_dir: &Path,
+    ) -> Result<BTreeMap<PathBuf, (&str, Vec<String>)>, SlsbError> {
+        // map<(RaceKey, Position Folder), Lines[]>
+        let mut events: HashMap<(&str, Option<&str>), Vec<String>> = HashMap::new();
+        let mut control: HashSet<&str> = HashSet::from(["__BLANK__", "__DEFAULT__"]);
+        for scene in self.exported_scenes() {
             for stage in &scene.stages {
                 for i in 0..stage.positions.len() {
                     let stage_position = &stage.positions[i];
                     let scene_position = &scene.positions[i];
-                    let event = &stage_position.event[0];
-                    if control.contains(event.as_str()) {
+                    let anim_obj = stage_position
+                        .anim_obj
+                        .split(',')
+                        .fold(vec![], |mut acc, x| {
+                            if !x.is_empty() {
+                                acc.push(self.exported_anim_object(x));
+                            }
+                            acc
+                        });
+                    let fixed_len = stage.extra.fixed_len > 0.0;
+                    let mut lines = vec![];
+                    if control.insert(&stage_position.event[0]) {
+                        lines = make_fnis_lines(
+                            &stage_position.event,
+                            &self.prefix_hash.0,
+                            fixed_len,
+                            &anim_obj,
+                        );
+                    }
+                    // variants stand in for the first event and are listed as basic animations
+                    for variant in &stage_position.variants {
+                        if control.insert(variant) {
+                            lines.extend(make_fnis_lines(
+                                &vec![variant.clone()],
+                                &self.prefix_hash.0,
+                                fixed_len,
+                                &anim_obj,
+                            ));
+                        }
+                    }
+                    if lines.is_empty() {
                         continue;
                     }
-                    control.insert(event);
-                    let lines = make_fnis_lines(
-                        &stage_position.event,
-                        &self.prefix_hash.0,
-                        stage.extra.fixed_len > 0.0,
-                        &stage_position
-                            .anim_obj
-                            .split(',')
-                            .fold(vec![], |mut acc, x| {
-                                if !x.is_empty() {
-                                    acc.push(x.to_string());
-                                }
-                                acc
-                            }),
-                    );
-                    let mut insert = |race| {
+                    let mut insert = |race, folder| {
                         events
-                            .entry(race)
+                            .entry((race, folder))
                             .and_modify(|list| list.append(&mut lines.clone()))
                             .or_insert(lines.clone());
                     };
                     let race = scene_position.race.as_str();
+                    // variants are listed with their base race unless given a folder of their own
+                    let race = match get_race_variant_base(race) {
+                        Some(base) if !self.behaviour_folders.contains_key(race) => base,
+                        _ => race,
+                    };
+                    // positions with their own folder are not shared with related races
+                    if let Some(folder) = scene_position.behaviour_folder.as_deref() {
+                        insert(race, Some(folder));
+                        continue;
+                    }
                     match race {
                         "Canine" => {
-                            insert(&race);
-                            insert("Dog");
-                            insert("Wolf");
+                            insert(race, None);
+                            insert("Dog", None);
+                            insert("Wolf", None);
                         }
                         "Dog" | "Wolf" => {
-                            insert(&race);
-                            insert("Canine");
+                            insert(race, None);
+                            insert("Canine", None);
                         }
-                        "Chaurus" | "Chaurus Reaper" => insert("Chaurus"),
-                        "Spider" | "Large Spider" | "Giant Spider" => insert("Spider"),
-                        "Boar" | "Boar (Mounted)" | "Boar (Any)" => insert("Boar (Any)"),
-                        _ => insert(&race),
+                        "Chaurus" | "Chaurus Reaper" => insert("Chaurus", None),
+                        "Spider" | "Large Spider" | "Giant Spider" => insert("Spider", None),
+                        "Boar" | "Boar (Mounted)" | "Boar (Any)" => insert("Boar (Any)", None),
+                        _ => insert(race, None),
                     }
                 }
             }
         }
-        info!("---------------------------------------------------------");
-        for (racekey, anim_events) in events {
-            let target_folder = map_race_to_folder(racekey)
-                .expect(format!("Cannot find folder for RaceKey {}", racekey).as_str());
-            let path = root_dir.join(format!(
-                "meshes\\actors\\{}\\animations\\{}",
-                target_folder, self.pack_name
-            ));
-            let crt = &target_folder[target_folder
-                .find('\\')
-                .and_then(|w| Some(w + 1))
-                .unwrap_or(0)..];
-            fs::create_dir_all(&path)?;
-
-            let create = |file_path: PathBuf| -> Result<(), std::io::Error> {
-                let name = file_path.to_str().unwrap_or("NONE".into()).to_string();
-                let file = fs::File::create(file_path)?;
-                let mut file = BufWriter::new(file);
-                info!(
-                    "Adding {} lines to race {} |||||| file: {}",
-                    anim_events.len(),
-                    racekey,
-                    name
-                );
-                for anim_event in anim_events {
-                    writeln!(file, "{}", anim_event)?;
-                }
-                Ok(())
+        // races may share a list, e.g. through a custom name, in which case their lines are merged
+        let mut keys: Vec<(&str, Option<&str>)> = events.keys().cloned().collect();
+        keys.sort();
+        let mut lists: BTreeMap<PathBuf, (&str, Vec<String>)> = BTreeMap::new();
+        for key in keys {
+            let (racekey, folder) = key;
+            let target_folder = match folder {
+                Some(folder) => normalize_behaviour_folder(folder)?,
+                None => self.behaviour_folder(racekey, None)?,
             };
-            match crt {
-                "character" => create(path.join(format!("FNIS_{}_List.txt", self.pack_name))),
-                "canine" => match racekey {
-                    "Canine" => {
-                        create(path.join(format!("FNIS_{}_canine_List.txt", self.pack_name)))
-                    }
-                    "Dog" => create(path.join(format!("FNIS_{}_dog_List.txt", self.pack_name))),
-                    _ => create(path.join(format!("FNIS_{}_wolf_List.txt", self.pack_name))),
-                },
-                _ => create(path.join(format!("FNIS_{}_{}_List.txt", self.pack_name, crt))),
-            }?;
+            let path = behaviour_dir(root_dir, &target_folder, &self.behaviour_name());
+            let crt = target_folder.rsplit('\\').next().unwrap_or_default();
+            let file_path = path.join(self.fnis_list_name(racekey, crt)?);
+            let (_, lines) = lists.entry(file_path).or_insert((racekey, vec![]));
+            for line in events.remove(&key).unwrap_or_default() {
+                if !lines.contains(&line) {
+                    lines.push(line);
+                }
+            }
         }
-        info!("---------------------------------------------------------");
-        Ok(())
+        Ok(lists)
+    }
+
+    // Write the FNIS lists, returning the lists owned by this pack and the lists merged with
+    // the lists of other packs
+    fn write_fnis_lists(
+        &self,
+        lists: BTreeMap<PathBuf, (&str, Vec<String>)>,
+        profile: &mut BuildProfile,
+    ) -> Result<(Vec<PathBuf>, Vec<PathBuf>), SlsbError> {
+        let start = Instant::now();
+        let (mut ret, mut shared_lists) = (vec![], vec![]);
+        for (file_path, (racekey, anim_events)) in lists {
+            if let Some(dir) = file_path.parent() {
+                create_export_dir(dir)?;
+            }
+            let mut lines = anim_events.clone();
+            // lists shared with other packs are not listed as files of this export
+            let mut shared = false;
+            if self.merge_fnis && long_path(&file_path).is_file() {
+                let existing = fs::read_to_string(long_path(&file_path))?;
+                let (merged, duplicates) =
+                    merge_fnis_lines(&existing, &anim_events, &self.prefix_hash.0);
+                for event in &duplicates {
+                    warn!(
+                        target: "export", event = event.as_str(), path:? = file_path;
+                        "Skipping event already defined by another pack"
+                    );
+                }
+                shared = merged.len() + duplicates.len() > anim_events.len();
+                lines = merged;
+            }
+            info!(
+                target: "export", race = racekey, path:? = file_path;
+                "Writing {} FNIS lines",
+                lines.len()
+            );
+            let file = create_export_file(&file_path)?;
+            let mut file = BufWriter::new(file);
+            for line in lines {
+                writeln!(file, "{}", line).map_err(|err| export_error(err, &file_path))?;
+            }
+            file.flush().map_err(|err| export_error(err, &file_path))?;
+            if shared {
+                shared_lists.push(file_path);
+            } else {
+                ret.push(file_path);
+            }
+        }
+        profile.record("io", start);
+        Ok((ret, shared_lists))
     }
 }
 
-impl EncodeBinary for Package {
+// The path relative to dir, using forward slashes. Paths on another drive are kept absolute
+fn portable_path(path: &Path, dir: &Path) -> PathBuf {
+    let dir = fs::canonicalize(dir).unwrap_or(dir.to_path_buf());
+    let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    let mut base = dir.components().peekable();
+    let mut rest = path.components().peekable();
+    if base.peek() != rest.peek() || !path.is_absolute() {
+        return path;
+    }
+    while base.peek().is_some() && base.peek() == rest.peek() {
+        base.next();
+        rest.next();
+    }
+    let parts: Vec<String> = base
+        .map(|_| "..".to_string())
+        .chain(rest.map(|c| c.as_os_str().to_string_lossy().into_owned()))
+        .collect();
+    if parts.is_empty() {
+        ".".into()
+    } else {
+        parts.join("/").into()
+    }
+}
+
+// Names FNIS has always been given lists under, canines sharing a folder with one list per race
+fn default_list_pattern(race: &str, folder: &str) -> &'static str {
+    match (folder, race) {
+        ("character", _) => "FNIS_{pack}_List.txt",
+        ("canine", "Canine") => "FNIS_{pack}_canine_List.txt",
+        ("canine", "Dog") => "FNIS_{pack}_dog_List.txt",
+        ("canine", _) => "FNIS_{pack}_wolf_List.txt",
+        _ => "FNIS_{pack}_{folder}_List.txt",
+    }
+}
+
+// FNIS only reads lists named "FNIS_*_List.txt" from the behaviour folder itself
+fn render_list_name(
+    pattern: &str,
+    pack: &str,
+    race: &str,
+    folder: &str,
+) -> Result<String, SlsbError> {
+    let race: String = race
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    let name = pattern
+        .replace("{pack}", pack)
+        .replace("{race}", &race)
+        .replace("{folder}", folder);
+    let lower = name.to_lowercase();
+    if !lower.starts_with("fnis_")
+        || !lower.ends_with("_list.txt")
+        || name.contains(['{', '}'])
+        || sanitize_file_name(&name) != name
+    {
+        return Err(Message::new("export.invalid_list_name")
+            .arg("name", name)
+            .into());
+    }
+    Ok(name)
+}
+
+// Output folders are joined component wise, so exports work on any platform
+pub fn registry_dir(root_dir: &Path) -> PathBuf {
+    root_dir.join("SKSE").join("SexLab").join("Registry")
+}
+
+// The folder of the FNIS lists of a pack. Race folders are given in game notation, e.g. "dlc02\scrib"
+pub fn behaviour_dir(root_dir: &Path, race_folder: &str, pack_name: &str) -> PathBuf {
+    let mut ret = root_dir.join("meshes").join("actors");
+    ret.extend(race_folder.split('\\').filter(|part| !part.is_empty()));
+    ret.join("animations").join(pack_name)
+}
+
+// "Pack.slsb.json" -> "Pack". Files without the project suffix are named after their stem
+pub fn project_name_from_path(path: &Path) -> Option<String> {
+    const SUFFIX: &str = ".slsb.json";
+    let name = path.file_name()?.to_string_lossy();
+    let split = name.len().checked_sub(SUFFIX.len());
+    let stem = match split.and_then(|i| name.get(i..).map(|suffix| (i, suffix))) {
+        Some((i, suffix)) if suffix.eq_ignore_ascii_case(SUFFIX) => name[..i].to_string(),
+        _ => path.file_stem()?.to_string_lossy().into_owned(),
+    };
+    Some(stem.trim().to_string()).filter(|stem| !stem.is_empty())
+}
+
+// Replace characters which are not allowed in Windows file names, so the export does not fail
+// halfway. Reserved device names such as "CON" are suffixed with an underscore
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut ret: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    ret.truncate(ret.trim_end_matches(['.', ' ']).len());
+    let upper = ret.split('.').next().unwrap_or_default().to_uppercase();
+    let reserved = matches!(upper.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || (upper.len() == 4
+            && (upper.starts_with("COM") || upper.starts_with("LPT"))
+            && upper.ends_with(|c: char| c.is_ascii_digit() && c != '0'));
+    if reserved {
+        ret.push('_');
+    }
+    ret
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SceneRename {
+    pub scene: NanoID,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ReadableIds {
+    // number of scene and stage ids replaced
+    pub changed: usize,
+    // scenes holding new ids or transitions to renamed scenes, and the old ids of renamed scenes
+    pub scenes: BTreeSet<NanoID>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExclusionReason {
+    // flagged with warnings which the author did not suppress
+    Flagged,
+    NoStages,
+}
+
+pub(crate) fn exclusion_reason(scene: &Scene) -> Option<ExclusionReason> {
+    if scene.is_flagged() {
+        Some(ExclusionReason::Flagged)
+    } else if scene.stages.is_empty() {
+        Some(ExclusionReason::NoStages)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ExcludedScene {
+    pub id: NanoID,
+    pub name: String,
+    pub reason: ExclusionReason,
+}
+
+// Named settings to build with, e.g. a release build and a test build into a mod manager
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct ExportPreset {
+    pub name: String,
+    pub out: PathBuf,
+    // bundles written into registries of their own, the scenes of other bundles are written into
+    // the main registry. All bundles if None
+    #[serde(default)]
+    pub bundles: Option<Vec<String>>,
+    #[serde(default)]
+    pub export_previews: bool,
+    #[serde(default)]
+    pub clean_export: bool,
+    #[serde(default)]
+    pub merge_fnis: bool,
+}
+
+// A discarded scene and when it was discarded, in seconds since unix epoch
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct TrashedScene {
+    pub timestamp: u64,
+    pub scene: Scene,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TrashSummary {
+    pub timestamp: u64,
+    pub scene: SceneSummary,
+}
+
+// Outcome of a build, emitted to the frontend as "on_build_finished"
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct BuildSummary {
+    pub scenes: usize,
+    pub excluded: Vec<ExcludedScene>,
+    pub distribution: Distribution,
+    // only collected if the project enables profile_export
+    pub profile: Option<BuildProfile>,
+}
+
+// Time spent in each phase of a build, in the order the phases first ran. Reading the FNIS
+// lists of other packs counts as io
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct BuildProfile {
+    pub phases: Vec<PhaseTiming>,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub ms: f64,
+}
+
+impl BuildProfile {
+    // Add the time passed since start to the phase
+    fn record(&mut self, phase: &str, start: Instant) {
+        let ms = millis(start);
+        match self.phases.iter_mut().find(|timing| timing.phase == phase) {
+            Some(timing) => timing.ms += ms,
+            None => self.phases.push(PhaseTiming {
+                phase: phase.into(),
+                ms,
+            }),
+        }
+    }
+}
+
+fn millis(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct UnsavedScenes {
+    pub added: Vec<NanoID>,
+    pub changed: Vec<NanoID>,
+    pub removed: Vec<NanoID>,
+}
+
+impl UnsavedScenes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ScenePage {
+    pub scenes: Vec<SceneSummary>,
+    // number of scenes in the project, across all pages
+    pub total: usize,
+    pub offset: usize,
+}
+
+// Shared stage table of a registry, and the index of each stage in it by scene and stage
+#[derive(Debug, Default)]
+struct SharedStages<'a> {
+    stages: Vec<&'a Stage>,
+    places: HashMap<NanoID, HashMap<NanoID, u32>>,
+}
+
+impl SharedStages<'_> {
+    fn contains(&self, scene: &NanoID, stage: &NanoID) -> bool {
+        self.places
+            .get(scene)
+            .is_some_and(|places| places.contains_key(stage))
+    }
+}
+
+// A set of scenes written into a single .slr file, along with the meta data of their package
+#[derive(Debug)]
+pub struct Registry<'a> {
+    pub package: &'a Package,
+    pub scenes: Vec<&'a Scene>,
+}
+
+impl<'a> Registry<'a> {
+    pub fn new(package: &'a Package, scenes: Vec<&'a Scene>) -> Self {
+        Self { package, scenes }
+    }
+
+    // Strings referenced by index from the binary, shared across all scenes of the registry
+    fn string_table(&self) -> StringTable {
+        let mut table = StringTable::default();
+        self.scenes
+            .iter()
+            .for_each(|scene| scene.intern_strings(&mut table));
+        table
+    }
+
+    // Stages of the library several scenes of the registry use unchanged. They are written once
+    // and the scenes refer to them by their index
+    fn shared_stages(&self) -> SharedStages<'a> {
+        let mut users: BTreeMap<&NanoID, Vec<&NanoID>> = BTreeMap::new();
+        for scene in &self.scenes {
+            for stage in &scene.stages {
+                let shared = self.package.stage_library.get(&stage.id);
+                if shared.is_some_and(|shared| same_stage(shared, stage)) {
+                    users.entry(&stage.id).or_default().push(&scene.id);
+                }
+            }
+        }
+        let mut ret = SharedStages::default();
+        for (id, scenes) in users.into_iter().filter(|(_, scenes)| scenes.len() > 1) {
+            let index = ret.stages.len() as u32;
+            ret.stages.push(&self.package.stage_library[id]);
+            for scene in scenes {
+                ret.places
+                    .entry(scene.clone())
+                    .or_default()
+                    .insert(id.clone(), index);
+            }
+        }
+        ret
+    }
+
+    // Sections written into the registry, each only if there is data for it: the string table if
+    // there are strings to intern, furniture and offset rotations if a scene deviates from the
+    // defaults, transitions, event variants, shared stages and requirements if a scene has any
+    fn capabilities(&self, strings: &StringTable, shared: &SharedStages) -> Capabilities {
+        let mut ret = Capabilities::empty();
+        if !strings.is_empty() {
+            ret |= Capabilities::STRING_TABLE;
+        }
+        if !shared.stages.is_empty() {
+            ret |= Capabilities::SHARED_STAGES;
+        }
+        let furniture = self
+            .scenes
+            .iter()
+            .any(|scene| !scene.furniture.is_default());
+        if furniture {
+            ret |= Capabilities::FURNITURE;
+        }
+        if self
+            .offsets(furniture, shared)
+            .any(|offset| offset.is_tilted())
+        {
+            ret |= Capabilities::OFFSETS_V2;
+        }
+        if self
+            .scenes
+            .iter()
+            .any(|scene| !scene.transitions.is_empty())
+        {
+            ret |= Capabilities::TRANSITIONS;
+        }
+        if self
+            .positions(shared)
+            .any(|position| !position.variants.is_empty())
+        {
+            ret |= Capabilities::EVENT_VARIANTS;
+        }
+        if self
+            .scenes
+            .iter()
+            .any(|scene| !scene.encoded_requirements().is_empty())
+        {
+            ret |= Capabilities::REQUIREMENTS;
+        }
+        ret
+    }
+
+    // Positions written into the registry, those of shared stages once
+    fn positions<'b>(&'b self, shared: &'b SharedStages) -> impl Iterator<Item = &'b Position> {
+        self.scenes
+            .iter()
+            .flat_map(move |scene| {
+                scene
+                    .stages
+                    .iter()
+                    .filter(move |stage| !shared.contains(&scene.id, &stage.id))
+            })
+            .chain(shared.stages.iter().copied())
+            .flat_map(|stage| &stage.positions)
+    }
+
+    // Offsets written into the registry, those of the furniture only if it is written
+    fn offsets<'b>(
+        &'b self,
+        furniture: bool,
+        shared: &'b SharedStages,
+    ) -> impl Iterator<Item = &'b Offset> {
+        let furniture = self
+            .scenes
+            .iter()
+            .filter(move |_| furniture)
+            .map(|scene| &scene.furniture.offset);
+        self.positions(shared)
+            .map(|position| &position.offset)
+            .chain(furniture)
+    }
+
+    // 0.0.0 if the package does not declare one
+    fn min_framework_version(&self) -> PackVersion {
+        self.package
+            .min_framework_version
+            .unwrap_or(PackVersion::new(0, 0, 0))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_sized(self.get_byte_size())
+    }
+
+    // Encode into a buffer of the size returned by get_byte_size
+    fn encode_sized(&self, size: usize) -> Vec<u8> {
+        let mut buf = ByteWriter::with_capacity(size);
+        self.write_byte(&mut buf);
+        buf.into_bytes()
+    }
+}
+
+impl EncodeBinary for Registry<'_> {
     fn get_byte_size(&self) -> usize {
-        self.version.get_byte_size()
-            + self.pack_name.get_byte_size()
-            + self.pack_author.get_byte_size()
-            + self.prefix_hash.get_byte_size()
+        let strings = self.string_table();
+        let shared = self.shared_stages();
+        let capabilities = self.capabilities(&strings, &shared);
+        let strings_size = if capabilities.contains(Capabilities::STRING_TABLE) {
+            strings.get_byte_size()
+        } else {
+            0
+        };
+        let transitions_size = if capabilities.contains(Capabilities::TRANSITIONS) {
+            self.scenes
+                .iter()
+                .map(|scene| scene.transitions.get_byte_size())
+                .sum()
+        } else {
+            0
+        };
+        let requirements_size = if capabilities.contains(Capabilities::REQUIREMENTS) {
+            self.scenes
+                .iter()
+                .map(|scene| scene.encoded_requirements().get_byte_size())
+                .sum()
+        } else {
+            0
+        };
+        let variants_size = if capabilities.contains(Capabilities::EVENT_VARIANTS) {
+            self.positions(&shared)
+                .map(|position| interned_list_size(position.variants.len()))
+                .sum()
+        } else {
+            0
+        };
+        let furniture = capabilities.contains(Capabilities::FURNITURE);
+        let furniture_size = if furniture {
+            self.scenes
+                .iter()
+                .map(|scene| scene.furniture.get_byte_size())
+                .sum()
+        } else {
+            0
+        };
+        let rotations_size = if capabilities.contains(Capabilities::OFFSETS_V2) {
+            self.offsets(furniture, &shared).count() * 2 * size_of::<i32>()
+        } else {
+            0
+        };
+        // the table and the references of every scene, which replace the copies of the stages
+        let (shared_size, replaced_size) = if capabilities.contains(Capabilities::SHARED_STAGES) {
+            let references = self.scenes.iter().map(|scene| {
+                let count = shared
+                    .places
+                    .get(&scene.id)
+                    .map_or(0, |places| places.len());
+                size_of::<u32>() + count * 2 * size_of::<u32>()
+            });
+            let replaced = self.scenes.iter().flat_map(|scene| {
+                scene
+                    .stages
+                    .iter()
+                    .filter(|stage| shared.contains(&scene.id, &stage.id))
+                    .map(|stage| stage.get_byte_size())
+            });
+            let table = size_of::<u32>()
+                + shared
+                    .stages
+                    .iter()
+                    .map(|stage| stage.get_byte_size())
+                    .sum::<usize>();
+            (table + references.sum::<usize>(), replaced.sum())
+        } else {
+            (0, 0)
+        };
+        self.package.version.get_byte_size()
+            + size_of::<u32>() // capabilities
+            + self.package.pack_name.get_byte_size()
+            + self.package.pack_author.get_byte_size()
+            + self.package.prefix_hash.get_byte_size()
+            + self.package.stage_timers.get_byte_size()
+            + self.min_framework_version().get_byte_size()
+            + strings_size
+            + transitions_size
+            + requirements_size
+            + variants_size
+            + furniture_size
+            + rotations_size
+            + shared_size
             + self
                 .scenes
                 .iter()
-                .filter(|(_, scene)| !scene.has_warnings && !scene.stages.is_empty())
-                .fold(size_of::<u32>(), |acc, (_, scene)| {
-                    acc + scene.get_byte_size()
-                })
+                .fold(size_of::<u32>(), |acc, scene| acc + scene.get_byte_size())
+            - replaced_size
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
-        self.version.write_byte(buf);
-        self.pack_name.write_byte(buf);
-        self.pack_author.write_byte(buf);
-        self.prefix_hash.write_byte(buf);
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        let strings = self.string_table();
+        let shared = self.shared_stages();
+        let capabilities = self.capabilities(&strings, &shared);
+        self.package.version.write_byte(buf);
+        capabilities.bits().write_byte(buf);
+        self.package.pack_name.write_byte(buf);
+        self.package.pack_author.write_byte(buf);
+        self.package.prefix_hash.write_byte(buf);
+        self.package.stage_timers.write_byte(buf);
+        self.min_framework_version().write_byte(buf);
+        if capabilities.contains(Capabilities::STRING_TABLE) {
+            strings.write_byte(buf);
+        }
+        buf.set_strings(strings);
+        buf.set_capabilities(capabilities);
+        if capabilities.contains(Capabilities::SHARED_STAGES) {
+            (shared.stages.len() as u32).write_byte(buf);
+            shared.stages.iter().for_each(|stage| stage.write_byte(buf));
+            buf.set_shared_stages(shared.places);
+        }
         buf.extend_from_slice(&(self.scenes.len() as u32).to_be_bytes());
-        self.scenes
-            .iter()
-            .filter(|(_, scene)| !scene.has_warnings && !scene.stages.is_empty())
-            .for_each(|(_, scene)| scene.write_byte(buf));
+        self.scenes.iter().for_each(|scene| scene.write_byte(buf));
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Package",
+            vec![
+                ("version", u8::layout()),
+                ("capabilities", Capabilities::layout()),
+                ("pack_name", String::layout()),
+                ("pack_author", String::layout()),
+                (
+                    "prefix_hash",
+                    Layout::Id {
+                        len: NanoID::PREFIX_HASH_LEN,
+                    },
+                ),
+                ("stage_timers", StageTimers::layout()),
+                ("min_framework_version", PackVersion::layout()),
+                (
+                    "strings",
+                    Layout::gated(Capabilities::STRING_TABLE, StringTable::layout()),
+                ),
+                (
+                    "shared_stages",
+                    Layout::gated(Capabilities::SHARED_STAGES, Vec::<Stage>::layout()),
+                ),
+                ("scenes", Vec::<Scene>::layout()),
+            ],
+        )
+    }
+}
+
+// The package as a whole encodes into a single registry of all exported scenes
+impl EncodeBinary for Package {
+    fn get_byte_size(&self) -> usize {
+        self.registry().get_byte_size()
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        self.registry().write_byte(buf);
+    }
+
+    fn layout() -> Layout {
+        Registry::layout()
     }
 }
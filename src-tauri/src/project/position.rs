@@ -1,14 +1,24 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::mem::size_of;
 
-use super::serialize::{deserialize_vec_or_string, EncodeBinary};
-use crate::project::{
-    define::{Offset, Sex, Stripping},
-    position_info::PositionInfo,
+use super::serialize::{
+    deserialize_vec_or_string, interned_list_size, ByteWriter, Capabilities, EncodeBinary, Layout,
+    StringTable,
+};
+use crate::{
+    error::SlsbError,
+    i18n::Message,
+    project::{
+        define::{Offset, Sex, Stripping},
+        position_info::PositionInfo,
+    },
 };
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct Position {
     #[serde(deserialize_with = "deserialize_vec_or_string")]
+    #[schemars(schema_with = "crate::project::schema::string_or_list")]
     pub event: Vec<String>,
     pub anim_obj: String,
     pub offset: Offset,
@@ -17,11 +27,20 @@ pub struct Position {
     pub climax: bool,
     #[serde(default)] // addition 2.0
     pub tags: Vec<String>,
+    // stage behaviour carried over from slal, not part of the registry
+    #[serde(default)] // addition 2.2
+    pub open_mouth: bool,
+    #[serde(default)] // addition 2.2
+    pub silent: bool,
+    // schlong bend, -9 to 9
+    #[serde(default)] // addition 2.2
+    pub schlong: i8,
+    // events the runtime may play instead of the first event, picked at random
+    #[serde(default)] // addition 2.2
+    pub variants: Vec<String>,
 
     // Unused fields, but kept for compatibility
     #[serde(skip_serializing, default)]
-    pub schlong: i8,
-    #[serde(skip_serializing, default)]
     pub extra: Extra,
     #[serde(skip_serializing, default)]
     pub sex: Sex,
@@ -40,41 +59,85 @@ impl Position {
             strip_data: reference.map_or_else(|| Stripping::default(), |p| p.strip_data.clone()),
             climax: false,
             tags: Default::default(),
+            open_mouth: false,
+            silent: false,
+            schlong: Default::default(),
+            variants: Default::default(),
             // Unused fields
             sex: Default::default(),
             race: "Human".into(),
-            schlong: Default::default(),
             extra: Default::default(),
             scale: 1.0,
         }
     }
 
-    pub fn import_offset(&mut self, yaml_obj: &serde_yaml::Mapping) -> Result<(), String> {
-        let loc = yaml_obj[&"Location".into()]
-            .as_sequence()
-            .ok_or("Location is not a sequence")?
+    // Animation objects of the comma separated list, e.g. "AnimObjA,AnimObjB"
+    pub fn anim_objects(&self) -> Vec<&str> {
+        self.anim_obj
+            .split(',')
+            .map(str::trim)
+            .filter(|obj| !obj.is_empty())
+            .collect()
+    }
+
+    pub fn import_offset(&mut self, yaml_obj: &serde_yaml::Mapping) -> Result<(), SlsbError> {
+        let loc = yaml_obj
+            .get(&"Location".into())
+            .and_then(|loc| loc.as_sequence())
+            .ok_or(Message::new("offset.location_type"))?
             .iter()
             .filter_map(|it| it.as_f64())
+            .filter(|it| it.is_finite())
             .collect::<Vec<_>>();
         if loc.len() != 3 {
-            return Err(format!(
-                "Invalid location vector, expected length 3 but got {}",
-                loc.len()
-            ));
+            return Err(Message::new("offset.location_length")
+                .arg("got", loc.len())
+                .into());
+        }
+        // a single angle rotates around the z axis, offset files v2 may list all three axes
+        let rotation = yaml_obj
+            .get(&"Rotation".into())
+            .ok_or(Message::new("offset.rotation_type"))?;
+        let rot: Vec<f64> = match rotation.as_f64() {
+            Some(z) => vec![0.0, 0.0, z],
+            None => rotation
+                .as_sequence()
+                .ok_or(Message::new("offset.rotation_type"))?
+                .iter()
+                .filter_map(|it| it.as_f64())
+                .collect(),
+        }
+        .into_iter()
+        .filter(|it| it.is_finite())
+        .collect();
+        if rot.len() != 3 {
+            return Err(Message::new("offset.rotation_length")
+                .arg("got", rot.len())
+                .into());
         }
-        let rot = yaml_obj[&"Rotation".into()]
-            .as_f64()
-            .ok_or("Rotation is not a float")?;
 
         self.offset.x = loc[0] as f32;
         self.offset.y = loc[1] as f32;
         self.offset.z = loc[2] as f32;
-        self.offset.r = rot as f32;
+        self.offset.rx = rot[0] as f32;
+        self.offset.ry = rot[1] as f32;
+        self.offset.r = rot[2] as f32;
 
         Ok(())
     }
 
-    pub fn update_to_latest_version(&mut self, old_version: u8) -> Result<(), String> {
+    pub fn export_offset(&self) -> serde_yaml::Mapping {
+        let offset = &self.offset;
+        let mut ret = serde_yaml::Mapping::new();
+        ret.insert("Location".into(), vec![offset.x, offset.y, offset.z].into());
+        ret.insert(
+            "Rotation".into(),
+            vec![offset.rx, offset.ry, offset.r].into(),
+        );
+        ret
+    }
+
+    pub fn update_to_latest_version(&mut self, old_version: u8) -> Result<(), SlsbError> {
         if old_version <= 3 {
             self.climax = self.extra.climax;
             self.tags = self.extra.custom.clone();
@@ -82,6 +145,20 @@ impl Position {
         Ok(())
     }
 
+    // The first event and its variants, any of which may start the position
+    pub fn event_pool(&self) -> impl Iterator<Item = &String> {
+        self.event.iter().take(1).chain(&self.variants)
+    }
+
+    pub fn intern_strings(&self, table: &mut StringTable) -> () {
+        for event in self.event_pool() {
+            table.intern(event);
+        }
+        for tag in &self.tags {
+            table.intern(tag);
+        }
+    }
+
     pub fn extract_position_info(&self) -> PositionInfo {
         PositionInfo {
             sex: self.sex.clone(),
@@ -90,6 +167,7 @@ impl Position {
             submissive: self.extra.submissive,
             vampire: self.extra.vampire,
             dead: self.extra.dead,
+            behaviour_folder: None,
         }
     }
 }
@@ -97,25 +175,73 @@ impl Position {
 impl EncodeBinary for Position {
     fn get_byte_size(&self) -> usize {
         assert!(!self.event.is_empty(), "Event list should not be empty");
-        self.event.first().map_or(0, |e| e.get_byte_size())
+        size_of::<u32>() // interned event
             + self.climax.get_byte_size()
             + self.offset.get_byte_size()
             + self.strip_data.get_byte_size()
-            + self.tags.get_byte_size()
+            + interned_list_size(self.tags.len())
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         // Only save initial event, all others are called by Havok
         assert!(!self.event.is_empty(), "Event list should not be empty");
-        self.event.first().unwrap().write_byte(buf);
+        buf.write_interned(self.event.first().unwrap());
         self.climax.write_byte(buf);
         self.offset.write_byte(buf);
         self.strip_data.write_byte(buf);
-        self.tags.write_byte(buf);
+        buf.write_interned_list(&self.tags);
+        // only registries announcing variants carry them, the registry accounts for their size
+        if buf.capabilities().contains(Capabilities::EVENT_VARIANTS) {
+            buf.write_interned_list(&self.variants);
+        }
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Position",
+            vec![
+                ("event", Layout::Interned),
+                ("climax", bool::layout()),
+                ("offset", Offset::layout()),
+                ("strip_data", Stripping::layout()),
+                (
+                    "tags",
+                    Layout::List {
+                        item: Box::new(Layout::Interned),
+                    },
+                ),
+                (
+                    "variants",
+                    Layout::gated(
+                        Capabilities::EVENT_VARIANTS,
+                        Layout::List {
+                            item: Box::new(Layout::Interned),
+                        },
+                    ),
+                ),
+            ],
+        )
+    }
+}
+
+// Positions are equal if they save the same, the unused fields are left out
+impl PartialEq for Position {
+    fn eq(&self, other: &Self) -> bool {
+        self.event == other.event
+            && self.anim_obj == other.anim_obj
+            && self.offset == other.offset
+            && self.strip_data == other.strip_data
+            && self.climax == other.climax
+            && self.tags == other.tags
+            && self.open_mouth == other.open_mouth
+            && self.silent == other.silent
+            && self.schlong == other.schlong
+            && self.variants == other.variants
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[schemars(rename = "PositionExtra")]
 pub struct Extra {
     pub submissive: bool,
     pub vampire: bool,
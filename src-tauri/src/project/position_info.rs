@@ -1,10 +1,11 @@
-use super::serialize::EncodeBinary;
+use super::serialize::{ByteWriter, EncodeBinary, Layout};
 use crate::project::define::Sex;
-use crate::racekeys::get_race_key_bytes;
+use crate::racekeys::{get_race_key_bytes, get_race_key_variants};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::mem::size_of;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
 pub struct PositionInfo {
     pub sex: Sex,
     pub race: String,
@@ -12,17 +13,21 @@ pub struct PositionInfo {
     pub submissive: bool,
     pub vampire: bool,
     pub dead: bool,
+    // folder below meshes\actors the animations are listed in instead of the one of the race,
+    // for custom creatures with their own behaviour files
+    #[serde(default)] // addition 2.2
+    pub behaviour_folder: Option<String>,
 }
 
 impl EncodeBinary for PositionInfo {
     fn get_byte_size(&self) -> usize {
+        size_of::<u8>() + // race key
         self.sex.get_byte_size() +
-        self.race.get_byte_size() +
-        size_of::<bool>() * 3 + // submissive, vampire, dead
-        size_of::<f32>() // scale
+        self.scale.get_byte_size() +
+        size_of::<u8>() // submissive, vampire, dead
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         buf.push(get_race_key_bytes(&self.race).unwrap());
         self.sex.write_byte(buf);
         self.scale.write_byte(buf);
@@ -32,6 +37,34 @@ impl EncodeBinary for PositionInfo {
                 + (1 << 2) * self.dead as u8,
         );
     }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "PositionInfo",
+            vec![
+                (
+                    "race",
+                    Layout::Enum {
+                        width: size_of::<u8>(),
+                        variants: get_race_key_variants(),
+                    },
+                ),
+                ("sex", Sex::layout()),
+                ("scale", f32::layout()),
+                (
+                    "extra",
+                    Layout::flags(
+                        size_of::<u8>(),
+                        &[
+                            ("submissive", 1 << 0),
+                            ("vampire", 1 << 1),
+                            ("dead", 1 << 2),
+                        ],
+                    ),
+                ),
+            ],
+        )
+    }
 }
 
 impl Default for PositionInfo {
@@ -43,6 +76,7 @@ impl Default for PositionInfo {
             submissive: false,
             vampire: false,
             dead: false,
+            behaviour_folder: None,
         }
     }
 }
@@ -0,0 +1,171 @@
+use log::{info, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    export_path::{create_export_dir, export_error, long_path},
+    package::{sanitize_file_name, Package},
+    NanoID,
+};
+
+pub const PREVIEW_DIR: &str = "previews";
+pub const PREVIEW_EXTENSIONS: [&str; 2] = ["gif", "webm"];
+// Generated schematics are kept apart from the clips and not exported
+pub const THUMBNAIL_EXTENSION: &str = "svg";
+
+// Folder next to the project file holding its previews, named after the scene they show
+pub fn previews_dir(package: &Package) -> Result<PathBuf, SlsbError> {
+    if !package.pack_path.is_file() {
+        return Err(Message::new("preview.unsaved_project").into());
+    }
+    let dir = package.pack_path.parent().unwrap_or(Path::new(""));
+    Ok(dir.join(PREVIEW_DIR))
+}
+
+// Export folder of the previews of a pack, its name made safe to use as a folder name
+pub fn preview_dir_of(root_dir: &Path, pack_name: &str) -> PathBuf {
+    root_dir
+        .join("SKSE")
+        .join("SexLab")
+        .join("Previews")
+        .join(sanitize_file_name(pack_name))
+}
+
+fn preview_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .filter(|ext| PREVIEW_EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn find_in(dir: &Path, scene: &NanoID) -> Option<PathBuf> {
+    PREVIEW_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", scene.0, ext)))
+        .find(|path| path.is_file())
+}
+
+pub fn find_preview(package: &Package, scene: &NanoID) -> Option<PathBuf> {
+    find_in(&previews_dir(package).ok()?, scene)
+}
+
+pub fn thumbnail_path(package: &Package, scene: &NanoID) -> Result<PathBuf, SlsbError> {
+    let dir = previews_dir(package)?;
+    Ok(dir.join(format!("{}.{}", scene.0, THUMBNAIL_EXTENSION)))
+}
+
+fn is_thumbnail(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(THUMBNAIL_EXTENSION))
+}
+
+// Copy a clip into the preview folder, replacing the previous preview of the scene
+pub fn attach_preview(
+    package: &Package,
+    scene: &NanoID,
+    source: &Path,
+) -> Result<PathBuf, SlsbError> {
+    if !package.scenes.contains_key(scene) {
+        return Err(Message::new("scene.invalid_id")
+            .arg("scene", &scene.0)
+            .into());
+    }
+    let Some(ext) = preview_extension(source) else {
+        return Err(Message::new("preview.unsupported_format")
+            .arg("path", source.display())
+            .into());
+    };
+    let dir = previews_dir(package)?;
+    fs::create_dir_all(&dir)?;
+    let target = dir.join(format!("{}.{}", scene.0, ext));
+    if let Some(previous) = find_in(&dir, scene).filter(|p| p != &target) {
+        fs::remove_file(previous)?;
+    }
+    fs::copy(source, &target)?;
+    info!(target: "project", scene = scene.0.as_str(), path:? = target; "Attached preview");
+    Ok(target)
+}
+
+pub fn remove_preview(package: &Package, scene: &NanoID) -> Result<bool, SlsbError> {
+    let dir = previews_dir(package)?;
+    let Some(path) = find_in(&dir, scene) else {
+        return Ok(false);
+    };
+    fs::remove_file(path)?;
+    Ok(true)
+}
+
+// Move the preview of a scene whose id changed, returns whether there was one. Its thumbnail is
+// moved along
+pub fn rename_preview(package: &Package, old: &NanoID, new: &NanoID) -> Result<bool, SlsbError> {
+    let dir = previews_dir(package)?;
+    let thumbnail = thumbnail_path(package, old)?;
+    if thumbnail.is_file() {
+        fs::rename(&thumbnail, thumbnail_path(package, new)?)?;
+    }
+    let Some(path) = find_in(&dir, old) else {
+        return Ok(false);
+    };
+    let ext = preview_extension(&path).unwrap_or_default();
+    fs::rename(&path, dir.join(format!("{}.{}", new.0, ext)))?;
+    Ok(true)
+}
+
+// Previews whose scene no longer exists, deleting them unless dry_run is set
+pub fn clean_previews(package: &Package, dry_run: bool) -> Result<Vec<PathBuf>, SlsbError> {
+    let dir = previews_dir(package)?;
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut ret = vec![];
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if !path.is_file() || (preview_extension(&path).is_none() && !is_thumbnail(&path)) {
+            continue;
+        }
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        if package.scenes.contains_key(&NanoID(stem.into())) {
+            continue;
+        }
+        if !dry_run {
+            fs::remove_file(&path)?;
+        }
+        ret.push(path);
+    }
+    ret.sort();
+    info!(
+        target: "project", dry_run = dry_run;
+        "Found {} orphaned previews",
+        ret.len()
+    );
+    Ok(ret)
+}
+
+// Copy the previews of all exported scenes into the export folder, returning the copies
+pub fn write_previews(package: &Package, root_dir: &Path) -> Result<Vec<PathBuf>, SlsbError> {
+    let Ok(dir) = previews_dir(package) else {
+        warn!(target: "export", "Project has not been saved, skipping previews");
+        return Ok(vec![]);
+    };
+    let target_dir = preview_dir_of(root_dir, &package.pack_name);
+    let mut ret = vec![];
+    for scene in package.exported_scenes() {
+        let Some(source) = find_in(&dir, &scene.id) else {
+            continue;
+        };
+        create_export_dir(&target_dir)?;
+        let target = target_dir.join(source.file_name().unwrap());
+        fs::copy(long_path(&source), long_path(&target))
+            .map_err(|err| export_error(err, &target))?;
+        ret.push(target);
+    }
+    info!(target: "export", path:? = target_dir; "Copied {} previews", ret.len());
+    Ok(ret)
+}
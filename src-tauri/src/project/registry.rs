@@ -0,0 +1,171 @@
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message, racekeys::map_race_to_folder};
+
+use super::{
+    inspect::{inspect_bytes, Inspection},
+    package::{registry_dir, sanitize_file_name, Package},
+    NanoID,
+};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    SceneName,
+    Event,
+    BehaviourFolder,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub registry: PathBuf,
+    pub scene: Option<NanoID>,
+    pub message: Message,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RegistryComparison {
+    pub registries: Vec<PathBuf>,
+    // registries which could not be decoded, with the reason
+    pub skipped: Vec<(PathBuf, Message)>,
+    // a previously installed version of this project, which is not compared against
+    pub own: Option<PathBuf>,
+    pub conflicts: Vec<Conflict>,
+}
+
+// All .slr files in the given folder. A game or mod folder is resolved to its registry folder
+pub fn registry_files(dir: &Path) -> Result<Vec<PathBuf>, SlsbError> {
+    let nested = registry_dir(dir);
+    let dir = if nested.is_dir() {
+        nested
+    } else {
+        dir.to_path_buf()
+    };
+    let mut ret: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "slr"))
+        .collect();
+    ret.sort();
+    Ok(ret)
+}
+
+// Scene names are compared case insensitive, as the game does
+fn scene_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+fn behaviour_folders<'a>(races: impl Iterator<Item = &'a str>, pack_name: &str) -> HashSet<String> {
+    races
+        .filter_map(|race| map_race_to_folder(race).ok())
+        .map(|folder| format!("{}\\{}", folder, sanitize_file_name(pack_name)).to_lowercase())
+        .collect()
+}
+
+pub fn compare_registries(package: &Package, dir: &Path) -> Result<RegistryComparison, SlsbError> {
+    let mut ret = RegistryComparison::default();
+    let registries: Vec<(PathBuf, Inspection)> = registry_files(dir)?
+        .into_iter()
+        .filter_map(|path| {
+            match fs::read(&path)
+                .map_err(SlsbError::from)
+                .and_then(|b| inspect_bytes(&b))
+            {
+                Ok(inspection) => Some((path, inspection)),
+                Err(err) => {
+                    ret.skipped.push((path, err.message()));
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let mut names: HashMap<String, &NanoID> = HashMap::new();
+    let mut events: HashMap<String, &NanoID> = HashMap::new();
+    for scene in package.exported_scenes() {
+        names.insert(scene_key(&scene.name), &scene.id);
+        for position in scene.stages.iter().flat_map(|stage| &stage.positions) {
+            for event in &position.event {
+                events.insert(format!("{}{}", package.prefix_hash.0, event), &scene.id);
+            }
+        }
+    }
+    let folders: HashSet<String> = package
+        .exported_scenes()
+        .flat_map(|scene| &scene.positions)
+        .filter_map(|info| package.behaviour_folder(&info.race, Some(info)).ok())
+        .map(|folder| format!("{}\\{}", folder, package.behaviour_name()).to_lowercase())
+        .collect();
+
+    for (path, registry) in registries {
+        ret.registries.push(path.clone());
+        if registry.prefix_hash == package.prefix_hash.0
+            && registry.pack_name == package.pack_name
+            && registry.pack_author == package.pack_author
+        {
+            ret.own = Some(path);
+            continue;
+        }
+        let mut push = |kind, scene: Option<&NanoID>, message| {
+            ret.conflicts.push(Conflict {
+                kind,
+                registry: path.clone(),
+                scene: scene.cloned(),
+                message,
+            })
+        };
+        let registry_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut seen = HashSet::new();
+        for scene in &registry.scenes {
+            if let Some(id) = names.get(&scene_key(&scene.name)) {
+                push(
+                    ConflictKind::SceneName,
+                    Some(id),
+                    Message::new("registry.scene_name")
+                        .arg("scene", &scene.name)
+                        .arg("registry", &registry_name),
+                );
+            }
+            for position in scene.stages.iter().flat_map(|stage| &stage.positions) {
+                let event = format!("{}{}", registry.prefix_hash, position.event);
+                match events.get(&event) {
+                    Some(id) if seen.insert(event.clone()) => push(
+                        ConflictKind::Event,
+                        Some(id),
+                        Message::new("registry.event")
+                            .arg("event", &event)
+                            .arg("registry", &registry_name),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+        let races: Vec<String> = registry
+            .scenes
+            .iter()
+            .flat_map(|scene| &scene.actors)
+            .filter_map(|actor| actor.race.as_str().map(|race| race.to_string()))
+            .collect();
+        let mut overlap: Vec<String> =
+            behaviour_folders(races.iter().map(|r| r.as_str()), &registry.pack_name)
+                .intersection(&folders)
+                .cloned()
+                .collect();
+        overlap.sort();
+        for folder in overlap {
+            push(
+                ConflictKind::BehaviourFolder,
+                None,
+                Message::new("registry.behaviour_folder")
+                    .arg("folder", folder)
+                    .arg("registry", &registry_name),
+            );
+        }
+    }
+    Ok(ret)
+}
@@ -0,0 +1,169 @@
+use log::info;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::i18n::Message;
+
+use super::{package::Package, scene::Scene, NanoID};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Repair {
+    pub scene: NanoID,
+    pub stage: Option<NanoID>,
+    pub message: Message,
+    // false if the problem has to be resolved by hand
+    pub fixed: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RepairReport {
+    pub repairs: Vec<Repair>,
+}
+
+impl RepairReport {
+    pub fn fixed(&self) -> usize {
+        self.repairs.iter().filter(|r| r.fixed).count()
+    }
+
+    pub fn unresolved(&self) -> usize {
+        self.repairs.len() - self.fixed()
+    }
+}
+
+// Problems of a single scene, fixing those which can be fixed without losing data if apply is set.
+// Each entry is (stage, message, fixable)
+fn repair_scene(
+    scene: &mut Scene,
+    scenes: &HashSet<NanoID>,
+    apply: bool,
+) -> Vec<(Option<NanoID>, Message, bool)> {
+    let mut ret = vec![];
+    let stages: HashSet<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+
+    let mut dangling: Vec<NanoID> = scene
+        .graph
+        .keys()
+        .filter(|id| !stages.contains(id))
+        .cloned()
+        .collect();
+    dangling.sort_by(|a, b| a.0.cmp(&b.0));
+    for id in dangling {
+        ret.push((
+            None,
+            Message::new("repair.dangling_node").arg("stage", &id.0),
+            true,
+        ));
+        if apply {
+            scene.graph.remove(&id);
+        }
+    }
+    for stage in &scene.stages {
+        let Some(node) = scene.graph.get(&stage.id) else {
+            ret.push((
+                Some(stage.id.clone()),
+                Message::new("repair.missing_node").arg("stage", &stage.name),
+                true,
+            ));
+            continue;
+        };
+        for dest in node.dest.iter().filter(|dest| !stages.contains(dest)) {
+            ret.push((
+                Some(stage.id.clone()),
+                Message::new("validate.unknown_dest").arg("dest", &dest.0),
+                true,
+            ));
+        }
+    }
+    if apply {
+        for stage in &scene.stages {
+            let node = scene.graph.entry(stage.id.clone()).or_default();
+            node.dest.retain(|dest| stages.contains(dest));
+        }
+    }
+
+    if !stages.contains(&scene.root) {
+        if let Some(first) = scene.stages.first() {
+            ret.push((
+                None,
+                Message::new("repair.root")
+                    .arg("scene", &scene.name)
+                    .arg("stage", &first.id.0),
+                true,
+            ));
+            if apply {
+                scene.root = first.id.clone();
+            }
+        }
+    }
+
+    let keep = |from: &NanoID, to: &NanoID| stages.contains(from) && scenes.contains(to);
+    for transition in &scene.transitions {
+        if !keep(&transition.from_stage, &transition.to_scene) {
+            ret.push((
+                None,
+                Message::new("repair.transition")
+                    .arg("stage", &transition.from_stage.0)
+                    .arg("scene", &transition.to_scene.0),
+                true,
+            ));
+        }
+    }
+    if apply {
+        scene
+            .transitions
+            .retain(|t| keep(&t.from_stage, &t.to_scene));
+    }
+
+    for stage in &scene.stages {
+        if stage.positions.len() != scene.positions.len() {
+            ret.push((
+                Some(stage.id.clone()),
+                Message::new("export.position_count")
+                    .arg("scene", &scene.name)
+                    .arg("stage", &stage.id.0)
+                    .arg("got", stage.positions.len())
+                    .arg("expected", scene.positions.len()),
+                false,
+            ));
+        }
+    }
+    ret
+}
+
+// Find dangling references between scenes, stages and the graph and fix those which can be fixed
+// safely. Locked scenes and dry runs are only reported
+pub fn repair_project(package: &mut Package, dry_run: bool) -> RepairReport {
+    let scenes: HashSet<NanoID> = package.scenes.keys().cloned().collect();
+    let mut ids: Vec<NanoID> = scenes.iter().cloned().collect();
+    ids.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut report = RepairReport::default();
+    for id in ids {
+        let scene = package.scenes.get_mut(&id).unwrap();
+        let apply = !dry_run && !scene.locked;
+        let found = repair_scene(scene, &scenes, apply);
+        let fixed = found.iter().filter(|(_, _, fixable)| *fixable).count();
+        if apply && fixed > 0 {
+            package.audit.record(
+                "repair_project",
+                Some(&id),
+                vec![],
+                Some(format!("Repaired {} references", fixed)),
+            );
+        }
+        report
+            .repairs
+            .extend(found.into_iter().map(|(stage, message, fixable)| Repair {
+                scene: id.clone(),
+                stage,
+                message,
+                fixed: apply && fixable,
+            }));
+    }
+    info!(
+        target: "project", fixed = report.fixed(), unresolved = report.unresolved();
+        "Checked references of {} scenes",
+        scenes.len()
+    );
+    report
+}
@@ -1,16 +1,27 @@
-use log::warn;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, vec};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    mem::size_of,
+    vec,
+};
+
+use crate::{error::SlsbError, i18n::Message};
 
 use super::{
-    define::{FurnitureData, Node},
+    define::{FurnitureData, Node, Requirements, Transition},
+    graph::{
+        layered_layout, longest_paths, reaching, shortest_paths, PathStatistics, SceneStatistics,
+    },
+    library::{SharedStage, StageReference},
+    manifest::fingerprint,
     position_info::PositionInfo,
-    serialize::EncodeBinary,
-    stage::Stage,
+    serialize::{interned_list_size, ByteWriter, Capabilities, EncodeBinary, Layout, StringTable},
+    stage::{encode_tag, Stage, StageTimers},
     NanoID,
 };
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct Scene {
     pub id: NanoID,
     pub name: String,
@@ -27,9 +38,124 @@ pub struct Scene {
     pub positions: Vec<PositionInfo>,
     #[serde(default)] // addition 1.1
     pub has_warnings: bool,
+    #[serde(default)] // addition 2.1
+    pub transitions: Vec<Transition>,
+    #[serde(default)] // addition 2.1
+    pub locked: bool,
+    #[serde(default)] // addition 2.1
+    pub revision: u32,
+    // credits of scenes combined from several packs
+    #[serde(default)] // addition 2.2
+    pub author: Option<String>,
+    #[serde(default)] // addition 2.2
+    pub source_pack: Option<String>,
+    #[serde(default)] // addition 2.2
+    pub requirements: Requirements,
+    // warnings the author accepts, e.g. a scene without climax
+    #[serde(default)] // addition 2.2
+    pub suppressed_warnings: Vec<SuppressedWarning>,
+    // composition of the actors, detected when the scene was imported
+    #[serde(default)] // addition 2.2
+    pub archetype: Option<Archetype>,
+    // stages of the library within the project file, empty once the project is loaded
+    #[serde(default)] // addition 2.2
+    pub shared_stages: Vec<SharedStage>,
+    // display names by language code, written into the translations next to the registry
+    #[serde(default)] // addition 2.2
+    pub localized_names: BTreeMap<String, String>,
+    // fingerprint of the content as of the last save, for tools telling which scenes changed
+    #[serde(default)] // addition 2.2
+    pub fingerprint: String,
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Archetype {
+    Solo,
+    PairedHuman,
+    HumanCreature,
+    // two creatures without a human
+    PairedCreature,
+    // three or more actors
+    Group,
+}
+
+// Warning the editor flags scenes with, which keeps them out of exports unless suppressed
+pub const FLAGGED_WARNING: &str = "validate.flagged";
+
+// A warning by its message key, for a single stage or the whole scene
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+pub struct SuppressedWarning {
+    pub key: String,
+    #[serde(default)]
+    pub stage: Option<NanoID>,
+}
+
+// What the scene list shows of a scene, cheap to send to the frontend
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SceneSummary {
+    pub id: NanoID,
+    pub name: String,
+    pub actors: usize,
+    pub stages: usize,
+    pub tags: Vec<String>,
+    pub has_warnings: bool,
+    pub locked: bool,
+    pub archetype: Archetype,
 }
 
 impl Scene {
+    pub fn summary(&self) -> SceneSummary {
+        SceneSummary {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            actors: self.positions.len(),
+            stages: self.stages.len(),
+            tags: self.tags.clone(),
+            has_warnings: self.has_warnings,
+            locked: self.locked,
+            archetype: self.effective_archetype(),
+        }
+    }
+
+    pub fn update_fingerprint(&mut self) {
+        self.fingerprint = fingerprint(self);
+    }
+
+    // Archetype the scene was imported as, or that of its actors for scenes made in the editor
+    pub fn effective_archetype(&self) -> Archetype {
+        self.archetype.unwrap_or_else(|| self.detect_archetype())
+    }
+
+    // Archetype of the current actors of the scene
+    pub fn detect_archetype(&self) -> Archetype {
+        let humans = self
+            .positions
+            .iter()
+            .filter(|info| info.race == "Human")
+            .count();
+        match (self.positions.len(), humans) {
+            (0..=1, _) => Archetype::Solo,
+            (2, 2) => Archetype::PairedHuman,
+            (2, 1) => Archetype::HumanCreature,
+            (2, _) => Archetype::PairedCreature,
+            _ => Archetype::Group,
+        }
+    }
+
+    pub fn is_suppressed(&self, key: &str, stage: Option<&NanoID>) -> bool {
+        self.suppressed_warnings
+            .iter()
+            .any(|w| w.key == key && (w.stage.is_none() || w.stage.as_ref() == stage))
+    }
+
+    // Flagged scenes are left out of exports, unless the author suppressed the flag
+    pub fn is_flagged(&self) -> bool {
+        self.has_warnings && !self.is_suppressed(FLAGGED_WARNING, None)
+    }
+
     pub fn get_stage(&self, id: &NanoID) -> Option<&Stage> {
         for it in &self.stages {
             if &it.id == id {
@@ -50,29 +176,447 @@ impl Scene {
         None
     }
 
-    pub fn import_offset(&mut self, yaml_obj: &serde_yaml::Mapping) -> Result<(), String> {
-        let self_id = self.id.0.clone();
-        for (scene_id_v, scene_obj) in yaml_obj {
-            let scene_id = scene_id_v
-                .as_str()
-                .ok_or(format!("Expected Stage id for Scene {}", self.id.0))?
-                .to_string();
-            let stage = self.get_stage_mut(&NanoID(scene_id.clone()));
-            if stage.is_none() {
-                warn!("Scene {} has no stage with id {}", self.id.0, scene_id);
-                continue;
+    pub fn require_stage(&self, id: &NanoID) -> Result<(), SlsbError> {
+        match self.get_stage(id) {
+            Some(_) => Ok(()),
+            None => Err(Message::new("scene.missing_stage")
+                .arg("scene", &self.id.0)
+                .arg("stage", &id.0)
+                .into()),
+        }
+    }
+
+    // Positions of interchangeable actors, paired two at a time in the order of the scene
+    fn paired_positions(&self) -> Vec<(usize, usize)> {
+        let infos = &self.positions;
+        let mut ret = vec![];
+        let mut open: Vec<usize> = vec![];
+        for (i, info) in infos.iter().enumerate() {
+            match open.iter().position(|&j| infos[j] == *info) {
+                Some(k) => ret.push((open.remove(k), i)),
+                None => open.push(i),
+            }
+        }
+        ret
+    }
+
+    // Mirror a stage left to right, or an unconnected copy of it if copy is set. Interchangeable
+    // actors swap places, so each position stays on its side of the scene
+    pub fn mirror_stage(&mut self, id: &NanoID, copy: bool) -> Result<MirroredStage, SlsbError> {
+        self.require_stage(id)?;
+        let mut target = id.clone();
+        if copy {
+            let mut stage = self.get_stage(id).unwrap().clone();
+            stage.id = NanoID::new_nanoid();
+            stage.name = format!("{} (Mirrored)", stage.name);
+            target = stage.id.clone();
+            self.graph.entry(stage.id.clone()).or_default();
+            self.stages.push(stage);
+        }
+        let pairs = self.paired_positions();
+        let stage = self.get_stage_mut(&target).unwrap();
+        let unpaired = stage.mirror();
+        let swapped: Vec<(usize, usize)> = pairs
+            .into_iter()
+            .filter(|(_, j)| *j < stage.positions.len())
+            .collect();
+        for (i, j) in &swapped {
+            stage.positions.swap(*i, *j);
+        }
+        Ok(MirroredStage {
+            stage: target,
+            unpaired,
+            swapped,
+        })
+    }
+
+    // Connect two stages of the scene. Returns false if the edge already exists
+    pub fn add_edge(&mut self, from: &NanoID, to: &NanoID) -> Result<bool, SlsbError> {
+        self.require_stage(from)?;
+        self.require_stage(to)?;
+        if from == to {
+            return Err(Message::new("scene.self_edge").arg("stage", &from.0).into());
+        }
+        let node = self.graph.entry(from.clone()).or_default();
+        if node.dest.contains(to) {
+            return Ok(false);
+        }
+        node.dest.push(to.clone());
+        Ok(true)
+    }
+
+    pub fn remove_edge(&mut self, from: &NanoID, to: &NanoID) -> Result<(), SlsbError> {
+        let dest = self.graph.get_mut(from).map(|node| &mut node.dest);
+        match dest.filter(|dest| dest.contains(to)) {
+            Some(dest) => {
+                dest.retain(|dest| dest != to);
+                Ok(())
             }
-            stage
-                .unwrap()
-                .import_offset(scene_obj.as_sequence().ok_or(format!(
-                    "Expecting sequence in scene {} for stage {}",
-                    self_id, scene_id
-                ))?)?;
+            None => Err(Message::new("scene.missing_edge")
+                .arg("from", &from.0)
+                .arg("to", &to.0)
+                .into()),
+        }
+    }
+
+    // Continue the scene from one of its ending stages into another scene. Returns false if the
+    // transition already exists
+    pub fn add_transition(&mut self, from: &NanoID, to_scene: &NanoID) -> Result<bool, SlsbError> {
+        self.require_stage(from)?;
+        if to_scene == &self.id {
+            return Err(Message::new("scene.self_transition")
+                .arg("scene", &self.name)
+                .into());
+        }
+        if !self.get_stage(from).is_some_and(|stage| stage.ending) {
+            return Err(Message::new("scene.transition_not_ending")
+                .arg("stage", &from.0)
+                .into());
+        }
+        let transition = Transition {
+            from_stage: from.clone(),
+            to_scene: to_scene.clone(),
+        };
+        if self.transitions.contains(&transition) {
+            return Ok(false);
+        }
+        self.transitions.push(transition);
+        Ok(true)
+    }
+
+    pub fn remove_transition(&mut self, from: &NanoID, to_scene: &NanoID) -> Result<(), SlsbError> {
+        let len = self.transitions.len();
+        self.transitions
+            .retain(|t| &t.from_stage != from || &t.to_scene != to_scene);
+        if self.transitions.len() == len {
+            return Err(Message::new("scene.missing_transition")
+                .arg("stage", &from.0)
+                .arg("scene", &to_scene.0)
+                .into());
+        }
+        Ok(())
+    }
+
+    pub fn set_root(&mut self, id: &NanoID) -> Result<(), SlsbError> {
+        self.require_stage(id)?;
+        self.root = id.clone();
+        Ok(())
+    }
+
+    // Order in which editors and runtime menus list the stages, independent of the graph. Stages
+    // not listed follow the listed ones in their current order
+    pub fn set_stage_order(&mut self, order: &[NanoID]) -> Result<(), SlsbError> {
+        for (i, id) in order.iter().enumerate() {
+            self.require_stage(id)?;
+            if order[..i].contains(id) {
+                return Err(Message::new("scene.stage_listed_twice")
+                    .arg("stage", &id.0)
+                    .into());
+            }
+        }
+        self.stages.sort_by_key(|stage| {
+            order
+                .iter()
+                .position(|id| id == &stage.id)
+                .unwrap_or(order.len())
+        });
+        Ok(())
+    }
+
+    // Replace the graph with a chain through the given stages, the last stage being the climax and ending
+    // Stages not listed are kept, but disconnected from the chain. Only the last stage keeps its climax
+    pub fn make_linear(&mut self, order: &[NanoID]) -> Result<(), SlsbError> {
+        if order.is_empty() {
+            return Err(Message::new("scene.chain_empty")
+                .arg("scene", &self.id.0)
+                .into());
+        }
+        self.set_stage_order(order)?;
+
+        self.graph.clear();
+        for stage in &self.stages {
+            self.graph.insert(stage.id.clone(), Node::default());
+        }
+        for pair in order.windows(2) {
+            self.graph.get_mut(&pair[0]).unwrap().dest = vec![pair[1].clone()];
+        }
+        self.root = order[0].clone();
+        for stage in &mut self.stages {
+            stage.ending = false;
+            for position in &mut stage.positions {
+                position.climax = false;
+            }
+        }
+        let last = self.get_stage_mut(order.last().unwrap()).unwrap();
+        last.ending = true;
+        for position in &mut last.positions {
+            position.climax = true;
         }
+        self.relayout();
         Ok(())
     }
 
-    pub fn update_to_latest_version(&mut self, old_version: u8) -> Result<&mut Self, String> {
+    // Move the given, connected set of stages into a new scene. If link is set, stages which
+    // lead into the extracted stages become endings and transition into the new scene instead
+    pub fn split(&mut self, stage_ids: &[NanoID], link: bool) -> Result<Scene, SlsbError> {
+        if stage_ids.is_empty() || stage_ids.len() >= self.stages.len() {
+            return Err(Message::new("scene.split_subset")
+                .arg("scene", &self.id.0)
+                .into());
+        }
+        let selected: HashSet<&NanoID> = stage_ids.iter().collect();
+        if let Some(id) = stage_ids.iter().find(|id| self.get_stage(id).is_none()) {
+            return Err(Message::new("scene.missing_stage")
+                .arg("scene", &self.id.0)
+                .arg("stage", &id.0)
+                .into());
+        }
+        if !self.is_connected(&selected) {
+            return Err(Message::new("scene.split_disconnected").into());
+        }
+
+        let mut ret = Scene {
+            name: format!("{} (Split)", self.name),
+            furniture: self.furniture.clone(),
+            private: self.private,
+            tags: self.tags.clone(),
+            positions: self.positions.clone(),
+            author: self.author.clone(),
+            source_pack: self.source_pack.clone(),
+            requirements: self.requirements.clone(),
+            ..Default::default()
+        };
+        let (moved, kept): (Vec<Stage>, Vec<Stage>) = std::mem::take(&mut self.stages)
+            .into_iter()
+            .partition(|stage| selected.contains(&stage.id));
+        self.stages = kept;
+        ret.stages = moved;
+
+        let entered_from_outside = |id: &NanoID| {
+            self.graph
+                .iter()
+                .any(|(from, node)| !selected.contains(from) && node.dest.contains(id))
+        };
+        ret.root = if selected.contains(&self.root) {
+            self.root.clone()
+        } else {
+            ret.stages
+                .iter()
+                .find(|stage| entered_from_outside(&stage.id))
+                .unwrap_or(&ret.stages[0])
+                .id
+                .clone()
+        };
+        for stage in &ret.stages {
+            let mut node = self.graph.remove(&stage.id).unwrap_or_default();
+            node.dest.retain(|dest| selected.contains(dest));
+            ret.graph.insert(stage.id.clone(), node);
+        }
+        let mut linked = vec![];
+        for (from, node) in &mut self.graph {
+            let len = node.dest.len();
+            node.dest.retain(|dest| !selected.contains(dest));
+            if link && node.dest.len() != len {
+                linked.push(from.clone());
+            }
+        }
+        // transitions only leave ending stages, so stages leading into the new scene become ones
+        for from in linked {
+            if let Some(stage) = self.get_stage_mut(&from) {
+                stage.ending = true;
+            }
+            self.transitions.push(Transition {
+                from_stage: from,
+                to_scene: ret.id.clone(),
+            });
+        }
+        self.transitions
+            .retain(|transition| !selected.contains(&transition.from_stage));
+        if selected.contains(&self.root) {
+            self.root = self.stages[0].id.clone();
+        }
+        ret.relayout();
+        Ok(ret)
+    }
+
+    // If the given stages form a single component when ignoring edge directions
+    fn is_connected(&self, stages: &HashSet<&NanoID>) -> bool {
+        let Some(start) = stages.iter().next() else {
+            return true;
+        };
+        let mut visited: HashSet<&NanoID> = HashSet::from([*start]);
+        let mut stack = vec![*start];
+        while let Some(id) = stack.pop() {
+            let successors = self.graph.get(id).map(|node| node.dest.iter());
+            let predecessors = self
+                .graph
+                .iter()
+                .filter(|(_, node)| node.dest.contains(id))
+                .map(|(from, _)| from);
+            for next in successors.into_iter().flatten().chain(predecessors) {
+                if stages.contains(next) && visited.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        visited.len() == stages.len()
+    }
+
+    // Stages reachable from the root which are marked as ending or have no outgoing edges
+    pub fn get_endings(&self) -> Vec<NanoID> {
+        let nodes: HashSet<&NanoID> = self.stages.iter().map(|s| &s.id).collect();
+        let reachable = shortest_paths(&self.root, &self.graph, &nodes);
+        self.stages
+            .iter()
+            .filter(|stage| reachable.contains_key(&stage.id))
+            .filter(|stage| {
+                stage.ending
+                    || self
+                        .graph
+                        .get(&stage.id)
+                        .map_or(true, |node| node.dest.iter().all(|d| !nodes.contains(d)))
+            })
+            .map(|stage| stage.id.clone())
+            .collect()
+    }
+
+    pub fn statistics(&self, timers: &StageTimers) -> SceneStatistics {
+        let nodes: HashSet<&NanoID> = self.stages.iter().map(|s| &s.id).collect();
+        let shortest = shortest_paths(&self.root, &self.graph, &nodes);
+        let longest = longest_paths(&self.root, &self.graph, &nodes);
+        let endings = self.get_endings();
+        let duration = |path: &Vec<NanoID>| -> f32 {
+            path.iter()
+                .filter_map(|id| self.get_stage(id))
+                .map(|stage| stage.duration(&self.tags, timers))
+                .sum()
+        };
+        let paths = endings
+            .iter()
+            .map(|ending| PathStatistics {
+                ending: ending.clone(),
+                shortest_duration: duration(&shortest[ending]),
+                longest_duration: duration(&longest[ending]),
+                shortest: shortest[ending].clone(),
+                longest: longest[ending].clone(),
+            })
+            .collect();
+        let alive = reaching(&endings, &self.graph, &nodes);
+        SceneStatistics {
+            scene: self.id.clone(),
+            paths,
+            dead_stages: self
+                .stages
+                .iter()
+                .filter(|stage| !shortest.contains_key(&stage.id) || !alive.contains(&stage.id))
+                .map(|stage| stage.id.clone())
+                .collect(),
+        }
+    }
+
+    // Give a stage a new id, updating every reference to it within the scene
+    pub fn rename_stage(&mut self, old: &NanoID, new: NanoID) -> () {
+        for stage in self.stages.iter_mut().filter(|s| &s.id == old) {
+            stage.id = new.clone();
+        }
+        if &self.root == old {
+            self.root = new.clone();
+        }
+        if let Some(node) = self.graph.remove(old) {
+            self.graph.insert(new.clone(), node);
+        }
+        for node in self.graph.values_mut() {
+            for dest in node.dest.iter_mut().filter(|d| *d == old) {
+                *dest = new.clone();
+            }
+        }
+        for transition in self.transitions.iter_mut().filter(|t| &t.from_stage == old) {
+            transition.from_stage = new.clone();
+        }
+    }
+
+    // Compute new editor coordinates for every stage node
+    pub fn relayout(&mut self) -> () {
+        let order: Vec<NanoID> = self.stages.iter().map(|s| s.id.clone()).collect();
+        for (id, (x, y)) in layered_layout(&order, &self.root, &self.graph) {
+            let node = self.graph.entry(id).or_default();
+            node.x = x;
+            node.y = y;
+        }
+    }
+
+    pub fn intern_strings(&self, table: &mut StringTable) -> () {
+        for stage in &self.stages {
+            stage.intern_strings(table);
+        }
+        for tag in self.encoded_tags() {
+            table.intern(&tag);
+        }
+        table.intern(self.author.as_deref().unwrap_or_default());
+        table.intern(self.source_pack.as_deref().unwrap_or_default());
+        self.encoded_requirements().intern_strings(table);
+    }
+
+    // Requirements including the DLCs of the races of the scene
+    pub fn encoded_requirements(&self) -> Requirements {
+        self.requirements
+            .with_races(self.positions.iter().map(|p| p.race.as_str()))
+    }
+
+    // Stages of the scene written into the shared stage table of the registry
+    pub fn stage_references(&self, buf: &ByteWriter) -> Vec<StageReference> {
+        self.stages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, stage)| {
+                buf.shared_stage(&self.id, &stage.id)
+                    .map(|index| StageReference {
+                        index: i as u32,
+                        stage: index,
+                    })
+            })
+            .collect()
+    }
+
+    fn encoded_tags(&self) -> Vec<String> {
+        self.tags.iter().map(|tag| encode_tag(tag)).collect()
+    }
+
+    // Tags of the given stage including those inherited from the scene
+    pub fn stage_tags(&self, stage: &Stage) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.tags
+            .iter()
+            .chain(&stage.tags)
+            .filter(|tag| seen.insert(encode_tag(tag)))
+            .cloned()
+            .collect()
+    }
+
+    // Move the tags all stages have in common into the scene
+    fn hoist_stage_tags(&mut self) -> () {
+        let Some((first, rest)) = self.stages.split_first() else {
+            return;
+        };
+        let mut seen = HashSet::new();
+        let common: Vec<String> = first
+            .tags
+            .iter()
+            .filter(|tag| {
+                let key = encode_tag(tag);
+                rest.iter()
+                    .all(|stage| stage.tags.iter().any(|t| encode_tag(t) == key))
+                    && seen.insert(key)
+            })
+            .cloned()
+            .collect();
+        for stage in &mut self.stages {
+            stage.tags.retain(|tag| !seen.contains(&encode_tag(tag)));
+        }
+        self.tags = common;
+    }
+
+    pub fn update_to_latest_version(&mut self, old_version: u8) -> Result<&mut Self, SlsbError> {
         for stage in &mut self.stages {
             stage.update_to_latest_version(old_version)?;
         }
@@ -81,14 +625,42 @@ impl Scene {
             self.positions = self
                 .stages
                 .first()
-                .ok_or("No stages found in Scene")?
+                .ok_or(Message::new("scene.no_stages"))?
                 .positions
                 .iter()
                 .map(|pos| pos.extract_position_info())
                 .collect();
         }
+        if old_version <= 5 {
+            // Addition 2.2 (v6): Stages inherit the tags of their scene. Scene tags used to only be
+            // copied into new stages, so they are replaced by the tags all stages have in common
+            self.hoist_stage_tags();
+        }
         Ok(self)
     }
+
+    // Description of the steps update_to_latest_version applies to scenes of the given version
+    pub fn migration_notes(old_version: u8) -> Vec<String> {
+        let mut ret = vec![];
+        if old_version <= 3 {
+            ret.push("Positions extracted from the first stage".into());
+            ret.push("Climax and custom tags moved out of position extras".into());
+        }
+        if old_version <= 5 {
+            ret.push("Scene tags replaced by the tags shared by all stages".into());
+        }
+        ret
+    }
+}
+
+// Outcome of mirroring a stage
+#[derive(Debug, Serialize, Clone)]
+pub struct MirroredStage {
+    pub stage: NanoID,
+    // events naming no side, which still play the animation of the original side
+    pub unpaired: Vec<String>,
+    // positions of interchangeable actors which traded their animations and offsets
+    pub swapped: Vec<(usize, usize)>,
 }
 
 impl Default for Scene {
@@ -104,6 +676,17 @@ impl Default for Scene {
             tags: Default::default(),
             positions: vec![PositionInfo::default(); 1], // Default to one position
             has_warnings: false,
+            transitions: Default::default(),
+            locked: false,
+            revision: 0,
+            author: None,
+            source_pack: None,
+            requirements: Default::default(),
+            suppressed_warnings: Default::default(),
+            archetype: None,
+            shared_stages: Default::default(),
+            localized_names: Default::default(),
+            fingerprint: Default::default(),
         }
     }
 }
@@ -114,19 +697,94 @@ impl EncodeBinary for Scene {
             + self.name.get_byte_size()
             + self.positions.get_byte_size()
             + self.stages.get_byte_size()
-            + self.root.get_byte_size()
-            + self.furniture.get_byte_size()
             + self.private.get_byte_size()
             + self.graph.get_byte_size()
+            + interned_list_size(self.tags.len())
+            + 2 * size_of::<u32>() // author, source_pack
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         self.id.write_byte(buf);
         self.name.write_byte(buf);
         self.positions.write_byte(buf);
-        self.stages.write_byte(buf);
+        // stages of the shared stage table are written as references into it, the registry
+        // accounts for the difference in size
+        let references = self.stage_references(buf);
+        let own: Vec<&Stage> = self
+            .stages
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !references.iter().any(|r| r.index as usize == *i))
+            .map(|(_, stage)| stage)
+            .collect();
+        (own.len() as u32).write_byte(buf);
+        own.iter().for_each(|stage| stage.write_byte(buf));
+        if buf.capabilities().contains(Capabilities::SHARED_STAGES) {
+            references.write_byte(buf);
+        }
         self.graph.write_byte(buf);
-        self.furniture.write_byte(buf);
+        // only registries announcing furniture carry it, the registry accounts for its size
+        if buf.capabilities().contains(Capabilities::FURNITURE) {
+            self.furniture.write_byte(buf);
+        }
         self.private.write_byte(buf);
+        buf.write_interned_list(&self.encoded_tags());
+        buf.write_interned(self.author.as_deref().unwrap_or_default());
+        buf.write_interned(self.source_pack.as_deref().unwrap_or_default());
+        // only registries announcing requirements carry them, the registry accounts for their size
+        if buf.capabilities().contains(Capabilities::REQUIREMENTS) {
+            self.encoded_requirements().write_byte(buf);
+        }
+        // only registries announcing transitions carry them, the registry accounts for their size
+        if buf.capabilities().contains(Capabilities::TRANSITIONS) {
+            self.transitions.write_byte(buf);
+        }
     }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Scene",
+            vec![
+                ("id", NanoID::layout()),
+                ("name", String::layout()),
+                ("positions", Vec::<PositionInfo>::layout()),
+                ("stages", Vec::<Stage>::layout()),
+                (
+                    "shared_stages",
+                    Layout::gated(Capabilities::SHARED_STAGES, Vec::<StageReference>::layout()),
+                ),
+                ("graph", HashMap::<NanoID, Node>::layout()),
+                (
+                    "furniture",
+                    Layout::gated(Capabilities::FURNITURE, FurnitureData::layout()),
+                ),
+                ("private", bool::layout()),
+                (
+                    "tags",
+                    Layout::List {
+                        item: Box::new(Layout::Interned),
+                    },
+                ),
+                ("author", Layout::Interned),
+                ("source_pack", Layout::Interned),
+                (
+                    "requirements",
+                    Layout::gated(Capabilities::REQUIREMENTS, Requirements::layout()),
+                ),
+                (
+                    "transitions",
+                    Layout::gated(Capabilities::TRANSITIONS, Vec::<Transition>::layout()),
+                ),
+            ],
+        )
+    }
+}
+
+// A scene name as shown in the runtime's scene lists: without control characters and with
+// whitespace collapsed into single spaces
+pub fn display_name(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
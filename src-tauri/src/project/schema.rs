@@ -0,0 +1,321 @@
+use once_cell::sync::Lazy;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject, SubschemaValidation},
+    schema_for,
+    visit::{visit_schema_object, Visitor},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::package::Package;
+
+// JSON Schema of a project file, derived from the serde attributes of the types stored in it, for
+// editors to check hand-edited files and to point out the first invalid value of a file
+static PROJECT_SCHEMA: Lazy<Value> = Lazy::new(|| {
+    let mut schema = schema_for!(Package);
+    LegacyFields.visit_root_schema(&mut schema);
+    serde_json::to_value(schema).unwrap_or_default()
+});
+
+// Fields serde reads but never writes are only found in old files, yet schemars requires them
+struct LegacyFields;
+
+impl Visitor for LegacyFields {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        if let Some(object) = schema.object.as_mut() {
+            let legacy: Vec<String> = object
+                .properties
+                .iter()
+                .filter(|(_, property)| match property {
+                    Schema::Object(property) => property
+                        .metadata
+                        .as_ref()
+                        .is_some_and(|metadata| metadata.write_only),
+                    Schema::Bool(_) => false,
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in legacy {
+                object.required.remove(&name);
+            }
+        }
+        visit_schema_object(self, schema);
+    }
+}
+
+pub fn project_schema() -> &'static Value {
+    &PROJECT_SCHEMA
+}
+
+// Events are read from a single string as well as from a list
+pub fn string_or_list(gen: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(vec![
+                gen.subschema_for::<String>(),
+                gen.subschema_for::<Vec<String>>(),
+            ]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SchemaError {
+    // JSON pointer of the offending value, e.g. /scenes/abc/stages/0/name
+    pub pointer: String,
+    pub expected: String,
+    pub found: String,
+}
+
+fn kind_of(value: &Value) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(b) => format!("boolean {}", b),
+        Value::Number(n) => format!("number {}", n),
+        Value::String(_) => "string".into(),
+        Value::Array(_) => "list".into(),
+        Value::Object(_) => "object".into(),
+    }
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// Range of the integer formats schemars annotates fixed size integers with
+fn format_range(format: &str) -> Option<(i128, i128)> {
+    Some(match format {
+        "int8" => (i8::MIN.into(), i8::MAX.into()),
+        "uint8" => (0, u8::MAX.into()),
+        "int16" => (i16::MIN.into(), i16::MAX.into()),
+        "uint16" => (0, u16::MAX.into()),
+        "int32" => (i32::MIN.into(), i32::MAX.into()),
+        "uint32" => (0, u32::MAX.into()),
+        "int64" => (i64::MIN.into(), i64::MAX.into()),
+        "uint64" | "uint" => (0, u64::MAX.into()),
+        _ => return None,
+    })
+}
+
+fn integer_range(schema: &Map<String, Value>) -> (Option<i128>, Option<i128>) {
+    let format = schema
+        .get("format")
+        .and_then(Value::as_str)
+        .and_then(format_range);
+    let bound = |key: &str| schema.get(key).and_then(Value::as_f64).map(|v| v as i128);
+    (
+        bound("minimum").or(format.map(|f| f.0)),
+        bound("maximum").or(format.map(|f| f.1)),
+    )
+}
+
+fn type_names(schema: &Map<String, Value>) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("null", Value::Null)
+        | ("boolean", Value::Bool(_))
+        | ("number", Value::Number(_))
+        | ("string", Value::String(_))
+        | ("array", Value::Array(_))
+        | ("object", Value::Object(_)) => true,
+        ("integer", Value::Number(n)) => n.is_i64() || n.is_u64(),
+        _ => false,
+    }
+}
+
+// Checks a value against a schema, resolving the definitions of the root schema
+struct Validator<'a> {
+    definitions: Option<&'a Map<String, Value>>,
+    out: Vec<SchemaError>,
+}
+
+impl<'a> Validator<'a> {
+    // Name of the definition a schema refers to, directly or as the only item of allOf as
+    // schemars wraps references carrying metadata
+    fn reference(&self, schema: &'a Value) -> Option<&'a str> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            return reference.rsplit('/').next();
+        }
+        match schema.get("allOf").and_then(Value::as_array) {
+            Some(all) if all.len() == 1 => self.reference(&all[0]),
+            _ => None,
+        }
+    }
+
+    fn resolve(&self, schema: &'a Value) -> &'a Value {
+        let definition = self
+            .reference(schema)
+            .and_then(|name| self.definitions?.get(name));
+        match definition {
+            Some(definition) => self.resolve(definition),
+            None => schema,
+        }
+    }
+
+    fn alternatives(&self, schema: &'a Map<String, Value>) -> Option<&'a Vec<Value>> {
+        ["anyOf", "oneOf"]
+            .iter()
+            .find_map(|key| schema.get(*key).and_then(Value::as_array))
+    }
+
+    // Whether the value has the shape of the schema, without looking into its items or fields
+    fn shallow_match(&self, value: &Value, schema: &'a Value) -> bool {
+        let Value::Object(schema) = self.resolve(schema) else {
+            return true;
+        };
+        if let Some(alternatives) = self.alternatives(schema) {
+            return alternatives
+                .iter()
+                .any(|alternative| self.shallow_match(value, alternative));
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            return values.contains(value);
+        }
+        let names = type_names(schema);
+        names.is_empty() || names.iter().any(|name| has_type(value, name))
+    }
+
+    // "list of Stage", "object Scene"
+    fn describe(&self, schema: &'a Value) -> String {
+        let name = self.reference(schema);
+        let Value::Object(schema) = self.resolve(schema) else {
+            return "any value".into();
+        };
+        if let Some(alternatives) = self.alternatives(schema) {
+            return alternatives
+                .iter()
+                .map(|alternative| self.describe(alternative))
+                .collect::<Vec<_>>()
+                .join(" or ");
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            let values: Vec<String> = values.iter().map(Value::to_string).collect();
+            return format!("one of {}", values.join(", "));
+        }
+        let names = type_names(schema);
+        if names.is_empty() {
+            return "any value".into();
+        }
+        names
+            .iter()
+            .map(|type_name| match *type_name {
+                "integer" => match integer_range(schema) {
+                    (Some(min), Some(max)) => format!("integer from {} to {}", min, max),
+                    _ => "integer".into(),
+                },
+                "array" => match schema.get("items") {
+                    Some(items) => format!("list of {}", self.describe(items)),
+                    None => "list".into(),
+                },
+                "object" => match (name, schema.get("additionalProperties")) {
+                    (Some(name), _) => format!("object {}", name),
+                    (None, Some(Value::Object(_))) => {
+                        format!("map of {}", self.describe(&schema["additionalProperties"]))
+                    }
+                    _ => "object".into(),
+                },
+                other => other.into(),
+            })
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+
+    fn mismatch(&mut self, value: &Value, schema: &'a Value, pointer: &str) {
+        self.out.push(SchemaError {
+            pointer: pointer.into(),
+            expected: self.describe(schema),
+            found: kind_of(value),
+        });
+    }
+
+    fn validate_at(&mut self, value: &Value, schema: &'a Value, pointer: &str) {
+        let Value::Object(resolved) = self.resolve(schema) else {
+            return;
+        };
+        if !self.shallow_match(value, schema) {
+            return self.mismatch(value, schema, pointer);
+        }
+        if let Some(alternatives) = self.alternatives(resolved) {
+            // shallow_match made sure one of them fits
+            if let Some(alternative) = alternatives
+                .iter()
+                .find(|alternative| self.shallow_match(value, alternative))
+            {
+                self.validate_at(value, alternative, pointer);
+            }
+            return;
+        }
+        match value {
+            Value::Number(n) if type_names(resolved).contains(&"integer") => {
+                let n = n.as_i64().map(i128::from).or(n.as_u64().map(i128::from));
+                let (min, max) = integer_range(resolved);
+                if n.is_some_and(|n| {
+                    min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max)
+                }) {
+                    self.mismatch(value, schema, pointer);
+                }
+            }
+            Value::Array(items) => {
+                if let Some(item) = resolved.get("items") {
+                    for (i, it) in items.iter().enumerate() {
+                        self.validate_at(it, item, &format!("{}/{}", pointer, i));
+                    }
+                }
+            }
+            Value::Object(entries) => {
+                let properties = resolved.get("properties").and_then(Value::as_object);
+                let required: Vec<&str> = resolved
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .map(|names| names.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+                for (name, field) in properties.into_iter().flatten() {
+                    let path = format!("{}/{}", pointer, escape(name));
+                    match entries.get(name) {
+                        Some(it) => self.validate_at(it, field, &path),
+                        None if required.contains(&name.as_str()) => self.out.push(SchemaError {
+                            pointer: path,
+                            expected: self.describe(field),
+                            found: "nothing".into(),
+                        }),
+                        None => {}
+                    }
+                }
+                if let Some(additional) = resolved
+                    .get("additionalProperties")
+                    .filter(|schema| schema.is_object())
+                {
+                    for (key, it) in entries {
+                        if properties.is_some_and(|properties| properties.contains_key(key)) {
+                            continue;
+                        }
+                        self.validate_at(it, additional, &format!("{}/{}", pointer, escape(key)));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Mismatches between a parsed file and a root schema, in document order. Unknown fields are
+// ignored, just as serde ignores them
+pub fn validate(value: &Value, schema: &Value) -> Vec<SchemaError> {
+    let mut validator = Validator {
+        definitions: schema.get("definitions").and_then(Value::as_object),
+        out: vec![],
+    };
+    validator.validate_at(value, schema, "");
+    validator.out
+}
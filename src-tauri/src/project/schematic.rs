@@ -0,0 +1,183 @@
+use log::{info, warn};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write, fs, path::PathBuf};
+
+use crate::error::SlsbError;
+
+use super::{
+    hkx::{find_stage, stage_preview, SkeletonPreview, HAVOK_TO_GAME},
+    package::Package,
+    preview::thumbnail_path,
+    NanoID,
+};
+
+// Space around the actors, in game units
+const MARGIN: f32 = 60.0;
+// Radius of the marker of an actor, in game units
+const MARKER: f32 = 12.0;
+// Positions further from the first position are likely misaligned, in game units
+pub const MAX_ACTOR_DISTANCE: f32 = 200.0;
+const COLORS: [&str; 5] = ["#d9534f", "#0275d8", "#5cb85c", "#f0ad4e", "#8e44ad"];
+
+// Top-down drawing of the actors of a stage, placed by their offsets
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Schematic {
+    pub svg: String,
+    // positions further than MAX_ACTOR_DISTANCE from the first position
+    pub distant: Vec<usize>,
+}
+
+// Rotate a point in the xy plane clockwise by the given angle, the way headings turn actors
+fn turn(x: f32, y: f32, degrees: f32) -> (f32, f32) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    (x * cos + y * sin, -x * sin + y * cos)
+}
+
+// Bones of the first frame as line segments in game units, relative to the actor
+fn bone_segments(preview: &SkeletonPreview, scale: f32) -> Vec<[(f32, f32); 2]> {
+    let Some(frame) = preview.frames.first() else {
+        return vec![];
+    };
+    let at = |i: usize| {
+        let [x, y, _] = frame[i];
+        (x * HAVOK_TO_GAME * scale, y * HAVOK_TO_GAME * scale)
+    };
+    preview
+        .bones
+        .iter()
+        .enumerate()
+        .filter(|(i, bone)| bone.parent >= 0 && *i < frame.len())
+        .filter(|(_, bone)| (bone.parent as usize) < frame.len())
+        .map(|(i, bone)| [at(bone.parent as usize), at(i)])
+        .collect()
+}
+
+// Draw the actors of the stage from above. If the project has an animation root, the skeletons
+// of the first frame of their animations are drawn as well
+pub fn stage_schematic(
+    package: &Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<Schematic, SlsbError> {
+    let (scene_ref, stage_ref) = find_stage(package, scene, stage)?;
+    let previews = match package.animation_root {
+        Some(_) => stage_preview(package, scene, stage).unwrap_or_else(|err| {
+            warn!(target: "project", stage = stage.0.as_str(); "No skeletons for schematic: {}", err);
+            BTreeMap::new()
+        }),
+        None => BTreeMap::new(),
+    };
+
+    let mut points = vec![];
+    let mut actors = vec![];
+    for (i, (position, info)) in stage_ref
+        .positions
+        .iter()
+        .zip(&scene_ref.positions)
+        .enumerate()
+    {
+        let offset = &position.offset;
+        let bones: Vec<[(f32, f32); 2]> = position
+            .event
+            .first()
+            .and_then(|event| previews.get(event))
+            .map(|preview| bone_segments(preview, info.scale))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|segment| {
+                segment.map(|(x, y)| {
+                    let (x, y) = turn(x, y, offset.r);
+                    (x + offset.x, y + offset.y)
+                })
+            })
+            .collect();
+        points.push((offset.x, offset.y));
+        points.extend(bones.iter().flatten().copied());
+        actors.push((i, info, offset, bones));
+    }
+
+    let (x0, y0) = points.first().copied().unwrap_or_default();
+    let distant = actors
+        .iter()
+        .filter(|(_, _, offset, _)| (offset.x - x0).hypot(offset.y - y0) > MAX_ACTOR_DISTANCE)
+        .map(|(i, ..)| *i)
+        .collect();
+
+    let min_x = points.iter().map(|p| p.0).fold(f32::INFINITY, f32::min) - MARGIN;
+    let max_x = points.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max) + MARGIN;
+    let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min) - MARGIN;
+    let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max) + MARGIN;
+    let (width, height) = (max_x - min_x, max_y - min_y);
+    // svg grows downwards, the game's y axis points forward
+    let to_svg = |(x, y): (f32, f32)| (x - min_x, max_y - y);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {:.1} {:.1}" width="{:.0}" height="{:.0}">"#,
+        width, height, width, height
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="100%" height="100%" fill="#1e1e1e"/>"##
+    );
+    for (i, info, offset, bones) in &actors {
+        let color = COLORS[i % COLORS.len()];
+        for [from, to] in bones {
+            let (x1, y1) = to_svg(*from);
+            let (x2, y2) = to_svg(*to);
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="1.5" opacity="0.7"/>"#,
+                x1, y1, x2, y2, color
+            );
+        }
+        let (cx, cy) = to_svg((offset.x, offset.y));
+        let (dx, dy) = turn(0.0, MARKER * 2.0, offset.r);
+        let (hx, hy) = to_svg((offset.x + dx, offset.y + dy));
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="none" stroke="{}" stroke-width="2"/>"#,
+            cx, cy, MARKER, color
+        );
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="2"/>"#,
+            cx, cy, hx, hy, color
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" fill="{}" font-family="sans-serif" font-size="10">{} {}</text>"#,
+            cx + MARKER + 2.0,
+            cy - MARKER,
+            color,
+            i + 1,
+            escape(&info.race)
+        );
+    }
+    svg.push_str("</svg>\n");
+    Ok(Schematic { svg, distant })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Write the schematic of a stage into the preview folder as the thumbnail of its scene
+pub fn write_thumbnail(
+    package: &Package,
+    scene: &NanoID,
+    stage: &NanoID,
+) -> Result<PathBuf, SlsbError> {
+    let schematic = stage_schematic(package, scene, stage)?;
+    let path = thumbnail_path(package, scene)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, schematic.svg)?;
+    info!(target: "project", scene = scene.0.as_str(), path:? = path; "Wrote thumbnail");
+    Ok(path)
+}
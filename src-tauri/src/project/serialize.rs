@@ -1,56 +1,8 @@
-use serde::{Deserializer, de::{self}};
-use std::{collections::HashMap, fmt, vec};
-
-pub fn map_race_to_folder(race: &str) -> Result<String, ()> {
-    match race {
-        "Human" => Ok("character".into()),
-        "Ash Hopper" => Ok("dlc02\\scrib".into()),
-        "Bear" => Ok("bear".into()),
-        "Boar" | "Boar (Any)" | "Boar (Mounted)" => Ok("dlc02\\boarriekling".into()),
-        "Canine" | "Dog" | "Wolf" | "Fox" => Ok("canine".into()),
-        "Chaurus" | "Chaurus Reaper" => Ok("chaurus".into()),
-        "Chaurus Hunter" => Ok("dlc01\\chaurusflyer".into()),
-        "Chicken" => Ok("ambient\\chicken".into()),
-        "Cow" => Ok("cow".into()),
-        "Deer" => Ok("deer".into()),
-        "Dragon Priest" => Ok("dragonpriest".into()),
-        "Dragon" => Ok("dragon".into()),
-        "Draugr" => Ok("draugr".into()),
-        "Dwarven Ballista" => Ok("dlc02\\dwarvenballistacenturion".into()),
-        "Dwarven Centurion" => Ok("dwarvensteamcenturion".into()),
-        "Dwarven Sphere" => Ok("dwarvenspherecenturion".into()),
-        "Dwarven Spider" => Ok("dwarvenspider".into()),
-        "Falmer" => Ok("falmer".into()),
-        "Flame Atronach" => Ok("atronachflame".into()),
-        "Frost Atronach" => Ok("atronachfrost".into()),
-        "Storm Atronach" => Ok("atronachstorm".into()),
-        "Gargoyle" => Ok("dlc01\\vampirebrute".into()),
-        "Giant" => Ok("giant".into()),
-        "Goat" => Ok("goat".into()),
-        "Hagraven" => Ok("hagraven".into()),
-        "Horker" => Ok("horker".into()),
-        "Horse" => Ok("horse".into()),
-        "Ice Wraith" => Ok("icewraith".into()),
-        "Lurker" => Ok("dlc02\\benthiclurker".into()),
-        "Mammoth" => Ok("mammoth".into()),
-        "Mudcrab" => Ok("mudcrab".into()),
-        "Netch" => Ok("dlc02\\netch".into()),
-        "Rabbit" => Ok("ambient\\hare".into()),
-        "Riekling" => Ok("dlc02\\riekling".into()),
-        "Sabrecat" => Ok("sabrecat".into()),
-        "Seeker" => Ok("dlc02\\hmdaedra".into()),
-        "Skeever" => Ok("skeever".into()),
-        "Slaughterfish" => Ok("slaughterfish".into()),
-        "Spider" | "Large Spider" | "Giant Spider" => Ok("frostbitespider".into()),
-        "Spriggan" => Ok("spriggan".into()),
-        "Troll" => Ok("troll".into()),
-        "Vampire Lord" => Ok("vampirelord".into()),
-        "Werewolf" => Ok("werewolfbeast".into()),
-        "Wispmother" => Ok("wisp".into()),
-        "Wisp" => Ok("witchlight".into()),
-        _ => Err(()),
-    }
-}
+use bitflags::bitflags;
+use serde::{Deserializer, Serialize, de::{self}};
+use std::{collections::{HashMap, HashSet}, fmt, vec};
+
+use super::NanoID;
 
 pub struct DeserializeVecOrString;
 impl<'de> de::Visitor<'de> for DeserializeVecOrString {
@@ -145,9 +97,292 @@ fn make_fnis_line(
     )
 }
 
+// Animation event of a FNIS list line, None for comments and blank lines
+pub fn fnis_event(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('\'') || line.starts_with("Version") {
+        return None;
+    }
+    let mut tokens = line.split_whitespace().skip(1).peekable();
+    if tokens.peek().is_some_and(|token| token.starts_with('-')) {
+        tokens.next();
+    }
+    tokens.next()
+}
+
+// Lines of an existing FNIS list merged with the lines of this pack. Lines with events of this
+// pack, starting with hash, are replaced. Lines whose event is already defined by another pack
+// are left out and returned as the second value
+pub fn merge_fnis_lines(
+    existing: &str,
+    lines: &[String],
+    hash: &str,
+) -> (Vec<String>, Vec<String>) {
+    let mut ret: Vec<String> = existing
+        .lines()
+        .filter(|line| !fnis_event(line).is_some_and(|event| event.starts_with(hash)))
+        .map(|line| line.to_string())
+        .collect();
+    let defined: HashSet<String> = ret
+        .iter()
+        .filter_map(|line| fnis_event(line))
+        .map(|event| event.to_lowercase())
+        .collect();
+    let mut duplicates = vec![];
+    for line in lines {
+        match fnis_event(line) {
+            Some(event) if defined.contains(&event.to_lowercase()) => {
+                duplicates.push(event.to_string())
+            }
+            _ => ret.push(line.clone()),
+        }
+    }
+    (ret, duplicates)
+}
+
+// Table of strings shared across the binary, referenced by their u32 index
+#[derive(Debug, Default, Clone)]
+pub struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl StringTable {
+    pub fn intern(&mut self, value: &str) -> u32 {
+        if let Some(index) = self.indices.get(value) {
+            return *index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(value.to_string());
+        self.indices.insert(value.to_string(), index);
+        index
+    }
+
+    pub fn lookup(&self, value: &str) -> Option<u32> {
+        self.indices.get(value).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl EncodeBinary for StringTable {
+    fn get_byte_size(&self) -> usize {
+        self.strings.get_byte_size()
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        self.strings.write_byte(buf);
+    }
+
+    fn layout() -> Layout {
+        Vec::<String>::layout()
+    }
+}
+
+bitflags! {
+    // Optional sections of a registry, announced in its header since version 8. A capability is
+    // only set if its section is written, readers reject capabilities they do not know
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Capabilities: u32 {
+        // the string table interned strings index into, omitted if no string is interned
+        const STRING_TABLE = 1 << 0;
+        // furniture data of scenes, omitted if no scene deviates from the default
+        const FURNITURE = 1 << 1;
+        // pitch and roll of offsets, omitted if no offset is tilted
+        const OFFSETS_V2 = 1 << 2;
+        // reserved for compressed scenes, not written yet
+        const COMPRESSION = 1 << 3;
+        // transitions of scenes into other scenes
+        const TRANSITIONS = 1 << 4;
+        // alternative events of positions
+        const EVENT_VARIANTS = 1 << 5;
+        // stages of the library used by several scenes, written once and referred to by index
+        const SHARED_STAGES = 1 << 6;
+        // plugins and keywords scenes depend on, omitted if no scene has any
+        const REQUIREMENTS = 1 << 7;
+    }
+}
+
+impl Capabilities {
+    pub fn layout() -> Layout {
+        Layout::Flags {
+            width: std::mem::size_of::<u32>(),
+            flags: Capabilities::all()
+                .iter_names()
+                .map(|(name, flag)| (name.to_lowercase(), flag.bits() as u64))
+                .collect(),
+        }
+    }
+}
+
+// Output buffer of the binary encoding, carrying the string table interned strings are written against
+#[derive(Debug, Default)]
+pub struct ByteWriter {
+    bytes: Vec<u8>,
+    strings: StringTable,
+    capabilities: Capabilities,
+    // index into the shared stage table by scene and stage
+    shared_stages: HashMap<NanoID, HashMap<NanoID, u32>>,
+}
+
+impl ByteWriter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(capacity),
+            strings: Default::default(),
+            capabilities: Capabilities::empty(),
+            shared_stages: Default::default(),
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> () {
+        self.bytes.push(byte);
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) -> () {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    pub fn set_strings(&mut self, strings: StringTable) -> () {
+        self.strings = strings;
+    }
+
+    pub fn strings(&self) -> &StringTable {
+        &self.strings
+    }
+
+    // Sections announced by the registry being written
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) -> () {
+        self.capabilities = capabilities;
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn set_shared_stages(&mut self, shared_stages: HashMap<NanoID, HashMap<NanoID, u32>>) {
+        self.shared_stages = shared_stages;
+    }
+
+    // Index of the stage of the scene in the shared stage table, if it is written there
+    pub fn shared_stage(&self, scene: &NanoID, stage: &NanoID) -> Option<u32> {
+        self.shared_stages.get(scene).and_then(|stages| stages.get(stage)).copied()
+    }
+
+    // Write the u32 index of the given string. The table is written before anything indexing into
+    // it, so every string must have been interned up front
+    pub fn write_interned(&mut self, value: &str) -> () {
+        let index = self
+            .strings
+            .lookup(value)
+            .unwrap_or_else(|| panic!("'{}' is missing from the string table", value));
+        self.extend_from_slice(&index.to_be_bytes());
+    }
+
+    pub fn write_interned_list(&mut self, list: &[String]) -> () {
+        self.extend_from_slice(&(list.len() as u32).to_be_bytes());
+        for value in list {
+            self.write_interned(value);
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub fn interned_list_size(len: usize) -> usize {
+    size_of::<u32>() * (1 + len) // u32 for length + u32 index per string
+}
+
 pub trait EncodeBinary {
     fn get_byte_size(&self) -> usize;
-    fn write_byte(&self, buf: &mut Vec<u8>) -> ();
+    fn write_byte(&self, buf: &mut ByteWriter) -> ();
+    // Description of the bytes produced by write_byte, used to generate the format specification
+    fn layout() -> Layout;
+}
+
+// Machine readable description of a binary layout. All integers are big endian
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Layout {
+    U8,
+    U32,
+    U64,
+    Bool,
+    // f32 multiplied by scale and written as i32
+    Fixed { scale: u32 },
+    // u32 byte length followed by utf-8 bytes
+    String,
+    // u32 index into the package string table
+    Interned,
+    // raw ascii bytes of a NanoID without length prefix
+    Id { len: usize },
+    // u32 item count followed by the items
+    List { item: Box<Layout> },
+    // u32 entry count followed by key/value pairs
+    Map { key: Box<Layout>, value: Box<Layout> },
+    Flags { width: usize, flags: Vec<(String, u64)> },
+    Enum { width: usize, variants: Vec<(String, u64)> },
+    Struct { name: String, fields: Vec<Field> },
+    // only present if the registry announces the given capability
+    Gated { capability: String, layout: Box<Layout> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub layout: Layout,
+}
+
+impl Layout {
+    pub fn structure(name: &str, fields: Vec<(&str, Layout)>) -> Self {
+        Layout::Struct {
+            name: name.into(),
+            fields: fields
+                .into_iter()
+                .map(|(name, layout)| Field {
+                    name: name.into(),
+                    layout,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn gated(capability: Capabilities, layout: Layout) -> Self {
+        Layout::Gated {
+            capability: capability
+                .iter_names()
+                .map(|(name, _)| name.to_lowercase())
+                .collect(),
+            layout: Box::new(layout),
+        }
+    }
+
+    pub fn flags(width: usize, flags: &[(&str, u64)]) -> Self {
+        Layout::Flags {
+            width,
+            flags: flags.iter().map(|(n, v)| (n.to_string(), *v)).collect(),
+        }
+    }
 }
 
 impl EncodeBinary for String {
@@ -155,11 +390,15 @@ impl EncodeBinary for String {
         size_of::<u32>() + self.len() // u32 for length + string bytes
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         let len = self.len() as u32;
         buf.extend_from_slice(&len.to_be_bytes());
         buf.extend_from_slice(self.as_bytes());
     }
+
+    fn layout() -> Layout {
+        Layout::String
+    }
 }
 
 impl EncodeBinary for f32 {
@@ -167,10 +406,14 @@ impl EncodeBinary for f32 {
         size_of::<f32>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
       let scaled_value = (self * 1000.0).round() as i32;
       buf.extend_from_slice(&scaled_value.to_be_bytes());
     }
+
+    fn layout() -> Layout {
+        Layout::Fixed { scale: 1000 }
+    }
 }
 
 impl EncodeBinary for bool {
@@ -178,9 +421,13 @@ impl EncodeBinary for bool {
         size_of::<bool>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         buf.push(*self as u8);
     }
+
+    fn layout() -> Layout {
+        Layout::Bool
+    }
 }
 
 impl EncodeBinary for u8 {
@@ -188,9 +435,13 @@ impl EncodeBinary for u8 {
         size_of::<u8>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         buf.push(*self);
     }
+
+    fn layout() -> Layout {
+        Layout::U8
+    }
 }
 
 impl EncodeBinary for u32 {
@@ -198,9 +449,13 @@ impl EncodeBinary for u32 {
         size_of::<u32>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         buf.extend_from_slice(&self.to_be_bytes());
     }
+
+    fn layout() -> Layout {
+        Layout::U32
+    }
 }
 
 impl EncodeBinary for u64 {
@@ -208,9 +463,13 @@ impl EncodeBinary for u64 {
         size_of::<u64>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         buf.extend_from_slice(&self.to_be_bytes());
     }
+
+    fn layout() -> Layout {
+        Layout::U64
+    }
 }
 
 impl<T: EncodeBinary> EncodeBinary for Vec<T> {
@@ -218,16 +477,23 @@ impl<T: EncodeBinary> EncodeBinary for Vec<T> {
         size_of::<u32>() + self.iter().map(|item| item.get_byte_size()).sum::<usize>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         let len = self.len() as u32;
         buf.extend_from_slice(&len.to_be_bytes());
         for item in self {
             item.write_byte(buf);
         }
     }
+
+    fn layout() -> Layout {
+        Layout::List {
+            item: Box::new(T::layout()),
+        }
+    }
 }
 
-impl<K: EncodeBinary, V: EncodeBinary> EncodeBinary for HashMap<K, V> {
+// Entries are written ordered by key, the iteration order of the map itself is not stable
+impl<K: EncodeBinary + Ord, V: EncodeBinary> EncodeBinary for HashMap<K, V> {
     fn get_byte_size(&self) -> usize {
         size_of::<u32>() + 
         self.iter()
@@ -235,13 +501,22 @@ impl<K: EncodeBinary, V: EncodeBinary> EncodeBinary for HashMap<K, V> {
             .sum::<usize>()
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         let len = self.len() as u32;
         buf.extend_from_slice(&len.to_be_bytes());
-        for (key, value) in self {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        for (key, value) in entries {
             key.write_byte(buf);
             value.write_byte(buf);
         }
     }
+
+    fn layout() -> Layout {
+        Layout::Map {
+            key: Box::new(K::layout()),
+            value: Box::new(V::layout()),
+        }
+    }
 }
 
@@ -1,11 +1,20 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::vec;
+use std::{mem::size_of, vec};
 
-use crate::project::scene::Scene;
+use crate::{error::SlsbError, i18n::Message, project::scene::Scene};
 
-use super::{position::Position, serialize::EncodeBinary, NanoID};
+use super::{
+    events::mirror_event,
+    position::Position,
+    serialize::{interned_list_size, ByteWriter, EncodeBinary, Layout, StringTable},
+    NanoID,
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+// Duration the runtime assumes for stages without a fixed length, in seconds
+pub const DEFAULT_STAGE_TIMER: f32 = 15.0;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct Stage {
     pub id: NanoID,
     pub name: String,
@@ -13,12 +22,88 @@ pub struct Stage {
     pub positions: Vec<Position>,
     pub tags: Vec<String>,
     pub extra: Extra,
+    // the scene may be started from this stage, skipping the stages leading up to it
+    #[serde(default)] // addition 2.2
+    pub shortcut: bool,
+    // the scene may end after this stage, even if it leads on to other stages
+    #[serde(default)] // addition 2.2
+    pub ending: bool,
+}
+
+// Default durations of stages without a fixed length, by category. A value of 0 leaves the
+// duration to the runtime
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default, PartialEq)]
+pub struct StageTimers {
+    pub foreplay: f32,
+    pub main: f32,
+    pub climax: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StageCategory {
+    Foreplay,
+    Main,
+    Climax,
+}
+
+impl StageTimers {
+    pub fn get(&self, category: StageCategory) -> f32 {
+        match category {
+            StageCategory::Foreplay => self.foreplay,
+            StageCategory::Main => self.main,
+            StageCategory::Climax => self.climax,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SlsbError> {
+        for (category, value) in [
+            ("foreplay", self.foreplay),
+            ("main", self.main),
+            ("climax", self.climax),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(Message::new("stage.invalid_timer")
+                    .arg("category", category)
+                    .arg("value", value)
+                    .into());
+            }
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+impl EncodeBinary for StageTimers {
+    fn get_byte_size(&self) -> usize {
+        self.foreplay.get_byte_size() + self.main.get_byte_size() + self.climax.get_byte_size()
+    }
+
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
+        self.foreplay.write_byte(buf);
+        self.main.write_byte(buf);
+        self.climax.write_byte(buf);
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "StageTimers",
+            vec![
+                ("foreplay", f32::layout()),
+                ("main", f32::layout()),
+                ("climax", f32::layout()),
+            ],
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default, PartialEq)]
+#[schemars(rename = "StageExtra")]
 pub struct Extra {
     pub fixed_len: f32,
     pub nav_text: String,
+    // sound effect played during the stage, carried over from slal
+    #[serde(default)] // addition 2.2
+    pub sound: Option<String>,
 }
 
 impl Stage {
@@ -31,28 +116,70 @@ impl Stage {
                 || vec![Position::new(None); parent_scene.positions.len()],
                 |s| s.positions.iter().map(|p| Position::new(Some(p))).collect(),
             ),
-            tags: parent_scene.tags.clone(),
+            tags: Default::default(), // scene tags are inherited
             extra: Default::default(),
+            shortcut: false,
+            ending: false,
         }
     }
 
-    pub fn import_offset(&mut self, yaml_obj: &serde_yaml::Sequence) -> Result<(), String> {
+    // Climax if any actor climaxes, foreplay if the stage or its scene is tagged as such, main
+    // otherwise. Tags common to all stages are stored with the scene, so its tags are required
+    pub fn category(&self, scene_tags: &[String]) -> StageCategory {
+        let mut tags = scene_tags.iter().chain(&self.tags);
+        if self.positions.iter().any(|p| p.climax) {
+            StageCategory::Climax
+        } else if tags.any(|t| t.eq_ignore_ascii_case("foreplay")) {
+            StageCategory::Foreplay
+        } else {
+            StageCategory::Main
+        }
+    }
+
+    // Expected time the runtime spends in this stage
+    pub fn duration(&self, scene_tags: &[String], timers: &StageTimers) -> f32 {
+        if self.extra.fixed_len > 0.0 {
+            return self.extra.fixed_len;
+        }
+        match timers.get(self.category(scene_tags)) {
+            timer if timer > 0.0 => timer,
+            _ => DEFAULT_STAGE_TIMER,
+        }
+    }
+
+    // Mirror the offsets of all positions and switch their events to the animations of the other
+    // side. Returns the events naming no side, which are kept as they are
+    pub fn mirror(&mut self) -> Vec<String> {
+        let mut unpaired = vec![];
+        for position in &mut self.positions {
+            position.offset = position.offset.mirrored();
+            for event in position.event.iter_mut().chain(&mut position.variants) {
+                match mirror_event(event) {
+                    Some(mirrored) => *event = mirrored,
+                    None if !event.is_empty() => unpaired.push(event.clone()),
+                    None => {}
+                }
+            }
+        }
+        unpaired
+    }
+
+    pub fn import_offset(&mut self, yaml_obj: &serde_yaml::Sequence) -> Result<(), SlsbError> {
         let list: Vec<_> = yaml_obj
             .iter()
-            .map_while(|obj| {
-                obj.as_mapping().and_then(|mapping| {
-                    mapping
-                        .get(&"transform".into())
-                        .and_then(|obj| obj.as_mapping())
-                })
+            .enumerate()
+            .map(|(i, obj)| {
+                obj.as_mapping()
+                    .and_then(|mapping| mapping.get(&"transform".into()))
+                    .and_then(|obj| obj.as_mapping())
+                    .ok_or(Message::new("offset.missing_transform").arg("position", i + 1))
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
         if list.len() != self.positions.len() {
-            return Err(format!(
-                "Invalid position length, got {} but exepcted {}",
-                list.len(),
-                self.positions.len(),
-            ));
+            return Err(Message::new("offset.position_count")
+                .arg("got", list.len())
+                .arg("expected", self.positions.len())
+                .into());
         }
         for (i, pos_obj) in list.iter().enumerate() {
             self.positions[i].import_offset(*pos_obj)?;
@@ -61,12 +188,33 @@ impl Stage {
         Ok(())
     }
 
-    pub fn update_to_latest_version(&mut self, old_version: u8) -> Result<(), String> {
+    pub fn update_to_latest_version(&mut self, old_version: u8) -> Result<(), SlsbError> {
         for pos in &mut self.positions {
             pos.update_to_latest_version(old_version)?;
         }
         Ok(())
     }
+
+    pub fn intern_strings(&self, table: &mut StringTable) -> () {
+        for position in &self.positions {
+            position.intern_strings(table);
+        }
+        for tag in self.encoded_tags() {
+            table.intern(&tag);
+        }
+    }
+
+    fn encoded_tags(&self) -> Vec<String> {
+        self.tags.iter().map(|tag| encode_tag(tag)).collect()
+    }
+}
+
+// A tag as it is written into the binary: lowercase and without whitespace
+pub fn encode_tag(tag: &str) -> String {
+    tag.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
 }
 
 impl EncodeBinary for Stage {
@@ -75,24 +223,39 @@ impl EncodeBinary for Stage {
             + self.positions.get_byte_size()
             + self.extra.fixed_len.get_byte_size()
             + self.extra.nav_text.get_byte_size()
-            + self.tags.get_byte_size()
+            + interned_list_size(self.tags.len())
+            + size_of::<u8>() // flags
     }
 
-    fn write_byte(&self, buf: &mut Vec<u8>) -> () {
+    fn write_byte(&self, buf: &mut ByteWriter) -> () {
         self.id.write_byte(buf);
         self.positions.write_byte(buf);
         self.extra.fixed_len.write_byte(buf);
         self.extra.nav_text.write_byte(buf);
-        self.tags
-            .iter()
-            .map(|tag| {
-                tag.chars()
-                    .filter(|c| !c.is_whitespace())
-                    .collect::<String>()
-                    .to_lowercase()
-            })
-            .collect::<Vec<_>>()
-            .write_byte(buf);
+        buf.write_interned_list(&self.encoded_tags());
+        buf.push(((1 << 0) * self.shortcut as u8) | ((1 << 1) * self.ending as u8));
+    }
+
+    fn layout() -> Layout {
+        Layout::structure(
+            "Stage",
+            vec![
+                ("id", NanoID::layout()),
+                ("positions", Vec::<Position>::layout()),
+                ("fixed_len", f32::layout()),
+                ("nav_text", String::layout()),
+                (
+                    "tags",
+                    Layout::List {
+                        item: Box::new(Layout::Interned),
+                    },
+                ),
+                (
+                    "flags",
+                    Layout::flags(size_of::<u8>(), &[("shortcut", 1 << 0), ("ending", 1 << 1)]),
+                ),
+            ],
+        )
     }
 }
 
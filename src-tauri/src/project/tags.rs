@@ -0,0 +1,111 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::i18n::Message;
+
+use super::{
+    scene::Scene,
+    stage::{encode_tag, Stage},
+    NanoID,
+};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub reason: Message,
+    // stages which should carry the tag but do not yet
+    pub stages: Vec<NanoID>,
+}
+
+// "Boar (Mounted)" -> "boar"
+fn race_tag(race: &str) -> String {
+    encode_tag(race.split(" (").next().unwrap_or(race))
+}
+
+fn furniture_tag(furniture: &str) -> Option<&'static str> {
+    match furniture {
+        f if f.starts_with("Bed") => Some("bed"),
+        f if f.starts_with("Chair") => Some("chair"),
+        f if f.starts_with("Bench") => Some("bench"),
+        f if f.starts_with("Table") => Some("table"),
+        f if f.starts_with("Throne") => Some("throne"),
+        "Wall" => Some("wall"),
+        "XCross" | "Pillory" => Some("furniture"),
+        _ => None,
+    }
+}
+
+fn is_climax(stage: &Stage) -> bool {
+    stage.positions.iter().any(|p| p.climax)
+}
+
+// A tag to suggest, the reason for it and the stages it applies to
+type Candidate = (String, Message, fn(&Stage) -> bool);
+
+// Suggest tags derived from data already present in the scene, such as its actors, races,
+// furniture and climaxes. Tags every relevant stage already carries, directly or through the
+// scene, are not suggested
+pub fn suggest_tags(scene: &Scene) -> Vec<TagSuggestion> {
+    let mut candidates: Vec<Candidate> = vec![];
+    let all: fn(&Stage) -> bool = |_| true;
+
+    let actors = scene.positions.len();
+    let count_tag = match actors {
+        0..=2 => None,
+        3 => Some("threesome"),
+        4 => Some("foursome"),
+        _ => Some("orgy"),
+    };
+    if let Some(tag) = count_tag {
+        let reason = Message::new("tags.actor_count").arg("count", actors);
+        candidates.push((tag.into(), reason, all));
+    }
+    let creatures: Vec<&str> = scene
+        .positions
+        .iter()
+        .map(|info| info.race.as_str())
+        .filter(|race| *race != "Human")
+        .collect();
+    if let Some(race) = creatures.first() {
+        let reason = Message::new("tags.creature").arg("race", race);
+        candidates.push(("creature".into(), reason, all));
+    }
+    for race in creatures {
+        let reason = Message::new("tags.creature").arg("race", race);
+        candidates.push((race_tag(race), reason, all));
+    }
+    let mut furniture: Vec<(&str, &str)> = scene
+        .furniture
+        .furni_types
+        .iter()
+        .filter_map(|f| furniture_tag(f).map(|tag| (tag, f.as_str())))
+        .collect();
+    if scene.furniture.allow_bed {
+        furniture.push(("bed", "Bed"));
+    }
+    for (tag, furni) in furniture {
+        let reason = Message::new("tags.furniture").arg("furniture", furni);
+        candidates.push((tag.into(), reason, all));
+    }
+    candidates.push(("orgasm".into(), Message::new("tags.climax"), is_climax));
+
+    let mut seen = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|(tag, _, _)| seen.insert(tag.clone()))
+        .filter_map(|(tag, reason, applies)| {
+            let stages: Vec<NanoID> = scene
+                .stages
+                .iter()
+                .filter(|stage| applies(stage))
+                .filter(|stage| !scene.stage_tags(stage).iter().any(|t| encode_tag(t) == tag))
+                .map(|stage| stage.id.clone())
+                .collect();
+            (!stages.is_empty()).then_some(TagSuggestion {
+                tag,
+                reason,
+                stages,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,560 @@
+use serde_json::json;
+use std::fs;
+
+use crate::{
+    project::{
+        aliases::{ActorType, SexAliases, ALIAS_FILE},
+        convert::{
+            convert_slal, slal_inputs, Confidence, ConversionKind, GenderGuess, RaceMapping,
+            SlalProgress,
+        },
+        package::{Package, VERSION},
+        scene::Archetype,
+        upgrade::{project_files, upgrade_projects},
+        validate::validate_scene,
+        NanoID,
+    },
+    racekeys::{guess_racekeys, load_race_overrides, map_legacy_to_racekey, RaceKeyOverrides},
+};
+
+use super::generators::TempDir;
+
+fn slal_pack(name: &str, animations: usize) -> serde_json::Value {
+    let animations: Vec<serde_json::Value> = (0..animations)
+        .map(|i| {
+            json!({
+                "name": format!("{} {}", name, i),
+                "tags": "Vaginal, Loving",
+                "actors": [
+                    { "type": "Female", "stages": [{ "id": "A1" }, { "id": "A2" }] },
+                    { "type": "Male", "stages": [{ "id": "B1" }, { "id": "B2" }] }
+                ]
+            })
+        })
+        .collect();
+    json!({ "name": name, "animations": animations })
+}
+
+#[test]
+fn folder_inputs_are_sorted_json_files() {
+    let dir = TempDir::new("convert");
+    fs::write(dir.join("b.json"), "{}").unwrap();
+    fs::write(dir.join("a.json"), "{}").unwrap();
+    fs::write(dir.join("notes.txt"), "").unwrap();
+
+    let inputs = slal_inputs(&dir).unwrap();
+    assert_eq!(inputs, vec![dir.join("a.json"), dir.join("b.json")]);
+    assert_eq!(slal_inputs(&dir.join("a.json")).unwrap().len(), 1);
+    assert!(slal_inputs(&dir.join("missing")).is_err());
+}
+
+#[test]
+fn conversion_continues_past_invalid_files() {
+    let dir = TempDir::new("convert");
+    fs::write(dir.join("a.json"), slal_pack("Alpha", 2).to_string()).unwrap();
+    fs::write(dir.join("b.json"), json!({ "name": "Broken" }).to_string()).unwrap();
+    fs::write(dir.join("c.json"), slal_pack("Gamma", 1).to_string()).unwrap();
+
+    let (projects, report) = convert_slal(&slal_inputs(&dir).unwrap(), false);
+    assert_eq!(projects.len(), 2);
+    assert_eq!(projects[0].pack_path, dir.join("a.json"));
+    assert_eq!(report.inputs.len(), 3);
+    assert_eq!(report.failed(), 1);
+    assert_eq!(report.inputs[0].scenes, 2);
+    assert_eq!(report.inputs[0].stages, 4);
+    assert!(projects[0]
+        .scenes
+        .values()
+        .all(|scene| scene.source_pack.as_deref() == Some("a")));
+    assert!(report.inputs[1].error.is_some());
+}
+
+#[test]
+fn merged_conversion_yields_a_single_project() {
+    let dir = TempDir::new("convert");
+    fs::write(dir.join("a.json"), slal_pack("Alpha", 2).to_string()).unwrap();
+    fs::write(dir.join("b.json"), slal_pack("Beta", 3).to_string()).unwrap();
+
+    let (projects, report) = convert_slal(&slal_inputs(&dir).unwrap(), true);
+    assert_eq!(projects.len(), 1);
+    assert_eq!(projects[0].pack_name, "Alpha");
+    assert_eq!(projects[0].scenes.len(), 5);
+    assert_eq!(report.failed(), 0);
+}
+
+#[test]
+fn malformed_slal_files_are_rejected() {
+    let mut uneven = slal_pack("Uneven", 1);
+    uneven["animations"][0]["actors"][1]["stages"] = json!([{ "id": "B1" }]);
+    let err = Package::from_slal_value(&uneven).unwrap_err();
+    assert_eq!(err.message().key, "slal.stage_count");
+
+    let mut no_actors = slal_pack("Empty", 1);
+    no_actors["animations"][0]["actors"] = json!([]);
+    let err = Package::from_slal_value(&no_actors).unwrap_err();
+    assert_eq!(err.message().key, "slal.no_stages");
+
+    let mut missing = slal_pack("Missing", 1);
+    missing["animations"][0]["actors"][0]
+        .as_object_mut()
+        .unwrap()
+        .remove("stages");
+    let err = Package::from_slal_value(&missing).unwrap_err();
+    assert_eq!(err.message().key, "slal.missing_attribute");
+
+    // attributes of the wrong type are rejected while reading the animations
+    let mut wrong_type = slal_pack("Wrong", 1);
+    wrong_type["animations"][0]["actors"][0]["stages"] = json!("A1");
+    let err = Package::from_slal_value(&wrong_type).unwrap_err();
+    assert_eq!(err.message().key, "error.json");
+
+    assert!(Package::from_slal_value(&slal_pack("Valid", 2)).is_ok());
+}
+
+#[test]
+fn conversions_are_recorded_per_scene() {
+    let slal = json!({
+        "name": "Report",
+        "animations": [{
+            "id": "Report_Canine",
+            "name": "Canine",
+            "furniture": "Bed",
+            "creature_race": "Canines",
+            "actors": [
+                { "type": "Type", "add_cum": 1, "stages": [{ "id": "A1", "strap_on": true }] },
+                { "type": "CreatureMale", "stages": [{ "id": "B1" }] }
+            ],
+            "stage": [{ "number": 1, "timer": 5.0, "camera": "Close" }]
+        }]
+    });
+    let mut package = Package::from_slal_value(&slal).unwrap();
+    assert_eq!(package.conversions.len(), 1);
+    let record = &package.conversions[0];
+    assert_eq!(record.kind, ConversionKind::Slal);
+    assert!(!record.migrations.is_empty());
+
+    let id = package.scenes.keys().next().unwrap().clone();
+    let conversion = record.scene(&id).unwrap();
+    assert_eq!(conversion.source_name.as_deref(), Some("Report_Canine"));
+    assert_eq!(
+        conversion.dropped,
+        vec![
+            "furniture",
+            "actors[0].add_cum",
+            "actors[0].stages.strap_on",
+            "stage.camera"
+        ]
+    );
+    assert_eq!(
+        conversion.guessed_genders,
+        vec![GenderGuess {
+            position: 0,
+            sex: "male".into(),
+            reason: "placeholder type".into(),
+        }]
+    );
+    assert_eq!(
+        conversion.races[1],
+        RaceMapping {
+            position: 1,
+            legacy: Some("Canines".into()),
+            race: "Canine".into(),
+            confidence: None,
+        }
+    );
+    assert_eq!(conversion.races[0].legacy, None);
+
+    // the report survives saving and is narrowed down to single scenes on request
+    let dir = TempDir::new("convert");
+    let path = dir.join("report.slsb.json");
+    package.write(path.clone()).unwrap();
+    let loaded = Package::open(&path).unwrap();
+    assert_eq!(loaded.conversions.len(), 1);
+    assert_eq!(loaded.get_conversions(Some(&id))[0].scenes.len(), 1);
+    assert!(loaded
+        .get_conversions(Some(&NanoID::new_nanoid()))
+        .is_empty());
+}
+
+#[test]
+fn migrations_of_old_projects_are_recorded() {
+    let mut package = Package::from_slal_value(&slal_pack("Old", 2)).unwrap();
+    package.version = 5;
+    package.conversions.clear();
+    let json = serde_json::to_string(&package).unwrap();
+    let dir = TempDir::new("convert");
+    let path = dir.join("old.slsb.json");
+    fs::write(&path, json).unwrap();
+
+    let loaded = Package::open(&path).unwrap();
+    assert_eq!(loaded.conversions.len(), 1);
+    let record = &loaded.conversions[0];
+    assert_eq!(record.kind, ConversionKind::Migration);
+    assert_eq!((record.from_version, record.to_version), (5, VERSION));
+    assert_eq!(record.scenes.len(), 2);
+    assert_eq!(record.migrations.len(), 1);
+}
+
+#[test]
+fn project_folders_are_upgraded_in_place() {
+    let dir = TempDir::new("convert");
+    let mut package = Package::from_slal_value(&slal_pack("Old", 1)).unwrap();
+    package.version = 5;
+    package.conversions.clear();
+    fs::create_dir_all(dir.join("split").join("nested")).unwrap();
+    fs::create_dir_all(dir.join(".git")).unwrap();
+    let old = dir.join("split").join("nested").join("old.slsb.json");
+    fs::write(&old, serde_json::to_string(&package).unwrap()).unwrap();
+    fs::write(dir.join(".git").join("hidden.slsb.json"), "{}").unwrap();
+    fs::write(dir.join("slal.json"), "{}").unwrap();
+    let current = dir.join("current.slsb.json");
+    Package::from_slal_value(&slal_pack("Current", 1))
+        .unwrap()
+        .write(current.clone())
+        .unwrap();
+    let untouched = fs::read_to_string(&current).unwrap();
+    let broken = dir.join("split").join("broken.slsb.json");
+    fs::write(&broken, "{").unwrap();
+    assert_eq!(
+        project_files(&dir).unwrap(),
+        vec![current.clone(), broken.clone(), old.clone()]
+    );
+
+    let report = upgrade_projects(&dir).unwrap();
+    assert_eq!((report.upgraded(), report.failed()), (1, 1));
+    assert_eq!(report.files[2].from_version, Some(5));
+    assert!(report.files[1].error.is_some());
+    assert!(!report.files[0].upgraded);
+    assert_eq!(fs::read_to_string(&current).unwrap(), untouched);
+    let upgraded = Package::open(&old).unwrap();
+    assert_eq!(upgraded.version, VERSION);
+    assert_eq!(upgraded.conversions.len(), 1);
+    // a second run finds nothing left to upgrade
+    assert_eq!(upgrade_projects(&dir).unwrap().upgraded(), 0);
+}
+
+#[test]
+fn projects_without_a_version_are_upgraded() {
+    let dir = TempDir::new("convert_unversioned");
+    let package = Package::from_slal_value(&slal_pack("First", 1)).unwrap();
+    let mut value = serde_json::to_value(&package).unwrap();
+    value.as_object_mut().unwrap().remove("version");
+    let path = dir.join("first.slsb.json");
+    fs::write(&path, value.to_string()).unwrap();
+
+    let report = upgrade_projects(&dir).unwrap();
+    assert_eq!(report.files[0].error, None);
+    assert_eq!(report.files[0].from_version, Some(0));
+    assert!(report.files[0].upgraded);
+    assert_eq!(Package::open(&path).unwrap().version, VERSION);
+}
+
+#[test]
+fn stage_parameters_are_carried_over() {
+    let mut slal = slal_pack("Params", 1);
+    let animation = &mut slal["animations"][0];
+    animation["sound"] = json!("Squishing");
+    animation["stage"] = json!([{ "number": 1, "sound": "Sucking" }]);
+    animation["actors"][0]["stages"] = json!([
+        { "id": "A1", "open_mouth": true, "silent": 1 },
+        { "id": "A2" }
+    ]);
+    animation["actors"][1]["stages"] = json!([
+        { "id": "B1", "sos": 3 },
+        { "id": "B2", "up_down": -20 }
+    ]);
+    let package = Package::from_slal_value(&slal).unwrap();
+    let scene = package.scenes.values().next().unwrap();
+    let stages = &scene.stages;
+    assert_eq!(stages[0].extra.sound.as_deref(), Some("Squishing"));
+    assert_eq!(stages[1].extra.sound.as_deref(), Some("Sucking"));
+
+    let female = &stages[0].positions[0];
+    assert!(female.open_mouth && female.silent);
+    assert!(!stages[1].positions[0].open_mouth);
+    assert_eq!(stages[0].positions[1].schlong, 3);
+    assert_eq!(stages[1].positions[1].schlong, -9);
+    assert!(package.conversions[0].scenes[0].dropped.is_empty());
+}
+
+#[test]
+fn actor_types_are_resolved_through_aliases() {
+    let mut slal = slal_pack("Aliases", 1);
+    slal["animations"][0]["actors"][0]["type"] = json!(" FUTA ");
+    slal["animations"][0]["actors"][1]["type"] = json!("TransMale");
+    let package = Package::from_slal_value(&slal).unwrap();
+    let scene = package.scenes.values().next().unwrap();
+    let positions = &scene.stages[0].positions;
+    assert!(positions[0].sex.futa && !positions[0].sex.female);
+    assert!(positions[1].sex.male && positions[1].sex.futa);
+    let guesses = &package.conversions[0].scenes[0].guessed_genders;
+    assert_eq!(guesses.len(), 2);
+    assert_eq!(guesses[1].sex, "male, futa");
+    assert_eq!(guesses[1].reason, "alias TransMale");
+
+    slal["animations"][0]["actors"][1]["type"] = json!("Femboy");
+    let err = Package::from_slal_value(&slal).unwrap_err();
+    assert_eq!(err.message().key, "slal.unknown_sex");
+
+    // aliases of the folder the slal file is in are picked up on conversion
+    let dir = TempDir::new("convert");
+    fs::write(
+        dir.join(ALIAS_FILE),
+        "Femboy: { male: true }\nCreatureFuta: { female: true, creature: true }\n",
+    )
+    .unwrap();
+    let aliases = SexAliases::load(&dir).unwrap();
+    assert_eq!(
+        aliases.resolve("creaturefuta").unwrap().describe(),
+        "female"
+    );
+    fs::write(dir.join("pack.json"), slal.to_string()).unwrap();
+    let package = Package::from_slal(dir.join("pack.json")).unwrap();
+    let scene = package.scenes.values().next().unwrap();
+    assert!(scene.stages[0].positions[1].sex.male);
+
+    let mut aliases = SexAliases::default();
+    let err = aliases.insert("Nobody", ActorType::default()).unwrap_err();
+    assert_eq!(err.message().key, "slal.invalid_alias");
+}
+
+#[test]
+fn creature_races_are_inferred_from_names_and_tags() {
+    assert_eq!(guess_racekeys("FB_HorseDoggy"), vec!["Horse"]);
+    assert_eq!(
+        guess_racekeys("Giant Spider, Wolves"),
+        vec!["Giant Spider", "Wolf"]
+    );
+    assert_eq!(
+        guess_racekeys("ChaurusHunter Chaurus"),
+        vec!["Chaurus Hunter", "Chaurus"]
+    );
+    assert!(guess_racekeys("Missionary").is_empty());
+
+    let mut slal = slal_pack("Creatures", 1);
+    let animation = &mut slal["animations"][0];
+    animation["name"] = json!("Stable Horse");
+    animation["tags"] = json!("Beast, Chaurus, Doggy");
+    animation["actors"][1]["type"] = json!("CreatureMale");
+    let package = Package::from_slal_value(&slal).unwrap();
+    let races = &package.conversions[0].scenes[0].races;
+    assert_eq!(races[1].race, "Chaurus");
+    assert_eq!(races[1].confidence, Some(Confidence::High));
+    assert_eq!(races[1].legacy, None);
+    assert_eq!(races[0].confidence, None);
+
+    // several or no races in the tags leave the result in doubt
+    slal["animations"][0]["tags"] = json!("Beast");
+    let package = Package::from_slal_value(&slal).unwrap();
+    let races = &package.conversions[0].scenes[0].races;
+    assert_eq!(races[1].race, "Horse");
+    assert_eq!(races[1].confidence, Some(Confidence::Low));
+
+    slal["animations"][0]["name"] = json!("Stable");
+    let err = Package::from_slal_value(&slal).unwrap_err();
+    assert_eq!(err.message().key, "slal.unknown_creature");
+}
+
+#[test]
+fn pasted_animations_are_added_to_the_project() {
+    let mut prjct = Package::new();
+    prjct.pack_name = "Mine".into();
+    let pack = slal_pack("Theirs", 2);
+    let snippet = format!("  {},\n", pack["animations"][1]);
+
+    let ids = prjct.import_slal_snippet(&snippet).unwrap();
+    assert_eq!(ids.len(), 1);
+    let scene = prjct.get_scene(&ids[0]).unwrap();
+    assert_eq!(scene.name, "Theirs 1");
+    assert_eq!(scene.stages.len(), 2);
+    assert_eq!(prjct.pack_name, "Mine");
+    let record = prjct.conversions.last().unwrap();
+    assert_eq!(record.source.as_deref(), Some("clipboard"));
+    assert!(record.scene(&ids[0]).is_some());
+
+    let list = pack["animations"].to_string();
+    assert_eq!(prjct.import_slal_snippet(&list).unwrap().len(), 2);
+    assert_eq!(prjct.scenes.len(), 3);
+
+    for text in ["{", "[]", "\"A1\"", &pack.to_string()] {
+        assert!(prjct.import_slal_snippet(text).is_err());
+    }
+    assert_eq!(prjct.scenes.len(), 3);
+}
+
+#[test]
+fn animations_convert_in_order_reporting_progress() {
+    let pack = slal_pack("Mega", 50);
+    let reported = std::sync::Mutex::new(vec![]);
+    let on_progress = |progress: SlalProgress| reported.lock().unwrap().push(progress);
+    let prjct = Package::from_slal_aliased(&pack, &SexAliases::default(), &on_progress).unwrap();
+
+    let names: Vec<&str> = prjct.conversions[0]
+        .scenes
+        .iter()
+        .map(|scene| scene.name.as_str())
+        .collect();
+    let expected: Vec<String> = (0..50).map(|i| format!("Mega {}", i)).collect();
+    assert_eq!(names, expected);
+    let mut converted: Vec<usize> = reported
+        .into_inner()
+        .unwrap()
+        .iter()
+        .map(|progress| {
+            assert_eq!(progress.total, Some(50));
+            progress.converted
+        })
+        .collect();
+    converted.sort();
+    assert_eq!(converted, (1..=50).collect::<Vec<_>>());
+
+    let mut broken = slal_pack("Mega", 50);
+    broken["animations"][30]["actors"][0]["type"] = json!("Ghost");
+    broken["animations"][40]["actors"] = json!(null);
+    let err = Package::from_slal_value(&broken).unwrap_err();
+    assert_eq!(err.message().key, "slal.unknown_sex");
+}
+
+#[test]
+fn slal_files_are_streamed_in_batches() {
+    let dir = TempDir::new("convert");
+    let mut pack = slal_pack("Huge", 600);
+    pack["version"] = json!(3);
+    fs::write(dir.join("huge.json"), pack.to_string()).unwrap();
+    let reported = std::sync::Mutex::new(vec![]);
+    let on_progress = |progress: SlalProgress| reported.lock().unwrap().push(progress);
+    let prjct = Package::from_slal_with_progress(dir.join("huge.json"), &on_progress).unwrap();
+
+    assert_eq!(prjct.pack_name, "Huge");
+    let names: Vec<&str> = prjct.conversions[0]
+        .scenes
+        .iter()
+        .map(|scene| scene.name.as_str())
+        .collect();
+    let expected: Vec<String> = (0..600).map(|i| format!("Huge {}", i)).collect();
+    assert_eq!(names, expected);
+    // streamed files are only read once, so progress is reported by the bytes read from them
+    let size = fs::metadata(dir.join("huge.json")).unwrap().len();
+    let reported = reported.into_inner().unwrap();
+    let mut converted: Vec<usize> = reported
+        .iter()
+        .map(|progress| {
+            assert_eq!(progress.total, None);
+            assert_eq!(progress.file_size, size);
+            assert!(progress.bytes_read > 0 && progress.bytes_read <= size);
+            progress.converted
+        })
+        .collect();
+    converted.sort();
+    assert_eq!(converted, (1..=600).collect::<Vec<_>>());
+    let first = reported.iter().min_by_key(|p| p.converted).unwrap();
+    let last = reported.iter().max_by_key(|p| p.converted).unwrap();
+    assert!(first.bytes_read < last.bytes_read);
+
+    // errors of later batches still reject the file
+    pack["animations"][500]["actors"][0]["type"] = json!("Ghost");
+    fs::write(dir.join("huge.json"), pack.to_string()).unwrap();
+    let err = Package::from_slal(dir.join("huge.json")).unwrap_err();
+    assert_eq!(err.message().key, "slal.unknown_sex");
+
+    fs::write(dir.join("huge.json"), r#"{ "name": "Huge" }"#).unwrap();
+    let err = Package::from_slal(dir.join("huge.json")).unwrap_err();
+    assert_eq!(err.message().key, "slal.missing_attribute");
+    fs::write(
+        dir.join("huge.json"),
+        r#"{ "name": "Huge", "animations": [] } x"#,
+    )
+    .unwrap();
+    assert!(Package::from_slal(dir.join("huge.json")).is_err());
+}
+
+#[test]
+fn imported_scenes_are_classified() {
+    let slal = json!({
+        "name": "Archetypes",
+        "animations": [
+            {
+                "name": "Solo",
+                "actors": [{ "type": "Female", "stages": [{ "id": "S1" }] }]
+            },
+            {
+                "name": "Canine",
+                "creature_race": "Canines",
+                "actors": [
+                    { "type": "Female", "stages": [{ "id": "A1" }] },
+                    { "type": "CreatureMale", "stages": [{ "id": "B1" }] }
+                ]
+            },
+            {
+                "name": "Threesome",
+                "actors": [
+                    { "type": "Female", "stages": [{ "id": "C1" }] },
+                    { "type": "Male", "stages": [{ "id": "D1" }] },
+                    { "type": "Male", "stages": [{ "id": "E1" }] }
+                ]
+            }
+        ]
+    });
+    let mut package = Package::from_slal_value(&slal).unwrap();
+    let archetype = |package: &Package, name: &str| {
+        let scene = package.scenes.values().find(|s| s.name == name).unwrap();
+        scene.archetype
+    };
+    assert_eq!(archetype(&package, "Solo"), Some(Archetype::Solo));
+    assert_eq!(
+        archetype(&package, "Canine"),
+        Some(Archetype::HumanCreature)
+    );
+    assert_eq!(archetype(&package, "Threesome"), Some(Archetype::Group));
+
+    let page = package.scene_page(0, 10, Some(Archetype::HumanCreature));
+    assert_eq!(page.total, 1);
+    assert_eq!(page.scenes[0].name, "Canine");
+
+    let scene = package
+        .scenes
+        .values_mut()
+        .find(|s| s.name == "Canine")
+        .unwrap();
+    scene.positions[1].race = "Human".into();
+    assert_eq!(scene.detect_archetype(), Archetype::PairedHuman);
+    assert!(validate_scene(scene, None)
+        .iter()
+        .any(|d| d.message.key == "validate.archetype"));
+}
+
+#[test]
+fn user_race_keys_extend_the_builtin_mappings() {
+    let dir = TempDir::new("convert");
+    let yaml = "legacy:\n  ExoticDogs: Canine\nfolders:\n  Canine: exotic/dog\n";
+    let overrides = RaceKeyOverrides::parse(yaml, "racekeys.yaml").unwrap();
+    assert_eq!(overrides.legacy_race("exoticdogs").unwrap(), "Canine");
+    assert_eq!(overrides.folder("Canine").unwrap(), "exotic\\dog");
+    let err =
+        RaceKeyOverrides::parse("legacy:\n  dogs: Exotic Dog\n", "racekeys.yaml").unwrap_err();
+    assert_eq!(err.message().key, "racekeys.unknown_race");
+    let err =
+        RaceKeyOverrides::parse("folders:\n  Canine: ../../meshes\n", "racekeys.yaml").unwrap_err();
+    assert_eq!(err.message().key, "racekeys.invalid_folder");
+
+    // the app data file is read last and wins over the one next to the app
+    let files = [
+        dir.join("app.yaml"),
+        dir.join("data.yaml"),
+        dir.join("missing.yaml"),
+    ];
+    fs::write(&files[0], "legacy:\n  slsbtestwolves: Canine\n").unwrap();
+    fs::write(&files[1], "legacy:\n  slsbtestwolves: Wolf\n").unwrap();
+    assert!(map_legacy_to_racekey("slsbtestwolves").is_err());
+    let loaded = load_race_overrides(&files).unwrap();
+    assert_eq!(loaded.legacy.len(), 1);
+    assert_eq!(map_legacy_to_racekey("SLSBTestWolves").unwrap(), "Wolf");
+    // a broken file keeps the previous mappings
+    fs::write(&files[1], "legacy: [").unwrap();
+    assert!(load_race_overrides(&files).is_err());
+    assert_eq!(map_legacy_to_racekey("slsbtestwolves").unwrap(), "Wolf");
+    fs::remove_file(&files[1]).unwrap();
+    fs::remove_file(&files[0]).unwrap();
+    load_race_overrides(&files).unwrap();
+    assert!(map_legacy_to_racekey("slsbtestwolves").is_err());
+}
@@ -0,0 +1,110 @@
+use crate::project::{
+    csv::{import_scenes, parse_csv, project_summary, SUMMARY_COLUMNS},
+    package::Package,
+};
+
+const SHEET: &str = "Scene,Stage,Events,Races,Sexes,Duration,Tags
+Doggy,2,A2;B2,,,8.5,\"Vaginal, Doggy\"
+Doggy,1,A1;B1,Human;Canines,Female;CreatureMale,,Foreplay
+\"Kiss, \"\"Slow\"\"\",1,K1,,futa,,
+";
+
+#[test]
+fn quoted_fields_and_empty_rows_are_parsed() {
+    let rows = parse_csv("a,\"b,c\",\"d\"\"e\"\r\n\r\n,,\n1,2,3").unwrap();
+    assert_eq!(rows, vec![vec!["a", "b,c", "d\"e"], vec!["1", "2", "3"]]);
+    let err = parse_csv("a,\"b\nc").unwrap_err();
+    assert_eq!(err.message().key, "csv.unterminated_quote");
+}
+
+#[test]
+fn stage_sheets_become_linear_scenes() {
+    let scenes = import_scenes(SHEET).unwrap();
+    assert_eq!(scenes.len(), 2);
+    let doggy = &scenes[0];
+    assert_eq!(doggy.name, "Doggy");
+    assert_eq!(doggy.stages.len(), 2);
+    assert_eq!(doggy.stages[0].positions[0].event, vec!["A1"]);
+    assert_eq!(doggy.stages[1].extra.fixed_len, 8.5);
+    assert_eq!(doggy.stages[1].tags, vec!["Vaginal", "Doggy"]);
+    assert!(doggy.stages[1].positions.iter().all(|p| p.climax));
+    assert!(!doggy.stages[0].positions[0].climax);
+    assert_eq!(doggy.positions[1].race, "Canine");
+    assert!(doggy.positions[0].sex.female && !doggy.positions[0].sex.male);
+    assert_eq!(doggy.root, doggy.stages[0].id);
+    assert_eq!(
+        doggy.graph[&doggy.stages[0].id].dest,
+        vec![doggy.stages[1].id.clone()]
+    );
+
+    let kiss = &scenes[1];
+    assert_eq!(kiss.name, "Kiss, \"Slow\"");
+    assert!(kiss.positions[0].sex.futa);
+    assert_eq!(kiss.positions[0].race, "Human");
+}
+
+#[test]
+fn invalid_sheets_name_the_offending_row() {
+    let err = import_scenes("Scene,Events\nA,B").unwrap_err();
+    assert_eq!(err.message().key, "csv.missing_column");
+
+    let err = import_scenes("Scene,Stage,Events,Duration\nA,1,X,soon").unwrap_err();
+    let message = err.message();
+    assert_eq!(message.key, "csv.invalid_value");
+    assert_eq!(message.params["row"], "2");
+    assert_eq!(message.params["column"], "duration");
+
+    let err = import_scenes("Scene,Stage,Events\nA,1,X;Y\nA,2,X").unwrap_err();
+    assert_eq!(err.message().key, "csv.actor_count");
+    let err = import_scenes("Scene,Stage,Events\nA,1,X\nA,1,Y").unwrap_err();
+    assert_eq!(err.message().key, "csv.duplicate_stage");
+    let err = import_scenes("Scene,Stage,Events,Races\nA,1,X,Unicorn").unwrap_err();
+    assert_eq!(err.message().key, "error.unknown_race");
+    let err = import_scenes("Scene,Stage,Events,Sexes\nA,1,X,Male\nA,2,X,Female").unwrap_err();
+    let message = err.message();
+    assert_eq!(message.key, "csv.conflicting_actor");
+    assert_eq!(message.params["row"], "3");
+    assert_eq!(message.params["expected"], "Male");
+}
+
+#[test]
+fn actors_may_be_described_by_any_stage() {
+    let scenes = import_scenes(
+        "Scene,Stage,Events,Races,Sexes\nA,1,X;Y,,\nA,2,X;Y,;Canines,\nA,3,X;Y,Human;,Male",
+    )
+    .unwrap();
+    assert_eq!(scenes[0].positions[1].race, "Canine");
+    assert_eq!(scenes[0].positions[0].race, "Human");
+    assert!(scenes[0].positions[0].sex.male);
+}
+
+#[test]
+fn summaries_list_every_position_of_every_stage() {
+    let mut package = Package::new();
+    for mut scene in import_scenes(SHEET).unwrap() {
+        if scene.name == "Doggy" {
+            let position = &mut scene.stages[1].positions[0];
+            position.event.push("A2_Alt".into());
+            position.variants = vec!["A2_V1".into(), "A2_V2".into()];
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    let summary = project_summary(&package);
+    let rows = parse_csv(&summary).unwrap();
+    assert_eq!(rows[0], SUMMARY_COLUMNS);
+    // two stages with two positions, one stage with one
+    assert_eq!(rows.len(), 1 + 4 + 1);
+    let doggy = &rows[1];
+    assert_eq!(doggy[0], "Doggy");
+    assert_eq!((doggy[2].as_str(), doggy[4].as_str()), ("1", "1"));
+    assert_eq!(doggy[5], "A1");
+    assert_eq!(&doggy[8..11], ["0", "1", "0"]);
+    assert_eq!(rows[3][5], "A2;A2_Alt");
+    assert_eq!(rows[3][6], "A2_V1;A2_V2");
+    assert_eq!(rows[4][6], "");
+    assert_eq!(rows[4][11], "8.5");
+    assert_eq!(rows[4][12], "Vaginal, Doggy");
+    assert_eq!(rows[4][14], "1");
+    assert_eq!(rows[5][0], "Kiss, \"Slow\"");
+    assert_eq!(rows[5][7], "Human");
+}
@@ -0,0 +1,110 @@
+use std::fmt::Debug;
+
+use super::generators::*;
+use crate::project::{
+    serialize::{ByteWriter, EncodeBinary, StringTable},
+    NanoID,
+};
+
+// The size reported by get_byte_size() must match the number of bytes written by write_byte()
+fn assert_encoded_size<T: EncodeBinary + Debug>(seed: u64, value: &T) {
+    assert_encoded_size_with(seed, value, StringTable::default());
+}
+
+// Same as assert_encoded_size, for values writing strings interned into the given table
+fn assert_encoded_size_with<T: EncodeBinary + Debug>(seed: u64, value: &T, strings: StringTable) {
+    let mut buf = ByteWriter::default();
+    buf.set_strings(strings);
+    value.write_byte(&mut buf);
+    assert_eq!(
+        buf.len(),
+        value.get_byte_size(),
+        "Size mismatch for seed {}: {:?}",
+        seed,
+        value
+    );
+}
+
+macro_rules! size_property {
+    ($name:ident, $gen:expr) => {
+        #[test]
+        fn $name() {
+            for seed in 0..CASES {
+                let mut rng = rng(seed);
+                let value = $gen(&mut rng);
+                assert_encoded_size(seed, &value);
+            }
+        }
+    };
+    ($name:ident, $gen:expr, interned) => {
+        #[test]
+        fn $name() {
+            for seed in 0..CASES {
+                let mut rng = rng(seed);
+                let value = $gen(&mut rng);
+                let mut table = StringTable::default();
+                value.intern_strings(&mut table);
+                assert_encoded_size_with(seed, &value, table);
+            }
+        }
+    };
+}
+
+size_property!(string_size, |rng: &mut _| arb_string(rng, 64));
+size_property!(strings_size, |rng: &mut _| arb_strings(rng, 8, 32));
+size_property!(offset_size, arb_offset);
+size_property!(sex_size, arb_sex);
+size_property!(stripping_size, arb_stripping);
+size_property!(furniture_size, arb_furniture);
+size_property!(position_info_size, arb_position_info);
+size_property!(position_size, arb_position, interned);
+size_property!(stage_size, |rng: &mut _| arb_stage(rng, 3), interned);
+size_property!(scene_size, arb_scene, interned);
+size_property!(package_size, arb_package);
+
+#[test]
+fn primitive_sizes() {
+    assert_encoded_size(0, &true);
+    assert_encoded_size(0, &7u8);
+    assert_encoded_size(0, &7u32);
+    assert_encoded_size(0, &7u64);
+    assert_encoded_size(0, &1.5f32);
+    assert_encoded_size(0, &NanoID::new_nanoid());
+    assert_encoded_size(0, &NanoID::new_prefix());
+}
+
+#[test]
+fn format_spec_describes_package() {
+    let spec = crate::project::package::Package::format_spec();
+    assert_eq!(spec["format"], "slr");
+    assert_eq!(spec["root"]["kind"], "struct");
+    assert_eq!(spec["root"]["name"], "Package");
+}
+
+#[test]
+fn string_table_deduplicates() {
+    let mut rng = rng(0);
+    let mut stage = arb_stage(&mut rng, 2);
+    stage.tags = vec!["Doggy".into(), "doggy".into(), " Dog gy".into()];
+    for position in &mut stage.positions {
+        position.event = vec!["Shared_A1".into()];
+        position.tags = vec!["doggy".into()];
+    }
+    let mut table = StringTable::default();
+    stage.intern_strings(&mut table);
+    assert_eq!(table.len(), 2);
+
+    let mut buf = ByteWriter::default();
+    buf.set_strings(table.clone());
+    stage.write_byte(&mut buf);
+    assert_eq!(buf.strings().len(), table.len());
+}
+
+#[test]
+#[should_panic(expected = "missing from the string table")]
+fn strings_must_be_interned_before_writing() {
+    let mut rng = rng(0);
+    let stage = arb_stage(&mut rng, 2);
+    let mut buf = ByteWriter::default();
+    stage.write_byte(&mut buf);
+}
@@ -0,0 +1,180 @@
+use super::generators::*;
+use crate::project::{
+    events::{
+        event_usage, fix_events, is_fnis_legal, known_event_source, prefix_events, sanitize_event,
+    },
+    package::Package,
+    validate::{scene_warnings, validate},
+    NanoID,
+};
+
+fn package_with_events(events: &[&[&str]]) -> Package {
+    let mut rng = rng(11);
+    let mut package = Package::new();
+    for scene_events in events {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        scene.stages.truncate(1);
+        scene.stages[0].positions.truncate(1);
+        scene.stages[0].positions[0].event = scene_events.iter().map(|e| e.to_string()).collect();
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    package
+}
+
+fn events_of(package: &Package) -> Vec<String> {
+    let mut ret: Vec<String> = package
+        .scenes
+        .values()
+        .flat_map(|s| s.stages[0].positions[0].event.clone())
+        .collect();
+    ret.sort();
+    ret
+}
+
+#[test]
+fn prefix_replaces_old_namespace() {
+    let mut package = package_with_events(&[&["Doggy01_A1", "Doggy01_A2"], &["Old_Missionary"]]);
+    let report = prefix_events(&mut package, "Pack_", Some("Old_"), None).unwrap();
+    assert!(report.applied);
+    assert_eq!(report.renamed, 3);
+    assert_eq!(report.scenes.len(), 2);
+    assert_eq!(
+        events_of(&package),
+        vec!["Pack_Doggy01_A1", "Pack_Doggy01_A2", "Pack_Missionary"]
+    );
+    // already prefixed events are left as is
+    let report = prefix_events(&mut package, "Pack_", None, None).unwrap();
+    assert_eq!(report.renamed, 0);
+    assert!(report.scenes.is_empty());
+}
+
+#[test]
+fn prefix_matches_whole_namespace() {
+    let mut package = package_with_events(&[&["Pack_A1", "Package_A2"], &["Oldie_B1"]]);
+    let report = prefix_events(&mut package, "Pack", Some("Old"), None).unwrap();
+    assert_eq!(report.renamed, 2);
+    assert_eq!(
+        events_of(&package),
+        vec!["Pack_A1", "Pack_Oldie_B1", "Pack_Package_A2"]
+    );
+}
+
+#[test]
+fn prefix_reports_collisions_without_renaming() {
+    let mut package = package_with_events(&[&["A_Doggy"], &["Doggy"]]);
+    let report = prefix_events(&mut package, "A_", None, None).unwrap();
+    assert!(!report.applied);
+    assert_eq!(report.collisions.len(), 1);
+    assert_eq!(report.collisions[0].event, "A_Doggy");
+    assert_eq!(report.collisions[0].sources, vec!["A_Doggy", "Doggy"]);
+    assert_eq!(events_of(&package), vec!["A_Doggy", "Doggy"]);
+}
+
+#[test]
+fn event_usage_reports_orphans_and_missing_files() {
+    let mut package = package_with_events(&[&["Pack_A1", "Pack_A2"], &["__BLANK__"], &["Pack_B1"]]);
+    let err = event_usage(&package).unwrap_err();
+    assert_eq!(err.message().key, "events.no_animation_root");
+
+    let root = TempDir::new("usage");
+    let nested = root.join("meshes").join("actors");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(root.join("pack_a1.HKX"), [0; 4]).unwrap();
+    std::fs::write(nested.join("Pack_A2.hkx"), [0; 4]).unwrap();
+    std::fs::write(nested.join("Orphan.hkx"), [0; 16]).unwrap();
+    std::fs::write(nested.join("Pack_B1.txt"), [0; 4]).unwrap();
+    package.animation_root = Some(root.clone());
+
+    let report = event_usage(&package).unwrap();
+    assert_eq!(report.files, 3);
+    assert_eq!(report.unused_files, vec![nested.join("Orphan.hkx")]);
+    assert_eq!(report.unused_bytes, 16);
+    let missing: Vec<&str> = report
+        .missing_files
+        .iter()
+        .map(|m| m.event.as_str())
+        .collect();
+    assert_eq!(missing, vec!["Pack_B1"]);
+    assert_eq!(report.missing_files[0].scenes.len(), 1);
+}
+
+#[test]
+fn illegal_events_are_sanitized() {
+    assert!(is_fnis_legal("Pack_A1_S1"));
+    assert!(is_fnis_legal("__BLANK__"));
+    for event in ["", "Pack A1", "Pack,A1", "Päck_A1", "-a", "'A1", "B", "fuo"] {
+        assert!(!is_fnis_legal(event), "{}", event);
+        assert!(is_fnis_legal(&sanitize_event(event)), "{}", event);
+    }
+    assert_eq!(sanitize_event(" Pack A1 "), "Pack_A1");
+    assert_eq!(sanitize_event("-a"), "a");
+    assert_eq!(sanitize_event("s"), "s_");
+
+    let mut rng = rng(62);
+    let mut package = Package::new();
+    let mut scenes = vec![];
+    for event in ["Fix Me", "Fix,Me", "Locked Event"] {
+        let mut scene = arb_scene(&mut rng);
+        for stage in &mut scene.stages {
+            for position in &mut stage.positions {
+                position.event = vec![event.into()];
+            }
+        }
+        scene.locked = event.starts_with("Locked");
+        scenes.push(scene.id.clone());
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    // both would become Fix_Me
+    let report = fix_events(&mut package, None);
+    assert!(!report.applied);
+    assert_eq!(report.collisions[0].event, "Fix_Me");
+
+    package.scenes.remove(&scenes[1]);
+    let report = fix_events(&mut package, None);
+    assert!(report.applied);
+    assert!(report.renamed > 0);
+    let events = |id: &NanoID| -> Vec<String> {
+        package.scenes[id]
+            .stages
+            .iter()
+            .flat_map(|stage| stage.positions.iter().flat_map(|p| p.event.clone()))
+            .collect()
+    };
+    assert!(events(&scenes[0]).iter().all(|e| e == "Fix_Me"));
+    assert!(events(&scenes[2]).iter().all(|e| e == "Locked Event"));
+}
+
+#[test]
+fn events_of_the_game_and_frameworks_are_warned_about() {
+    assert_eq!(
+        known_event_source("idleforcedefaultstate", false),
+        Some("Skyrim")
+    );
+    assert_eq!(
+        known_event_source("Arrok_Missionary_A1_S1", false),
+        Some("SexLab")
+    );
+    assert_eq!(known_event_source("SOSBend5", false), Some("SOS"));
+    assert_eq!(known_event_source("Pack_Arrok_A1_S1", false), None);
+    // standalone packs of the same authors are converted from SLAL
+    assert_eq!(known_event_source("Arrok_Missionary_A1_S1", true), None);
+    assert_eq!(known_event_source("AP_Cowgirl_A1_S1", false), None);
+    assert_eq!(known_event_source("IdleStop", true), Some("Skyrim"));
+
+    let package = package_with_events(&[&["Pack_A1_S1"], &["Pack_A2_S1", "IdleStop"]]);
+    let collisions: Vec<_> = validate(&package)
+        .into_iter()
+        .filter(|d| d.message.key == "validate.known_event")
+        .collect();
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].message.params["event"], "IdleStop");
+    assert_eq!(collisions[0].message.params["source"], "Skyrim");
+    // listed with the warnings of the scene, so an intended collision can be suppressed
+    let scene = package
+        .get_scene(collisions[0].scene.as_ref().unwrap())
+        .unwrap();
+    assert!(scene_warnings(scene, Some(&package))
+        .iter()
+        .any(|w| w.diagnostic.message.key == "validate.known_event" && !w.suppressed));
+}
@@ -0,0 +1,259 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    project::{
+        define::{FurnitureData, Node, Offset, Requirements, Sex, Stripping},
+        package::Package,
+        position::Position,
+        position_info::PositionInfo,
+        scene::Scene,
+        stage::{Extra, Stage, StageTimers},
+        NanoID,
+    },
+    racekeys::get_race_keys_string,
+};
+
+// Number of generated cases per property
+pub const CASES: u64 = 256;
+
+const CHARSET: &[char] = &[
+    'a', 'B', 'c', 'D', 'e', 'z', '0', '9', '_', '-', ' ', '\t', 'ä', 'ß', 'é', 'ж', '日', '本',
+];
+
+const FURNITURE: &[&str] = &[
+    "None",
+    "BedRoll",
+    "BedSingle",
+    "BedDouble",
+    "Wall",
+    "Table",
+    "Chair",
+    "Throne",
+    "XCross",
+    "Pillory",
+    "NotAFurniture",
+];
+
+pub fn rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+// A new folder below the system temp folder, removed with everything in it when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("slsb_{}_{}", name, NanoID::new_nanoid().0));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn arb_string(rng: &mut StdRng, max_len: usize) -> String {
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| *CHARSET.choose(rng).unwrap()).collect()
+}
+
+// Events only carry characters FNIS can read, their content does not matter otherwise
+pub fn arb_event(rng: &mut StdRng) -> String {
+    let name: String = arb_string(rng, 16)
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .collect();
+    format!("E{}", name)
+}
+
+pub fn arb_strings(rng: &mut StdRng, max_count: usize, max_len: usize) -> Vec<String> {
+    let count = rng.gen_range(0..=max_count);
+    (0..count).map(|_| arb_string(rng, max_len)).collect()
+}
+
+pub fn arb_offset(rng: &mut StdRng) -> Offset {
+    Offset {
+        x: rng.gen_range(-500.0..500.0),
+        y: rng.gen_range(-500.0..500.0),
+        z: rng.gen_range(-500.0..500.0),
+        r: rng.gen_range(0.0..360.0),
+        rx: rng.gen_range(-90.0..90.0),
+        ry: rng.gen_range(-90.0..90.0),
+    }
+}
+
+pub fn arb_sex(rng: &mut StdRng) -> Sex {
+    // at least one flag must be set for the sex to be encodable
+    let bits = rng.gen_range(1..8u8);
+    Sex {
+        male: bits & 1 != 0,
+        female: bits & 2 != 0,
+        futa: bits & 4 != 0,
+    }
+}
+
+pub fn arb_stripping(rng: &mut StdRng) -> Stripping {
+    serde_json::from_value(serde_json::json!({
+        "default": rng.gen_bool(0.5),
+        "everything": rng.gen_bool(0.5),
+        "nothing": rng.gen_bool(0.5),
+        "helmet": rng.gen_bool(0.5),
+        "gloves": rng.gen_bool(0.5),
+        "boots": rng.gen_bool(0.5),
+    }))
+    .unwrap()
+}
+
+pub fn arb_furniture(rng: &mut StdRng) -> FurnitureData {
+    let count = rng.gen_range(1..4);
+    FurnitureData {
+        furni_types: (0..count)
+            .map(|_| FURNITURE.choose(rng).unwrap().to_string())
+            .collect(),
+        allow_bed: rng.gen_bool(0.5),
+        offset: arb_offset(rng),
+    }
+}
+
+pub fn arb_position_info(rng: &mut StdRng) -> PositionInfo {
+    PositionInfo {
+        sex: arb_sex(rng),
+        race: get_race_keys_string().choose(rng).unwrap().clone(),
+        scale: rng.gen_range(0.5..2.0),
+        submissive: rng.gen_bool(0.5),
+        vampire: rng.gen_bool(0.5),
+        dead: rng.gen_bool(0.5),
+        behaviour_folder: None,
+    }
+}
+
+pub fn arb_position(rng: &mut StdRng) -> Position {
+    let mut position = Position::new(None);
+    let events = rng.gen_range(1..4);
+    position.event = (0..events).map(|_| arb_event(rng)).collect();
+    position.anim_obj = arb_string(rng, 12);
+    position.offset = arb_offset(rng);
+    position.strip_data = arb_stripping(rng);
+    position.climax = rng.gen_bool(0.3);
+    position.tags = arb_strings(rng, 4, 10);
+    position.open_mouth = rng.gen_bool(0.2);
+    position.silent = rng.gen_bool(0.2);
+    position.schlong = rng.gen_range(-9..=9);
+    position
+}
+
+pub fn arb_stage(rng: &mut StdRng, position_count: usize) -> Stage {
+    Stage {
+        id: NanoID::new_nanoid(),
+        name: arb_string(rng, 16),
+        positions: (0..position_count).map(|_| arb_position(rng)).collect(),
+        tags: arb_strings(rng, 6, 12),
+        extra: Extra {
+            fixed_len: if rng.gen_bool(0.5) {
+                0.0
+            } else {
+                rng.gen_range(0.0..60.0)
+            },
+            nav_text: arb_string(rng, 24),
+            sound: rng.gen_bool(0.5).then(|| arb_string(rng, 12)),
+        },
+        shortcut: false,
+        ending: false,
+    }
+}
+
+pub fn arb_scene(rng: &mut StdRng) -> Scene {
+    let position_count = rng.gen_range(1..=5);
+    let stage_count = rng.gen_range(1..=8);
+    let mut stages: Vec<Stage> = (0..stage_count)
+        .map(|_| arb_stage(rng, position_count))
+        .collect();
+    let mut graph = HashMap::new();
+    for stage in &stages {
+        let dest_count = rng.gen_range(0..=2.min(stages.len()));
+        let dest = stages
+            .choose_multiple(rng, dest_count)
+            .map(|s| s.id.clone())
+            .collect();
+        graph.insert(
+            stage.id.clone(),
+            Node {
+                dest,
+                x: rng.gen_range(0.0..1000.0),
+                y: rng.gen_range(0.0..1000.0),
+            },
+        );
+    }
+    // only stages reachable from the root are valid shortcuts
+    let root = stages[0].id.clone();
+    for stage in stages.iter_mut().skip(1) {
+        stage.shortcut = graph[&root].dest.contains(&stage.id) && rng.gen_bool(0.5);
+    }
+    // every scene needs a reachable ending
+    let ending = graph[&root].dest.first().unwrap_or(&root).clone();
+    for stage in &mut stages {
+        stage.ending = stage.id == ending;
+    }
+    Scene {
+        id: NanoID::new_nanoid(),
+        name: arb_string(rng, 24),
+        root,
+        stages,
+        graph,
+        furniture: arb_furniture(rng),
+        private: rng.gen_bool(0.5),
+        tags: arb_strings(rng, 4, 12),
+        positions: (0..position_count)
+            .map(|_| arb_position_info(rng))
+            .collect(),
+        has_warnings: rng.gen_bool(0.1),
+        author: rng.gen_bool(0.5).then(|| arb_string(rng, 12)),
+        source_pack: rng.gen_bool(0.5).then(|| arb_string(rng, 12)),
+        requirements: Requirements {
+            plugins: (0..rng.gen_range(0..=2))
+                .map(|_| format!("{}.esp", arb_string(rng, 12)))
+                .collect(),
+            keywords: arb_strings(rng, 2, 12),
+        },
+        ..Default::default()
+    }
+}
+
+pub fn arb_package(rng: &mut StdRng) -> Package {
+    let mut package = Package::new();
+    package.pack_name = arb_string(rng, 24);
+    package.pack_author = arb_string(rng, 16);
+    let scene_count = rng.gen_range(0..=6);
+    for _ in 0..scene_count {
+        let scene = arb_scene(rng);
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    package.stage_timers = StageTimers {
+        foreplay: rng.gen_range(0..60) as f32,
+        main: rng.gen_range(0..60) as f32,
+        climax: rng.gen_range(0..60) as f32,
+    };
+    package
+}
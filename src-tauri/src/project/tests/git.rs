@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use crate::project::{
+    audit::AuditEntry,
+    git::{
+        commit_message, commit_project, init_repository, project_history, restore_project,
+        uncommitted_changes,
+    },
+    package::Package,
+};
+
+use super::generators::{arb_scene, rng, TempDir};
+
+// The folder is removed once the returned guard is dropped
+fn saved_project() -> (TempDir, Package) {
+    let dir = TempDir::new("git");
+    let mut package = Package::new();
+    package.pack_name = "Versioned".into();
+    let path: PathBuf = dir.join("Versioned.slsb.json");
+    package.write(path.clone()).unwrap();
+    package.pack_path = path;
+    (dir, package)
+}
+
+#[test]
+fn projects_are_committed_and_restored() {
+    let (_dir, mut package) = saved_project();
+    let err = commit_project(&package, "Too early").unwrap_err();
+    assert_eq!(err.message().key, "git.no_repository");
+
+    init_repository(&package).unwrap();
+    assert_eq!(project_history(&package).unwrap().len(), 1);
+    assert!(uncommitted_changes(&package).unwrap().is_empty());
+    // nothing changed since the last commit
+    assert_eq!(commit_project(&package, "Empty").unwrap(), None);
+
+    let scene = arb_scene(&mut rng(7));
+    package.scenes.insert(scene.id.clone(), scene);
+    package.write(package.pack_path.clone()).unwrap();
+    let changes = uncommitted_changes(&package).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].status, " M");
+
+    let hash = commit_project(&package, "Add a scene").unwrap().unwrap();
+    let history = project_history(&package).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].hash, hash);
+    assert_eq!(history[0].message, "Add a scene");
+
+    let restored = restore_project(&package, &history[1].hash).unwrap();
+    assert!(restored.scenes.is_empty());
+    assert_eq!(restored.pack_path, package.pack_path);
+    assert_eq!(uncommitted_changes(&package).unwrap().len(), 1);
+
+    let err = restore_project(&package, "--orphan").unwrap_err();
+    assert_eq!(err.message().key, "git.invalid_revision");
+}
+
+#[test]
+fn projects_in_a_subfolder_commit_only_their_files() {
+    let dir = TempDir::new("git");
+    let repo = git2::Repository::init(&*dir).unwrap();
+    std::fs::write(dir.join("notes.txt"), "staged, but not part of the project").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(std::path::Path::new("notes.txt")).unwrap();
+    index.write().unwrap();
+
+    let mut package = Package::new();
+    package.pack_name = "Nested".into();
+    std::fs::create_dir_all(dir.join("packs")).unwrap();
+    let path: PathBuf = dir.join("packs").join("Nested.slsb.json");
+    package.write(path.clone()).unwrap();
+    package.pack_path = path;
+    init_repository(&package).unwrap();
+
+    let head = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(head.get_path("packs/Nested.slsb.json".as_ref()).is_ok());
+    assert!(head.get_path("notes.txt".as_ref()).is_err());
+    assert_eq!(project_history(&package).unwrap().len(), 1);
+    assert!(uncommitted_changes(&package).unwrap().is_empty());
+}
+
+#[test]
+fn commit_messages_list_the_changes_since_the_last_save() {
+    let (_dir, mut package) = saved_project();
+    let scene = arb_scene(&mut rng(3));
+    let id = scene.id.clone();
+    let name = scene.name.clone();
+    package.scenes.insert(id.clone(), scene);
+    let entry = |operation: &str| AuditEntry {
+        timestamp: 0,
+        user: "Tester".into(),
+        operation: operation.into(),
+        scene: Some(id.clone()),
+        stages: vec![],
+        message: None,
+    };
+    let entries = vec![
+        entry("save_scene"),
+        entry("save_scene"),
+        entry("lock_scene"),
+    ];
+    assert_eq!(
+        commit_message(&package, &entries, Some("Rework positions")),
+        format!(
+            "Rework positions\n\n- save_scene {0}\n- lock_scene {0}",
+            name
+        )
+    );
+    assert_eq!(commit_message(&package, &[], None), "Update Versioned");
+}
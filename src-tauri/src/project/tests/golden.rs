@@ -0,0 +1,111 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::project::{
+    credits::CREDITS_FILE,
+    dependencies::{DEPENDENCIES_FILE, DEPENDENCIES_LIST_FILE},
+    package::Package,
+};
+
+use super::generators::TempDir;
+
+// Projects covering the different kinds of input, each with the exported files expected from them
+// in a "golden" folder. Run with SLSB_UPDATE_GOLDEN=1 to accept changes to the output
+const FIXTURES: [&str; 3] = ["tiny", "creatures", "slal"];
+
+fn fixture_dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("project")
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+// Registries, FNIS lists, credits and dependencies below dir, by their path relative to it
+fn exported_files(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    fn walk(dir: &Path, base: &Path, ret: &mut Vec<(String, Vec<u8>)>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                walk(&path, base, ret);
+                continue;
+            }
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            if path.extension().is_some_and(|ext| ext == "slr")
+                || file_name.starts_with("FNIS_")
+                || [CREDITS_FILE, DEPENDENCIES_FILE, DEPENDENCIES_LIST_FILE]
+                    .contains(&file_name.as_ref())
+            {
+                let relative: Vec<String> = path
+                    .strip_prefix(base)
+                    .unwrap()
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                ret.push((relative.join("/"), fs::read(&path).unwrap()));
+            }
+        }
+    }
+    let mut ret = vec![];
+    if dir.is_dir() {
+        walk(dir, dir, &mut ret);
+    }
+    ret.sort_by(|a, b| a.0.cmp(&b.0));
+    ret
+}
+
+#[test]
+fn exports_match_golden_files() {
+    let update = std::env::var_os("SLSB_UPDATE_GOLDEN").is_some();
+    for name in FIXTURES {
+        let dir = fixture_dir(name);
+        let mut package = Package::open(&dir.join("project.slsb.json")).unwrap();
+        let out = TempDir::new("golden");
+        package.build(out.clone()).unwrap();
+        let actual = exported_files(&out);
+        assert!(!actual.is_empty(), "{} exported nothing", name);
+
+        let golden = dir.join("golden");
+        if update {
+            let _ = fs::remove_dir_all(&golden);
+            for (path, bytes) in &actual {
+                let path = golden.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, bytes).unwrap();
+            }
+            continue;
+        }
+        let expected = exported_files(&golden);
+        let paths = |files: &[(String, Vec<u8>)]| -> Vec<String> {
+            files.iter().map(|(path, _)| path.clone()).collect()
+        };
+        assert_eq!(
+            paths(&actual),
+            paths(&expected),
+            "files exported by {}",
+            name
+        );
+        for ((path, actual), (_, expected)) in actual.iter().zip(&expected) {
+            assert!(
+                actual == expected,
+                "{}: {} differs from its golden file",
+                name,
+                path
+            );
+        }
+    }
+}
+
+#[test]
+fn repeated_exports_are_identical() {
+    let dir = fixture_dir("creatures");
+    let package = Package::open(&dir.join("project.slsb.json")).unwrap();
+    let bytes = package.encode();
+    for _ in 0..4 {
+        let package = Package::open(&dir.join("project.slsb.json")).unwrap();
+        assert!(package.encode() == bytes);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use super::generators::{arb_scene, rng};
+use crate::project::{
+    define::Node,
+    dot::{import_dot, parse_dot, scene_to_dot},
+    graph::{layered_layout, longest_paths, shortest_paths},
+    NanoID,
+};
+
+fn id(s: &str) -> NanoID {
+    NanoID(s.into())
+}
+
+fn node(dest: &[&str]) -> Node {
+    Node {
+        dest: dest.iter().map(|d| id(d)).collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn layout_places_chain_in_columns() {
+    let order = vec![id("a"), id("b"), id("c")];
+    let graph = HashMap::from([
+        (id("a"), node(&["b"])),
+        (id("b"), node(&["c"])),
+        (id("c"), node(&[])),
+    ]);
+    let coords = layered_layout(&order, &id("a"), &graph);
+    assert_eq!(coords[&id("a")], (40.0, 40.0));
+    assert_eq!(coords[&id("b")], (240.0, 40.0));
+    assert_eq!(coords[&id("c")], (440.0, 40.0));
+}
+
+#[test]
+fn layout_handles_branches_cycles_and_unreachable_stages() {
+    let order = vec![id("a"), id("b"), id("c"), id("d"), id("x")];
+    let graph = HashMap::from([
+        (id("a"), node(&["b", "c"])),
+        (id("b"), node(&["d", "missing"])),
+        (id("c"), node(&["d"])),
+        (id("d"), node(&["a"])),
+        (id("x"), node(&[])),
+    ]);
+    let coords = layered_layout(&order, &id("a"), &graph);
+    assert_eq!(coords.len(), order.len());
+    assert_eq!(coords[&id("b")].0, coords[&id("c")].0);
+    assert_ne!(coords[&id("b")].1, coords[&id("c")].1);
+    assert_eq!(coords[&id("d")].0, 440.0);
+    // unreachable stages share the first column with the root
+    assert_eq!(coords[&id("x")].0, 40.0);
+    assert_ne!(coords[&id("x")].1, coords[&id("a")].1);
+}
+
+#[test]
+fn shortest_and_longest_paths_ignore_cycles() {
+    let nodes_list = vec![id("a"), id("b"), id("c"), id("d")];
+    let nodes = nodes_list.iter().collect();
+    let graph = HashMap::from([
+        (id("a"), node(&["b", "d"])),
+        (id("b"), node(&["c"])),
+        (id("c"), node(&["d", "a"])),
+        (id("d"), node(&[])),
+    ]);
+    let shortest = shortest_paths(&id("a"), &graph, &nodes);
+    let longest = longest_paths(&id("a"), &graph, &nodes);
+    assert_eq!(shortest[&id("d")], vec![id("a"), id("d")]);
+    assert_eq!(longest[&id("d")], vec![id("a"), id("b"), id("c"), id("d")]);
+}
+
+#[test]
+fn stage_graphs_are_imported_from_dot() {
+    let mut rng = rng(71);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 3 {
+        scene = arb_scene(&mut rng);
+    }
+    for (stage, name) in scene.stages.iter_mut().zip(["Intro", "Middle", "End"]) {
+        stage.name = name.into();
+    }
+    let ids: Vec<NanoID> = scene.stages.iter().map(|stage| stage.id.clone()).collect();
+    let mut copy = scene.clone();
+    import_dot(&mut copy, &scene_to_dot(&scene)).unwrap();
+    assert_eq!(copy.root, scene.root);
+    for id in &ids {
+        let dest = |graph: &HashMap<NanoID, Node>| graph.get(id).map(|n| n.dest.clone());
+        assert_eq!(
+            dest(&copy.graph).unwrap_or_default(),
+            dest(&scene.graph).unwrap_or_default()
+        );
+    }
+
+    let dot = format!(
+        "// restructured\ndigraph \"{}\" {{\n  rankdir=LR\n  node [shape=box];\n  intro -> \"Middle\" -> {}\n  \"End\" [label=<<b>End</b>>, root=true]\n}}\n",
+        scene.name, ids[2].0
+    );
+    let parsed = parse_dot(&dot).unwrap();
+    assert_eq!(parsed.nodes, vec!["intro", "Middle", &ids[2].0, "End"]);
+    assert_eq!(parsed.root.as_deref(), Some("End"));
+    let positions: Vec<_> = ids
+        .iter()
+        .map(|id| scene.graph.get(id).map(|n| (n.x, n.y)))
+        .collect();
+    import_dot(&mut scene, &dot).unwrap();
+    assert_eq!(scene.root, ids[2]);
+    assert_eq!(scene.graph[&ids[0]].dest, vec![ids[1].clone()]);
+    assert_eq!(scene.graph[&ids[1]].dest, vec![ids[2].clone()]);
+    for stage in &scene.stages[2..] {
+        assert_eq!(scene.graph.get(&stage.id).map_or(0, |n| n.dest.len()), 0);
+    }
+    let kept: Vec<_> = ids
+        .iter()
+        .map(|id| scene.graph.get(id).map(|n| (n.x, n.y)))
+        .collect();
+    for (before, after) in positions.iter().zip(&kept) {
+        if let Some(before) = before {
+            assert_eq!(Some(*before), *after);
+        }
+    }
+    // without a root marked the first stage nothing leads to is picked, stages left out of the
+    // file are no longer reached
+    let warnings = import_dot(&mut scene, "digraph { Middle -> Intro }").unwrap();
+    assert_eq!(scene.root, ids[1]);
+    assert_eq!(warnings.len(), scene.stages.len() - 2);
+    assert!(warnings
+        .iter()
+        .all(|warning| warning.key == "validate.unreachable"));
+    assert_eq!(warnings[0].params["stage"], "End");
+
+    let error = |text: &str, scene: &mut crate::project::scene::Scene| {
+        import_dot(scene, text).unwrap_err().message().key
+    };
+    assert_eq!(
+        error("digraph { Intro -> Nowhere }", &mut scene),
+        "dot.unknown_stage"
+    );
+    assert_eq!(
+        error("digraph { Intro -> intro }", &mut scene),
+        "scene.self_edge"
+    );
+    assert_eq!(error("digraph { Intro -> }", &mut scene), "dot.syntax");
+    assert_eq!(error("digraph {}", &mut scene), "dot.empty_graph");
+    assert_eq!(error("digraph { \"Intro }", &mut scene), "dot.syntax");
+    scene.stages[1].name = "Intro".into();
+    assert_eq!(
+        error("digraph { Intro -> End }", &mut scene),
+        "dot.ambiguous_stage"
+    );
+    // failed imports leave the graph as it was
+    assert_eq!(scene.root, ids[1]);
+    assert_eq!(scene.graph[&ids[1]].dest, vec![ids[0].clone()]);
+}
@@ -0,0 +1,382 @@
+use super::generators::*;
+use crate::project::{
+    hkx::{
+        read_animation, read_duration, read_skeleton, stage_preview, suggest_offsets, HAVOK_TO_GAME,
+    },
+    package::Package,
+    preview::clean_previews,
+    schematic::{stage_schematic, write_thumbnail},
+    validate::validate_durations,
+    NanoID,
+};
+
+const SKELETON: &str = r##"<?xml version="1.0" encoding="ascii"?>
+<hkpackfile classversion="8" contentsversion="hk_2010.2.0-r1" toplevelobject="#0050">
+	<hksection name="__data__">
+		<hkobject name="#0051" class="hkaSkeleton" signature="0x366e8220">
+			<hkparam name="name">Root</hkparam>
+			<hkparam name="parentIndices" numelements="3">-1 0 1</hkparam>
+			<hkparam name="bones" numelements="3">
+				<hkobject><hkparam name="name">NPC Root [Root]</hkparam></hkobject>
+				<hkobject><hkparam name="name">NPC Spine [Spn0]</hkparam></hkobject>
+				<hkobject><hkparam name="name">NPC Head [Head]</hkparam></hkobject>
+			</hkparam>
+			<hkparam name="referencePose" numelements="3">
+				(0.0 0.0 0.0)(0.0 0.0 0.0 1.0)(1.0 1.0 1.0)
+				(0.0 0.0 10.0)(0.0 0.0 0.0 1.0)(1.0 1.0 1.0)
+				(0.0 0.0 5.0)(0.0 0.0 0.0 1.0)(1.0 1.0 1.0)
+			</hkparam>
+		</hkobject>
+	</hksection>
+</hkpackfile>
+"##;
+
+const ANIMATION: &str = r##"<?xml version="1.0" encoding="ascii"?>
+<hkpackfile classversion="8" contentsversion="hk_2010.2.0-r1" toplevelobject="#0040">
+	<hksection name="__data__">
+		<!-- the spine bends forward in the second frame -->
+		<hkobject name="#0042" class="hkaDefaultAnimatedReferenceFrame" signature="0x6d85e445">
+			<hkparam name="referenceFrameSamples" numelements="2">(0.0 0.0 0.0 0.0)(0.0 20.0 0.0 1.5)</hkparam>
+		</hkobject>
+		<hkobject name="#0041" class="hkaInterleavedUncompressedAnimation" signature="0x930af031">
+			<hkparam name="duration">1.0</hkparam>
+			<hkparam name="numberOfTransformTracks">2</hkparam>
+			<hkparam name="extractedMotion">#0042</hkparam>
+			<hkparam name="annotationTracks" numelements="2">
+				<hkobject><hkparam name="trackName">NPC Root [Root]</hkparam></hkobject>
+				<hkobject><hkparam name="trackName">NPC Spine [Spn0]</hkparam></hkobject>
+			</hkparam>
+			<hkparam name="transforms" numelements="4">
+				(0.0 0.0 0.0)(0.0 0.0 0.0 1.0)(1.0 1.0 1.0)
+				(0.0 0.0 10.0)(0.0 0.0 0.0 1.0)(1.0 1.0 1.0)
+				(0.0 0.0 0.0)(0.0 0.0 0.0 1.0)(1.0 1.0 1.0)
+				(0.0 0.0 10.0)(0.7071068 0.0 0.0 0.7071068)(1.0 1.0 1.0)
+			</hkparam>
+		</hkobject>
+	</hksection>
+</hkpackfile>
+"##;
+
+// Binary packfile holding a spline compressed animation of the given length, for 32 or 64 bit
+// pointers. Its tracks are left empty
+fn binary_animation(pointer_size: u8, duration: f32) -> Vec<u8> {
+    let words =
+        |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+    let mut names = vec![0x5c, 0x8f, 0x5b, 0x37, 0x09];
+    names.extend(b"hkaSplineCompressedAnimation\0");
+    names.resize(48, 0xFF);
+    // vtable, size and reference count, then type and duration
+    let mut data = vec![0; if pointer_size == 8 { 16 } else { 8 }];
+    data.extend(words(&[3, duration.to_bits()]));
+    data.resize(32, 0);
+    let fixups = data.len() as u32;
+    data.extend(words(&[0, 0, 5, u32::MAX, 0, 0]));
+    let end = data.len() as u32;
+
+    let mut ret = vec![0x57, 0xE0, 0xE0, 0x57, 0x10, 0xC0, 0xC0, 0x10];
+    ret.extend(words(&[0, 8]));
+    ret.extend([pointer_size, 1, 0, 1]);
+    ret.extend(words(&[2, 1, 0, 0, 0]));
+    ret.extend(b"hk_2010.2.0-r1\0\0");
+    ret.extend(words(&[0, 0]));
+    let section = |tag: &str, start: u32, fixups: u32, end: u32| {
+        let mut header = tag.as_bytes().to_vec();
+        header.resize(20, 0);
+        header.extend(words(&[start, end, end, fixups, end, end, end]));
+        header
+    };
+    let start = (ret.len() + 2 * 48) as u32;
+    let names_len = names.len() as u32;
+    ret.extend(section("__classnames__", start, names_len, names_len));
+    ret.extend(section("__data__", start + names_len, fixups, end));
+    ret.extend(names);
+    ret.extend(data);
+    ret
+}
+
+// A single frame animation posing the root bone
+fn root_animation(transform: &str) -> String {
+    format!(
+        r##"<hkpackfile><hksection name="__data__">
+		<hkobject name="#0041" class="hkaInterleavedUncompressedAnimation">
+			<hkparam name="duration">0.0</hkparam>
+			<hkparam name="numberOfTransformTracks">1</hkparam>
+			<hkparam name="transforms" numelements="1">{}(1.0 1.0 1.0)</hkparam>
+		</hkobject>
+	</hksection></hkpackfile>"##,
+        transform
+    )
+}
+
+fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+    for i in 0..3 {
+        assert!(
+            (actual[i] - expected[i]).abs() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+}
+
+#[test]
+fn stage_previews_pose_the_race_skeleton() {
+    let mut rng = rng(50);
+    let root = TempDir::new("hkx");
+    let assets = root
+        .join("meshes")
+        .join("actors")
+        .join("character")
+        .join("character assets");
+    let animations = root
+        .join("meshes")
+        .join("actors")
+        .join("character")
+        .join("animations");
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::create_dir_all(&animations).unwrap();
+    std::fs::write(assets.join("skeleton.xml"), SKELETON).unwrap();
+    // the binary skeleton the xml was converted from is ignored
+    std::fs::write(
+        assets.join("skeleton.hkx"),
+        [0x57, 0xE0, 0xE0, 0x57, 0x10, 0xC0, 0xC0, 0x10, 0, 0],
+    )
+    .unwrap();
+    std::fs::write(animations.join("pack_a1.xml"), ANIMATION).unwrap();
+    std::fs::write(
+        animations.join("pack_root_a.xml"),
+        root_animation("(0.0 0.0 0.0)(0.0 0.0 0.7071068 0.7071068)"),
+    )
+    .unwrap();
+    std::fs::write(
+        animations.join("pack_root_b.xml"),
+        root_animation("(1.0 0.0 0.0)(0.0 0.0 1.0 0.0)"),
+    )
+    .unwrap();
+
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.stages.truncate(1);
+    scene.stages[0].positions.truncate(1);
+    scene.stages[0].positions[0].event = vec!["Pack_A1".into(), "Pack_Missing".into()];
+    scene.positions.truncate(1);
+    scene.positions[0].race = "Human".into();
+    let (scene_id, stage_id) = (scene.id.clone(), scene.stages[0].id.clone());
+    package.scenes.insert(scene_id.clone(), scene);
+
+    let err = stage_preview(&package, &scene_id, &stage_id).unwrap_err();
+    assert_eq!(err.message().key, "events.no_animation_root");
+    package.animation_root = Some(root.clone());
+    let previews = stage_preview(&package, &scene_id, &stage_id).unwrap();
+    assert_eq!(previews.keys().collect::<Vec<_>>(), vec!["Pack_A1"]);
+    let preview = &previews["Pack_A1"];
+    assert_eq!(preview.duration, 1.0);
+    assert_eq!(preview.bones[2].name, "NPC Head [Head]");
+    assert_eq!(preview.bones[2].parent, 1);
+    assert_eq!(preview.frames.len(), 2);
+    assert_near(preview.frames[0][2], [0.0, 0.0, 15.0]);
+    assert_near(preview.frames[1][2], [0.0, -5.0, 10.0]);
+    assert_eq!(preview.root_motion[1], [0.0, 20.0, 0.0, 1.5]);
+
+    let scene = package.scenes.get_mut(&scene_id).unwrap();
+    scene.positions.push(scene.positions[0].clone());
+    let position = scene.stages[0].positions[0].clone();
+    scene.stages[0].positions.push(position);
+    scene.stages[0].positions[0].event = vec!["Pack_Root_A".into()];
+    scene.stages[0].positions[1].event = vec!["__BLANK__".into(), "Pack_Root_B".into()];
+    let suggestions = suggest_offsets(&package, &scene_id, &stage_id).unwrap();
+    assert_eq!(suggestions.len(), 2);
+    assert_eq!(suggestions[1].position, 1);
+    assert_eq!(suggestions[0].motion.heading, 90.0);
+    let offset = &suggestions[1].offset;
+    assert_near([offset.x, offset.y, offset.z], [0.0, -HAVOK_TO_GAME, 0.0]);
+    assert!((offset.r - 90.0).abs() < 1e-3);
+    assert!((suggestions[1].distance - HAVOK_TO_GAME).abs() < 1e-3);
+    assert!((suggestions[1].angle + 90.0).abs() < 1e-3);
+
+    package.scenes.get_mut(&scene_id).unwrap().positions[0].race = "Horse".into();
+    let err = stage_preview(&package, &scene_id, &stage_id).unwrap_err();
+    assert_eq!(err.message().key, "hkx.no_skeleton");
+}
+
+#[test]
+fn unsupported_packfiles_are_rejected() {
+    let dir = TempDir::new("hkx");
+    let binary = dir.join("binary.hkx");
+    std::fs::write(
+        &binary,
+        [0x57, 0xE0, 0xE0, 0x57, 0x10, 0xC0, 0xC0, 0x10, 0, 0],
+    )
+    .unwrap();
+    let err = read_animation(&binary).unwrap_err();
+    assert_eq!(err.message().key, "hkx.binary_packfile");
+    assert_eq!(
+        read_duration(&binary).unwrap_err().message().key,
+        "hkx.invalid"
+    );
+    let mut newer = binary_animation(8, 1.0);
+    newer[12] = 11;
+    std::fs::write(&binary, newer).unwrap();
+    let err = read_duration(&binary).unwrap_err();
+    assert_eq!(err.message().key, "hkx.unsupported_packfile");
+    assert_eq!(err.message().params["version"], "11");
+
+    let malformed = dir.join("malformed.xml");
+    std::fs::write(&malformed, "<hkpackfile><hksection></hkpackfile>").unwrap();
+    let err = read_animation(&malformed).unwrap_err();
+    assert_eq!(err.message().key, "hkx.invalid");
+
+    let compressed = dir.join("compressed.xml");
+    let xml = ANIMATION.replace(
+        "hkaInterleavedUncompressedAnimation",
+        "hkaSplineCompressedAnimation",
+    );
+    std::fs::write(&compressed, xml).unwrap();
+    assert_eq!(
+        read_animation(&compressed).unwrap_err().message().key,
+        "hkx.compressed"
+    );
+
+    let broken = dir.join("broken.xml");
+    std::fs::write(&broken, &SKELETON[..SKELETON.len() / 2]).unwrap();
+    assert_eq!(
+        read_skeleton(&broken).unwrap_err().message().key,
+        "hkx.invalid"
+    );
+}
+
+#[test]
+fn binary_packfiles_tell_their_length() {
+    let dir = TempDir::new("hkx");
+    for pointer_size in [4, 8] {
+        let path = dir.join(format!("binary{}.hkx", pointer_size));
+        std::fs::write(&path, binary_animation(pointer_size, 2.5)).unwrap();
+        assert_eq!(read_duration(&path).unwrap(), Some(2.5));
+    }
+}
+
+#[test]
+fn stages_with_animations_of_different_length_are_flagged() {
+    let mut rng = rng(51);
+    let root = TempDir::new("hkx");
+    let long = ANIMATION.replace(
+        "<hkparam name=\"duration\">1.0</hkparam>",
+        "<hkparam name=\"duration\">3.0</hkparam>",
+    );
+    std::fs::write(root.join("Pack_Long.xml"), long).unwrap();
+    let hkanno = "# numOriginalFrames: 61\n# duration: 2\n# numAnnotationTracks: 99\n";
+    std::fs::write(root.join("Pack_Short.txt"), hkanno).unwrap();
+    std::fs::write(root.join("Pack_Binary.hkx"), binary_animation(8, 3.0)).unwrap();
+
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    scene.stages.truncate(1);
+    scene.positions.truncate(3);
+    scene.stages[0].positions.truncate(3);
+    while scene.positions.len() < 3 {
+        scene.positions.push(scene.positions[0].clone());
+        let position = scene.stages[0].positions[0].clone();
+        scene.stages[0].positions.push(position);
+    }
+    for (position, event) in
+        scene.stages[0]
+            .positions
+            .iter_mut()
+            .zip(["Pack_Long", "Pack_Short", "Pack_Binary"])
+    {
+        position.event = vec![event.into()];
+    }
+    let id = scene.id.clone();
+    package.scenes.insert(id.clone(), scene);
+    assert!(validate_durations(&package).is_empty());
+
+    package.animation_root = Some(root.clone());
+    let diagnostics = validate_durations(&package);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message.key, "validate.duration_mismatch");
+    assert_eq!(diagnostics[0].message.params["event"], "Pack_Short");
+    assert_eq!(diagnostics[0].message.params["expected"], "3.00");
+
+    let hkanno = hkanno.replace("duration: 2", "duration: 2.9");
+    std::fs::write(root.join("Pack_Short.txt"), hkanno).unwrap();
+    assert!(validate_durations(&package).is_empty());
+
+    std::fs::write(root.join("Pack_Long.xml"), "<hkpackfile>").unwrap();
+    let diagnostics = validate_durations(&package);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message.key, "validate.unreadable_animation");
+    assert_eq!(
+        diagnostics[0].stage,
+        Some(package.scenes[&id].stages[0].id.clone())
+    );
+
+    package.animation_root = Some(root.join("missing"));
+    let diagnostics = validate_durations(&package);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message.key, "events.invalid_animation_root");
+}
+
+#[test]
+fn schematics_draw_actors_and_skeletons() {
+    let mut rng = rng(73);
+    let root = TempDir::new("schematic");
+    let assets = root
+        .join("meshes")
+        .join("actors")
+        .join("character")
+        .join("character assets");
+    let animations = root
+        .join("meshes")
+        .join("actors")
+        .join("character")
+        .join("animations");
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::create_dir_all(&animations).unwrap();
+    std::fs::write(assets.join("skeleton.xml"), SKELETON).unwrap();
+    std::fs::write(animations.join("pack_a1.xml"), ANIMATION).unwrap();
+
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.stages.truncate(1);
+    scene.positions.truncate(2);
+    while scene.positions.len() < 2 {
+        scene.positions.push(scene.positions[0].clone());
+    }
+    for info in &mut scene.positions {
+        info.race = "Human".into();
+    }
+    let stage = &mut scene.stages[0];
+    stage.positions.truncate(2);
+    while stage.positions.len() < 2 {
+        stage.positions.push(stage.positions[0].clone());
+    }
+    stage.positions[0].event = vec!["Pack_A1".into()];
+    stage.positions[0].offset = Default::default();
+    stage.positions[1].offset = Default::default();
+    stage.positions[1].offset.x = 300.0;
+    let (scene_id, stage_id) = (scene.id.clone(), scene.stages[0].id.clone());
+    package.scenes.insert(scene_id.clone(), scene);
+
+    let schematic = stage_schematic(&package, &scene_id, &stage_id).unwrap();
+    assert_eq!(schematic.distant, vec![1]);
+    assert_eq!(schematic.svg.matches("<circle").count(), 2);
+    assert_eq!(schematic.svg.matches("<line").count(), 2);
+    assert!(schematic.svg.contains(">2 Human</text>"));
+    assert!(stage_schematic(&package, &scene_id, &NanoID::new_nanoid()).is_err());
+
+    // the skeleton of the first position adds a line per bone with a parent
+    package.animation_root = Some(root.clone());
+    let schematic = stage_schematic(&package, &scene_id, &stage_id).unwrap();
+    assert_eq!(schematic.svg.matches("<line").count(), 4);
+
+    let project = root.join("Schematic.slsb.json");
+    assert!(write_thumbnail(&package, &scene_id, &stage_id).is_err());
+    package.write(project.clone()).unwrap();
+    package.pack_path = project;
+    let path = write_thumbnail(&package, &scene_id, &stage_id).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), schematic.svg);
+    assert!(clean_previews(&package, true).unwrap().is_empty());
+    package.scenes.clear();
+    assert_eq!(clean_previews(&package, false).unwrap(), vec![path]);
+}
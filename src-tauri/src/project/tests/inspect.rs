@@ -0,0 +1,297 @@
+use super::generators::*;
+use crate::project::{
+    inspect::{inspect_bytes, InspectedTransition},
+    package::Package,
+    registry::{compare_registries, ConflictKind},
+    serialize::EncodeBinary,
+    NanoID,
+};
+
+#[test]
+fn encoded_packages_decode() {
+    for seed in 0..CASES {
+        let mut rng = rng(seed);
+        let package = arb_package(&mut rng);
+        let bytes = package.encode();
+        let inspection = inspect_bytes(&bytes).unwrap();
+
+        assert_eq!(inspection.pack_name, package.pack_name);
+        assert_eq!(inspection.pack_author, package.pack_author);
+        assert_eq!(inspection.prefix_hash, package.prefix_hash.0);
+        assert_eq!(inspection.stage_timers, package.stage_timers);
+        assert_eq!(
+            inspection.min_framework_version,
+            package.min_framework_version
+        );
+        assert_eq!(inspection.scenes.len(), package.exported_scenes().count());
+        assert_eq!(inspection.sizes.total, bytes.len());
+        assert_eq!(
+            inspection.sizes.scenes,
+            4 + inspection.scenes.iter().map(|s| s.bytes).sum::<usize>()
+        );
+        for inspected in &inspection.scenes {
+            let scene = package
+                .exported_scenes()
+                .find(|scene| scene.id.0 == inspected.id)
+                .unwrap();
+            assert_eq!(inspected.name, scene.name);
+            assert_eq!(inspected.author, scene.author.clone().unwrap_or_default());
+            assert_eq!(
+                inspected.source_pack,
+                scene.source_pack.clone().unwrap_or_default()
+            );
+            assert_eq!(inspected.requirements, scene.encoded_requirements());
+            assert_eq!(inspected.actors.len(), scene.positions.len());
+            assert_eq!(inspected.stages.len(), scene.stages.len());
+            for (inspected, stage) in inspected.stages.iter().zip(&scene.stages) {
+                assert_eq!(inspected.id, stage.id.0);
+                let events: Vec<&String> = stage.positions.iter().map(|p| &p.event[0]).collect();
+                let decoded: Vec<&String> = inspected.positions.iter().map(|p| &p.event).collect();
+                assert_eq!(decoded, events);
+            }
+        }
+    }
+}
+
+#[test]
+fn requirements_include_dlc_races() {
+    let mut rng = rng(53);
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    scene.positions.truncate(2);
+    for stage in &mut scene.stages {
+        stage.positions.truncate(2);
+    }
+    scene.positions[0].race = "Riekling".into();
+    scene.positions[1].race = "Human".into();
+    scene.requirements.plugins = vec![" dragonborn.esm".into(), "ZaZAnimationPack.esm".into()];
+    scene.requirements.keywords = vec!["zbfFurniture".into(), "ZBFFURNITURE".into()];
+    package.scenes.insert(scene.id.clone(), scene);
+
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    let requirements = &inspection.scenes[0].requirements;
+    assert_eq!(
+        requirements.plugins,
+        vec!["dragonborn.esm", "ZaZAnimationPack.esm"]
+    );
+    assert_eq!(requirements.keywords, vec!["zbfFurniture"]);
+    assert!(inspection
+        .to_string()
+        .contains("requires: dragonborn.esm, ZaZAnimationPack.esm, zbfFurniture"));
+}
+
+#[test]
+fn malformed_registries_are_rejected() {
+    let mut rng = rng(7);
+    let package = arb_package(&mut rng);
+    let mut bytes = package.encode();
+
+    let err = inspect_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+    assert_eq!(err.message().key, "inspect.truncated");
+
+    bytes.push(0);
+    let err = inspect_bytes(&bytes).unwrap_err();
+    assert_eq!(err.message().key, "inspect.trailing_bytes");
+
+    bytes[0] += 1;
+    let err = inspect_bytes(&bytes).unwrap_err();
+    assert_eq!(err.message().key, "inspect.unsupported_version");
+}
+
+#[test]
+fn installed_registries_are_compared() {
+    let mut rng = rng(8);
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    scene.name = "Shared Scene".into();
+    for info in &mut scene.positions {
+        info.race = "Human".into();
+    }
+    let mut package = Package::new();
+    package.pack_name = "Pack".into();
+    package.save_scene(scene.clone(), false).unwrap();
+
+    let mut other = Package::new();
+    other.pack_name = "Pack".into();
+    other.prefix_hash = package.prefix_hash.clone();
+    scene.id = NanoID::new_nanoid();
+    scene.name = "shared scene ".into();
+    other.save_scene(scene, false).unwrap();
+
+    let dir = TempDir::new("registry");
+    let registry = dir.join("SKSE").join("SexLab").join("Registry");
+    std::fs::create_dir_all(&registry).unwrap();
+    std::fs::write(registry.join("Own.slr"), package.encode()).unwrap();
+    other.pack_author = "Someone Else".into();
+    std::fs::write(registry.join("Other.slr"), other.encode()).unwrap();
+    std::fs::write(registry.join("Broken.slr"), [5, 0, 0]).unwrap();
+
+    // the installed copy of the project itself is recognized by name, author and prefix
+    package.pack_name = "Renamed".into();
+    let comparison = compare_registries(&package, &dir).unwrap();
+    assert_eq!(comparison.registries.len(), 2);
+    assert_eq!(comparison.skipped.len(), 1);
+    assert_eq!(comparison.own, None);
+    let kinds: Vec<ConflictKind> = comparison
+        .conflicts
+        .iter()
+        .filter(|c| c.registry.ends_with("Other.slr"))
+        .map(|c| c.kind)
+        .collect();
+    assert!(kinds.contains(&ConflictKind::SceneName));
+    assert!(kinds.contains(&ConflictKind::Event));
+    assert!(!kinds.contains(&ConflictKind::BehaviourFolder));
+
+    package.pack_name = "Pack".into();
+    let comparison = compare_registries(&package, &dir).unwrap();
+    assert_eq!(comparison.own, Some(registry.join("Own.slr")));
+    assert!(comparison
+        .conflicts
+        .iter()
+        .any(|c| c.kind == ConflictKind::BehaviourFolder));
+}
+
+#[test]
+fn registries_announce_their_sections() {
+    let package = Package::new();
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    assert!(inspection.capabilities.is_empty());
+    assert_eq!(inspection.sizes.strings, 0);
+
+    // default furniture, flat offsets and missing requirements are left out
+    let mut rng = rng(9);
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    for info in &mut scene.positions {
+        info.race = "Human".into();
+    }
+    let furniture = std::mem::take(&mut scene.furniture);
+    let requirements = std::mem::take(&mut scene.requirements);
+    let offsets: Vec<_> = scene
+        .stages
+        .iter_mut()
+        .flat_map(|stage| &mut stage.positions)
+        .map(|position| {
+            let offset = position.offset.clone();
+            (position.offset.rx, position.offset.ry) = (0.0, 0.0);
+            offset
+        })
+        .collect();
+    package.scenes.insert(scene.id.clone(), scene.clone());
+    let bytes = package.encode();
+    assert_eq!(bytes.len(), package.get_byte_size());
+    let inspection = inspect_bytes(&bytes).unwrap();
+    assert_eq!(inspection.capabilities, vec!["string_table"]);
+
+    scene.furniture = furniture;
+    scene.requirements = requirements;
+    let positions = scene
+        .stages
+        .iter_mut()
+        .flat_map(|stage| &mut stage.positions);
+    for (position, offset) in positions.zip(offsets) {
+        position.offset = offset;
+    }
+    package.scenes.insert(scene.id.clone(), scene);
+    let mut bytes = package.encode();
+    assert_eq!(bytes.len(), package.get_byte_size());
+    let inspection = inspect_bytes(&bytes).unwrap();
+    assert_eq!(
+        inspection.capabilities,
+        vec!["string_table", "furniture", "offsets_v2", "requirements"]
+    );
+    assert!(inspection
+        .to_string()
+        .contains("capabilities: string_table"));
+
+    bytes[4] |= 1 << 3; // compressed scenes
+    let err = inspect_bytes(&bytes).unwrap_err();
+    assert_eq!(err.message().key, "inspect.unsupported_capabilities");
+    bytes[4] &= !(1 << 3);
+    bytes[1] |= 1 << 7; // unknown to this version
+    let err = inspect_bytes(&bytes).unwrap_err();
+    assert_eq!(err.message().key, "inspect.unsupported_capabilities");
+}
+
+#[test]
+fn transitions_are_encoded_when_present() {
+    let mut rng = rng(61);
+    let mut package = Package::new();
+    let mut scenes = vec![];
+    for _ in 0..2 {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        scenes.push(scene);
+    }
+    let (from, to) = (scenes[0].id.clone(), scenes[1].id.clone());
+    let ending = scenes[0].stages.iter().find(|s| s.ending).unwrap().id.clone();
+    let other = scenes[0].stages.iter().find(|s| !s.ending).map(|s| s.id.clone());
+    for scene in scenes {
+        package.save_scene(scene, false).unwrap();
+    }
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    assert!(!inspection.capabilities.contains(&"transitions".to_string()));
+
+    if let Some(other) = other {
+        let err = package.add_transition(&from, &other, &to).unwrap_err();
+        assert_eq!(err.message().key, "scene.transition_not_ending");
+    }
+    let err = package.add_transition(&from, &ending, &from).unwrap_err();
+    assert_eq!(err.message().key, "scene.self_transition");
+    assert!(package
+        .add_transition(&from, &ending, &NanoID::new_nanoid())
+        .is_err());
+    assert!(package.add_transition(&from, &ending, &to).unwrap());
+    assert!(!package.add_transition(&from, &ending, &to).unwrap());
+
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    assert!(inspection.capabilities.contains(&"transitions".to_string()));
+    let chained = inspection.scenes.iter().find(|s| s.id == from.0).unwrap();
+    assert_eq!(
+        chained.transitions,
+        vec![InspectedTransition {
+            from_stage: ending.0.clone(),
+            to_scene: to.0.clone(),
+        }]
+    );
+    package.remove_transition(&from, &ending, &to).unwrap();
+    assert!(package.remove_transition(&from, &ending, &to).is_err());
+}
+
+#[test]
+fn event_variants_are_listed_and_encoded() {
+    let mut rng = rng(67);
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    for info in &mut scene.positions {
+        info.race = "Human".into();
+    }
+    let id = scene.id.clone();
+    let stage = scene.stages[0].id.clone();
+    scene.stages[0].positions[0].variants = vec!["VariantA".into(), "VariantB".into()];
+    package.save_scene(scene, false).unwrap();
+
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    assert!(inspection.capabilities.contains(&"event_variants".to_string()));
+    let inspected = inspection.scenes.iter().find(|s| s.id == id.0).unwrap();
+    assert_eq!(inspected.stages[0].id, stage.0);
+    assert_eq!(
+        inspected.stages[0].positions[0].variants,
+        vec!["VariantA", "VariantB"]
+    );
+    assert!(inspected.stages[1..]
+        .iter()
+        .flat_map(|stage| &stage.positions)
+        .all(|position| position.variants.is_empty()));
+
+    let dir = TempDir::new("variants");
+    let files = package.write_fnis_files(&dir).unwrap();
+    let list = std::fs::read_to_string(&files[0]).unwrap();
+    for variant in ["VariantA", "VariantB"] {
+        let line = format!(" {}{} {}.hkx", package.prefix_hash.0, variant, variant);
+        assert_eq!(list.matches(&line).count(), 1, "{}", list);
+    }
+}
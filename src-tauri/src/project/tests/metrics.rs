@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+use super::generators::TempDir;
+use crate::project::{metrics::UsageMetrics, NanoID};
+
+#[test]
+fn disabled_metrics_record_nothing() {
+    let mut metrics = UsageMetrics::default();
+    let id = NanoID::default();
+    metrics.record_edit("Pack", &id, "Scene");
+    metrics.record_build("Pack");
+    metrics.focus_scene("Pack", Some((&id, "Scene")));
+    assert!(metrics.projects.is_empty());
+}
+
+#[test]
+fn report_sorts_by_edits_and_tracks_time() {
+    let mut metrics = UsageMetrics::default();
+    metrics.set_enabled(true);
+    let a = NanoID("aaaaaaaa".into());
+    let b = NanoID("bbbbbbbb".into());
+    metrics.record_edit("Pack", &a, "A");
+    metrics.record_edit("Pack", &b, "B");
+    metrics.record_edit("Pack", &b, "B Renamed");
+    metrics.record_build("Pack");
+    metrics.record_build("Other");
+
+    let start = Instant::now();
+    metrics.focus_scene_at("Pack", Some((&a, "A")), start);
+    metrics.focus_scene_at(
+        "Pack",
+        Some((&b, "B Renamed")),
+        start + Duration::from_secs(90),
+    );
+    metrics.focus_scene_at("Pack", None, start + Duration::from_secs(100));
+
+    let report = metrics.report("Pack");
+    assert_eq!(report.builds, 1);
+    assert!(report.last_build.is_some());
+    assert_eq!(report.total_seconds, 100);
+    assert_eq!(report.scenes.len(), 2);
+    assert_eq!(report.scenes[0].id, b);
+    assert_eq!(report.scenes[0].metrics.name, "B Renamed");
+    assert_eq!(report.scenes[0].metrics.edits, 2);
+    assert_eq!(report.scenes[0].metrics.seconds, 10);
+    assert_eq!(report.scenes[1].metrics.seconds, 90);
+}
+
+#[test]
+fn metrics_persist_to_disk() {
+    let dir = TempDir::new("metrics");
+    let path = dir.join("metrics.json");
+    let id = NanoID("aaaaaaaa".into());
+    let mut metrics = UsageMetrics::load(path.clone());
+    metrics.set_enabled(true);
+    metrics.record_edit("Pack", &id, "A");
+    metrics.save().unwrap();
+
+    let loaded = UsageMetrics::load(path);
+    assert!(loaded.enabled);
+    assert_eq!(loaded.report("Pack").scenes[0].metrics.edits, 1);
+}
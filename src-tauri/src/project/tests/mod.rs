@@ -0,0 +1,34 @@
+// Test subsystem for the project model and its binary encoding
+
+// Seeded random generators for the project data model
+pub mod generators;
+
+mod encode;
+
+mod graph;
+
+mod scene;
+
+mod events;
+
+mod package;
+
+mod metrics;
+
+mod validate;
+
+mod convert;
+
+mod inspect;
+
+mod tags;
+
+mod offsets;
+
+mod golden;
+
+mod hkx;
+
+mod git;
+
+mod csv;
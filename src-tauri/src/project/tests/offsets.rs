@@ -0,0 +1,248 @@
+use super::generators::*;
+use crate::project::{
+    define::Sex,
+    offsets::{
+        apply_offsets, apply_template, export_offsets, save_template, OffsetMatching,
+        OFFSET_VERSION,
+    },
+    package::Package,
+    scene::Scene,
+    NanoID,
+};
+
+fn scene_with_events(rng: &mut rand::rngs::StdRng, name: &str, events: &[&str]) -> Scene {
+    let mut scene = arb_scene(rng);
+    scene.name = name.into();
+    scene.locked = false;
+    scene.positions.truncate(1);
+    while scene.positions.len() < events.len() {
+        scene.positions.push(arb_position_info(rng));
+    }
+    scene.stages.truncate(1);
+    let stage = &mut scene.stages[0];
+    stage.name = "Intro".into();
+    stage.positions = events
+        .iter()
+        .map(|event| {
+            let mut position = arb_position(rng);
+            position.event = vec![event.to_string()];
+            position
+        })
+        .collect();
+    scene
+}
+
+fn offsets(yaml: &str) -> serde_yaml::Mapping {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+const TRANSFORM: &str = "{ Location: [1.0, 2.0, 3.0], Rotation: 90.0 }";
+
+#[test]
+fn offsets_are_matched_by_name_and_events() {
+    let mut rng = rng(61);
+    let mut package = Package::new();
+    let first = scene_with_events(&mut rng, "Doggy", &["Doggy_A1", "Doggy_A2"]);
+    let second = scene_with_events(&mut rng, "Missionary", &["Miss_A1", "Miss_A2"]);
+    let third = scene_with_events(&mut rng, "Missionary", &["Miss_B1", "Miss_B2"]);
+    let ids = [first.id.clone(), second.id.clone(), third.id.clone()];
+    let stage = first.stages[0].id.clone();
+    for scene in [first, second, third] {
+        package.save_scene(scene, false).unwrap();
+    }
+
+    let yaml = format!(
+        "{id}:\n  {stage}:\n    - transform: {t}\n    - transform: {t}\n\
+         doggy:\n  intro:\n    - transform: {t}\n    - transform: {t}\n\
+         Missionary:\n  intro:\n    - transform: {t}\n    - transform: {t}\n\
+         Gone:\n  Old:\n    - {{ event: miss_b1, transform: {t} }}\n    - {{ event: Miss_B2, transform: {t} }}\n",
+        id = ids[0].0,
+        stage = stage.0,
+        t = TRANSFORM
+    );
+
+    let report = apply_offsets(&mut package, &offsets(&yaml), OffsetMatching::Id).unwrap();
+    assert_eq!(report.applied.len(), 1);
+    assert_eq!(report.skipped.len(), 3);
+    let offset = &package.get_scene(&ids[0]).unwrap().stages[0].positions[0].offset;
+    assert_eq!((offset.x, offset.r), (1.0, 90.0));
+
+    let report = apply_offsets(&mut package, &offsets(&yaml), OffsetMatching::Name).unwrap();
+    assert_eq!(report.applied.len(), 2);
+    assert_eq!(report.ambiguous.len(), 1);
+    assert_eq!(report.ambiguous[0].scene, "Missionary");
+    assert_eq!(report.ambiguous[0].targets.len(), 2);
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(
+        report.skipped[0].reason.as_ref().unwrap().key,
+        "offset.no_match"
+    );
+
+    let report = apply_offsets(&mut package, &offsets(&yaml), OffsetMatching::Events).unwrap();
+    assert_eq!(report.applied.len(), 3);
+    assert_eq!(report.applied[2].targets[0].0, ids[2]);
+    let offset = &package.get_scene(&ids[2]).unwrap().stages[0].positions[1].offset;
+    assert_eq!(offset.z, 3.0);
+}
+
+#[test]
+fn malformed_offsets_are_reported() {
+    let mut rng = rng(65);
+    let mut package = Package::new();
+    let scene = scene_with_events(&mut rng, "Doggy", &["Doggy_A1", "Doggy_A2"]);
+    let (id, stage) = (scene.id.clone(), scene.stages[0].id.clone());
+    package.save_scene(scene, false).unwrap();
+
+    let yaml = format!(
+        "{id}:\n  {stage}:\n    - transform: {t}\n    - scale: 1.0\n  Other: 3\nBroken: 5\n",
+        id = id.0,
+        stage = stage.0,
+        t = TRANSFORM
+    );
+    let report = apply_offsets(&mut package, &offsets(&yaml), OffsetMatching::Id).unwrap();
+    assert!(report.applied.is_empty());
+    let reasons: Vec<&str> = report
+        .skipped
+        .iter()
+        .map(|entry| entry.reason.as_ref().unwrap().key)
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            "offset.missing_transform",
+            "offset.expected_sequence",
+            "offset.expected_mapping"
+        ]
+    );
+}
+
+#[test]
+fn exported_offsets_round_trip() {
+    let mut rng = rng(62);
+    let mut package = arb_package(&mut rng);
+    for scene in package.scenes.values_mut() {
+        scene.locked = false;
+    }
+    let before: Vec<_> = package.scenes.values().cloned().collect();
+    let exported = export_offsets(&package, None);
+    assert_eq!(exported[&"version".into()].as_u64(), Some(OFFSET_VERSION));
+
+    for scene in package.scenes.values_mut() {
+        for info in &mut scene.positions {
+            info.scale = 1.0;
+        }
+        for position in scene.stages.iter_mut().flat_map(|s| &mut s.positions) {
+            position.offset = Default::default();
+        }
+    }
+    let yaml: serde_yaml::Mapping =
+        serde_yaml::from_str(&serde_yaml::to_string(&exported).unwrap()).unwrap();
+    let report = apply_offsets(&mut package, &yaml, OffsetMatching::Id).unwrap();
+    assert!(report.skipped.is_empty() && report.ambiguous.is_empty());
+    for scene in before {
+        let imported = package.get_scene(&scene.id).unwrap();
+        for (a, b) in scene.positions.iter().zip(&imported.positions) {
+            assert_eq!(a.scale, b.scale);
+        }
+        for (a, b) in scene.stages.iter().zip(&imported.stages) {
+            for (a, b) in a.positions.iter().zip(&b.positions) {
+                let (a, b) = (&a.offset, &b.offset);
+                assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+                assert_eq!((a.rx, a.ry, a.r), (b.rx, b.ry, b.r));
+            }
+        }
+    }
+
+    let err = apply_offsets(
+        &mut package,
+        &offsets("version: 3\nscenes: {}"),
+        OffsetMatching::Id,
+    )
+    .unwrap_err();
+    assert_eq!(err.message().key, "offset.unsupported_version");
+}
+
+#[test]
+fn templates_apply_to_scenes_with_the_same_actors() {
+    let mut rng = rng(63);
+    let male = Sex {
+        male: true,
+        female: false,
+        futa: false,
+    };
+    let mut package = Package::new();
+    let mut ids = vec![];
+    for (races, locked) in [
+        (["Human", "Canine"], false),
+        (["Canine", "Human"], false),
+        (["Human", "Human"], false),
+        (["Human", "Canine"], true),
+        (["Human", "Canine"], false),
+    ] {
+        let mut scene = scene_with_events(&mut rng, "Scene", &["A", "B"]);
+        scene.locked = false;
+        for (info, race) in scene.positions.iter_mut().zip(races) {
+            info.race = race.into();
+            info.sex = male.clone();
+        }
+        ids.push(scene.id.clone());
+        package.save_scene(scene, false).unwrap();
+        package.get_scene_mut(ids.last().unwrap()).unwrap().locked = locked;
+    }
+    // no stage of this scene fits the template
+    package.get_scene_mut(&ids[4]).unwrap().stages[0]
+        .positions
+        .pop();
+
+    let source = package.get_scene_mut(&ids[0]).unwrap();
+    let stage = source.stages[0].id.clone();
+    source.stages[0].positions[0].offset.x = 10.0;
+    source.stages[0].positions[1].offset.x = -20.0;
+    let err = save_template(&mut package, " ", &ids[0], &stage).unwrap_err();
+    assert_eq!(err.message().key, "offset.template_unnamed");
+    save_template(&mut package, "Human + Canine", &ids[0], &stage).unwrap();
+    save_template(&mut package, "human + canine", &ids[0], &stage).unwrap();
+    assert_eq!(package.offset_templates.len(), 1);
+
+    let report = apply_template(&mut package, "HUMAN + CANINE").unwrap();
+    let scenes: Vec<&NanoID> = report.applied.iter().map(|(scene, _)| scene).collect();
+    assert_eq!(scenes.len(), 2);
+    assert!(scenes.contains(&&ids[1]));
+    assert_eq!(report.locked, vec![ids[3].clone()]);
+    let audited: Vec<NanoID> = package
+        .audit
+        .recent(10)
+        .into_iter()
+        .filter(|entry| entry.operation == "apply_offset_template")
+        .filter_map(|entry| entry.scene)
+        .collect();
+    assert_eq!(audited.len(), 2);
+    assert!(!audited.contains(&ids[4]));
+    // positions are mapped by actor, not by index
+    let swapped = &package.get_scene(&ids[1]).unwrap().stages[0];
+    assert_eq!(swapped.positions[0].offset.x, -20.0);
+    assert_eq!(swapped.positions[1].offset.x, 10.0);
+
+    let err = apply_template(&mut package, "Missing").unwrap_err();
+    assert_eq!(err.message().key, "offset.unknown_template");
+}
+
+#[test]
+fn malformed_transforms_are_skipped() {
+    let mut rng = rng(64);
+    let mut package = Package::new();
+    let scene = scene_with_events(&mut rng, "Doggy", &["Doggy_A1"]);
+    package.save_scene(scene, false).unwrap();
+
+    for transform in [
+        "{ Rotation: 90.0 }",
+        "{ Location: [1.0, 2.0, 3.0] }",
+        "{ Location: [.nan, 2.0, 3.0], Rotation: 90.0 }",
+        "{ Location: [1.0, 2.0, 3.0], Rotation: [.inf, 0.0, 0.0] }",
+    ] {
+        let yaml = format!("Doggy:\n  Intro:\n    - transform: {}\n", transform);
+        let report = apply_offsets(&mut package, &offsets(&yaml), OffsetMatching::Name).unwrap();
+        assert!(report.applied.is_empty(), "{}", transform);
+        assert_eq!(report.skipped.len(), 1);
+    }
+}
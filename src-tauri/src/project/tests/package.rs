@@ -0,0 +1,1817 @@
+use super::generators::*;
+use crate::crash;
+use crate::error::SlsbError;
+use crate::i18n::{template, Message};
+use crate::logging::{format_record, last_build_log, with_build_log, BUILD_LOG_FILE};
+use crate::project::{
+    audit::log_path,
+    bundle::{Bundle, TagQuery},
+    convert::{ConversionKind, ConversionRecord, SceneConversion},
+    credits::Credits,
+    define::Transition,
+    dependencies::{Dependencies, Dependency},
+    export_path::{export_error, extended_path, MAX_PATH},
+    inspect::inspect_bytes,
+    library::{detach_stage, library_entries, link_stage, share_stage},
+    manifest::{fingerprint, make_release, stale_files, Manifest},
+    package::{
+        behaviour_dir, project_name_from_path, registry_dir, ExclusionReason, ExportPreset,
+        Package, VERSION,
+    },
+    preview::{attach_preview, clean_previews, find_preview, preview_dir_of},
+    scene::Scene,
+    schema::{project_schema, validate},
+    serialize::{fnis_event, merge_fnis_lines, EncodeBinary},
+    translations::{mcm_lines, translations_dir, SceneTranslations},
+    NanoID,
+};
+use crate::racekeys::{
+    get_race_infos, get_race_key_bytes, get_race_keys_string, guess_racekeys,
+    map_legacy_to_racekey, map_race_to_folder, normalize_behaviour_folder, BehaviourEngine,
+};
+use crate::watch::{rebuild, FileWatcher, DEBOUNCE};
+use rand::rngs::StdRng;
+
+#[test]
+fn locked_scenes_reject_edits_unless_forced() {
+    let mut rng = rng(12);
+    let mut package = Package::new();
+    let scene = arb_scene(&mut rng);
+    let id = scene.id.clone();
+    package.save_scene(scene.clone(), false).unwrap();
+    package.set_scene_locked(&id, true).unwrap();
+
+    let mut edit = scene.clone();
+    edit.name = "Edited".into();
+    assert!(package.save_scene(edit.clone(), false).is_err());
+    assert!(package.discard_scene(&id).is_err());
+    assert!(package.get_unlocked_scene_mut(&id).is_err());
+    assert_eq!(package.get_scene(&id).unwrap().name, scene.name);
+
+    package.save_scene(edit, true).unwrap();
+    assert_eq!(package.get_scene(&id).unwrap().name, "Edited");
+    package.set_scene_locked(&id, false).unwrap();
+    assert!(package.discard_scene(&id).is_ok());
+}
+
+#[test]
+fn discarded_scenes_are_kept_in_the_trash() {
+    let mut rng = rng(87);
+    let mut package = Package::new();
+    let scene = arb_scene(&mut rng);
+    let id = scene.id.clone();
+    package.save_scene(scene, false).unwrap();
+    package.discard_scene(&id).unwrap();
+    assert!(package.get_scene(&id).is_none());
+    assert!(package.ids().contains(&id));
+    let trash = package.trashed_scenes();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].scene.id, id);
+    assert!(trash[0].timestamp > 0);
+
+    // the trash is saved with the project
+    let dir = TempDir::new("trash");
+    let path = dir.join("trash.slsb.json");
+    package.write(path.clone()).unwrap();
+    let mut package = Package::open(&path).unwrap();
+    assert_eq!(package.trash.len(), 1);
+
+    let restored = package.restore_scene(&id).unwrap();
+    assert_eq!(restored.id, id);
+    assert!(package.trash.is_empty());
+    let err = package.restore_scene(&id).unwrap_err();
+    assert_eq!(err.message().key, "scene.not_in_trash");
+
+    package.discard_scene(&id).unwrap();
+    let mut copy = package.trash[0].scene.clone();
+    copy.name = "Copy".into();
+    package.save_scene(copy, false).unwrap();
+    let err = package.restore_scene(&id).unwrap_err();
+    assert_eq!(err.message().key, "scene.restore_conflict");
+    assert_eq!(package.empty_trash(), 1);
+    assert_eq!(package.empty_trash(), 0);
+    assert!(package.trashed_scenes().is_empty());
+}
+
+#[test]
+fn audit_log_is_flushed_next_to_project() {
+    let dir = TempDir::new("audit");
+    let project_path = dir.join("Pack.slsb.json");
+    assert_eq!(log_path(&project_path), dir.join("Pack.slsb.log"));
+
+    let mut rng = rng(13);
+    let mut package = Package::new();
+    let scene = arb_scene(&mut rng);
+    let id = scene.id.clone();
+    package.save_scene(scene, false).unwrap();
+    package.set_scene_locked(&id, true).unwrap();
+    package.write(project_path.clone()).unwrap();
+    package.pack_path = project_path.clone();
+    package
+        .audit
+        .record("save_project", None, vec![], Some("First release".into()));
+    package.audit.flush(&log_path(&project_path)).unwrap();
+    package.discard_scene(&NanoID::new_nanoid()).unwrap_err();
+
+    let entries = package.get_audit_log(None);
+    let operations: Vec<&str> = entries.iter().map(|e| e.operation.as_str()).collect();
+    assert_eq!(operations, vec!["save_scene", "lock_scene", "save_project"]);
+    assert_eq!(entries[2].message.as_deref(), Some("First release"));
+    assert_eq!(package.get_audit_log(Some(&id)).len(), 2);
+}
+
+#[test]
+fn release_versions_follow_scene_changes() {
+    let mut rng = rng(14);
+    let mut package = Package::new();
+    for _ in 0..3 {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        package.save_scene(scene, false).unwrap();
+    }
+    let first = make_release(&package, None).changelog;
+    assert_eq!(first.version.to_string(), "1.0.0");
+    assert_eq!(first.added.len(), 3);
+    let manifest = Manifest::from_package(&package);
+
+    // moving nodes around is not a change
+    let mut ids: Vec<NanoID> = package.scenes.keys().cloned().collect();
+    ids.sort_by_key(|id| id.0.clone());
+    for node in package.get_scene_mut(&ids[0]).unwrap().graph.values_mut() {
+        node.x += 10.0;
+    }
+    assert!(make_release(&package, Some(&manifest)).changelog.is_empty());
+
+    package.get_scene_mut(&ids[0]).unwrap().name = "Renamed".into();
+    let patch = make_release(&package, Some(&manifest));
+    assert_eq!(patch.changelog.version.to_string(), "1.0.1");
+    // nothing changes until the release is applied
+    assert_eq!(package.get_scene(&ids[0]).unwrap().revision, 0);
+    assert_eq!(package.pack_version.to_string(), "1.0.0");
+    patch.apply(&mut package);
+    assert_eq!(package.get_scene(&ids[0]).unwrap().revision, 1);
+    assert_eq!(package.pack_version.to_string(), "1.0.1");
+    let manifest = Manifest::from_package(&package);
+
+    package.save_scene(arb_scene(&mut rng), false).unwrap();
+    package.discard_scene(&ids[1]).unwrap();
+    let major = make_release(&package, Some(&manifest)).changelog;
+    assert_eq!(major.version.to_string(), "2.0.0");
+    assert!(major.to_markdown().starts_with("## 2.0.0\n"));
+    assert_eq!(major.removed.len(), 1);
+}
+
+#[test]
+fn errors_serialize_with_kind_and_message() {
+    let mut package = Package::new();
+    let err = package.discard_scene(&NanoID::new_nanoid()).unwrap_err();
+    let value = serde_json::to_value(&err).unwrap();
+    assert_eq!(value["kind"], "validation");
+    assert_eq!(value["key"], "scene.invalid_id");
+    assert!(value["params"]["scene"].is_string());
+    assert_eq!(value["message"], err.to_string());
+
+    let err = Package::from_file(std::fs::File::open(file!()).unwrap()).unwrap_err();
+    assert!(matches!(err, SlsbError::Json(_)));
+    let err = crate::racekeys::map_legacy_to_racekey("not a race").unwrap_err();
+    assert_eq!(serde_json::to_value(&err).unwrap()["kind"], "unknown_race");
+}
+
+#[test]
+fn builds_report_excluded_scenes() {
+    let mut rng = rng(22);
+    let mut package = Package::new();
+    for name in ["b", "A", "c"] {
+        let mut scene = arb_scene(&mut rng);
+        scene.name = name.into();
+        scene.has_warnings = name == "A";
+        if name == "c" {
+            scene.stages.clear();
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    let dir = TempDir::new("excluded");
+    let summary = package.build(dir.clone()).unwrap();
+    assert_eq!(summary.scenes, 1);
+    let excluded: Vec<(&str, ExclusionReason)> = summary
+        .excluded
+        .iter()
+        .map(|scene| (scene.name.as_str(), scene.reason))
+        .collect();
+    assert_eq!(
+        excluded,
+        vec![
+            ("A", ExclusionReason::Flagged),
+            ("c", ExclusionReason::NoStages)
+        ]
+    );
+}
+
+#[test]
+fn builds_count_scenes_per_tag_and_race() {
+    let mut rng = rng(23);
+    let mut package = Package::new();
+    for races in [["Human", "Wolf"], ["Human", "Human"]] {
+        let mut scene = arb_scene(&mut rng);
+        while scene.positions.len() < 2 {
+            scene = arb_scene(&mut rng);
+        }
+        scene.has_warnings = false;
+        scene.tags = vec!["Vaginal".into(), " vaginal".into()];
+        scene.positions.truncate(2);
+        for (info, race) in scene.positions.iter_mut().zip(races) {
+            info.race = race.into();
+        }
+        for stage in &mut scene.stages {
+            stage.positions.truncate(2);
+            stage.tags = vec!["Kissing".into()];
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    let dir = TempDir::new("distribution");
+    let summary = package.build(dir.clone()).unwrap();
+    let distribution = &summary.distribution;
+    assert_eq!(distribution.tags["vaginal"], 2);
+    assert_eq!(distribution.tags["kissing"], 2);
+    assert_eq!(distribution.races["Human"], 2);
+    assert_eq!(distribution.races["Wolf"], 1);
+    assert_eq!(&Manifest::read(&dir).unwrap().distribution, distribution);
+}
+
+#[test]
+fn build_writes_export_log() {
+    let mut rng = rng(21);
+    let mut package = arb_package(&mut rng);
+    let dir = TempDir::new("buildlog");
+    package.build(dir.clone()).unwrap();
+    let path = dir.join(BUILD_LOG_FILE);
+    assert!(path.is_file());
+    assert_eq!(last_build_log(), Some(path.clone()));
+
+    // a log which cannot be written never takes the place of the outcome of the export
+    std::fs::remove_file(&path).unwrap();
+    std::fs::create_dir(&path).unwrap();
+    package.build(dir.clone()).unwrap();
+    let err = with_build_log::<()>(&dir, || Err(Message::new("export.missing_event").into()))
+        .unwrap_err();
+    assert_eq!(err.message().key, "export.missing_event");
+
+    let fields = [("scene", "abc")];
+    let record = log::Record::builder()
+        .level(log::Level::Warn)
+        .target("offsets")
+        .args(format_args!("Scene has no such stage"))
+        .key_values(&fields)
+        .build();
+    assert_eq!(
+        format_record(&record),
+        "[WARN] [offsets] Scene has no such stage scene=abc"
+    );
+}
+
+#[test]
+fn messages_have_english_templates() {
+    let msg = Message::new("scene.missing_stage")
+        .arg("scene", "abc")
+        .arg("stage", 3);
+    assert_eq!(msg.to_string(), "Scene abc has no stage with id 3");
+    assert_eq!(Message::new("not.a.key").to_string(), "not.a.key");
+
+    let sources = [
+        include_str!("../../main.rs"),
+        include_str!("../../cli.rs"),
+        include_str!("../../error.rs"),
+        include_str!("../package.rs"),
+        include_str!("../scene.rs"),
+        include_str!("../stage.rs"),
+        include_str!("../position.rs"),
+        include_str!("../events.rs"),
+        include_str!("../validate.rs"),
+        include_str!("../convert.rs"),
+        include_str!("../aliases.rs"),
+        include_str!("../git.rs"),
+        include_str!("../schema.rs"),
+        include_str!("../csv.rs"),
+        include_str!("../inspect.rs"),
+        include_str!("../registry.rs"),
+        include_str!("../tags.rs"),
+        include_str!("../offsets.rs"),
+        include_str!("../repair.rs"),
+        include_str!("../preview.rs"),
+        include_str!("../hkx.rs"),
+        include_str!("../dot.rs"),
+    ];
+    for source in sources {
+        for part in source.split("Message::new(\"").skip(1) {
+            let key = &part[..part.find('"').unwrap()];
+            assert!(template(key).is_some(), "Missing template for {}", key);
+        }
+    }
+}
+
+#[test]
+fn crash_reports_are_anonymous_and_archived() {
+    let mut rng = rng(33);
+    let package = arb_package(&mut rng);
+    let dir = TempDir::new("crash");
+    crash::autosave(&dir, &package).unwrap();
+    let restored = crash::load_autosave(&dir).unwrap();
+    assert_eq!(restored.scenes.len(), package.scenes.len());
+
+    let report = crash::make_report("boom".into(), None);
+    assert!(report.autosave);
+    let stats = crash::ProjectStats::from_package(&package);
+    assert_eq!(stats.scenes, package.scenes.len());
+    let data = serde_json::to_string(&crash::CrashReport {
+        project: stats,
+        ..report
+    })
+    .unwrap();
+    for scene in package.scenes.values().filter(|s| s.name.len() > 3) {
+        assert!(!data.contains(&scene.name));
+    }
+
+    std::fs::write(dir.join(crash::CRASH_FILE), &data).unwrap();
+    assert!(crash::take_report(&dir).is_some());
+    assert!(crash::take_report(&dir).is_none());
+    crash::discard_autosave(&dir);
+    assert!(crash::load_autosave(&dir).is_err());
+}
+
+#[test]
+fn export_rejects_positions_without_events() {
+    let mut rng = rng(34);
+    let mut package = Package::new();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    scene.stages[0].positions[0].event.clear();
+    package.save_scene(scene, false).unwrap();
+    let dir = TempDir::new("noevent");
+    let err = package.build(dir.clone()).unwrap_err();
+    assert_eq!(err.message().key, "export.missing_event");
+}
+
+#[test]
+fn watched_projects_rebuild_after_debounce() {
+    let mut rng = rng(35);
+    let dir = TempDir::new("watch");
+    let path = dir.join("watched.slsb.json");
+    let mut watcher = FileWatcher::new(path.clone(), DEBOUNCE);
+    let start = std::time::Instant::now();
+    assert!(!watcher.poll_at(start));
+
+    let mut package = arb_package(&mut rng);
+    package.write(path.clone()).unwrap();
+    assert!(!watcher.poll_at(start));
+    assert!(!watcher.poll_at(start + DEBOUNCE / 2));
+    assert!(watcher.poll_at(start + DEBOUNCE));
+    assert!(!watcher.poll_at(start + DEBOUNCE * 2));
+
+    let build = rebuild(&path, &dir.join("out"));
+    assert!(build.error.is_none(), "{:?}", build.error);
+    assert_eq!(build.scenes, package.exported_scenes().count());
+    let build = rebuild(&dir.join("missing.slsb.json"), &dir.join("out"));
+    assert_eq!(build.error.map(|e| e.key), Some("error.io"));
+}
+
+#[test]
+fn project_names_are_derived_from_paths() {
+    let name = |path: &str| project_name_from_path(std::path::Path::new(path));
+    assert_eq!(name("mods/Pack.slsb.json"), Some("Pack".into()));
+    assert_eq!(name("Überpack ♥.SLSB.JSON"), Some("Überpack ♥".into()));
+    assert_eq!(name("ö.json"), Some("ö".into()));
+    assert_eq!(name("Pack.slsb.json.bak"), Some("Pack.slsb.json".into()));
+    assert_eq!(name(".slsb.json"), None);
+
+    let mut package = Package::new();
+    assert_eq!(
+        package.rename("  ").unwrap_err().message().key,
+        "project.empty_name"
+    );
+    package.rename(" Renamed ").unwrap();
+    assert_eq!(package.pack_name, "Renamed");
+}
+
+fn files_under(dir: &std::path::Path, out: &mut Vec<std::path::PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files_under(&path, out);
+        } else {
+            out.push(path);
+        }
+    }
+}
+
+#[test]
+fn export_paths_are_platform_independent() {
+    let root = std::path::Path::new("out");
+    assert_eq!(
+        behaviour_dir(root, "dlc02\\scrib", "Pack"),
+        root.join("meshes")
+            .join("actors")
+            .join("dlc02")
+            .join("scrib")
+            .join("animations")
+            .join("Pack")
+    );
+
+    let mut rng = rng(22);
+    let mut package = arb_package(&mut rng);
+    // separators in the pack name do not leak into the paths either
+    package.pack_name = "Pack\\Sub".into();
+    let dir = TempDir::new("paths");
+    package.build(dir.clone()).unwrap();
+    let mut files = vec![];
+    files_under(&dir, &mut files);
+    for file in &files {
+        let mut components = file.strip_prefix(&dir).unwrap().components();
+        assert!(components.all(|c| !c.as_os_str().to_string_lossy().contains('\\')));
+    }
+    assert!(registry_dir(&dir).join("Pack_Sub.slr").is_file());
+    assert!(files.iter().any(|f| f
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("FNIS_Pack_Sub_"))
+        && f.starts_with(dir.join("meshes").join("actors"))));
+}
+
+#[test]
+fn animation_root_is_stored_relative_to_the_project() {
+    let dir = TempDir::new("portable");
+    let anims = dir.join("Data").join("meshes");
+    std::fs::create_dir_all(&anims).unwrap();
+    std::fs::create_dir_all(dir.join("projects")).unwrap();
+    let path = dir.join("projects").join("Pack.slsb.json");
+
+    let mut package = Package::new();
+    package.animation_root = Some(anims.clone());
+    package.write(path.clone()).unwrap();
+    assert_eq!(package.animation_root, Some(anims.clone()));
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["animation_root"], "../Data/meshes");
+
+    // moving the whole folder keeps the reference intact
+    let moved = TempDir::new("portable");
+    std::fs::rename(&dir, &moved).unwrap();
+    let package = Package::open(&moved.join("projects").join("Pack.slsb.json")).unwrap();
+    let root = package.animation_root.unwrap();
+    assert!(root.is_dir());
+    assert_eq!(
+        std::fs::canonicalize(root).unwrap(),
+        std::fs::canonicalize(moved.join("Data").join("meshes")).unwrap()
+    );
+}
+
+#[test]
+fn credits_group_scenes_by_author_and_source() {
+    let mut rng = rng(48);
+    let mut package = Package::new();
+    package.pack_author = "Packer".into();
+    for (author, source, anim_obj) in [
+        (None, None, "AnimObjA"),
+        (
+            Some("Billyy"),
+            Some("BillyyCreatures"),
+            "AnimObjB, AnimObjA",
+        ),
+        (Some("Billyy"), Some("BillyyCreatures"), ""),
+    ] {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        scene.author = author.map(str::to_string);
+        scene.source_pack = source.map(str::to_string);
+        for position in scene.stages.iter_mut().flat_map(|s| &mut s.positions) {
+            position.anim_obj = anim_obj.into();
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+
+    let credits = Credits::from_package(&package);
+    let groups: Vec<(&str, Option<&str>, usize)> = credits
+        .groups
+        .iter()
+        .map(|g| (g.author.as_str(), g.source_pack.as_deref(), g.scenes.len()))
+        .collect();
+    assert_eq!(
+        groups,
+        vec![("Billyy", Some("BillyyCreatures"), 2), ("Packer", None, 1)]
+    );
+    let objects: Vec<(&str, usize)> = credits
+        .anim_objects
+        .iter()
+        .map(|o| (o.name.as_str(), o.scenes))
+        .collect();
+    assert_eq!(objects, vec![("AnimObjA", 2), ("AnimObjB", 1)]);
+    let text = credits.to_text();
+    assert!(text.contains("Billyy, from BillyyCreatures (2 scenes"));
+    assert!(text.contains("- AnimObjA (2 scenes)"));
+}
+
+#[test]
+fn dependencies_aggregate_requirements_and_anim_objects() {
+    let mut rng = rng(54);
+    let mut package = Package::new();
+    package.pack_name = "Deps".into();
+    assert!(Dependencies::from_package(&package).is_empty());
+    for (name, plugins, anim_obj) in [
+        ("Alpha", vec!["ZaZAnimationPack.esm"], "AnimObjA"),
+        (
+            "Beta",
+            vec!["zazanimationpack.esm", "Other.esp"],
+            " AnimObjA ,AnimObjB",
+        ),
+    ] {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        scene.name = name.into();
+        for info in &mut scene.positions {
+            info.race = "Human".into();
+        }
+        scene.requirements.plugins = plugins.into_iter().map(str::to_string).collect();
+        scene.requirements.keywords.clear();
+        for position in scene.stages.iter_mut().flat_map(|s| &mut s.positions) {
+            position.anim_obj = anim_obj.into();
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+
+    let dependencies = Dependencies::from_package(&package);
+    let names = |list: &[Dependency]| -> Vec<(String, Vec<String>)> {
+        list.iter()
+            .map(|d| (d.name.to_lowercase(), d.scenes.clone()))
+            .collect()
+    };
+    assert_eq!(
+        names(&dependencies.plugins),
+        vec![
+            ("other.esp".into(), vec!["Beta".into()]),
+            (
+                "zazanimationpack.esm".into(),
+                vec!["Alpha".into(), "Beta".into()]
+            ),
+        ]
+    );
+    assert!(dependencies.keywords.is_empty());
+    assert_eq!(
+        names(&dependencies.anim_objects),
+        vec![
+            ("animobja".into(), vec!["Alpha".into(), "Beta".into()]),
+            ("animobjb".into(), vec!["Beta".into()]),
+        ]
+    );
+    let text = dependencies.to_text();
+    assert!(text
+        .to_lowercase()
+        .contains("- zazanimationpack.esm (2 scenes)"));
+    assert!(text.contains("- AnimObjB (1 scene)"));
+    assert!(!text.contains("Keywords"));
+}
+
+#[test]
+fn previews_are_attached_cleaned_and_exported() {
+    let mut rng = rng(49);
+    let dir = TempDir::new("preview");
+    let mut package = Package::new();
+    package.pack_name = "Previews: Pack".into();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    let id = scene.id.clone();
+    package.scenes.insert(id.clone(), scene);
+    let clip = dir.join("clip.GIF");
+    std::fs::write(&clip, [1; 8]).unwrap();
+    std::fs::write(dir.join("clip.mp4"), [1; 8]).unwrap();
+
+    let err = attach_preview(&package, &id, &clip).unwrap_err();
+    assert_eq!(err.message().key, "preview.unsaved_project");
+    package.pack_path = dir.join("Previews.slsb.json");
+    std::fs::write(&package.pack_path, "{}").unwrap();
+    let err = attach_preview(&package, &id, &dir.join("clip.mp4")).unwrap_err();
+    assert_eq!(err.message().key, "preview.unsupported_format");
+
+    let gif = attach_preview(&package, &id, &clip).unwrap();
+    assert!(gif.ends_with(format!("previews/{}.gif", id.0)));
+    let webm = dir.join("clip.webm");
+    std::fs::write(&webm, [2; 8]).unwrap();
+    let webm = attach_preview(&package, &id, &webm).unwrap();
+    assert!(!gif.exists());
+    assert_eq!(find_preview(&package, &id), Some(webm.clone()));
+
+    let orphan = webm.with_file_name("orphan.gif");
+    std::fs::write(&orphan, [3; 8]).unwrap();
+    assert_eq!(
+        clean_previews(&package, true).unwrap(),
+        vec![orphan.clone()]
+    );
+    assert!(orphan.exists());
+    assert_eq!(
+        clean_previews(&package, false).unwrap(),
+        vec![orphan.clone()]
+    );
+    assert!(!orphan.exists() && webm.exists());
+
+    let out = dir.join("export");
+    package.export_previews = true;
+    package.build(out.clone()).unwrap();
+    let exported = preview_dir_of(&out, "Previews: Pack").join(format!("{}.webm", id.0));
+    assert!(exported.starts_with(
+        out.join("SKSE")
+            .join("SexLab")
+            .join("Previews")
+            .join("Previews_ Pack")
+    ));
+    assert_eq!(std::fs::read(exported).unwrap(), vec![2; 8]);
+}
+
+#[test]
+fn pretty_saves_are_stable_and_sorted() {
+    let mut rng = rng(33);
+    let mut package = Package::new();
+    package.pack_name = "Pretty".into();
+    package.pretty_save = true;
+    for _ in 0..4 {
+        let scene = arb_scene(&mut rng);
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    let dir = TempDir::new("pretty");
+    let path = dir.join("Pretty.slsb.json");
+    package.write(path.clone()).unwrap();
+    let first = std::fs::read_to_string(&path).unwrap();
+    package.write(path.clone()).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), first);
+    assert!(first.lines().count() > package.scenes.len());
+
+    // scenes are written in the order of their ids
+    let mut ids: Vec<&String> = package.scenes.keys().map(|id| &id.0).collect();
+    ids.sort();
+    let offsets: Vec<usize> = ids
+        .iter()
+        .map(|id| first.find(&format!("\"{}\": {{", id)).unwrap())
+        .collect();
+    assert!(offsets.windows(2).all(|w| w[0] < w[1]));
+
+    let loaded = Package::open(&path).unwrap();
+    assert!(loaded.pretty_save);
+    assert_eq!(loaded.scenes.len(), package.scenes.len());
+}
+
+#[test]
+fn failed_saves_keep_the_previous_file() {
+    let mut package = Package::new();
+    package.pack_name = "Kept".into();
+    let dir = TempDir::new("kept");
+    let path = dir.join("Kept.slsb.json");
+    package.write(path.clone()).unwrap();
+    let saved = std::fs::read(&path).unwrap();
+    assert_eq!(std::fs::read_dir(&*dir).unwrap().count(), 1);
+
+    // a folder in place of the temporary file makes the save fail before the file is replaced
+    std::fs::create_dir(dir.join("Kept.slsb.json.tmp")).unwrap();
+    package.pack_name = "Changed".into();
+    assert!(package.write(path.clone()).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), saved);
+}
+
+#[test]
+fn saved_projects_match_the_schema() {
+    for seed in 0..CASES {
+        let package = arb_package(&mut rng(seed));
+        let value = serde_json::to_value(&package).unwrap();
+        assert_eq!(validate(&value, project_schema()), vec![], "seed {}", seed);
+    }
+    let schema = project_schema();
+    assert_eq!(schema["title"], "Package");
+    assert!(schema["required"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("scenes")));
+}
+
+#[test]
+fn invalid_project_files_point_at_the_offending_value() {
+    let mut package = arb_package(&mut rng(4));
+    let scene = arb_scene(&mut rng(5));
+    let id = scene.id.0.clone();
+    package.scenes.insert(scene.id.clone(), scene);
+    let mut value = serde_json::to_value(&package).unwrap();
+    value["scenes"][&id]["stages"][0]["name"] = serde_json::json!(12);
+    value["scenes"][&id]["furniture"]
+        .as_object_mut()
+        .unwrap()
+        .remove("allow_bed");
+
+    let dir = TempDir::new("schema");
+    let path = dir.join("Invalid.slsb.json");
+    std::fs::write(&path, value.to_string()).unwrap();
+    let message = Package::open(&path).unwrap_err().message();
+    assert_eq!(message.key, "project.invalid_file");
+    assert_eq!(message.params["count"], "2");
+    let errors = validate(&value, project_schema());
+    assert!(errors.iter().any(
+        |e| e.pointer == format!("/scenes/{}/furniture/allow_bed", id) && e.found == "nothing"
+    ));
+    let name = errors
+        .iter()
+        .find(|e| e.pointer == format!("/scenes/{}/stages/0/name", id))
+        .unwrap();
+    assert_eq!(
+        (name.expected.as_str(), name.found.as_str()),
+        ("string", "number 12")
+    );
+
+    value["version"] = serde_json::json!(300);
+    value["scenes"][&id]["archetype"] = serde_json::json!("crowd");
+    let errors = validate(&value, project_schema());
+    let version = errors.iter().find(|e| e.pointer == "/version").unwrap();
+    assert_eq!(version.expected, "integer from 0 to 255");
+    assert!(errors
+        .iter()
+        .any(|e| e.pointer == format!("/scenes/{}/archetype", id) && e.found == "string"));
+}
+
+#[test]
+fn generated_ids_are_unique_within_the_project() {
+    let mut rng = rng(31);
+    let mut package = Package::new();
+    let scene = arb_scene(&mut rng);
+    package.save_scene(scene.clone(), false).unwrap();
+
+    // a copy of the scene collides with every id of the original
+    let mut copy = scene.clone();
+    let replaced = package.claim_ids(&mut copy);
+    assert_eq!(replaced, 1 + scene.stages.len());
+    assert!(!package.ids().contains(&copy.id));
+    assert_eq!(copy.root, copy.stages[0].id);
+    // edges follow the stages to their new ids
+    for (old, new) in scene.stages.iter().zip(&copy.stages) {
+        assert!(!package.ids().contains(&new.id));
+        let dest: Vec<usize> = scene.graph[&old.id]
+            .dest
+            .iter()
+            .map(|id| scene.stages.iter().position(|s| &s.id == id).unwrap())
+            .collect();
+        let copied: Vec<usize> = copy.graph[&new.id]
+            .dest
+            .iter()
+            .map(|id| copy.stages.iter().position(|s| &s.id == id).unwrap())
+            .collect();
+        assert_eq!(dest, copied);
+    }
+    assert_eq!(package.claim_ids(&mut copy), 0);
+    package.save_scene(copy, false).unwrap();
+    assert_eq!(package.scenes.len(), 2);
+
+    let blank = package.new_scene();
+    assert!(!package.ids().contains(&blank.id));
+    let stage = package.new_stage(&scene);
+    assert!(!package.ids().contains(&stage.id));
+}
+
+#[test]
+fn collision_probability_follows_the_birthday_bound() {
+    assert_eq!(NanoID::collision_probability(0, NanoID::NANOID_LENGTH), 0.0);
+    assert_eq!(NanoID::collision_probability(1, NanoID::NANOID_LENGTH), 0.0);
+    // 36 ids of length 1 collide with a probability close to 1 - 1/e^17.5
+    let p = NanoID::collision_probability(36, 1);
+    assert!((p - (1.0 - (-17.5f64).exp())).abs() < 1e-9);
+    let p = NanoID::collision_probability(50_000, NanoID::NANOID_LENGTH);
+    assert!(p > 1e-4 && p < 1e-3, "{}", p);
+    let prefix = NanoID::collision_probability(50_000, NanoID::PREFIX_HASH_LEN);
+    assert!(prefix > p);
+
+    let ids = Package::new().id_statistics();
+    assert_eq!(ids.alphabet.len(), NanoID::NANOID_ALPHABET.len());
+    assert_eq!(ids.ids, 0);
+}
+
+#[test]
+fn readable_ids_follow_scene_names() {
+    let mut rng = rng(32);
+    let mut package = Package::new();
+    let mut scenes = vec![];
+    for name in ["Doggy Style", "Doggy Style", "69"] {
+        let mut scene = arb_scene(&mut rng);
+        scene.name = name.into();
+        scenes.push(scene.id.clone());
+        package.save_scene(scene, false).unwrap();
+    }
+    let scene = package.get_scene_mut(&scenes[0]).unwrap();
+    scene.transitions.push(Transition {
+        from_stage: scene.root.clone(),
+        to_scene: scenes[2].clone(),
+    });
+
+    let readable = package.make_ids_readable(None, false).unwrap();
+    assert!(readable.changed > 0);
+    // the renamed scenes under their old and new ids
+    assert_eq!(readable.scenes.len(), 6);
+    assert_eq!(package.scenes.len(), 3);
+    let mut ids: Vec<&str> = package.scenes.keys().map(|id| id.0.as_str()).collect();
+    ids.sort();
+    assert!(ids[0].starts_with("69"));
+    assert!(ids[1].starts_with("doggy") && ids[2].starts_with("doggy"));
+    assert_ne!(ids[1], ids[2]);
+    let mut total = package.scenes.len();
+    for scene in package.scenes.values() {
+        assert_eq!(scene.id.0.len(), NanoID::NANOID_LENGTH);
+        assert_eq!(scene.root, scene.stages[0].id);
+        for (i, stage) in scene.stages.iter().enumerate() {
+            assert!(scene.graph.contains_key(&stage.id));
+            let prefix: String = NanoID::readable_label(&scene.name)
+                .chars()
+                .take(3)
+                .collect();
+            assert!(stage.id.0.starts_with(&format!("{}{}", prefix, i + 1)));
+        }
+        total += scene.stages.len();
+    }
+    assert_eq!(package.ids().len(), total);
+    // transitions follow the renamed scene
+    let transition = package
+        .scenes
+        .values()
+        .flat_map(|scene| &scene.transitions)
+        .next()
+        .unwrap();
+    assert!(package.get_scene(&transition.to_scene).unwrap().name == "69");
+    assert!(package.get_stage(&transition.from_stage).is_some());
+    // the ids are stable
+    assert_eq!(package.make_ids_readable(None, false).unwrap().changed, 0);
+    // and stay the same between versions
+    assert_eq!(
+        NanoID::new_readable("Doggy Style", "seed", |_| false).0,
+        "doggyqtt"
+    );
+}
+
+#[test]
+fn readable_ids_spare_released_scenes_and_keep_references() {
+    let mut rng = rng(34);
+    let dir = TempDir::new("readable_ids");
+    let mut package = Package::new();
+    let mut ids = vec![];
+    for name in ["Missionary", "Cowgirl", "Kissing"] {
+        let mut scene = arb_scene(&mut rng);
+        scene.name = name.into();
+        ids.push(scene.id.clone());
+        package.save_scene(scene, false).unwrap();
+    }
+    package.set_scene_locked(&ids[2], true).unwrap();
+    let mut record = ConversionRecord::new(ConversionKind::Slal, 0, VERSION);
+    record.scenes.push(SceneConversion {
+        scene: ids[0].clone(),
+        name: "Missionary".into(),
+        ..Default::default()
+    });
+    package.conversions.push(record);
+    // only the second scene was exported before
+    let mut manifest = Manifest::from_package(&package);
+    manifest.scenes.retain(|id, _| *id == ids[1].0);
+    manifest.write(&dir).unwrap();
+    package.export_presets.push(ExportPreset {
+        name: "Release".into(),
+        out: dir.to_path_buf(),
+        bundles: None,
+        export_previews: false,
+        clean_export: false,
+        merge_fnis: false,
+    });
+    // entries of earlier saves are rewritten with the next save
+    let log = dir.join("Pack.slsb.log");
+    package.audit.flush(&log).unwrap();
+
+    let err = package
+        .make_ids_readable(Some(&ids[1..2]), false)
+        .unwrap_err();
+    assert_eq!(err.message().key, "scene.released");
+    let err = package
+        .make_ids_readable(Some(&ids[2..]), false)
+        .unwrap_err();
+    assert_eq!(err.message().key, "scene.locked");
+    assert!(package.make_ids_readable(None, false).unwrap().changed > 0);
+    assert!(package.scenes.contains_key(&ids[1]));
+    assert!(package.scenes.contains_key(&ids[2]));
+    let renamed = package
+        .scenes
+        .values()
+        .find(|scene| scene.name == "Missionary")
+        .unwrap();
+    assert_ne!(renamed.id, ids[0]);
+    assert_eq!(package.conversions[0].scenes[0].scene, renamed.id);
+    let entries = package.audit.entries(Some(&log));
+    assert!(entries
+        .iter()
+        .any(|entry| entry.scene.as_ref() == Some(&renamed.id)));
+    assert!(entries
+        .iter()
+        .all(|entry| entry.scene.as_ref() != Some(&ids[0])));
+    let stages: Vec<&NanoID> = renamed.stages.iter().map(|s| &s.id).collect();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.scene.as_ref() == Some(&renamed.id))
+    {
+        assert!(entry.stages.iter().all(|stage| stages.contains(&stage)));
+    }
+
+    package.audit.flush(&log).unwrap();
+    assert!(!std::fs::read_to_string(&log).unwrap().contains(&ids[0].0));
+
+    assert!(
+        package
+            .make_ids_readable(Some(&ids[1..]), true)
+            .unwrap()
+            .changed
+            > 0
+    );
+    assert!(!package.scenes.contains_key(&ids[1]));
+}
+
+#[test]
+fn rebuilds_report_and_clean_files_no_longer_written() {
+    let mut rng = rng(33);
+    let mut package = arb_package(&mut rng);
+    package.pack_name = "Pack".into();
+    let dir = TempDir::new("stale");
+    package.build(dir.clone()).unwrap();
+    let first = Manifest::read(&dir).unwrap();
+    assert!(first
+        .files
+        .contains(&"SKSE/SexLab/Registry/Pack.slr".to_string()));
+    assert!(first.files.iter().any(|f| f.contains("FNIS_Pack")));
+    // an edited manifest must not lead out of the export folder
+    let outside = dir.with_extension("txt");
+    std::fs::write(&outside, "").unwrap();
+    let mut edited = first.clone();
+    edited.files.push(format!(
+        "../{}",
+        outside.file_name().unwrap().to_string_lossy()
+    ));
+    let current = Manifest::from_package(&package);
+    assert_eq!(
+        stale_files(&edited, &current, &dir).len(),
+        first.files.len()
+    );
+
+    // renamed packs leave the old registry and lists behind until cleaning is enabled
+    package.pack_name = "Renamed".into();
+    package.build(dir.clone()).unwrap();
+    let stale: Vec<&String> = first.files.iter().collect();
+    let second = Manifest::read(&dir).unwrap();
+    assert!(stale.iter().all(|file| second.files.contains(file)));
+    assert!(registry_dir(&dir).join("Pack.slr").is_file());
+
+    package.clean_export = true;
+    package.build(dir.clone()).unwrap();
+    let third = Manifest::read(&dir).unwrap();
+    assert!(stale.iter().all(|file| !third.files.contains(file)));
+    assert!(!registry_dir(&dir).join("Pack.slr").is_file());
+    assert!(registry_dir(&dir).join("Renamed.slr").is_file());
+    assert!(outside.is_file());
+    let _ = std::fs::remove_file(outside);
+}
+
+#[test]
+fn profiled_builds_time_every_phase() {
+    let mut package = arb_package(&mut rng(84));
+    package.pack_name = "Profiled".into();
+    let dir = TempDir::new("profile");
+    assert!(package.build(dir.clone()).unwrap().profile.is_none());
+
+    package.profile_export = true;
+    let profile = package.build(dir.clone()).unwrap().profile.unwrap();
+    let phases: Vec<&str> = profile
+        .phases
+        .iter()
+        .map(|timing| timing.phase.as_str())
+        .collect();
+    assert_eq!(
+        phases,
+        vec!["validate", "fnis", "byte_size", "encode", "io"]
+    );
+    let sum: f64 = profile.phases.iter().map(|timing| timing.ms).sum();
+    assert!(sum <= profile.total_ms);
+}
+
+#[test]
+fn read_only_targets_fail_the_export_before_writing() {
+    assert_eq!(extended_path(r"C:\Mods\Pack"), r"\\?\C:\Mods\Pack");
+    assert_eq!(extended_path("C:/Mods/Pack"), r"\\?\C:\Mods\Pack");
+    assert_eq!(
+        extended_path(r"\\server\share\Pack"),
+        r"\\?\UNC\server\share\Pack"
+    );
+    assert_eq!(extended_path(r"\\?\C:\Mods"), r"\\?\C:\Mods");
+
+    let long = std::path::PathBuf::from("x".repeat(MAX_PATH));
+    let err = export_error(std::io::Error::from_raw_os_error(206), &long);
+    assert_eq!(err.message().key, "export.path_too_long");
+    let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+    let err = export_error(denied, &long);
+    assert_eq!(err.message().key, "export.permission_denied");
+
+    let mut package = arb_package(&mut rng(33));
+    package.pack_name = "ReadOnly".into();
+    let dir = TempDir::new("readonly");
+    package.build(dir.clone()).unwrap();
+    let registry = registry_dir(&dir).join("ReadOnly.slr");
+    let before = std::fs::read(&registry).unwrap();
+    let list = Manifest::read(&dir)
+        .unwrap()
+        .files
+        .into_iter()
+        .find(|f| f.contains("FNIS_ReadOnly"))
+        .unwrap();
+    let list = dir.join(list);
+    let mut permissions = std::fs::metadata(&list).unwrap().permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(&list, permissions).unwrap();
+
+    package.pack_author = "Someone else".into();
+    let err = package.build(dir.clone()).unwrap_err();
+    assert_eq!(err.message().key, "export.read_only");
+    assert!(err.message().params["path"].ends_with("_List.txt"));
+    assert_eq!(std::fs::read(&registry).unwrap(), before);
+
+    // failed exports leave the version alone, retrying releases the next one
+    let version = package.pack_version;
+    let id = package.exported_scenes().next().unwrap().id.clone();
+    package.get_scene_mut(&id).unwrap().name = "Changed".into();
+    for _ in 0..2 {
+        assert!(package.build(dir.clone()).is_err());
+    }
+    assert_eq!(package.pack_version, version);
+    assert_eq!(package.scenes[&id].revision, 0);
+    let mut permissions = std::fs::metadata(&list).unwrap().permissions();
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(&list, permissions).unwrap();
+    package.build(dir.clone()).unwrap();
+    assert_eq!(package.pack_version.patch, version.patch + 1);
+    assert_eq!(package.scenes[&id].revision, 1);
+    assert_eq!(
+        Manifest::read(&dir).unwrap().pack_version,
+        package.pack_version
+    );
+}
+
+#[test]
+fn fnis_lists_of_other_packs_are_merged() {
+    assert_eq!(fnis_event("b -a,Tn abcdA1 A1.hkx"), Some("abcdA1"));
+    assert_eq!(fnis_event("s abcdA1 A1.hkx"), Some("abcdA1"));
+    assert_eq!(fnis_event("' comment"), None);
+    assert_eq!(fnis_event("  "), None);
+
+    let existing = "' other pack\nb otherX X.hkx\nb abcdOld Old.hkx\n";
+    let lines = vec!["b abcdA1 A1.hkx".to_string(), "b OTHERX X.hkx".to_string()];
+    let (merged, duplicates) = merge_fnis_lines(existing, &lines, "abcd");
+    assert_eq!(
+        merged,
+        vec!["' other pack", "b otherX X.hkx", "b abcdA1 A1.hkx"]
+    );
+    assert_eq!(duplicates, vec!["OTHERX"]);
+
+    let mut rng = rng(34);
+    let mut package = arb_package(&mut rng);
+    package.pack_name = "Shared".into();
+    let dir = TempDir::new("merge");
+    package.build(dir.clone()).unwrap();
+    let file = Manifest::read(&dir)
+        .unwrap()
+        .files
+        .into_iter()
+        .find(|f| f.contains("FNIS_Shared"))
+        .unwrap();
+    let list = dir.join(&file);
+    let own = std::fs::read_to_string(&list).unwrap();
+    std::fs::write(&list, format!("b zzzzX X.hkx\n{}", own)).unwrap();
+
+    package.merge_fnis = true;
+    package.build(dir.clone()).unwrap();
+    let content = std::fs::read_to_string(&list).unwrap();
+    assert_eq!(content, format!("b zzzzX X.hkx\n{}", own));
+    let manifest = Manifest::read(&dir).unwrap();
+    assert!(!manifest.files.contains(&file));
+
+    package.merge_fnis = false;
+    package.build(dir.clone()).unwrap();
+    assert_eq!(std::fs::read_to_string(&list).unwrap(), own);
+}
+
+#[test]
+fn behaviour_folders_can_be_overridden() {
+    assert_eq!(
+        normalize_behaviour_folder(" dlc02/scrib ").unwrap(),
+        "dlc02\\scrib"
+    );
+    assert!(normalize_behaviour_folder("..\\scrib").is_err());
+    assert!(normalize_behaviour_folder("c:\\scrib").is_err());
+    assert!(normalize_behaviour_folder(" / ").is_err());
+
+    let mut rng = rng(35);
+    let mut package = arb_package(&mut rng);
+    package.pack_name = "Folders".into();
+    assert!(package.set_behaviour_folder("NotARace", Some("x")).is_err());
+    for scene in package.scenes.values_mut() {
+        for info in &mut scene.positions {
+            info.race = "Human".into();
+        }
+    }
+    package
+        .set_behaviour_folder("Human", Some("custom/humans"))
+        .unwrap();
+    let id = package.exported_scenes().next().unwrap().id.clone();
+    let info = &mut package.scenes.get_mut(&id).unwrap().positions[0];
+    info.behaviour_folder = Some("scrib".into());
+    let info = info.clone();
+    assert_eq!(
+        package.behaviour_folder("Human", None).unwrap(),
+        "custom\\humans"
+    );
+    assert_eq!(
+        package.behaviour_folder("Human", Some(&info)).unwrap(),
+        "scrib"
+    );
+
+    let dir = TempDir::new("folders");
+    package.build(dir.clone()).unwrap();
+    let files = Manifest::read(&dir).unwrap().files;
+    let lists: Vec<&String> = files
+        .iter()
+        .filter(|f| f.contains("FNIS_Folders"))
+        .collect();
+    assert!(lists.iter().any(|f| f.contains("scrib")), "{:?}", lists);
+    assert!(
+        lists.iter().all(|f| !f.contains("character")),
+        "{:?}",
+        lists
+    );
+
+    package.set_behaviour_folder("Human", None).unwrap();
+    assert_eq!(
+        package.behaviour_folder("Human", None).unwrap(),
+        "character"
+    );
+}
+
+#[test]
+fn race_infos_list_every_race_with_its_folder() {
+    let races = get_race_infos(None);
+    assert_eq!(races.len(), get_race_keys_string().len());
+    assert!(races.windows(2).all(|pair| pair[0].key < pair[1].key));
+    assert!(races.iter().all(|race| !race.examples.is_empty()));
+    let canine = races.iter().find(|race| race.race == "Canine").unwrap();
+    assert_eq!(canine.folder.as_deref(), Some("canine"));
+    assert_eq!(canine.group, vec!["Dog", "Fox", "Wolf"]);
+    let troll = races
+        .iter()
+        .find(|race| race.race == "Frost Troll")
+        .unwrap();
+    assert_eq!(troll.variant_of, Some("Troll"));
+    assert!(troll.group.contains(&"Troll".to_string()));
+    // races the export writes FNIS lists for are told apart from those left to Nemesis
+    let package = Package::new();
+    for race in &races {
+        let listed = package.behaviour_folder(&race.race, None).is_ok();
+        assert_eq!(race.behaviour_engine == BehaviourEngine::Fnis, listed);
+    }
+    let human = races.iter().find(|race| race.race == "Human").unwrap();
+    assert_eq!(human.behaviour_engine, BehaviourEngine::Fnis);
+
+    let found: Vec<_> = get_race_infos(Some(" frostbite "))
+        .into_iter()
+        .map(|race| race.race)
+        .collect();
+    assert_eq!(found, vec!["Giant Spider", "Large Spider", "Spider"]);
+    assert_eq!(get_race_infos(Some("witchlight"))[0].race, "Wisp");
+    assert!(get_race_infos(Some("no such creature")).is_empty());
+}
+
+#[test]
+fn creature_variants_share_the_folder_of_their_base_race() {
+    assert_eq!(map_race_to_folder("Armored Troll").unwrap(), "troll");
+    assert_eq!(map_race_to_folder("Skeletal Dragon").unwrap(), "dragon");
+    assert_ne!(
+        get_race_key_bytes("Armored Troll"),
+        get_race_key_bytes("Troll")
+    );
+    assert_eq!(guess_racekeys("ArmoredTroll_Doggy"), vec!["Armored Troll"]);
+    assert_eq!(
+        map_legacy_to_racekey("SkeletalDragons").unwrap(),
+        "Skeletal Dragon"
+    );
+
+    let mut rng = rng(36);
+    let mut package = arb_package(&mut rng);
+    package.pack_name = "Variants".into();
+    for scene in package.scenes.values_mut() {
+        for info in &mut scene.positions {
+            info.race = "Armored Troll".into();
+        }
+    }
+    package
+        .set_behaviour_folder("Troll", Some("custom/troll"))
+        .unwrap();
+    assert_eq!(
+        package.behaviour_folder("Armored Troll", None).unwrap(),
+        "custom\\troll"
+    );
+    let dir = TempDir::new("variants");
+    package.build(dir.clone()).unwrap();
+    let files = Manifest::read(&dir).unwrap().files;
+    let lists: Vec<&String> = files
+        .iter()
+        .filter(|f| f.contains("FNIS_Variants"))
+        .collect();
+    assert_eq!(lists.len(), 1, "{:?}", lists);
+    assert!(lists[0].contains("custom"), "{:?}", lists);
+}
+
+#[test]
+fn scene_pages_list_summaries_by_name() {
+    let mut rng = rng(37);
+    let mut package = Package::new();
+    for name in ["delta", "Alpha", "charlie", "bravo", "echo"] {
+        let mut scene = arb_scene(&mut rng);
+        scene.name = name.into();
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    let page = package.scene_page(1, 2, None);
+    assert_eq!(page.total, 5);
+    assert_eq!(page.offset, 1);
+    let names: Vec<&str> = page.scenes.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["bravo", "charlie"]);
+    let scene = package.get_scene(&page.scenes[0].id).unwrap();
+    assert_eq!(page.scenes[0], scene.summary());
+    assert_eq!(page.scenes[0].actors, scene.positions.len());
+
+    assert_eq!(package.scene_page(4, 10, None).scenes.len(), 1);
+    assert!(package.scene_page(10, 10, None).scenes.is_empty());
+}
+
+#[test]
+fn scenes_differing_from_the_file_are_unsaved() {
+    let mut rng = rng(38);
+    let mut package = Package::new();
+    let scenes: Vec<_> = (0..3).map(|_| arb_scene(&mut rng)).collect();
+    for scene in &scenes {
+        package.scenes.insert(scene.id.clone(), scene.clone());
+    }
+    assert_eq!(package.unsaved_scenes().added.len(), 3);
+
+    let dir = TempDir::new("unsaved");
+    let path = dir.join("unsaved.slsb.json");
+    package.write(path.clone()).unwrap();
+    assert!(package.unsaved_scenes().is_empty());
+    let mut package = Package::open(&path).unwrap();
+    assert!(package.unsaved_scenes().is_empty());
+
+    package
+        .scenes
+        .get_mut(&scenes[0].id)
+        .unwrap()
+        .name
+        .push('!');
+    package.scenes.remove(&scenes[1].id);
+    let scene = arb_scene(&mut rng);
+    package.scenes.insert(scene.id.clone(), scene.clone());
+    let unsaved = package.unsaved_scenes();
+    assert_eq!(unsaved.changed, vec![scenes[0].id.clone()]);
+    assert_eq!(unsaved.removed, vec![scenes[1].id.clone()]);
+    assert_eq!(unsaved.added, vec![scene.id]);
+
+    // scenes read back from the frontend compare equal regardless of map order
+    let copy: Scene = serde_json::from_str(&serde_json::to_string(&scenes[2]).unwrap()).unwrap();
+    package.scenes.insert(copy.id.clone(), copy);
+    assert!(!package.unsaved_scenes().changed.contains(&scenes[2].id));
+}
+
+#[test]
+fn scenes_store_their_fingerprint_on_save() {
+    let mut rng = rng(85);
+    let mut package = Package::new();
+    let scene = arb_scene(&mut rng);
+    let id = scene.id.clone();
+    package.save_scene(scene, false).unwrap();
+    let saved = &package.scenes[&id];
+    assert_eq!(saved.fingerprint, fingerprint(saved));
+    let before = saved.fingerprint.clone();
+    // moving nodes in the editor changes nothing about the scene
+    let mut moved = saved.clone();
+    for node in moved.graph.values_mut() {
+        node.x += 10.0;
+    }
+    package.save_scene(moved, false).unwrap();
+    assert_eq!(package.scenes[&id].fingerprint, before);
+
+    package.set_localized_name(&id, "de", Some("Name")).unwrap();
+    assert_eq!(package.scenes[&id].fingerprint, before);
+    let dir = TempDir::new("fingerprint");
+    let path = dir.join("fingerprint.slsb.json");
+    package.write(path.clone()).unwrap();
+    let changed = package.scenes[&id].fingerprint.clone();
+    assert_ne!(changed, before);
+    assert_eq!(changed, fingerprint(&package.scenes[&id]));
+    let package = Package::open(&path).unwrap();
+    assert_eq!(package.scenes[&id].fingerprint, changed);
+    assert!(package.unsaved_scenes().is_empty());
+}
+
+#[test]
+fn anim_objects_can_be_prefixed() {
+    let mut rng = rng(36);
+    let mut package = Package::new();
+    package.pack_name = "Objects".into();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    for info in &mut scene.positions {
+        info.race = "Human".into();
+    }
+    for position in scene.stages.iter_mut().flat_map(|s| &mut s.positions) {
+        position.anim_obj = "AnimObjA,AnimObjB".into();
+    }
+    package.scenes.insert(scene.id.clone(), scene);
+    let prefix = package.prefix_hash.0.clone();
+    assert_eq!(package.exported_anim_object("AnimObjA"), "AnimObjA");
+
+    package.prefix_anim_objects = true;
+    let prefixed = format!("{}_AnimObjA", prefix);
+    assert_eq!(package.exported_anim_object("AnimObjA"), prefixed);
+    // names starting like the prefix are prefixed all the same
+    let lookalike = format!("{}AnimObjA", prefix);
+    assert_eq!(
+        package.exported_anim_object(&lookalike),
+        format!("{}_{}", prefix, lookalike)
+    );
+    let dependencies = Dependencies::from_package(&package);
+    assert!(dependencies
+        .anim_objects
+        .iter()
+        .all(|obj| obj.name.starts_with(&prefix)));
+
+    let dir = TempDir::new("objects");
+    let files = package.write_fnis_files(&dir).unwrap();
+    let list = std::fs::read_to_string(&files[0]).unwrap();
+    assert!(
+        list.contains(&format!(" {} {}_AnimObjB", prefixed, prefix)),
+        "{}",
+        list
+    );
+}
+
+#[test]
+fn fnis_list_names_can_be_configured() {
+    let mut rng = rng(39);
+    let mut package = Package::new();
+    package.pack_name = "Lists".into();
+    for race in ["Human", "Dog", "Wolf", "Fox"] {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        for info in &mut scene.positions {
+            info.race = race.into();
+        }
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    assert_eq!(
+        package.fnis_list_name("Human", "character").unwrap(),
+        "FNIS_Lists_List.txt"
+    );
+    assert_eq!(
+        package.fnis_list_name("Dog", "canine").unwrap(),
+        "FNIS_Lists_dog_List.txt"
+    );
+    assert!(package.set_fnis_list_name("NotARace", Some("x")).is_err());
+    for pattern in [
+        "Lists.txt",
+        "FNIS_{pack}/x_List.txt",
+        "FNIS_{unknown}_List.txt",
+    ] {
+        let err = package
+            .set_fnis_list_name("Fox", Some(pattern))
+            .unwrap_err();
+        assert_eq!(err.message().key, "export.invalid_list_name");
+    }
+    package
+        .set_fnis_list_name("Fox", Some("FNIS_{pack}_{race}_List.txt"))
+        .unwrap();
+    package
+        .set_fnis_list_name("Human", Some("FNIS_{pack}Legacy_List.txt"))
+        .unwrap();
+
+    let dir = TempDir::new("lists");
+    let files = package.write_fnis_files(&dir).unwrap();
+    let mut names: Vec<String> = files
+        .iter()
+        .map(|f| f.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "FNIS_ListsLegacy_List.txt",
+            "FNIS_Lists_canine_List.txt",
+            "FNIS_Lists_dog_List.txt",
+            "FNIS_Lists_fox_List.txt",
+            "FNIS_Lists_wolf_List.txt"
+        ]
+    );
+
+    let count = |name: &str| {
+        let path = files.iter().find(|f| f.ends_with(name)).unwrap();
+        std::fs::read_to_string(path).unwrap().lines().count()
+    };
+    let expected = count("FNIS_Lists_wolf_List.txt") + count("FNIS_Lists_fox_List.txt");
+
+    // races sharing a name share the list
+    package
+        .set_fnis_list_name("Fox", Some("FNIS_{pack}_wolf_List.txt"))
+        .unwrap();
+    let files = package.write_fnis_files(&dir).unwrap();
+    assert_eq!(files.len(), 4);
+    let wolf = files
+        .iter()
+        .find(|f| f.ends_with("FNIS_Lists_wolf_List.txt"))
+        .unwrap();
+    let lines = std::fs::read_to_string(wolf).unwrap().lines().count();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn presets_build_with_their_own_settings() {
+    let mut rng = rng(40);
+    let mut package = Package::new();
+    package.pack_name = "Presets".into();
+    for tag in ["oral", "anal"] {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        scene.tags = vec![tag.into()];
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    package.bundles = ["oral", "anal"]
+        .map(|tag| Bundle {
+            name: tag.into(),
+            query: TagQuery {
+                any: vec![tag.into()],
+                ..Default::default()
+            },
+        })
+        .to_vec();
+    let dir = TempDir::new("presets");
+    let preset = |name: &str, bundles: Option<Vec<String>>| ExportPreset {
+        name: name.into(),
+        out: dir.join(name),
+        bundles,
+        export_previews: false,
+        clean_export: true,
+        merge_fnis: false,
+    };
+    assert!(package.save_export_preset(preset(" ", None)).is_err());
+    package.save_export_preset(preset("release", None)).unwrap();
+    package
+        .save_export_preset(preset("test", Some(vec!["oral".into()])))
+        .unwrap();
+    package
+        .save_export_preset(preset("test", Some(vec!["anal".into()])))
+        .unwrap();
+    assert_eq!(package.export_presets.len(), 2);
+
+    package.build_preset("release").unwrap();
+    let registries = |name: &str| {
+        let mut files: Vec<String> = std::fs::read_dir(registry_dir(&dir.join(name)))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    };
+    assert_eq!(
+        registries("release"),
+        vec!["Presets_anal.slr", "Presets_oral.slr"]
+    );
+    package.build_preset("test").unwrap();
+    assert_eq!(registries("test"), vec!["Presets.slr", "Presets_anal.slr"]);
+    // the settings of the project are restored
+    assert_eq!(package.bundles.len(), 2);
+    assert!(!package.clean_export);
+
+    let err = package.build_preset("missing").unwrap_err();
+    assert_eq!(err.message().key, "export.unknown_preset");
+    package.remove_export_preset("test").unwrap();
+    assert!(package.remove_export_preset("test").is_err());
+}
+
+// Two scenes with the same actors, the first stage of the first shared with the second. Returns
+// the ids of both scenes and of the shared stage
+fn linked_scenes(package: &mut Package, rng: &mut StdRng) -> (NanoID, NanoID, NanoID) {
+    let first = arb_scene(rng);
+    let mut second = arb_scene(rng);
+    second.positions = first.positions.clone();
+    for stage in &mut second.stages {
+        stage.positions.truncate(first.positions.len());
+        while stage.positions.len() < first.positions.len() {
+            stage.positions.push(first.stages[0].positions[0].clone());
+        }
+    }
+    let (a, b) = (first.id.clone(), second.id.clone());
+    let shared = first.stages[0].id.clone();
+    package.save_scene(first, false).unwrap();
+    package.save_scene(second, false).unwrap();
+
+    share_stage(package, &a, &shared).unwrap();
+    assert!(share_stage(package, &a, &shared).is_err());
+    link_stage(package, &b, &shared).unwrap();
+    assert!(link_stage(package, &b, &shared).is_err());
+    assert_eq!(library_entries(package)[0].references, 2);
+    (a, b, shared)
+}
+
+#[test]
+fn shared_stages_are_stored_once() {
+    let dir = TempDir::new("library");
+    let path = dir.join("Library.slsb.json");
+    let mut rng = rng(41);
+    let mut package = Package::new();
+    let (a, b, shared) = linked_scenes(&mut package, &mut rng);
+
+    // edits made in one scene carry over to the other
+    let mut edited = package.scenes[&a].clone();
+    edited.stages[0].name = "Shared".into();
+    package.save_scene(edited, false).unwrap();
+    let linked = package.scenes[&b].get_stage(&shared).unwrap();
+    assert_eq!(linked.name, "Shared");
+
+    package.write(path.clone()).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let stored = json["scenes"][&b.0]["stages"].as_array().unwrap();
+    assert!(stored.iter().all(|stage| stage["id"] != shared.0.as_str()));
+    let reopened = Package::from_file(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reopened.scenes[&a].stages[0].id, shared);
+    assert_eq!(reopened.scenes[&a].stages, package.scenes[&a].stages);
+    assert_eq!(reopened.scenes[&b].stages, package.scenes[&b].stages);
+    assert!(reopened.scenes[&b].shared_stages.is_empty());
+
+    // stages no scene uses anymore are dropped from the library on save
+    let copy_a = detach_stage(&mut package, &a, &shared).unwrap();
+    let copy_b = detach_stage(&mut package, &b, &shared).unwrap();
+    assert_ne!(copy_a, copy_b);
+    package.write(path.clone()).unwrap();
+    assert!(package.stage_library.is_empty());
+}
+
+#[test]
+fn restored_scenes_catch_up_with_the_library() {
+    let dir = TempDir::new("library_trash");
+    let path = dir.join("Library.slsb.json");
+    let mut rng = rng(44);
+    let mut package = Package::new();
+    let (a, b, shared) = linked_scenes(&mut package, &mut rng);
+    package.discard_scene(&b).unwrap();
+
+    // edits made while the scene is in the trash are not undone by restoring it
+    let mut edited = package.scenes[&a].clone();
+    edited.stages[0].name = "Edited".into();
+    package.save_scene(edited, false).unwrap();
+    package.write(path.clone()).unwrap();
+    let mut package = Package::open(&path).unwrap();
+    let restored = package.restore_scene(&b).unwrap();
+    assert_eq!(restored.get_stage(&shared).unwrap().name, "Edited");
+    assert_eq!(package.stage_library[&shared].name, "Edited");
+
+    // the library keeps the stages only discarded scenes use
+    package.discard_scene(&b).unwrap();
+    detach_stage(&mut package, &a, &shared).unwrap();
+    package.write(path.clone()).unwrap();
+    assert!(package.stage_library.contains_key(&shared));
+    let mut package = Package::open(&path).unwrap();
+    let restored = package.restore_scene(&b).unwrap();
+    assert_eq!(restored.get_stage(&shared).unwrap().name, "Edited");
+}
+
+#[test]
+fn shared_stages_never_overwrite_what_does_not_fit() {
+    let dir = TempDir::new("library_sync");
+    let path = dir.join("Library.slsb.json");
+    let mut rng = rng(42);
+    let mut package = Package::new();
+    let (a, b, shared) = linked_scenes(&mut package, &mut rng);
+
+    // locked scenes keep their copy, which stays within the scene on save
+    package.set_scene_locked(&b, true).unwrap();
+    let mut edited = package.scenes[&a].clone();
+    edited.stages[0].name = "Edited".into();
+    package.save_scene(edited, false).unwrap();
+    assert_ne!(
+        package.scenes[&b].get_stage(&shared).unwrap().name,
+        "Edited"
+    );
+    package.write(path.clone()).unwrap();
+    let reopened = Package::from_file(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reopened.scenes[&b].stages, package.scenes[&b].stages);
+    assert_eq!(reopened.scenes[&a].stages, package.scenes[&a].stages);
+    package.set_scene_locked(&b, false).unwrap();
+
+    // edits which skip save_scene are kept as well
+    package.get_scene_mut(&a).unwrap().stages[0].extra.nav_text = "In place".into();
+    package.write(path.clone()).unwrap();
+    let reopened = Package::from_file(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(reopened.scenes[&a].stages[0].extra.nav_text, "In place");
+
+    // a shared stage given another actor no longer fits the other scene
+    let before = package.scenes[&b].clone();
+    let mut edited = package.scenes[&a].clone();
+    let position = edited.stages[0].positions[0].clone();
+    edited.stages[0].positions.push(position);
+    package.save_scene(edited, false).unwrap();
+    assert!(package.scenes[&a].get_stage(&shared).is_none());
+    assert_eq!(package.scenes[&b].stages, before.stages);
+    assert_eq!(
+        package.stage_library[&shared].positions.len(),
+        before.positions.len()
+    );
+
+    // readable ids leave the stages of the library alone
+    package.make_ids_readable(None, false).unwrap();
+    let linked = package
+        .scenes
+        .values()
+        .find(|scene| scene.name == before.name)
+        .unwrap();
+    assert!(linked.get_stage(&shared).is_some());
+}
+
+#[test]
+fn outdated_copies_keep_edits_of_shared_stages() {
+    let mut rng = rng(45);
+    let mut package = Package::new();
+    let (a, b, shared) = linked_scenes(&mut package, &mut rng);
+    // the editor still holds the copy of b from before the shared stage was edited through a
+    let mut outdated = package.scenes[&b].clone();
+    let mut edited = package.scenes[&a].clone();
+    edited.stages[0].name = "Edited".into();
+    let synced = package.save_scene(edited, false).unwrap();
+    assert_eq!(synced.into_iter().collect::<Vec<_>>(), vec![b.clone()]);
+    assert_ne!(package.scenes[&b].fingerprint, outdated.fingerprint);
+
+    // other edits of the outdated copy are saved, its shared stage is taken from the library
+    outdated.name = "Renamed".into();
+    assert!(package.save_scene(outdated, false).unwrap().is_empty());
+    assert_eq!(package.scenes[&b].name, "Renamed");
+    assert_eq!(package.stage_library[&shared].name, "Edited");
+    for id in [&a, &b] {
+        let stage = package.scenes[id].get_stage(&shared).unwrap();
+        assert_eq!(stage.name, "Edited");
+    }
+
+    // a copy made after the update edits the shared stage as usual
+    let mut current = package.scenes[&b].clone();
+    current.get_stage_mut(&shared).unwrap().name = "Edited in b".into();
+    let synced = package.save_scene(current, false).unwrap();
+    assert_eq!(synced.into_iter().collect::<Vec<_>>(), vec![a.clone()]);
+    let stage = package.scenes[&a].get_stage(&shared).unwrap();
+    assert_eq!(stage.name, "Edited in b");
+}
+
+#[test]
+fn registries_write_shared_stages_once() {
+    let mut rng = rng(43);
+    let mut package = Package::new();
+    let (a, b, shared) = linked_scenes(&mut package, &mut rng);
+    for id in [&a, &b] {
+        package.get_scene_mut(id).unwrap().has_warnings = false;
+    }
+    let bytes = package.encode();
+    assert_eq!(bytes.len(), package.get_byte_size());
+    let inspection = inspect_bytes(&bytes).unwrap();
+    assert!(inspection
+        .capabilities
+        .contains(&"shared_stages".to_string()));
+    assert!(inspection.sizes.shared_stages > 0);
+    for inspected in &inspection.scenes {
+        let scene = &package.scenes[&NanoID(inspected.id.clone())];
+        let ids: Vec<&str> = inspected.stages.iter().map(|s| s.id.as_str()).collect();
+        let expected: Vec<&str> = scene.stages.iter().map(|s| s.id.0.as_str()).collect();
+        assert_eq!(ids, expected);
+    }
+    assert!(inspection
+        .scenes
+        .iter()
+        .all(|scene| scene.stages.iter().any(|stage| stage.id == shared.0)));
+
+    // without the library every scene carries a copy of its own
+    let library = std::mem::take(&mut package.stage_library);
+    let unshared = package.encode();
+    package.stage_library = library;
+    assert!(unshared.len() > bytes.len());
+    assert!(!inspect_bytes(&unshared)
+        .unwrap()
+        .capabilities
+        .contains(&"shared_stages".to_string()));
+}
+
+#[test]
+fn localized_names_are_written_next_to_the_registry() {
+    let mut rng = rng(79);
+    let mut package = Package::new();
+    package.pack_name = "Localized".into();
+    let mut ids = vec![];
+    for flagged in [false, true] {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = flagged;
+        ids.push(scene.id.clone());
+        package.save_scene(scene, false).unwrap();
+    }
+    let err = package
+        .set_localized_name(&ids[0], "German", Some("Name"))
+        .unwrap_err();
+    assert_eq!(err.message().key, "scene.invalid_language");
+    package
+        .set_localized_name(&ids[0], " DE ", Some("  Die   Szene "))
+        .unwrap();
+    package
+        .set_localized_name(&ids[0], "pt_BR", Some("A cena"))
+        .unwrap();
+    package
+        .set_localized_name(&ids[1], "de", Some("Ausgelassen"))
+        .unwrap();
+    assert_eq!(package.scenes[&ids[0]].localized_names.len(), 2);
+
+    let dir = TempDir::new("localized");
+    package.build(dir.clone()).unwrap();
+    let path = SceneTranslations::file_path(&package, &dir);
+    assert!(path.ends_with("Localized.translations.json"));
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["languages"]["de"][&ids[0].0], "Die Szene");
+    assert_eq!(json["languages"]["pt-br"][&ids[0].0], "A cena");
+    // scenes left out of the build are not translated
+    assert!(json["languages"]["de"].get(&ids[1].0).is_none());
+
+    package.set_localized_name(&ids[0], "de", None).unwrap();
+    package
+        .set_localized_name(&ids[0], "pt-br", Some(" "))
+        .unwrap();
+    assert!(package.scenes[&ids[0]].localized_names.is_empty());
+    package.clean_export = true;
+    package.build(dir.clone()).unwrap();
+    assert!(!path.exists());
+}
+
+#[test]
+fn mcm_translations_fall_back_to_scene_names() {
+    let mut rng = rng(83);
+    let mut package = Package::new();
+    package.pack_name = "Mcm".into();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    scene.name = "Base  Name".into();
+    scene.tags = vec!["Doggy".into(), " doggy ".into()];
+    for stage in &mut scene.stages {
+        stage.tags.clear();
+    }
+    let id = scene.id.clone();
+    package.save_scene(scene, false).unwrap();
+    package
+        .set_localized_name(&id, "de-AT", Some("Deutscher Name"))
+        .unwrap();
+    let err = package
+        .set_translation_languages(&["Klingon".into()])
+        .unwrap_err();
+    assert_eq!(err.message().key, "export.unknown_language");
+    package
+        .set_translation_languages(&["german".into(), "FRENCH".into(), "english".into()])
+        .unwrap();
+    assert_eq!(package.translation_languages, vec!["GERMAN", "FRENCH"]);
+
+    let key = |kind: &str, name: &str| format!("$SLSB_{}_{}_{}", package.prefix_hash.0, kind, name);
+    assert_eq!(
+        mcm_lines(&package, "GERMAN"),
+        vec![
+            format!("{}\tDeutscher Name", key("Scene", &id.0)),
+            format!("{}\tdoggy", key("Tag", "doggy")),
+        ]
+    );
+    assert_eq!(
+        mcm_lines(&package, "FRENCH")[0],
+        format!("{}\tBase Name", key("Scene", &id.0))
+    );
+
+    let dir = TempDir::new("mcm");
+    package.build(dir.clone()).unwrap();
+    let mut names: Vec<String> = std::fs::read_dir(translations_dir(&dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["Mcm_ENGLISH.txt", "Mcm_FRENCH.txt", "Mcm_GERMAN.txt"]
+    );
+    let bytes = std::fs::read(translations_dir(&dir).join("Mcm_GERMAN.txt")).unwrap();
+    assert_eq!(&bytes[..2], &[0xFF, 0xFE]);
+    let units: Vec<u16> = bytes[2..]
+        .chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let text = String::from_utf16(&units).unwrap();
+    assert!(text.contains("\tDeutscher Name\r\n"), "{}", text);
+}
@@ -0,0 +1,366 @@
+use super::generators::*;
+use crate::project::{
+    define::Offset,
+    events::mirror_event,
+    inspect::inspect_bytes,
+    package::Package,
+    stage::{Stage, StageCategory, StageTimers, DEFAULT_STAGE_TIMER},
+    validate::{has_errors, validate},
+    NanoID,
+};
+
+#[test]
+fn make_linear_chains_stages_in_order() {
+    let mut rng = rng(7);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 3 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    let order: Vec<NanoID> = scene.stages.iter().rev().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order).unwrap();
+
+    assert_eq!(scene.root, order[0]);
+    assert_eq!(scene.stages[0].id, order[0]);
+    assert_eq!(scene.graph.len(), scene.stages.len());
+    for pair in order.windows(2) {
+        assert_eq!(scene.graph[&pair[0]].dest, vec![pair[1].clone()]);
+    }
+    assert!(scene.graph[order.last().unwrap()].dest.is_empty());
+    assert!(scene.stages[order.len() - 1]
+        .positions
+        .iter()
+        .all(|p| p.climax));
+    assert!(scene.stages[..order.len() - 1]
+        .iter()
+        .all(|stage| !stage.ending && stage.positions.iter().all(|p| !p.climax)));
+}
+
+#[test]
+fn make_linear_rejects_unknown_and_duplicate_stages() {
+    let mut rng = rng(8);
+    let mut scene = arb_scene(&mut rng);
+    let first = scene.stages[0].id.clone();
+    assert!(scene.make_linear(&[]).is_err());
+    assert!(scene.make_linear(&[NanoID("missing0".into())]).is_err());
+    let stage_count = scene.stages.len();
+    assert!(scene.make_linear(&[first.clone(), first]).is_err());
+    assert_eq!(scene.stages.len(), stage_count);
+}
+
+#[test]
+fn split_moves_connected_stages_into_new_scene() {
+    let mut rng = rng(9);
+    let mut scene = arb_scene(&mut rng);
+    scene.stages = (0..4)
+        .map(|_| arb_stage(&mut rng, scene.positions.len()))
+        .collect();
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order).unwrap();
+
+    assert!(scene
+        .split(&[order[1].clone(), order[3].clone()], true)
+        .is_err());
+    let split = scene.split(&order[2..], true).unwrap();
+
+    assert_eq!(scene.stages.len(), 2);
+    assert_eq!(split.stages.len(), 2);
+    assert_eq!(split.root, order[2]);
+    assert_eq!(split.graph[&order[2]].dest, vec![order[3].clone()]);
+    assert!(scene.graph[&order[1]].dest.is_empty());
+    assert!(!scene.graph.contains_key(&order[2]));
+    assert_eq!(scene.transitions.len(), 1);
+    assert_eq!(scene.transitions[0].from_stage, order[1]);
+    assert_eq!(scene.transitions[0].to_scene, split.id);
+    assert_eq!(split.positions.len(), scene.positions.len());
+    assert!(scene.get_stage(&order[1]).unwrap().ending);
+}
+
+#[test]
+fn linked_split_scenes_validate() {
+    let mut rng = rng(11);
+    let mut scene = arb_scene(&mut rng);
+    scene.stages = (0..4)
+        .map(|_| arb_stage(&mut rng, scene.positions.len()))
+        .collect();
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order).unwrap();
+    let split = scene.split(&order[2..], true).unwrap();
+
+    let mut package = Package::new();
+    package.scenes.insert(scene.id.clone(), scene);
+    package.scenes.insert(split.id.clone(), split);
+    let diagnostics = validate(&package);
+    assert!(!has_errors(&diagnostics), "{:?}", diagnostics);
+    assert!(
+        diagnostics
+            .iter()
+            .all(|d| d.message.key != "validate.transition_not_ending"),
+        "{:?}",
+        diagnostics
+    );
+}
+
+#[test]
+fn statistics_report_paths_and_dead_stages() {
+    let mut rng = rng(10);
+    let mut scene = arb_scene(&mut rng);
+    scene.stages = (0..4)
+        .map(|_| arb_stage(&mut rng, scene.positions.len()))
+        .collect();
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order[..3]).unwrap();
+    scene.stages[0].extra.fixed_len = 10.0;
+    scene.stages[1].extra.fixed_len = 0.0;
+    scene.stages[2].extra.fixed_len = 5.0;
+
+    let stats = scene.statistics(&Default::default());
+    assert_eq!(stats.paths.len(), 1);
+    assert_eq!(stats.paths[0].ending, order[2]);
+    assert_eq!(stats.paths[0].shortest, order[..3].to_vec());
+    assert_eq!(
+        stats.paths[0].shortest_duration,
+        15.0 + crate::project::stage::DEFAULT_STAGE_TIMER
+    );
+    assert_eq!(stats.dead_stages, vec![order[3].clone()]);
+}
+
+#[test]
+fn stage_tags_common_to_all_stages_move_into_the_scene() {
+    let mut rng = rng(52);
+    let mut scene = arb_scene(&mut rng);
+    scene.stages.truncate(1);
+    while scene.stages.len() < 3 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    let tags = [
+        vec!["Vaginal", "Loving", "Kissing"],
+        vec!["loving", "vaginal"],
+        vec!["VAGINAL", "Loving ", "Oral"],
+    ];
+    for (stage, tags) in scene.stages.iter_mut().zip(tags) {
+        stage.tags = tags.into_iter().map(String::from).collect();
+    }
+    scene.tags = vec!["Template".into()];
+    let before: Vec<Vec<String>> = scene.stages.iter().map(|s| scene.stage_tags(s)).collect();
+
+    scene.update_to_latest_version(5).unwrap();
+    assert_eq!(scene.tags, vec!["Vaginal", "Loving"]);
+    assert_eq!(scene.stages[0].tags, vec!["Kissing"]);
+    assert!(scene.stages[1].tags.is_empty());
+    assert_eq!(scene.stages[2].tags, vec!["Oral"]);
+    for (stage, before) in scene.stages.iter().zip(before) {
+        let after = scene.stage_tags(stage);
+        assert_eq!(after.len() + 1, before.len(), "{:?} {:?}", after, before);
+    }
+}
+
+#[test]
+fn edges_are_only_added_between_existing_stages() {
+    let mut rng = rng(10);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 2 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    let a = scene.stages[0].id.clone();
+    let b = scene.stages[1].id.clone();
+    let missing = NanoID("missing0".into());
+    scene.graph.clear();
+
+    assert!(scene.add_edge(&a, &b).unwrap());
+    assert!(!scene.add_edge(&a, &b).unwrap());
+    assert_eq!(scene.graph[&a].dest, vec![b.clone()]);
+    let err = scene.add_edge(&a, &missing).unwrap_err();
+    assert_eq!(err.message().key, "scene.missing_stage");
+    let err = scene.add_edge(&a, &a).unwrap_err();
+    assert_eq!(err.message().key, "scene.self_edge");
+
+    scene.remove_edge(&a, &b).unwrap();
+    assert!(scene.graph[&a].dest.is_empty());
+    let err = scene.remove_edge(&a, &b).unwrap_err();
+    assert_eq!(err.message().key, "scene.missing_edge");
+
+    scene.set_root(&b).unwrap();
+    assert_eq!(scene.root, b);
+    assert!(scene.set_root(&missing).is_err());
+    assert_eq!(scene.root, b);
+}
+
+#[test]
+fn stage_timers_apply_to_stages_without_fixed_length() {
+    let mut rng = rng(53);
+    let scene = arb_scene(&mut rng);
+    let mut stage = scene.stages[0].clone();
+    for position in &mut stage.positions {
+        position.climax = false;
+    }
+    stage.tags = vec!["Foreplay".into()];
+    stage.extra.fixed_len = 0.0;
+    let timers = StageTimers {
+        foreplay: 8.0,
+        main: 0.0,
+        climax: 4.0,
+    };
+    assert_eq!(stage.category(&[]), StageCategory::Foreplay);
+    assert_eq!(stage.duration(&[], &timers), 8.0);
+
+    stage.tags.clear();
+    assert_eq!(stage.category(&[]), StageCategory::Main);
+    assert_eq!(stage.duration(&[], &timers), DEFAULT_STAGE_TIMER);
+    // tags of the scene apply to all its stages
+    let scene_tags = vec!["foreplay".to_string()];
+    assert_eq!(stage.category(&scene_tags), StageCategory::Foreplay);
+
+    stage.positions[0].climax = true;
+    assert_eq!(stage.duration(&[], &timers), 4.0);
+    stage.extra.fixed_len = 2.0;
+    assert_eq!(stage.duration(&[], &timers), 2.0);
+
+    let err = StageTimers {
+        main: -1.0,
+        ..timers
+    }
+    .validate()
+    .unwrap_err();
+    assert_eq!(err.message().key, "stage.invalid_timer");
+}
+
+#[test]
+fn stage_order_is_kept_in_the_registry() {
+    let mut rng = rng(71);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 4 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    scene.has_warnings = false;
+    let ids: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    let id = scene.id.clone();
+    let mut package = Package::new();
+    package.save_scene(scene, false).unwrap();
+
+    let err = package
+        .set_stage_order(&id, &[ids[1].clone(), ids[1].clone()])
+        .unwrap_err();
+    assert_eq!(err.message().key, "scene.stage_listed_twice");
+    assert!(package
+        .set_stage_order(&id, &[NanoID::new_nanoid()])
+        .is_err());
+    package
+        .set_stage_order(&id, &[ids[3].clone(), ids[1].clone()])
+        .unwrap();
+    let expected = vec![&ids[3], &ids[1], &ids[0], &ids[2]];
+    let order: Vec<&NanoID> = package.scenes[&id].stages.iter().map(|s| &s.id).collect();
+    assert_eq!(order, expected);
+
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    let encoded: Vec<&String> = inspection.scenes[0].stages.iter().map(|s| &s.id).collect();
+    let expected: Vec<&String> = expected.into_iter().map(|id| &id.0).collect();
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn stages_are_mirrored_left_to_right() {
+    assert_eq!(mirror_event("Pack_A1_S1_L").unwrap(), "Pack_A1_S1_R");
+    assert_eq!(mirror_event("pack_left_a1").unwrap(), "pack_right_a1");
+    assert_eq!(mirror_event("Pack_Right_A1").unwrap(), "Pack_Left_A1");
+    assert_eq!(mirror_event("PACK_LEFT").unwrap(), "PACK_RIGHT");
+    assert_eq!(mirror_event("Pack_Lr_A1"), None);
+
+    let mut rng = rng(19);
+    let mut scene = arb_scene(&mut rng);
+    scene.locked = false;
+    let id = scene.stages[0].id.clone();
+    let stage = &mut scene.stages[0];
+    stage.positions[0].event = vec!["Pack_A1_S1_L".into()];
+    stage.positions[0].variants = vec!["Pack_A1_S1_Left".into()];
+    stage.positions[0].offset.x = 12.5;
+    stage.positions[0].offset.r = 90.0;
+    stage.positions[0].offset.rx = 10.0;
+    stage.positions[0].offset.ry = -5.0;
+    for position in stage.positions.iter_mut().skip(1) {
+        position.event = vec!["Pack_A2_S1".into()];
+        position.offset.x = 0.0;
+        // headings within -180 and 180 degrees come back unchanged
+        position.offset.r -= 180.0;
+    }
+    let original = scene.stages[0].clone();
+    let json = |stage: &Stage| serde_json::to_value(stage).unwrap();
+    let count = scene.stages.len();
+
+    let mirrored = scene.mirror_stage(&id, true).unwrap();
+    assert_eq!(scene.stages.len(), count + 1);
+    assert_ne!(mirrored.stage, id);
+    assert!(scene.graph[&mirrored.stage].dest.is_empty());
+    assert_eq!(
+        mirrored.unpaired,
+        vec!["Pack_A2_S1"; original.positions.len() - 1]
+    );
+    assert_eq!(json(&scene.stages[0]), json(&original));
+    let copy = scene.get_stage(&mirrored.stage).unwrap();
+    assert_eq!(copy.name, format!("{} (Mirrored)", original.name));
+    let position = &copy.positions[0];
+    assert_eq!(position.event, vec!["Pack_A1_S1_R"]);
+    assert_eq!(position.variants, vec!["Pack_A1_S1_Right"]);
+    let offset = &position.offset;
+    assert_eq!(
+        (offset.x, offset.r, offset.rx, offset.ry),
+        (-12.5, -90.0, 10.0, 5.0)
+    );
+    assert!(copy.positions[1..]
+        .iter()
+        .all(|p| p.offset.x.is_sign_positive()));
+    let turned = |r: f32| {
+        Offset {
+            r,
+            ..Default::default()
+        }
+        .mirrored()
+        .r
+    };
+    assert_eq!(
+        (turned(270.0), turned(180.0), turned(-180.0)),
+        (90.0, 180.0, 180.0)
+    );
+
+    // mirroring in place twice restores the stage
+    scene.mirror_stage(&id, false).unwrap();
+    assert_eq!(scene.stages[0].positions[0].event, vec!["Pack_A1_S1_R"]);
+    scene.mirror_stage(&id, false).unwrap();
+    assert_eq!(json(&scene.stages[0]), json(&original));
+    let err = scene
+        .mirror_stage(&NanoID::new_nanoid(), false)
+        .unwrap_err();
+    assert_eq!(err.message().key, "scene.missing_stage");
+}
+
+#[test]
+fn interchangeable_actors_keep_their_side_when_mirrored() {
+    let mut rng = rng(20);
+    let mut scene = arb_scene(&mut rng);
+    while scene.positions.len() < 3 {
+        scene = arb_scene(&mut rng);
+    }
+    scene.locked = false;
+    scene.positions[2] = scene.positions[0].clone();
+    let id = scene.stages[0].id.clone();
+    let stage = &mut scene.stages[0];
+    for (i, (event, x)) in [(0, ("Pack_A1_S1_L", -30.0)), (2, ("Pack_A1_S1_R", 30.0))] {
+        stage.positions[i].event = vec![event.into()];
+        stage.positions[i].offset.x = x;
+    }
+
+    let mirrored = scene.mirror_stage(&id, false).unwrap();
+    assert_eq!(mirrored.swapped, vec![(0, 2)]);
+    let positions = &scene.stages[0].positions;
+    assert_eq!(positions[0].event, vec!["Pack_A1_S1_L"]);
+    assert_eq!(positions[0].offset.x, -30.0);
+    assert_eq!(positions[2].event, vec!["Pack_A1_S1_R"]);
+    assert_eq!(positions[2].offset.x, 30.0);
+}
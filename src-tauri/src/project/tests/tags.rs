@@ -0,0 +1,169 @@
+use super::generators::*;
+use crate::project::{
+    bundle::{Bundle, TagQuery},
+    inspect::inspect_bytes,
+    package::{sanitize_file_name, Package},
+    tags::suggest_tags,
+    validate::validate,
+};
+
+#[test]
+fn tags_are_suggested_from_scene_content() {
+    let mut rng = rng(51);
+    let mut scene = arb_scene(&mut rng);
+    while scene.positions.len() < 3 {
+        scene.positions.push(arb_position_info(&mut rng));
+    }
+    scene.positions.truncate(3);
+    scene.positions[0].race = "Human".into();
+    scene.positions[1].race = "Boar (Mounted)".into();
+    scene.positions[2].race = "Human".into();
+    scene.furniture.furni_types = vec!["BedDouble".into(), "ChairWood".into()];
+    for stage in &mut scene.stages {
+        stage.tags = vec!["Three Some".into()];
+        for position in &mut stage.positions {
+            position.climax = false;
+        }
+    }
+    scene.stages[0].positions[0].climax = true;
+    scene.tags = vec!["Bed".into()];
+
+    let suggestions = suggest_tags(&scene);
+    let tags: Vec<&str> = suggestions.iter().map(|s| s.tag.as_str()).collect();
+    assert_eq!(tags, vec!["creature", "boar", "chair", "orgasm"]);
+    let orgasm = suggestions.last().unwrap();
+    assert_eq!(orgasm.stages, vec![scene.stages[0].id.clone()]);
+    assert!(suggestions[0].reason.to_string().contains("Boar (Mounted)"));
+}
+
+#[test]
+fn bundles_split_the_export_into_registries() {
+    let mut rng = rng(53);
+    let mut package = Package::new();
+    package.pack_name = "Pack".into();
+    for tags in [
+        vec!["Aggressive"],
+        vec!["Loving", "Oral"],
+        vec!["Loving"],
+        vec![],
+    ] {
+        let mut scene = arb_scene(&mut rng);
+        scene.has_warnings = false;
+        scene.tags = tags.into_iter().map(String::from).collect();
+        for stage in &mut scene.stages {
+            stage.tags.clear();
+        }
+        package.save_scene(scene, false).unwrap();
+    }
+    package.bundles = vec![
+        Bundle {
+            name: "Aggressive".into(),
+            query: TagQuery {
+                any: vec!["aggressive".into()],
+                ..Default::default()
+            },
+        },
+        Bundle {
+            name: "Consensual".into(),
+            query: TagQuery {
+                all: vec!["loving".into()],
+                none: vec!["ORAL".into()],
+                ..Default::default()
+            },
+        },
+    ];
+
+    let registries = package.registries();
+    let files: Vec<(&str, usize)> = registries
+        .iter()
+        .map(|(name, registry)| (name.as_str(), registry.scenes.len()))
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            ("Pack.slr", 2),
+            ("Pack_Aggressive.slr", 1),
+            ("Pack_Consensual.slr", 1)
+        ]
+    );
+    for (_, registry) in &registries {
+        let inspection = inspect_bytes(&registry.encode()).unwrap();
+        assert_eq!(inspection.scenes.len(), registry.scenes.len());
+    }
+    let bundle_keys = |package: &Package| -> Vec<&'static str> {
+        validate(package)
+            .iter()
+            .map(|d| d.message.key)
+            .filter(|key| key.starts_with("validate.bundle"))
+            .collect()
+    };
+    assert!(bundle_keys(&package).is_empty());
+
+    package.bundles[1].name = "aggressive".into();
+    assert_eq!(bundle_keys(&package), vec!["validate.bundle_duplicate"]);
+}
+
+#[test]
+fn registry_file_names_are_sanitized() {
+    assert_eq!(sanitize_file_name(" Pack: Part 2? "), "Pack_ Part 2_");
+    assert_eq!(sanitize_file_name("Dots..."), "Dots");
+    assert_eq!(sanitize_file_name("con"), "con_");
+    assert_eq!(sanitize_file_name("COM1.x"), "COM1.x_");
+    assert_eq!(sanitize_file_name("Compact"), "Compact");
+    assert_eq!(sanitize_file_name("Liebe ♥"), "Liebe ♥");
+
+    let mut rng = rng(54);
+    let mut package = Package::new();
+    package.pack_name = "A/B".into();
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    scene.tags = vec!["Oral".into()];
+    package.save_scene(scene, false).unwrap();
+    let file_keys = |package: &Package| -> Vec<&'static str> {
+        validate(package)
+            .iter()
+            .map(|d| d.message.key)
+            .filter(|key| key.starts_with("validate.file"))
+            .collect()
+    };
+    assert_eq!(package.registries()[0].0, "A_B.slr");
+    assert_eq!(package.behaviour_name(), "A_B");
+    assert_eq!(
+        package.fnis_list_name("Human", "character").unwrap(),
+        "FNIS_A_B_List.txt"
+    );
+    assert_eq!(file_keys(&package), vec!["validate.file_name_sanitized"]);
+
+    package.export_name = Some("Registry".into());
+    package.export_extension = Some(".bin".into());
+    package.bundles = vec![Bundle {
+        name: "Ora:l".into(),
+        query: TagQuery {
+            any: vec!["oral".into()],
+            ..Default::default()
+        },
+    }];
+    let files: Vec<String> = package.registries().into_iter().map(|(f, _)| f).collect();
+    assert_eq!(files, vec!["Registry_Ora_l.bin"]);
+    assert!(file_keys(&package).is_empty());
+
+    package.export_extension = Some("a:b".into());
+    assert_eq!(file_keys(&package), vec!["validate.file_extension"]);
+}
+
+#[test]
+fn bundles_filter_by_source_pack() {
+    let mut rng = rng(57);
+    let mut scene = arb_scene(&mut rng);
+    let query = TagQuery {
+        sources: vec!["Billyy".into()],
+        ..Default::default()
+    };
+    assert!(!query.is_empty());
+    scene.source_pack = None;
+    assert!(!query.matches(&scene));
+    scene.source_pack = Some("billyy ".into());
+    assert!(query.matches(&scene));
+    scene.source_pack = Some("Anub".into());
+    assert!(!query.matches(&scene));
+}
@@ -0,0 +1,479 @@
+use super::generators::*;
+use crate::project::{
+    define::{Sex, Transition},
+    inspect::inspect_bytes,
+    manifest::PackVersion,
+    orphans::{collect_orphans, find_orphans, OrphanAction, OrphanKind},
+    package::Package,
+    repair::repair_project,
+    scene::{Scene, SuppressedWarning},
+    validate::{
+        check_scene, has_errors, scene_warnings, validate, validate_anim_objects,
+        validate_framework_version, validate_scene, validate_scene_names, Severity,
+    },
+    NanoID,
+};
+
+#[test]
+fn generated_scenes_have_no_errors() {
+    let mut rng = rng(41);
+    for _ in 0..CASES {
+        let scene = arb_scene(&mut rng);
+        let diagnostics = validate_scene(&scene, None);
+        assert!(!has_errors(&diagnostics), "{:?}", diagnostics);
+    }
+}
+
+#[test]
+fn broken_scenes_report_errors() {
+    let mut rng = rng(42);
+    let mut scene = arb_scene(&mut rng);
+    scene.positions[0].race = "Not A Race".into();
+    scene.stages[0].positions[0].event.clear();
+    scene.transitions.push(Transition {
+        from_stage: scene.stages[0].id.clone(),
+        to_scene: NanoID::new_nanoid(),
+    });
+    let stage = scene.stages[0].id.clone();
+    scene
+        .graph
+        .get_mut(&stage)
+        .unwrap()
+        .dest
+        .push(NanoID::new_nanoid());
+
+    let mut package = Package::new();
+    package.save_scene(scene, false).unwrap();
+    let diagnostics = validate(&package);
+    let keys: Vec<&str> = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.message.key)
+        .collect();
+    for key in [
+        "error.unknown_race",
+        "export.missing_event",
+        "validate.unknown_transition",
+        "validate.unknown_dest",
+    ] {
+        assert!(keys.contains(&key), "missing {} in {:?}", key, keys);
+    }
+    // errors are listed first
+    let first_warning = diagnostics
+        .iter()
+        .position(|d| d.severity == Severity::Warning)
+        .unwrap_or(diagnostics.len());
+    assert!(diagnostics[first_warning..]
+        .iter()
+        .all(|d| d.severity == Severity::Warning));
+    assert!(diagnostics[0].to_string().starts_with("error: "));
+}
+
+#[test]
+fn unreachable_stages_are_warnings() {
+    let mut rng = rng(43);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 2 {
+        scene = arb_scene(&mut rng);
+    }
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    for stage in &mut scene.stages {
+        stage.shortcut = false;
+    }
+    scene.make_linear(&order[..1]).unwrap();
+    let diagnostics = validate_scene(&scene, None);
+    assert!(!has_errors(&diagnostics));
+    let unreachable = diagnostics
+        .iter()
+        .filter(|d| d.message.key == "validate.unreachable")
+        .count();
+    assert_eq!(unreachable, scene.stages.len() - 1);
+}
+
+#[test]
+fn tags_are_checked_against_actors() {
+    let mut rng = rng(44);
+    let mut scene = arb_scene(&mut rng);
+    scene.positions.truncate(1);
+    while scene.positions.len() < 2 {
+        scene.positions.push(arb_position_info(&mut rng));
+    }
+    let sex = |male, female| Sex {
+        male,
+        female,
+        futa: false,
+    };
+    scene.positions[0].race = "Human".into();
+    scene.positions[0].sex = sex(true, false);
+    scene.positions[1].race = "Human".into();
+    scene.positions[1].sex = sex(false, true);
+    scene.tags = vec!["FF".into(), "Creature".into(), "MF".into()];
+    for stage in &mut scene.stages {
+        stage.tags.clear();
+    }
+    scene.stages[0].tags = vec!["fm".into(), "MMF".into()];
+
+    let mismatches = |scene: &crate::project::scene::Scene| -> Vec<(bool, String)> {
+        validate_scene(scene, None)
+            .into_iter()
+            .filter(|d| d.message.key.starts_with("validate.tag_"))
+            .map(|d| (d.stage.is_some(), d.message.to_string()))
+            .collect()
+    };
+    let found = mismatches(&scene);
+    assert_eq!(found.len(), 3, "{:?}", found);
+    assert!(found[0].1.contains("FF") && !found[0].0);
+    assert!(found[1].1.contains("Creature") && !found[1].0);
+    assert!(found[2].1.contains("MMF") && found[2].0);
+
+    // futa actors fill either role, creatures are tagged with "C"
+    scene.positions[0].sex.futa = true;
+    scene.positions[1].race = "Wolf".into();
+    scene.tags = vec!["FC".into(), "creature".into()];
+    scene.stages[0].tags.clear();
+    assert!(mismatches(&scene).is_empty());
+}
+
+#[test]
+fn repair_fixes_dangling_references() {
+    let mut rng = rng(45);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 3 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order).unwrap();
+    scene.locked = false;
+    scene.transitions.clear();
+    // delete the root without cleaning up the graph, as hand edits do
+    scene.stages.remove(0);
+    scene.graph.remove(&order[1]);
+    scene.transitions.push(Transition {
+        from_stage: order[2].clone(),
+        to_scene: NanoID::new_nanoid(),
+    });
+    scene.stages[1].positions.pop();
+    let id = scene.id.clone();
+    let mut package = Package::new();
+    package.save_scene(scene, false).unwrap();
+
+    let report = repair_project(&mut package, true);
+    let keys: Vec<&str> = report.repairs.iter().map(|r| r.message.key).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "repair.dangling_node",
+            "repair.missing_node",
+            "repair.root",
+            "repair.transition",
+            "export.position_count"
+        ]
+    );
+    assert_eq!(report.fixed(), 0);
+
+    let report = repair_project(&mut package, false);
+    assert_eq!((report.fixed(), report.unresolved()), (4, 1));
+    let scene = package.get_scene(&id).unwrap();
+    assert_eq!(scene.root, order[1]);
+    assert_eq!(scene.graph.len(), 2);
+    assert!(scene.graph[&order[1]].dest.is_empty());
+    assert!(scene.transitions.is_empty());
+    let keys: Vec<&str> = validate_scene(scene, Some(&package))
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.message.key)
+        .collect();
+    assert_eq!(keys, vec!["export.position_count"]);
+}
+
+#[test]
+fn shortcuts_must_be_reachable() {
+    let mut rng = rng(46);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 3 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order[..2]).unwrap();
+    for stage in &mut scene.stages {
+        stage.shortcut = true;
+    }
+    let keys: Vec<(Severity, &str)> = validate_scene(&scene, None)
+        .iter()
+        .filter(|d| d.message.key.starts_with("validate.shortcut"))
+        .map(|d| (d.severity, d.message.key))
+        .collect();
+    assert_eq!(keys[0], (Severity::Warning, "validate.shortcut_root"));
+    assert_eq!(keys.len(), scene.stages.len() - 1);
+    assert!(keys[1..]
+        .iter()
+        .all(|k| *k == (Severity::Error, "validate.shortcut_unreachable")));
+}
+
+#[test]
+fn scenes_need_a_reachable_ending() {
+    let mut rng = rng(47);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 2 {
+        scene
+            .stages
+            .push(arb_stage(&mut rng, scene.positions.len()));
+    }
+    let order: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    scene.make_linear(&order[..2]).unwrap();
+    assert!(scene.stages[1].ending);
+    // loop back to the root, leaving no stage without outgoing edges
+    scene.graph.get_mut(&order[1]).unwrap().dest = vec![order[0].clone()];
+    scene.stages[1].ending = false;
+    let has_error = |scene: &Scene| {
+        validate_scene(scene, None)
+            .iter()
+            .any(|d| d.message.key == "validate.no_ending")
+    };
+    assert!(has_error(&scene));
+    scene.stages[1].ending = true;
+    assert!(!has_error(&scene));
+    assert_eq!(scene.get_endings(), vec![order[1].clone()]);
+}
+
+#[test]
+fn duplicate_scene_names_are_suffixed() {
+    let mut rng = rng(48);
+    let mut package = Package::new();
+    let names = ["Doggy", "doggy ", "Doggy (2)", "Solo\nScene", "Doggy"];
+    let mut ids = vec![];
+    for (i, name) in names.iter().enumerate() {
+        let mut scene = arb_scene(&mut rng);
+        scene.name = name.to_string();
+        scene.locked = i == 4;
+        ids.push(scene.id.clone());
+        package.scenes.insert(scene.id.clone(), scene);
+    }
+    let warned = validate_scene_names(&package);
+    assert_eq!(warned.len(), 3);
+    assert!(warned
+        .iter()
+        .all(|d| d.message.key == "validate.duplicate_scene_name"));
+    let formatted = validate_scene(&package.scenes[&ids[3]], None);
+    assert!(formatted
+        .iter()
+        .any(|d| d.message.key == "validate.scene_name_format"));
+
+    let renamed = package.make_scene_names_unique();
+    assert_eq!(renamed.len(), 3);
+    // the locked scene keeps its name, the others continue after the existing "Doggy (2)"
+    assert_eq!(package.scenes[&ids[4]].name, "Doggy");
+    let mut suffixed = vec![
+        package.scenes[&ids[0]].name.to_lowercase(),
+        package.scenes[&ids[1]].name.to_lowercase(),
+    ];
+    suffixed.sort();
+    assert_eq!(suffixed, vec!["doggy (3)", "doggy (4)"]);
+    assert_eq!(package.scenes[&ids[2]].name, "Doggy (2)");
+    assert_eq!(package.scenes[&ids[3]].name, "Solo Scene");
+    assert!(validate_scene_names(&package).is_empty());
+    assert!(package.make_scene_names_unique().is_empty());
+}
+
+#[test]
+fn features_newer_than_the_declared_framework_are_reported() {
+    let mut rng = rng(47);
+    let mut scene = arb_scene(&mut rng);
+    scene.furniture.furni_types = vec!["BedRoll".into()];
+    scene.transitions.clear();
+    let mut package = Package::new();
+    package.save_scene(scene, false).unwrap();
+    assert!(validate_framework_version(&package).is_empty());
+
+    package.min_framework_version = Some(PackVersion::new(1, 9, 0));
+    let diagnostics = validate_framework_version(&package);
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(diagnostics[0].message.key, "validate.framework_version");
+    assert_eq!(diagnostics[0].message.params["feature"], "furniture");
+
+    package.min_framework_version = Some(PackVersion::new(2, 0, 0));
+    assert!(validate_framework_version(&package).is_empty());
+    let inspection = inspect_bytes(&package.encode()).unwrap();
+    assert_eq!(
+        inspection.min_framework_version,
+        Some(PackVersion::new(2, 0, 0))
+    );
+}
+
+#[test]
+fn inconsistent_scenes_are_rejected_before_saving() {
+    let mut rng = rng(48);
+    let mut scene = arb_scene(&mut rng);
+    scene.name = "Checked".into();
+    for stage in &mut scene.stages {
+        for position in &mut stage.positions {
+            position.event = vec!["Checked_A1".into()];
+        }
+    }
+    check_scene(&scene).unwrap();
+    let key = |scene: &Scene| check_scene(scene).unwrap_err().message().key;
+
+    let mut broken = scene.clone();
+    broken.name = " ".into();
+    assert_eq!(key(&broken), "scene.empty_name");
+
+    let mut broken = scene.clone();
+    let position = broken.stages[0].positions[0].clone();
+    broken.stages[0].positions.push(position);
+    assert_eq!(key(&broken), "scene.position_count");
+
+    let mut broken = scene.clone();
+    let stage = broken.stages[0].id.clone();
+    broken
+        .graph
+        .get_mut(&stage)
+        .unwrap()
+        .dest
+        .push(NanoID::new_nanoid());
+    assert_eq!(key(&broken), "scene.unknown_stage");
+
+    let mut broken = scene.clone();
+    broken.root = NanoID::new_nanoid();
+    assert_eq!(key(&broken), "scene.unknown_stage");
+
+    for event in ["Checked A1", "Checked,A1", "Checked_Ä1"] {
+        let mut broken = scene.clone();
+        broken.stages[0].positions[0].event = vec![event.into()];
+        assert_eq!(key(&broken), "scene.invalid_event");
+    }
+}
+
+#[test]
+fn suppressed_warnings_are_hidden() {
+    let mut rng = rng(47);
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = true;
+    for position in scene.stages.iter_mut().flat_map(|s| &mut s.positions) {
+        position.climax = false;
+    }
+    let keys = |scene: &Scene| -> Vec<&'static str> {
+        validate_scene(scene, None)
+            .iter()
+            .map(|d| d.message.key)
+            .collect()
+    };
+    assert!(keys(&scene).contains(&"validate.no_climax"));
+    assert!(keys(&scene).contains(&"validate.flagged"));
+
+    let mut package = Package::new();
+    package.scenes.insert(scene.id.clone(), scene.clone());
+    assert_eq!(package.exported_scenes().count(), 0);
+    for key in ["validate.no_climax", "validate.flagged"] {
+        let warning = SuppressedWarning {
+            key: key.into(),
+            stage: None,
+        };
+        package.suppress_warning(&scene.id, warning, true).unwrap();
+    }
+    assert_eq!(package.exported_scenes().count(), 1);
+
+    let scene = &package.scenes[&scene.id];
+    assert!(!keys(scene).contains(&"validate.no_climax"));
+    assert!(!keys(scene).contains(&"validate.flagged"));
+    let warnings = scene_warnings(scene, None);
+    assert!(warnings
+        .iter()
+        .any(|w| w.suppressed && w.diagnostic.message.key == "validate.no_climax"));
+}
+
+#[test]
+fn orphaned_stages_are_deleted_or_reattached() {
+    let mut rng = rng(48);
+    let mut scene = arb_scene(&mut rng);
+    while scene.stages.len() < 3 {
+        scene = arb_scene(&mut rng);
+    }
+    let ids: Vec<NanoID> = scene.stages.iter().map(|s| s.id.clone()).collect();
+    for stage in &mut scene.stages {
+        stage.shortcut = false;
+    }
+    scene.make_linear(&ids[..1]).unwrap();
+    scene.graph.remove(&ids[1]);
+    let dangling = NanoID::new_nanoid();
+    scene.graph.insert(dangling.clone(), Default::default());
+    let id = scene.id.clone();
+    let mut package = Package::new();
+    package.scenes.insert(id.clone(), scene);
+
+    let orphans = find_orphans(&package, Some(&id));
+    let kinds: Vec<(&NanoID, OrphanKind)> = orphans.iter().map(|o| (&o.stage, o.kind)).collect();
+    assert_eq!(kinds[0], (&ids[1], OrphanKind::MissingNode));
+    assert_eq!(kinds[1], (&ids[2], OrphanKind::Unreachable));
+    assert_eq!(
+        kinds.last().unwrap(),
+        &(&dangling, OrphanKind::DanglingNode)
+    );
+    assert_eq!(kinds.len(), ids.len());
+
+    // stages which are not orphaned are left alone
+    let untouched = collect_orphans(&mut package, &id, &ids[..1], OrphanAction::Delete);
+    assert_eq!(untouched.unwrap(), 0);
+    let resolved = collect_orphans(
+        &mut package,
+        &id,
+        &[ids[1].clone(), dangling.clone()],
+        OrphanAction::Reattach,
+    )
+    .unwrap();
+    assert_eq!(resolved, 2);
+    assert!(package.scenes[&id].graph[&ids[0]].dest.contains(&ids[1]));
+    assert!(!package.scenes[&id].graph.contains_key(&dangling));
+
+    let rest: Vec<NanoID> = ids[2..].to_vec();
+    collect_orphans(&mut package, &id, &rest, OrphanAction::Delete).unwrap();
+    assert_eq!(package.scenes[&id].stages.len(), 2);
+    assert!(find_orphans(&package, None).is_empty());
+}
+
+#[test]
+fn anim_objects_missing_from_listed_plugins_are_errors() {
+    let mut rng = rng(48);
+    let mut scene = arb_scene(&mut rng);
+    scene.has_warnings = false;
+    for (i, position) in scene
+        .stages
+        .iter_mut()
+        .flat_map(|stage| &mut stage.positions)
+        .enumerate()
+    {
+        position.anim_obj = if i % 2 == 0 { "Flute,Lute" } else { "Lute" }.into();
+    }
+    let id = scene.id.clone();
+    let mut package = Package::new();
+    package.save_scene(scene, false).unwrap();
+    assert!(validate_anim_objects(&package).is_empty());
+
+    let dir = TempDir::new("forms");
+    std::fs::write(dir.join("Props.txt"), "; props\nLUTE [ANIO:00000D62]\n\n").unwrap();
+    let listed = package
+        .import_anim_object_forms(" Props.esp ", &dir.join("Props.txt"))
+        .unwrap();
+    assert_eq!(listed, 1);
+    let diagnostics = validate_anim_objects(&package);
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].scene, Some(id));
+    assert_eq!(diagnostics[0].message.params["objects"], "Flute");
+    let export = TempDir::new("forms_out");
+    let err = package.build(export.clone()).unwrap_err();
+    assert_eq!(err.message().key, "validate.missing_anim_objects");
+
+    package.set_anim_object_forms("Props.esp", &["Lute".into(), "flute".into()]);
+    assert!(validate_anim_objects(&package).is_empty());
+    // prefixed objects have to be listed with their prefix
+    package.prefix_anim_objects = true;
+    assert_eq!(validate_anim_objects(&package).len(), 1);
+    package.set_anim_object_forms("Props.esp", &[]);
+    assert!(package.anim_object_forms.is_empty());
+    assert!(validate_anim_objects(&package).is_empty());
+}
@@ -0,0 +1,183 @@
+use log::info;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::{
+    export_path::{create_export_dir, write_export_file},
+    package::{registry_dir, Package},
+    scene::display_name,
+    NanoID,
+};
+
+// Languages of the game with the language code of their localized names
+pub const GAME_LANGUAGES: [(&str, &str); 10] = [
+    ("ENGLISH", "en"),
+    ("FRENCH", "fr"),
+    ("GERMAN", "de"),
+    ("ITALIAN", "it"),
+    ("SPANISH", "es"),
+    ("POLISH", "pl"),
+    ("RUSSIAN", "ru"),
+    ("JAPANESE", "ja"),
+    ("CHINESE", "zh"),
+    ("CZECH", "cs"),
+];
+
+// Lowercase language code with optional subtags, e.g. "de" or "pt-br"
+pub fn normalize_language(lang: &str) -> Result<String, SlsbError> {
+    let ret = lang.trim().to_lowercase().replace('_', "-");
+    let mut parts = ret.split('-');
+    let primary = parts.next().unwrap_or_default();
+    let valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_lowercase())
+        && parts.all(|part| {
+            (2..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    if !valid {
+        return Err(Message::new("scene.invalid_language")
+            .arg("lang", lang)
+            .into());
+    }
+    Ok(ret)
+}
+
+// Display names of the exported scenes per language, written next to the registries so the
+// runtime can show them without patching the binary
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SceneTranslations {
+    pub pack_name: String,
+    pub languages: BTreeMap<String, BTreeMap<NanoID, String>>,
+}
+
+impl SceneTranslations {
+    pub fn from_package(package: &Package) -> Self {
+        let mut languages: BTreeMap<String, BTreeMap<NanoID, String>> = BTreeMap::new();
+        for scene in package.exported_scenes() {
+            for (lang, name) in &scene.localized_names {
+                let name = display_name(name);
+                if name.is_empty() {
+                    continue;
+                }
+                languages
+                    .entry(lang.clone())
+                    .or_default()
+                    .insert(scene.id.clone(), name);
+            }
+        }
+        Self {
+            pack_name: package.pack_name.clone(),
+            languages,
+        }
+    }
+
+    pub fn file_path(package: &Package, root_dir: &Path) -> PathBuf {
+        registry_dir(root_dir).join(format!("{}.translations.json", package.export_stem()))
+    }
+
+    // Returns the file written, nothing is written if no scene has a localized name
+    pub fn write(&self, path: &Path) -> Result<Option<PathBuf>, SlsbError> {
+        if self.languages.is_empty() {
+            return Ok(None);
+        }
+        if let Some(dir) = path.parent() {
+            create_export_dir(dir)?;
+        }
+        write_export_file(path, serde_json::to_string_pretty(self)?)?;
+        Ok(Some(path.to_path_buf()))
+    }
+}
+
+pub fn translations_dir(root_dir: &Path) -> PathBuf {
+    root_dir.join("Interface").join("Translations")
+}
+
+// Key of a string in the translation files, namespaced by the prefix of the pack since SkyUI
+// shares keys between all mods
+fn mcm_key(package: &Package, kind: &str, name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("$SLSB_{}_{}_{}", package.prefix_hash.0, kind, name)
+}
+
+// Game language of a language code, e.g. GERMAN for de-at
+fn game_language(lang: &str) -> Option<&'static str> {
+    let primary = lang.split('-').next().unwrap_or_default();
+    GAME_LANGUAGES
+        .iter()
+        .find(|(_, code)| *code == primary)
+        .map(|(language, _)| *language)
+}
+
+// Lines of the translation file of a game language. Scenes without a name in that language
+// fall back to their name, tags are not localized and keep their name in every language
+pub fn mcm_lines(package: &Package, language: &str) -> Vec<String> {
+    let mut scenes: Vec<_> = package.exported_scenes().collect();
+    scenes.sort_by_key(|scene| &scene.id.0);
+    let mut ret = vec![];
+    // tags differing only in characters keys cannot hold share the key of the first one
+    let mut tags = BTreeMap::new();
+    for scene in scenes {
+        let localized = scene
+            .localized_names
+            .iter()
+            .find(|(lang, _)| game_language(lang) == Some(language))
+            .map(|(_, name)| display_name(name))
+            .filter(|name| !name.is_empty());
+        let name = localized.unwrap_or_else(|| display_name(&scene.name));
+        ret.push(format!(
+            "{}\t{}",
+            mcm_key(package, "Scene", &scene.id.0),
+            name
+        ));
+        let stage_tags = scene.stages.iter().flat_map(|stage| &stage.tags);
+        for tag in scene.tags.iter().chain(stage_tags) {
+            let tag = tag.trim();
+            if !tag.is_empty() {
+                let tag = tag.to_lowercase();
+                tags.entry(mcm_key(package, "Tag", &tag)).or_insert(tag);
+            }
+        }
+    }
+    for (key, tag) in tags {
+        ret.push(format!("{}\t{}", key, tag));
+    }
+    ret
+}
+
+// Write the SkyUI translation files of english and the configured languages, as UTF-16 with a
+// byte order mark and windows line endings. Returns the files written
+pub fn write_mcm_translations(
+    package: &Package,
+    root_dir: &Path,
+) -> Result<Vec<PathBuf>, SlsbError> {
+    if package.exported_scenes().next().is_none() {
+        return Ok(vec![]);
+    }
+    let dir = translations_dir(root_dir);
+    create_export_dir(&dir)?;
+    let mut languages = vec!["ENGLISH"];
+    for language in &package.translation_languages {
+        if !languages.contains(&language.as_str()) {
+            languages.push(language);
+        }
+    }
+    let mut ret = vec![];
+    for language in languages {
+        let text = mcm_lines(package, language).join("\r\n") + "\r\n";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        let path = dir.join(format!("{}_{}.txt", package.export_stem(), language));
+        write_export_file(&path, bytes)?;
+        ret.push(path);
+    }
+    info!(target: "export", path:? = dir; "Wrote {} translation files", ret.len());
+    Ok(ret)
+}
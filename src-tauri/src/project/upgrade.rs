@@ -0,0 +1,103 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+use super::package::{Package, VERSION};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UpgradedFile {
+    pub path: PathBuf,
+    // version the file was written with, none if it could not be read
+    pub from_version: Option<u8>,
+    pub upgraded: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct UpgradeReport {
+    pub files: Vec<UpgradedFile>,
+}
+
+impl UpgradeReport {
+    pub fn upgraded(&self) -> usize {
+        self.files.iter().filter(|file| file.upgraded).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.error.is_some())
+            .count()
+    }
+}
+
+#[derive(Deserialize)]
+struct FileVersion {
+    // missing from the first project files, which are read as version 0
+    #[serde(default)]
+    version: u8,
+}
+
+// Every project file below dir, skipping hidden folders such as .git
+pub fn project_files(dir: &Path) -> Result<Vec<PathBuf>, SlsbError> {
+    if !dir.is_dir() {
+        return Err(Message::new("cli.invalid_input").into());
+    }
+    let mut ret = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for path in fs::read_dir(&dir)?.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if path.is_dir() && !name.starts_with('.') {
+                pending.push(path);
+            } else if path.is_file() && name.ends_with(".slsb.json") {
+                ret.push(path);
+            }
+        }
+    }
+    ret.sort();
+    Ok(ret)
+}
+
+// Bring every project file below dir to the current format, writing back only the files which
+// were migrated. Files which fail to load are listed in the report and left as they are
+pub fn upgrade_projects(dir: &Path) -> Result<UpgradeReport, SlsbError> {
+    let mut report = UpgradeReport::default();
+    for path in project_files(dir)? {
+        let mut file = UpgradedFile {
+            path: path.clone(),
+            from_version: None,
+            upgraded: false,
+            error: None,
+        };
+        if let Err(err) = upgrade_file(&path, &mut file) {
+            file.error = Some(err.to_string());
+        }
+        report.files.push(file);
+    }
+    Ok(report)
+}
+
+fn upgrade_file(path: &Path, file: &mut UpgradedFile) -> Result<(), SlsbError> {
+    let version =
+        serde_json::from_reader::<_, FileVersion>(BufReader::new(fs::File::open(path)?))?.version;
+    file.from_version = Some(version);
+    if version >= VERSION {
+        return Ok(());
+    }
+    let mut package = Package::open(path)?;
+    package.write(path.to_path_buf())?;
+    file.upgraded = true;
+    info!(
+        target: "project", path:? = path, from = version, to = VERSION;
+        "Upgraded project {}",
+        package.pack_name
+    );
+    Ok(())
+}
@@ -0,0 +1,740 @@
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    error::SlsbError,
+    i18n::Message,
+    racekeys::{get_race_key_bytes, normalize_behaviour_folder},
+};
+
+use super::{
+    bundle::bundle_of,
+    events::{is_fnis_legal, known_event_source, sanitize_event},
+    graph::depths,
+    hkx::stage_durations,
+    manifest::PackVersion,
+    package::{exclusion_reason, sanitize_file_name, Package},
+    position_info::PositionInfo,
+    scene::{display_name, Scene, FLAGGED_WARNING},
+    stage::encode_tag,
+    NanoID,
+};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub scene: Option<NanoID>,
+    pub stage: Option<NanoID>,
+    pub message: Message,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)?;
+        if let Some(scene) = &self.scene {
+            write!(f, " [scene {}", scene.0)?;
+            if let Some(stage) = &self.stage {
+                write!(f, ", stage {}", stage.0)?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+// Structural checks a scene sent by the frontend has to pass before it is stored. Unlike the
+// diagnostics below, a scene failing these is inconsistent rather than unfinished
+pub fn check_scene(scene: &Scene) -> Result<(), SlsbError> {
+    if scene.name.trim().is_empty() {
+        return Err(Message::new("scene.empty_name").into());
+    }
+    let stages: HashSet<&NanoID> = scene.stages.iter().map(|stage| &stage.id).collect();
+    if !scene.stages.is_empty() && !stages.contains(&scene.root) {
+        return Err(Message::new("scene.unknown_stage")
+            .arg("scene", &scene.name)
+            .arg("stage", &scene.root.0)
+            .into());
+    }
+    for (id, node) in &scene.graph {
+        if let Some(unknown) = std::iter::once(id)
+            .chain(&node.dest)
+            .find(|id| !stages.contains(id))
+        {
+            return Err(Message::new("scene.unknown_stage")
+                .arg("scene", &scene.name)
+                .arg("stage", &unknown.0)
+                .into());
+        }
+    }
+    for stage in &scene.stages {
+        if stage.positions.len() != scene.positions.len() {
+            return Err(Message::new("scene.position_count")
+                .arg("stage", &stage.name)
+                .arg("found", stage.positions.len())
+                .arg("expected", scene.positions.len())
+                .into());
+        }
+        let events = stage
+            .positions
+            .iter()
+            .flat_map(|position| position.event.iter().chain(&position.variants));
+        if let Some(event) = events
+            .filter(|event| !event.is_empty())
+            .find(|event| !is_fnis_legal(event))
+        {
+            return Err(Message::new("scene.invalid_event")
+                .arg("stage", &stage.name)
+                .arg("event", event)
+                .into());
+        }
+    }
+    Ok(())
+}
+
+// Full validation pass over a project, errors first
+pub fn validate(package: &Package) -> Vec<Diagnostic> {
+    let mut scenes: Vec<&Scene> = package.scenes.values().collect();
+    scenes.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.0.cmp(&b.id.0)));
+    let mut ret: Vec<Diagnostic> = scenes
+        .into_iter()
+        .flat_map(|scene| validate_scene(scene, Some(package)))
+        .collect();
+    ret.extend(validate_scene_names(package));
+    ret.extend(validate_bundles(package));
+    ret.extend(validate_export_name(package));
+    ret.extend(validate_durations(package));
+    ret.extend(validate_framework_version(package));
+    ret.extend(validate_anim_objects(package));
+    ret.sort_by_key(|diagnostic| Reverse(diagnostic.severity));
+    ret
+}
+
+// The runtime lists scenes by name, so scenes sharing a name cannot be told apart
+pub fn validate_scene_names(package: &Package) -> Vec<Diagnostic> {
+    let mut by_name: HashMap<String, Vec<&Scene>> = HashMap::new();
+    for scene in package.scenes.values() {
+        let key = display_name(&scene.name).to_lowercase();
+        if !key.is_empty() {
+            by_name.entry(key).or_default().push(scene);
+        }
+    }
+    let mut ret = vec![];
+    for scenes in by_name.values().filter(|scenes| scenes.len() > 1) {
+        for scene in scenes {
+            ret.push(Diagnostic {
+                severity: Severity::Warning,
+                scene: Some(scene.id.clone()),
+                stage: None,
+                message: Message::new("validate.duplicate_scene_name")
+                    .arg("scene", &scene.name)
+                    .arg("count", scenes.len()),
+            });
+        }
+    }
+    ret.sort_by(|a, b| {
+        a.scene
+            .as_ref()
+            .map(|s| &s.0)
+            .cmp(&b.scene.as_ref().map(|s| &s.0))
+    });
+    ret
+}
+
+// Bundles must have distinct names, as each is written into a file of that name
+pub fn validate_bundles(package: &Package) -> Vec<Diagnostic> {
+    let mut ret = vec![];
+    let mut push = |severity, message| {
+        ret.push(Diagnostic {
+            severity,
+            scene: None,
+            stage: None,
+            message,
+        })
+    };
+    let mut names = HashSet::new();
+    for (i, bundle) in package.bundles.iter().enumerate() {
+        if bundle.name.trim().is_empty() {
+            push(Severity::Error, Message::new("validate.bundle_unnamed"));
+        } else if !names.insert(bundle.name.to_lowercase()) {
+            push(
+                Severity::Error,
+                Message::new("validate.bundle_duplicate").arg("bundle", &bundle.name),
+            );
+        }
+        if bundle.query.is_empty() {
+            push(
+                Severity::Warning,
+                Message::new("validate.bundle_no_query").arg("bundle", &bundle.name),
+            );
+        }
+        let empty = !package
+            .exported_scenes()
+            .any(|scene| bundle_of(&package.bundles, scene) == Some(i));
+        if empty {
+            push(
+                Severity::Warning,
+                Message::new("validate.bundle_no_scenes").arg("bundle", &bundle.name),
+            );
+        }
+    }
+    ret
+}
+
+// Relative difference in length above which the animations of a stage are considered out of sync
+pub const DURATION_TOLERANCE: f32 = 0.05;
+
+// Actors of a stage drift apart if one of their animations loops earlier than the others. Only
+// checked for animations whose length is known, i.e. without an animation root nothing is reported
+pub fn validate_durations(package: &Package) -> Vec<Diagnostic> {
+    if package.animation_root.is_none() {
+        return vec![];
+    }
+    let stages = match stage_durations(package) {
+        Ok(stages) => stages,
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Warning,
+                scene: None,
+                stage: None,
+                message: e.message(),
+            }]
+        }
+    };
+    let mut ret = vec![];
+    for stage in stages {
+        ret.extend(stage.errors.iter().map(|message| Diagnostic {
+            severity: Severity::Warning,
+            scene: Some(stage.scene.clone()),
+            stage: Some(stage.stage.clone()),
+            message: message.clone(),
+        }));
+        let known: Vec<(&Vec<String>, f32)> = stage
+            .positions
+            .iter()
+            .filter_map(|(events, duration)| duration.map(|d| (events, d)))
+            .collect();
+        let Some(&(longest, max)) = known.iter().max_by(|a, b| a.1.total_cmp(&b.1)) else {
+            continue;
+        };
+        for &(events, duration) in &known {
+            if max - duration <= max * DURATION_TOLERANCE {
+                continue;
+            }
+            ret.push(Diagnostic {
+                severity: Severity::Warning,
+                scene: Some(stage.scene.clone()),
+                stage: Some(stage.stage.clone()),
+                message: Message::new("validate.duration_mismatch")
+                    .arg("event", events.join(", "))
+                    .arg("duration", format!("{:.2}", duration))
+                    .arg("longest", longest.join(", "))
+                    .arg("expected", format!("{:.2}", max)),
+            });
+        }
+    }
+    ret
+}
+
+// Oldest framework versions reading the given features of a scene
+pub const FRAMEWORK_FEATURES: [(&str, PackVersion); 3] = [
+    ("furniture", PackVersion::new(2, 0, 0)),
+    ("transitions", PackVersion::new(2, 2, 0)),
+    ("event_variants", PackVersion::new(2, 2, 0)),
+];
+
+fn uses_feature(scene: &Scene, feature: &str) -> bool {
+    match feature {
+        "furniture" => {
+            scene.furniture.allow_bed
+                || scene
+                    .furniture
+                    .furni_types
+                    .iter()
+                    .any(|furniture| furniture != "None")
+        }
+        "transitions" => !scene.transitions.is_empty(),
+        "event_variants" => scene
+            .stages
+            .iter()
+            .flat_map(|stage| &stage.positions)
+            .any(|position| !position.variants.is_empty()),
+        _ => false,
+    }
+}
+
+// Scenes using features the declared minimum framework version does not know about yet would
+// fail in game without an obvious reason. Nothing is reported if no minimum is declared
+pub fn validate_framework_version(package: &Package) -> Vec<Diagnostic> {
+    let Some(declared) = package.min_framework_version else {
+        return vec![];
+    };
+    let mut ret = vec![];
+    for scene in package.exported_scenes() {
+        for (feature, required) in FRAMEWORK_FEATURES {
+            if declared >= required || !uses_feature(scene, feature) {
+                continue;
+            }
+            ret.push(Diagnostic {
+                severity: Severity::Warning,
+                scene: Some(scene.id.clone()),
+                stage: None,
+                message: Message::new("validate.framework_version")
+                    .arg("scene", &scene.name)
+                    .arg("feature", feature)
+                    .arg("required", required)
+                    .arg("declared", declared),
+            });
+        }
+    }
+    ret
+}
+
+// Animation objects of the exported scenes which none of the configured plugins provides, one
+// error per scene. Projects without configured plugins are not checked
+pub fn validate_anim_objects(package: &Package) -> Vec<Diagnostic> {
+    if package.anim_object_forms.is_empty() {
+        return vec![];
+    }
+    let available: HashSet<String> = package
+        .anim_object_forms
+        .values()
+        .flatten()
+        .map(|form| form.to_lowercase())
+        .collect();
+    let mut ret = vec![];
+    for scene in package.exported_scenes() {
+        let mut missing = vec![];
+        for position in scene.stages.iter().flat_map(|stage| &stage.positions) {
+            for obj in position.anim_objects() {
+                let obj = package.exported_anim_object(obj);
+                if !available.contains(&obj.to_lowercase()) && !missing.contains(&obj) {
+                    missing.push(obj);
+                }
+            }
+        }
+        if missing.is_empty() {
+            continue;
+        }
+        ret.push(Diagnostic {
+            severity: Severity::Error,
+            scene: Some(scene.id.clone()),
+            stage: None,
+            message: Message::new("validate.missing_anim_objects")
+                .arg("scene", &scene.name)
+                .arg("objects", missing.join(", ")),
+        });
+    }
+    ret
+}
+
+fn is_creature(info: &PositionInfo) -> bool {
+    info.race != "Human"
+}
+
+// Composition tags such as "FF" or "MMF" name the sex of every actor, "C" standing for a creature.
+// Futa actors may fill either an "M" or an "F"
+fn fits_composition(tag: &str, positions: &[PositionInfo]) -> Option<bool> {
+    if tag.len() < 2 || !tag.chars().all(|c| matches!(c, 'm' | 'f' | 'c')) {
+        return None;
+    }
+    let count = |c| tag.chars().filter(|t| *t == c).count();
+    let humans: Vec<&PositionInfo> = positions.iter().filter(|p| !is_creature(p)).collect();
+    let males = humans
+        .iter()
+        .filter(|p| p.sex.male && !p.sex.female && !p.sex.futa)
+        .count();
+    let females = humans
+        .iter()
+        .filter(|p| p.sex.female && !p.sex.male && !p.sex.futa)
+        .count();
+    Some(
+        positions.len() - humans.len() == count('c')
+            && humans.len() == count('m') + count('f')
+            && males <= count('m')
+            && females <= count('f'),
+    )
+}
+
+// Tags contradicting the sex and race of the scene's actors
+fn tag_mismatches(tags: &[String], positions: &[PositionInfo]) -> Vec<Message> {
+    let mut seen = HashSet::new();
+    tags.iter()
+        .filter(|tag| seen.insert(encode_tag(tag)))
+        .filter_map(|tag| {
+            let encoded = encode_tag(tag);
+            if encoded == "creature" && !positions.iter().any(is_creature) {
+                Some(Message::new("validate.tag_creature").arg("tag", tag))
+            } else if fits_composition(&encoded, positions) == Some(false) {
+                Some(Message::new("validate.tag_composition").arg("tag", tag))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// The registry file names, which may be overridden by the project and are sanitized on export
+pub fn validate_export_name(package: &Package) -> Vec<Diagnostic> {
+    let mut ret = vec![];
+    let mut push = |severity, message| {
+        ret.push(Diagnostic {
+            severity,
+            scene: None,
+            stage: None,
+            message,
+        })
+    };
+    let stem = package.export_stem();
+    let given = [
+        package.export_name.as_deref(),
+        Some(package.pack_name.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|name| !name.trim().is_empty());
+    if let Some(name) = given.filter(|name| name.trim() != stem) {
+        push(
+            Severity::Warning,
+            Message::new("validate.file_name_sanitized")
+                .arg("name", name)
+                .arg("file", &stem),
+        );
+    }
+    if !stem.is_ascii() {
+        push(
+            Severity::Warning,
+            Message::new("validate.file_name_unicode").arg("file", &stem),
+        );
+    }
+    if let Some(ext) = &package.export_extension {
+        let ext = ext.trim_start_matches('.');
+        if ext.is_empty() || sanitize_file_name(ext) != ext || ext.contains('.') {
+            push(
+                Severity::Error,
+                Message::new("validate.file_extension").arg("extension", ext),
+            );
+        }
+    }
+    let mut names = HashSet::new();
+    for (file, _) in package.registries() {
+        if !names.insert(file.to_lowercase()) {
+            push(
+                Severity::Error,
+                Message::new("validate.file_name_collision").arg("file", file),
+            );
+        }
+    }
+    ret
+}
+
+// Problems of a scene, leaving out the warnings its author suppressed
+pub fn validate_scene(scene: &Scene, package: Option<&Package>) -> Vec<Diagnostic> {
+    let mut ret = scene_diagnostics(scene, package);
+    ret.retain(|d| {
+        d.severity == Severity::Error || !scene.is_suppressed(d.message.key, d.stage.as_ref())
+    });
+    ret
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SceneWarning {
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+    pub suppressed: bool,
+}
+
+// All warnings of a scene, including the suppressed ones
+pub fn scene_warnings(scene: &Scene, package: Option<&Package>) -> Vec<SceneWarning> {
+    scene_diagnostics(scene, package)
+        .into_iter()
+        .filter(|d| d.severity == Severity::Warning)
+        .map(|diagnostic| SceneWarning {
+            suppressed: scene.is_suppressed(diagnostic.message.key, diagnostic.stage.as_ref()),
+            diagnostic,
+        })
+        .collect()
+}
+
+// Validate a single scene. Transitions can only be checked if the owning package is known
+fn scene_diagnostics(scene: &Scene, package: Option<&Package>) -> Vec<Diagnostic> {
+    let converted = package.is_some_and(|package| {
+        package
+            .conversions
+            .iter()
+            .any(|record| record.scene(&scene.id).is_some())
+    });
+    let mut ret = vec![];
+    let mut push = |severity, stage: Option<&NanoID>, message| {
+        ret.push(Diagnostic {
+            severity,
+            scene: Some(scene.id.clone()),
+            stage: stage.cloned(),
+            message,
+        })
+    };
+    if scene.name.trim().is_empty() {
+        push(
+            Severity::Warning,
+            None,
+            Message::new("validate.unnamed_scene"),
+        );
+    }
+    if !scene.name.trim().is_empty() && display_name(&scene.name) != scene.name {
+        push(
+            Severity::Warning,
+            None,
+            Message::new("validate.scene_name_format").arg("scene", &scene.name),
+        );
+    }
+    if scene.has_warnings {
+        push(
+            Severity::Warning,
+            None,
+            Message::new(FLAGGED_WARNING).arg("scene", &scene.name),
+        );
+    }
+    let climax = scene
+        .stages
+        .iter()
+        .flat_map(|stage| &stage.positions)
+        .any(|position| position.climax);
+    if !scene.stages.is_empty() && !climax {
+        push(
+            Severity::Warning,
+            None,
+            Message::new("validate.no_climax").arg("scene", &scene.name),
+        );
+    }
+    if let Some(archetype) = scene.archetype {
+        let detected = scene.detect_archetype();
+        if archetype != detected {
+            push(
+                Severity::Warning,
+                None,
+                Message::new("validate.archetype")
+                    .arg("scene", &scene.name)
+                    .arg("archetype", format!("{:?}", archetype))
+                    .arg("detected", format!("{:?}", detected)),
+            );
+        }
+    }
+    for (n, info) in scene.positions.iter().enumerate() {
+        let Some(folder) = &info.behaviour_folder else {
+            continue;
+        };
+        if normalize_behaviour_folder(folder).is_err() {
+            push(
+                Severity::Error,
+                None,
+                Message::new("validate.behaviour_folder")
+                    .arg("position", n + 1)
+                    .arg("folder", folder),
+            );
+        }
+    }
+    for plugin in &scene.requirements.plugins {
+        let plugin = plugin.trim().to_lowercase();
+        if ![".esp", ".esm", ".esl"]
+            .iter()
+            .any(|ext| plugin.ends_with(ext))
+        {
+            push(
+                Severity::Warning,
+                None,
+                Message::new("validate.requirement_plugin").arg("plugin", &plugin),
+            );
+        }
+    }
+    for keyword in &scene.requirements.keywords {
+        if keyword.trim().is_empty() || keyword.trim().contains(char::is_whitespace) {
+            push(
+                Severity::Warning,
+                None,
+                Message::new("validate.requirement_keyword").arg("keyword", keyword),
+            );
+        }
+    }
+    if scene.stages.is_empty() {
+        push(
+            Severity::Warning,
+            None,
+            Message::new("validate.no_stages").arg("scene", &scene.name),
+        );
+        return ret;
+    }
+
+    let nodes: HashSet<&NanoID> = scene.stages.iter().map(|s| &s.id).collect();
+    if !nodes.contains(&scene.root) {
+        push(
+            Severity::Error,
+            None,
+            Message::new("validate.invalid_root").arg("scene", &scene.name),
+        );
+    } else {
+        let reachable = depths(&scene.root, &scene.graph, &nodes);
+        for stage in scene
+            .stages
+            .iter()
+            .filter(|s| !reachable.contains_key(&s.id))
+        {
+            push(
+                Severity::Warning,
+                Some(&stage.id),
+                Message::new("validate.unreachable").arg("stage", &stage.name),
+            );
+        }
+        if scene.get_endings().is_empty() {
+            push(
+                Severity::Error,
+                None,
+                Message::new("validate.no_ending").arg("scene", &scene.name),
+            );
+        }
+        // the runtime jumps straight into shortcuts, which must still lead on through the scene
+        for stage in scene.stages.iter().filter(|s| s.shortcut) {
+            if stage.id == scene.root {
+                push(
+                    Severity::Warning,
+                    Some(&stage.id),
+                    Message::new("validate.shortcut_root").arg("stage", &stage.name),
+                );
+            } else if !reachable.contains_key(&stage.id) {
+                push(
+                    Severity::Error,
+                    Some(&stage.id),
+                    Message::new("validate.shortcut_unreachable").arg("stage", &stage.name),
+                );
+            }
+        }
+    }
+    for (id, node) in &scene.graph {
+        for dest in node.dest.iter().filter(|dest| !nodes.contains(dest)) {
+            push(
+                Severity::Error,
+                Some(id),
+                Message::new("validate.unknown_dest").arg("dest", &dest.0),
+            );
+        }
+    }
+    for info in &scene.positions {
+        if get_race_key_bytes(&info.race).is_none() {
+            push(
+                Severity::Error,
+                None,
+                Message::new("error.unknown_race").arg("race", &info.race),
+            );
+        }
+    }
+    for tag in tag_mismatches(&scene.tags, &scene.positions) {
+        push(Severity::Warning, None, tag);
+    }
+    for stage in &scene.stages {
+        for tag in tag_mismatches(&stage.tags, &scene.positions) {
+            push(Severity::Warning, Some(&stage.id), tag);
+        }
+        if stage.positions.len() != scene.positions.len() {
+            push(
+                Severity::Error,
+                Some(&stage.id),
+                Message::new("export.position_count")
+                    .arg("scene", &scene.name)
+                    .arg("stage", &stage.id.0)
+                    .arg("got", stage.positions.len())
+                    .arg("expected", scene.positions.len()),
+            );
+        }
+        if stage.positions.iter().any(|p| p.event.is_empty()) {
+            push(
+                Severity::Error,
+                Some(&stage.id),
+                Message::new("export.missing_event")
+                    .arg("scene", &scene.name)
+                    .arg("stage", &stage.id.0),
+            );
+        }
+        let events = stage
+            .positions
+            .iter()
+            .flat_map(|p| p.event.iter().chain(&p.variants));
+        for event in events.filter(|event| !event.is_empty() && !is_fnis_legal(event)) {
+            push(
+                Severity::Error,
+                Some(&stage.id),
+                Message::new("validate.illegal_event")
+                    .arg("event", event)
+                    .arg("suggestion", sanitize_event(event)),
+            );
+        }
+        let events = stage
+            .positions
+            .iter()
+            .flat_map(|p| p.event.iter().chain(&p.variants));
+        for event in events {
+            if let Some(source) = known_event_source(event, converted) {
+                push(
+                    Severity::Warning,
+                    Some(&stage.id),
+                    Message::new("validate.known_event")
+                        .arg("event", event)
+                        .arg("source", source),
+                );
+            }
+        }
+    }
+    for transition in &scene.transitions {
+        if !nodes.contains(&transition.from_stage) {
+            push(
+                Severity::Error,
+                None,
+                Message::new("validate.unknown_dest").arg("dest", &transition.from_stage.0),
+            );
+        }
+        if scene
+            .get_stage(&transition.from_stage)
+            .is_some_and(|stage| !stage.ending)
+        {
+            push(
+                Severity::Warning,
+                Some(&transition.from_stage),
+                Message::new("validate.transition_not_ending")
+                    .arg("stage", &transition.from_stage.0),
+            );
+        }
+        if let Some(package) = package {
+            match package.get_scene(&transition.to_scene) {
+                None => push(
+                    Severity::Error,
+                    Some(&transition.from_stage),
+                    Message::new("validate.unknown_transition")
+                        .arg("scene", &transition.to_scene.0),
+                ),
+                Some(target) if exclusion_reason(target).is_some() => push(
+                    Severity::Warning,
+                    Some(&transition.from_stage),
+                    Message::new("validate.transition_excluded").arg("scene", &target.name),
+                ),
+                Some(_) => (),
+            }
+        }
+    }
+    ret
+}
@@ -1,4 +1,199 @@
-use std::collections::HashMap;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+
+use crate::{error::SlsbError, i18n::Message};
+
+// Mappings added by the user, read from next to the app and from its data folder
+pub const RACEKEYS_FILE: &str = "racekeys.yaml";
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+// Legacy keys and behaviour folders of user mappings. Races themselves are fixed by the
+// registry format, so mappings can only point at races listed in RaceKey
+#[derive(Debug, Deserialize, Default, Clone, PartialEq)]
+pub struct RaceKeyOverrides {
+    // slal race keys to race names, e.g. "exoticdogs: Canine"
+    #[serde(default)]
+    pub legacy: HashMap<String, String>,
+    // behaviour folders of races below meshes\actors, e.g. "Canine: exotic\dog"
+    #[serde(default)]
+    pub folders: HashMap<String, String>,
+}
+
+static OVERRIDES: Lazy<RwLock<RaceKeyOverrides>> = Lazy::new(Default::default);
+
+impl RaceKeyOverrides {
+    pub fn parse(text: &str, path: &str) -> Result<Self, SlsbError> {
+        let file: RaceKeyOverrides = serde_yaml::from_str(text)?;
+        let unknown = file
+            .legacy
+            .values()
+            .chain(file.folders.keys())
+            .find(|race| get_race_key_bytes(race).is_none());
+        if let Some(race) = unknown {
+            return Err(Message::new("racekeys.unknown_race")
+                .arg("race", race)
+                .arg("path", path)
+                .into());
+        }
+        let folders = file
+            .folders
+            .into_iter()
+            .map(|(race, folder)| match normalize_behaviour_folder(&folder) {
+                Ok(folder) => Ok((race, folder)),
+                Err(_) => Err(Message::new("racekeys.invalid_folder")
+                    .arg("race", race)
+                    .arg("folder", folder)
+                    .arg("path", path)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            legacy: file
+                .legacy
+                .into_iter()
+                .map(|(key, race)| (key.trim().to_lowercase(), race))
+                .collect(),
+            folders,
+        })
+    }
+
+    // Entries of other take precedence
+    pub fn extend(&mut self, other: RaceKeyOverrides) {
+        self.legacy.extend(other.legacy);
+        self.folders.extend(other.folders);
+    }
+
+    pub fn legacy_race(&self, legacykey: &str) -> Option<&String> {
+        self.legacy.get(&legacykey.to_lowercase())
+    }
+
+    pub fn folder(&self, race: &str) -> Option<&String> {
+        self.folders.get(race)
+    }
+}
+
+// Replace the user mappings by those of the given files, later files overriding earlier ones.
+// Missing files are skipped, on error the previous mappings are kept
+pub fn load_race_overrides(paths: &[PathBuf]) -> Result<RaceKeyOverrides, SlsbError> {
+    let mut ret = RaceKeyOverrides::default();
+    for path in paths.iter().filter(|path| path.is_file()) {
+        let text = fs::read_to_string(path)?;
+        ret.extend(RaceKeyOverrides::parse(&text, &path.to_string_lossy())?);
+        info!(target: "racekeys", path:? = path; "Loaded race key mappings");
+    }
+    *OVERRIDES.write().unwrap() = ret.clone();
+    Ok(ret)
+}
+
+// Reload the user mappings whenever one of their files is added, changed or removed, for as
+// long as the app runs
+pub fn watch_race_overrides(paths: &[PathBuf]) {
+    let modified_times = || -> Vec<Option<SystemTime>> {
+        paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .collect()
+    };
+    let mut modified = modified_times();
+    loop {
+        std::thread::sleep(RELOAD_INTERVAL);
+        let current = modified_times();
+        if current != modified {
+            if let Err(err) = load_race_overrides(paths) {
+                warn!(target: "racekeys", "Keeping previous race key mappings: {}", err);
+            }
+            modified = current;
+        }
+    }
+}
+
+// Behaviour folders are relative to meshes\actors and written with backslashes, e.g.
+// "dlc02\scrib". Either slash is accepted
+pub fn normalize_behaviour_folder(folder: &str) -> Result<String, SlsbError> {
+    let parts: Vec<&str> = folder
+        .split(['\\', '/'])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty()
+        || parts
+            .iter()
+            .any(|part| *part == "." || *part == ".." || part.contains(':'))
+    {
+        return Err(Message::new("export.invalid_behaviour_folder")
+            .arg("folder", folder)
+            .into());
+    }
+    Ok(parts.join("\\"))
+}
+
+pub fn race_folder_override(race: &str) -> Option<String> {
+    OVERRIDES.read().unwrap().folder(race).cloned()
+}
+
+pub fn map_race_to_folder(race: &str) -> Result<String, ()> {
+    if let Some(folder) = race_folder_override(race) {
+        return Ok(folder);
+    }
+    match race {
+        "Human" => Ok("character".into()),
+        "Ash Hopper" => Ok("dlc02\\scrib".into()),
+        "Bear" => Ok("bear".into()),
+        "Boar" | "Boar (Any)" | "Boar (Mounted)" => Ok("dlc02\\boarriekling".into()),
+        "Canine" | "Dog" | "Wolf" | "Fox" => Ok("canine".into()),
+        "Chaurus" | "Chaurus Reaper" => Ok("chaurus".into()),
+        "Chaurus Hunter" => Ok("dlc01\\chaurusflyer".into()),
+        "Chicken" => Ok("ambient\\chicken".into()),
+        "Cow" => Ok("cow".into()),
+        "Deer" => Ok("deer".into()),
+        "Dragon Priest" => Ok("dragonpriest".into()),
+        "Dragon" => Ok("dragon".into()),
+        "Draugr" => Ok("draugr".into()),
+        "Dwarven Ballista" => Ok("dlc02\\dwarvenballistacenturion".into()),
+        "Dwarven Centurion" => Ok("dwarvensteamcenturion".into()),
+        "Dwarven Sphere" => Ok("dwarvenspherecenturion".into()),
+        "Dwarven Spider" => Ok("dwarvenspider".into()),
+        "Falmer" => Ok("falmer".into()),
+        "Flame Atronach" => Ok("atronachflame".into()),
+        "Frost Atronach" => Ok("atronachfrost".into()),
+        "Storm Atronach" => Ok("atronachstorm".into()),
+        "Gargoyle" => Ok("dlc01\\vampirebrute".into()),
+        "Giant" => Ok("giant".into()),
+        "Goat" => Ok("goat".into()),
+        "Hagraven" => Ok("hagraven".into()),
+        "Horker" => Ok("horker".into()),
+        "Horse" => Ok("horse".into()),
+        "Ice Wraith" => Ok("icewraith".into()),
+        "Lurker" => Ok("dlc02\\benthiclurker".into()),
+        "Mammoth" => Ok("mammoth".into()),
+        "Mudcrab" => Ok("mudcrab".into()),
+        "Netch" => Ok("dlc02\\netch".into()),
+        "Rabbit" => Ok("ambient\\hare".into()),
+        "Riekling" => Ok("dlc02\\riekling".into()),
+        "Sabrecat" => Ok("sabrecat".into()),
+        "Seeker" => Ok("dlc02\\hmdaedra".into()),
+        "Skeever" => Ok("skeever".into()),
+        "Slaughterfish" => Ok("slaughterfish".into()),
+        "Spider" | "Large Spider" | "Giant Spider" => Ok("frostbitespider".into()),
+        "Spriggan" => Ok("spriggan".into()),
+        "Troll" => Ok("troll".into()),
+        "Vampire Lord" => Ok("vampirelord".into()),
+        "Werewolf" => Ok("werewolfbeast".into()),
+        "Wispmother" => Ok("wisp".into()),
+        "Wisp" => Ok("witchlight".into()),
+        _ => match get_race_variant_base(race) {
+            Some(base) => map_race_to_folder(base),
+            None => Err(()),
+        },
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum RaceKey {
@@ -55,9 +250,17 @@ pub enum RaceKey {
     Wisp,
     Wispmother,
     Wolf,
+    // variants, appended to keep the keys above stable
+    ArmoredTroll,
+    FrostTroll,
+    ArmoredFrostTroll,
+    SkeletalDragon,
 }
 
-pub fn map_legacy_to_racekey(legacykey: &str) -> Result<String, String> {
+pub fn map_legacy_to_racekey(legacykey: &str) -> Result<String, SlsbError> {
+    if let Some(race) = OVERRIDES.read().unwrap().legacy_race(legacykey) {
+        return Ok(race.clone());
+    }
     let key = legacykey.to_lowercase();
     match key.as_str() {
         "humans" => Ok("Human".into()),
@@ -113,8 +316,71 @@ pub fn map_legacy_to_racekey(legacykey: &str) -> Result<String, String> {
         "wisps" => Ok("Wisp".into()),
         "wispmothers" => Ok("Wispmother".into()),
         "wolves" => Ok("Wolf".into()),
-        _ => Err(format!("Unrecognized legacy key: {}", legacykey).into()),
+        "armoredtrolls" => Ok("Armored Troll".into()),
+        "frosttrolls" => Ok("Frost Troll".into()),
+        "armoredfrosttrolls" => Ok("Armored Frost Troll".into()),
+        "skeletaldragons" => Ok("Skeletal Dragon".into()),
+        _ => Err(SlsbError::UnknownRace(legacykey.into())),
+    }
+}
+
+// Lowercase words of a name, splitting camel case, e.g. "FB_HorseDoggy" -> fb, horse, doggy
+fn words_of(text: &str) -> Vec<String> {
+    let mut ret = vec![];
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in text.chars() {
+        if (!c.is_alphanumeric() || (c.is_uppercase() && prev_lower)) && !word.is_empty() {
+            ret.push(std::mem::take(&mut word));
+        }
+        if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        }
+        prev_lower = c.is_lowercase();
+    }
+    if !word.is_empty() {
+        ret.push(word);
+    }
+    ret
+}
+
+// Creature races mentioned in free text such as an animation name, in order of appearance.
+// Pairs of words are matched first, so "Giant Spider" is not read as a giant and a spider
+pub fn guess_racekeys(text: &str) -> Vec<String> {
+    let races: HashMap<String, String> = get_race_keys_string()
+        .into_iter()
+        .filter(|race| race != "Human" && !race.contains('('))
+        .map(|race| (race.to_lowercase().replace(' ', ""), race))
+        .collect();
+    let lookup = |word: &str| {
+        let singular = [
+            word.strip_suffix("ves").map(|w| format!("{}f", w)),
+            word.strip_suffix("es").map(str::to_string),
+            word.strip_suffix('s').map(str::to_string),
+        ];
+        races
+            .get(word)
+            .or_else(|| singular.iter().flatten().find_map(|w| races.get(w)))
+            .cloned()
+    };
+
+    let words = words_of(text);
+    let mut ret: Vec<String> = vec![];
+    let mut i = 0;
+    while i < words.len() {
+        let pair = words
+            .get(i + 1)
+            .and_then(|next| lookup(&format!("{}{}", words[i], next)));
+        let (race, len) = match pair {
+            Some(race) => (Some(race), 2),
+            None => (lookup(&words[i]), 1),
+        };
+        if let Some(race) = race.filter(|race| !ret.contains(race)) {
+            ret.push(race);
+        }
+        i += len;
     }
+    ret
 }
 
 fn get_race_map() -> HashMap<String, RaceKey> {
@@ -172,9 +438,23 @@ fn get_race_map() -> HashMap<String, RaceKey> {
         ("Wispmother".into(), RaceKey::Wispmother),
         ("Wisp".into(), RaceKey::Wisp),
         ("Wolf".into(), RaceKey::Wolf),
+        ("Armored Troll".into(), RaceKey::ArmoredTroll),
+        ("Frost Troll".into(), RaceKey::FrostTroll),
+        ("Armored Frost Troll".into(), RaceKey::ArmoredFrostTroll),
+        ("Skeletal Dragon".into(), RaceKey::SkeletalDragon),
     ])
 }
 
+// Variants share the behaviour files of their base race but keep a key of their own,
+// so scenes can be restricted to the exact creature
+pub fn get_race_variant_base(race: &str) -> Option<&'static str> {
+    match race {
+        "Armored Troll" | "Frost Troll" | "Armored Frost Troll" => Some("Troll"),
+        "Skeletal Dragon" => Some("Dragon"),
+        _ => None,
+    }
+}
+
 pub fn get_race_keys_string() -> Vec<String> {
     get_race_map()
         .iter()
@@ -187,3 +467,150 @@ pub fn get_race_key_bytes(race: &str) -> Option<u8> {
         .get(race)
         .map(|&key| key as u8)
 }
+
+pub fn get_race_key_variants() -> Vec<(String, u64)> {
+    let mut variants: Vec<_> = get_race_map()
+        .into_iter()
+        .map(|(race, key)| (race, key as u64))
+        .collect();
+    variants.sort_by_key(|(_, key)| *key);
+    variants
+}
+
+// What generates the behaviour of a race's animations
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviourEngine {
+    // the export writes an FNIS list into the behaviour folder of the race
+    Fnis,
+    // no folder to write a list to, the behaviour has to be patched through Nemesis
+    Nemesis,
+}
+
+// A race as listed by the creature picker
+#[derive(Debug, Serialize, Clone)]
+pub struct RaceInfo {
+    pub race: String,
+    pub key: u8,
+    pub folder: Option<String>,
+    // other races animated through the same behaviour folder
+    pub group: Vec<String>,
+    pub variant_of: Option<&'static str>,
+    pub examples: &'static [&'static str],
+    pub behaviour_engine: BehaviourEngine,
+}
+
+fn race_examples(race: &str) -> &'static [&'static str] {
+    match race {
+        "Human" => &["Nord", "Khajiit", "Argonian"],
+        "Ash Hopper" => &["Ash Hopper"],
+        "Bear" => &["Brown Bear", "Cave Bear", "Snow Bear"],
+        "Boar" | "Boar (Any)" => &["Bristleback"],
+        "Boar (Mounted)" => &["Riekling riding a Bristleback"],
+        "Canine" => &["Dog", "Wolf", "Fox"],
+        "Chaurus" => &["Chaurus"],
+        "Chaurus Hunter" => &["Chaurus Hunter"],
+        "Chaurus Reaper" => &["Chaurus Reaper"],
+        "Chicken" => &["Chicken"],
+        "Cow" => &["Cow", "Highland Cow"],
+        "Deer" => &["Elk", "Deer", "Vale Deer"],
+        "Dog" => &["Dog", "Barbas", "Meeko"],
+        "Dragon" => &["Blood Dragon", "Frost Dragon", "Alduin"],
+        "Dragon Priest" => &["Dragon Priest"],
+        "Draugr" => &["Draugr", "Draugr Deathlord"],
+        "Dwarven Ballista" => &["Dwarven Ballista"],
+        "Dwarven Centurion" => &["Dwarven Centurion"],
+        "Dwarven Sphere" => &["Dwarven Sphere"],
+        "Dwarven Spider" => &["Dwarven Spider"],
+        "Falmer" => &["Falmer", "Falmer Gloomlurker"],
+        "Flame Atronach" => &["Flame Atronach"],
+        "Fox" => &["Red Fox", "Snow Fox"],
+        "Frost Atronach" => &["Frost Atronach"],
+        "Gargoyle" => &["Gargoyle"],
+        "Giant" => &["Giant"],
+        "Goat" => &["Goat"],
+        "Hagraven" => &["Hagraven"],
+        "Horker" => &["Horker"],
+        "Horse" => &["Horse", "Shadowmere", "Arvak"],
+        "Ice Wraith" => &["Ice Wraith"],
+        "Lurker" => &["Lurker"],
+        "Mammoth" => &["Mammoth"],
+        "Mudcrab" => &["Mudcrab"],
+        "Netch" => &["Bull Netch", "Betty Netch"],
+        "Rabbit" => &["Hare"],
+        "Riekling" => &["Riekling"],
+        "Sabrecat" => &["Sabre Cat", "Snowy Sabre Cat"],
+        "Seeker" => &["Seeker"],
+        "Skeever" => &["Skeever"],
+        "Slaughterfish" => &["Slaughterfish"],
+        "Spider" => &["Frostbite Spider"],
+        "Large Spider" => &["Large Frostbite Spider"],
+        "Giant Spider" => &["Giant Frostbite Spider"],
+        "Spriggan" => &["Spriggan", "Spriggan Matron"],
+        "Storm Atronach" => &["Storm Atronach"],
+        "Troll" => &["Troll"],
+        "Vampire Lord" => &["Vampire Lord"],
+        "Werewolf" => &["Werewolf"],
+        "Wisp" => &["Witchlight"],
+        "Wispmother" => &["Wispmother"],
+        "Wolf" => &["Wolf", "Ice Wolf"],
+        "Armored Troll" => &["Armored Troll"],
+        "Frost Troll" => &["Frost Troll"],
+        "Armored Frost Troll" => &["Armored Frost Troll"],
+        "Skeletal Dragon" => &["Skeletal Dragon"],
+        _ => &[],
+    }
+}
+
+// All races ordered by their key, narrowed down to those whose name, folder or examples contain
+// the search text if any. Folders include the mappings of racekeys.yaml
+pub fn get_race_infos(search: Option<&str>) -> Vec<RaceInfo> {
+    let races = get_race_key_variants();
+    let folders: HashMap<&str, Option<String>> = races
+        .iter()
+        .map(|(race, _)| (race.as_str(), map_race_to_folder(race).ok()))
+        .collect();
+    let search = search.map(|text| text.trim().to_lowercase());
+    races
+        .iter()
+        .map(|(race, key)| {
+            let folder = folders[race.as_str()].clone();
+            let mut group: Vec<String> = races
+                .iter()
+                .filter(|(other, _)| {
+                    other != race && folder.is_some() && folders[other.as_str()] == folder
+                })
+                .map(|(other, _)| other.clone())
+                .collect();
+            group.sort();
+            // FNIS lists are written for every race the export finds a behaviour folder for
+            let behaviour_engine = match folder {
+                Some(_) => BehaviourEngine::Fnis,
+                None => BehaviourEngine::Nemesis,
+            };
+            RaceInfo {
+                race: race.clone(),
+                key: *key as u8,
+                folder,
+                group,
+                variant_of: get_race_variant_base(race),
+                examples: race_examples(race),
+                behaviour_engine,
+            }
+        })
+        .filter(|info| match &search {
+            Some(text) => {
+                info.race.to_lowercase().contains(text)
+                    || info
+                        .folder
+                        .as_ref()
+                        .is_some_and(|folder| folder.to_lowercase().contains(text))
+                    || info
+                        .examples
+                        .iter()
+                        .any(|example| example.to_lowercase().contains(text))
+            }
+            None => true,
+        })
+        .collect()
+}
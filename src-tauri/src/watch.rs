@@ -0,0 +1,104 @@
+use log::info;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    i18n::Message,
+    project::package::{BuildSummary, ExcludedScene, Package},
+};
+
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Detects modifications of a file by its modification time. A change is only reported once the
+// file remained untouched for the debounce period, so a save written in several steps triggers
+// a single rebuild
+#[derive(Debug)]
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    pending: Option<Instant>,
+    debounce: Duration,
+}
+
+impl FileWatcher {
+    pub fn new(path: PathBuf, debounce: Duration) -> Self {
+        Self {
+            modified: modified_time(&path),
+            path,
+            pending: None,
+            debounce,
+        }
+    }
+
+    pub fn poll(&mut self) -> bool {
+        self.poll_at(Instant::now())
+    }
+
+    pub fn poll_at(&mut self, now: Instant) -> bool {
+        let modified = modified_time(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            self.pending = Some(now);
+            return false;
+        }
+        match self.pending {
+            Some(since) if now.duration_since(since) >= self.debounce => {
+                self.pending = None;
+                // a deleted file is not worth a rebuild
+                modified.is_some()
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// Outcome of a single rebuild, emitted to the frontend as "on_watch_build"
+#[derive(Debug, Serialize, Clone)]
+pub struct WatchBuild {
+    pub project: PathBuf,
+    pub out: PathBuf,
+    pub scenes: usize,
+    pub excluded: Vec<ExcludedScene>,
+    pub seconds: f32,
+    pub error: Option<Message>,
+}
+
+pub fn rebuild(project: &Path, out: &Path) -> WatchBuild {
+    let start = Instant::now();
+    let result = Package::open(project).and_then(|mut package| package.build(out.to_path_buf()));
+    let (summary, error) = match result {
+        Ok(summary) => (summary, None),
+        Err(err) => (BuildSummary::default(), Some(err.message())),
+    };
+    WatchBuild {
+        project: project.to_path_buf(),
+        out: out.to_path_buf(),
+        scenes: summary.scenes,
+        excluded: summary.excluded,
+        seconds: start.elapsed().as_secs_f32(),
+        error,
+    }
+}
+
+// Rebuild the project into out whenever its file changes, until stop is set
+pub fn watch(project: &Path, out: &Path, stop: &AtomicBool, mut on_build: impl FnMut(WatchBuild)) {
+    info!(target: "watch", project:? = project, out:? = out; "Watching project");
+    let mut watcher = FileWatcher::new(project.to_path_buf(), DEBOUNCE);
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(POLL_INTERVAL);
+        if watcher.poll() {
+            on_build(rebuild(project, out));
+        }
+    }
+    info!(target: "watch", project:? = project; "Stopped watching project");
+}
@@ -23,10 +23,14 @@ function makeMenuItem(label, key, icon, children, disabled, danger) {
   return { key, icon, children, label, disabled, danger };
 }
 import { tagsSFW, tagsNSFW } from "./common/Tags"
+import { formatMessage } from "./common/Messages";
 import TagTree from "./components/TagTree";
 import { remove } from "@tauri-apps/plugin-fs";
 
 const ZOOM_OPTIONS = { minScale: 0.25, maxScale: 5 };
+// Parts of the active scene edited in the graph, which updates from the backend leave alone while
+// there are unsaved changes
+const GRAPH_EDITOR_FIELDS = ['name', 'stages', 'root', 'graph', 'positions', 'tags', 'furniture', 'private', 'has_warnings', 'fingerprint'];
 
 function App() {
   const [isDark, setIsDark] = useState(false);
@@ -51,6 +55,11 @@ function App() {
     };
   }, []);
 
+  // Usage metrics, time spent per scene
+  useEffect(() => {
+    invoke('track_scene_focus', { id: activeScene ? activeScene.id : null });
+  }, [activeScene ? activeScene.id : null]);
+
   function generatePositionId() {
     return `${Date.now()}_${Math.random().toString(36).substr(2, 9)}`;
   }
@@ -66,6 +75,29 @@ function App() {
     };
   }, []);
 
+  // Rebuild on Save
+  useEffect(() => {
+    const unlisten = listen('on_watch_build', (event) => {
+      const build = event.payload;
+      if (build.error) {
+        api.error({
+          message: 'Rebuild Failed',
+          description: formatMessage(build.error),
+          placement: 'bottomLeft',
+        });
+      } else {
+        api.success({
+          message: 'Rebuilt Project',
+          description: `Exported ${build.scenes} scenes in ${build.seconds.toFixed(1)}s.`,
+          placement: 'bottomLeft',
+        });
+      }
+    });
+    return () => {
+      unlisten.then(f => f());
+    };
+  }, []);
+
   // Graph
   useEffect(() => {
     const newGraph = new Graph({
@@ -349,6 +381,48 @@ function App() {
     }
   }, [graph])
 
+  // Scenes changed by the backend, null for scenes which no longer exist
+  useEffect(() => {
+    if (!graph) return;
+    const unlisten = listen('on_scenes_update', (event) => {
+      const changes = event.payload;
+      updateScenes(draft => {
+        for (const [id, scene] of Object.entries(changes)) {
+          const idx = draft.findIndex(it => it.id === id);
+          if (!scene) {
+            if (idx !== -1) draft.splice(idx, 1);
+          } else if (idx === -1) {
+            draft.push(scene);
+          } else {
+            draft[idx] = scene;
+          }
+        }
+      });
+      if (!activeScene || !(activeScene.id in changes)) return;
+      const scene = changes[activeScene.id];
+      if (!scene) {
+        inEdit.current = true;
+        graph.clearCells();
+        inEdit.current = false;
+        updateActiveScene(null);
+        setEdited(false);
+      } else if (!edited) {
+        setActiveScene(scene);
+      } else {
+        // keep the unsaved edits of the graph, the backend tells saves of outdated stages apart
+        // by the fingerprint the scene was loaded with
+        updateActiveScene(draft => {
+          for (const [key, value] of Object.entries(scene)) {
+            if (!GRAPH_EDITOR_FIELDS.includes(key)) draft[key] = value;
+          }
+        });
+      }
+    });
+    return () => {
+      unlisten.then(res => { res() });
+    }
+  }, [graph, activeScene, edited])
+
   const clearGraph = () => {
     if (graph.getCellCount() == 0)
       return;
@@ -510,19 +584,30 @@ function App() {
       }(),
       has_warnings,
     };
-    invoke('save_scene', { scene }).then(() => {
-      console.log("Saved scene", scene);
-      updateActiveScene(scene);
+    invoke('save_scene', { scene }).then((saved) => {
+      console.log("Saved scene", saved);
+      // The backend may have taken shared stages from the library or detached them under a new id
+      if (JSON.stringify(saved.stages) !== JSON.stringify(scene.stages)) {
+        inEdit.current = true;
+        setActiveScene(saved);
+      } else {
+        updateActiveScene(saved);
+      }
       updateScenes(prev => {
-        const w = prev.findIndex(it => it.id === scene.id);
+        const w = prev.findIndex(it => it.id === saved.id);
         if (w === -1) {
-          prev.push(scene);
+          prev.push(saved);
         } else {
-          prev[w] = scene;
+          prev[w] = saved;
         }
       });
       setEdited(false);
-      console.log("Saved Scene", scene);
+    }).catch(err => {
+      api.error({
+        message: err.kind === 'validation' ? 'Invalid Scene' : 'Unable to save Scene',
+        description: formatMessage(err),
+        placement: 'bottomLeft',
+      });
     });
   }
 
@@ -969,6 +1054,54 @@ function App() {
                           >
                             Private
                           </Checkbox>
+                          <Input
+                            addonBefore={'Author'}
+                            value={activeScene ? activeScene.author || '' : ''}
+                            onChange={(e) => {
+                              updateActiveScene((prev) => {
+                                prev.author = e.target.value || null;
+                              });
+                              setEdited(true);
+                            }}
+                            placeholder="Original animator"
+                          />
+                          <Input
+                            addonBefore={'Source'}
+                            value={activeScene ? activeScene.source_pack || '' : ''}
+                            onChange={(e) => {
+                              updateActiveScene((prev) => {
+                                prev.source_pack = e.target.value || null;
+                              });
+                              setEdited(true);
+                            }}
+                            placeholder="Pack the scene originates from"
+                          />
+                          <Select
+                            mode="tags"
+                            style={{ width: '100%' }}
+                            value={activeScene && activeScene.requirements ? activeScene.requirements.plugins : []}
+                            onChange={(value) => {
+                              updateActiveScene((prev) => {
+                                prev.requirements = { keywords: [], ...prev.requirements, plugins: value };
+                              });
+                              setEdited(true);
+                            }}
+                            tokenSeparators={[',']}
+                            placeholder="Required plugins, e.g. ZaZAnimationPack.esm"
+                          />
+                          <Select
+                            mode="tags"
+                            style={{ width: '100%' }}
+                            value={activeScene && activeScene.requirements ? activeScene.requirements.keywords : []}
+                            onChange={(value) => {
+                              updateActiveScene((prev) => {
+                                prev.requirements = { plugins: [], ...prev.requirements, keywords: value };
+                              });
+                              setEdited(true);
+                            }}
+                            tokenSeparators={[',']}
+                            placeholder="Required keywords"
+                          />
                           <Row gutter={[12, 12]} justify={'space-evenly'}>
                             <Col>
                               <InputNumber
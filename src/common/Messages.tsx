@@ -0,0 +1,22 @@
+// Translations of backend messages, keyed by locale and message key. Templates use {param}
+// placeholders. Keys missing here fall back to the english message sent by the backend
+export const Translations: Record<string, Record<string, string>> = {
+  en: {},
+};
+
+export function formatMessage(
+  error: { key?: string, params?: Record<string, string>, message?: string, text?: string } | string,
+  locale: string = navigator.language.split('-')[0],
+): string {
+  if (typeof error === 'string') {
+    return error;
+  }
+  const template = error.key ? Translations[locale]?.[error.key] : undefined;
+  if (!template) {
+    return error.message ?? error.text ?? error.key ?? '';
+  }
+  return Object.entries(error.params ?? {}).reduce(
+    (acc, [name, value]) => acc.split(`{${name}}`).join(value),
+    template,
+  );
+}
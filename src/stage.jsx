@@ -4,7 +4,7 @@ import { invoke } from "@tauri-apps/api/core"
 import ReactDOM from "react-dom/client";
 import { useImmer } from "use-immer";
 import { AlipaySquareFilled, FileDoneOutlined, TagsOutlined, SaveOutlined, TeamOutlined } from '@ant-design/icons';
-import { Input, Button, Tag, Space, Tooltip, InputNumber, Card, Layout, Divider, Menu, Row, Col, Tabs, TreeSelect, notification, Collapse, ConfigProvider, theme } from 'antd';
+import { Input, Button, Tag, Space, Tooltip, InputNumber, Checkbox, Card, Layout, Divider, Menu, Row, Col, Tabs, TreeSelect, notification, Collapse, ConfigProvider, theme } from 'antd';
 
 import { tagsSFW, tagsNSFW } from "./common/Tags"
 import PositionField from "./stage/PositionField";
@@ -65,6 +65,8 @@ function Editor({ _sceneId, _stage, _positions }) {
   const [tags, setTags] = useState(_stage.tags);
   const [fixedLen, setFixedLen] = useState(_stage.extra.fixed_len);
   const [navText, setNavText] = useState(_stage.extra.nav_text);
+  const [shortcut, setShortcut] = useState(_stage.shortcut || false);
+  const [ending, setEnding] = useState(_stage.ending || false);
 
   useEffect(() => {
     // Listen for the toggle_darkmode event from Tauri
@@ -126,11 +128,15 @@ function Editor({ _sceneId, _stage, _positions }) {
       positionsInfo.push(scene_p);
     }
     const stage = {
+      ..._stage,
       id: _stage.id,
       name,
+      shortcut,
+      ending,
       positions: positionArg,
       tags,
       extra: {
+        ..._stage.extra,
         fixed_len: fixedLen || 0.0,
         nav_text: navText || '',
       },
@@ -245,6 +251,30 @@ function Editor({ _sceneId, _stage, _positions }) {
                   value={navText}
                   onChange={(e) => setNavText(e.target.value)}
                 ></TextArea>
+                <Tooltip
+                  title={
+                    'Allow the player to start the scene from this stage, skipping the stages before it.'
+                  }
+                >
+                  <Checkbox
+                    checked={shortcut}
+                    onChange={(e) => setShortcut(e.target.checked)}
+                  >
+                    Start Shortcut
+                  </Checkbox>
+                </Tooltip>
+                <Tooltip
+                  title={
+                    'Allow the scene to end after this stage, even if it leads on to other stages.'
+                  }
+                >
+                  <Checkbox
+                    checked={ending}
+                    onChange={(e) => setEnding(e.target.checked)}
+                  >
+                    Ending
+                  </Checkbox>
+                </Tooltip>
               </Card>
             </Col>
             <Col span={12}>
@@ -257,6 +257,23 @@ function PositionField({ position, info, onChange }) {
                   </div>
                 </Tooltip>
               </Col>
+              {[
+                { attr: 'open_mouth', label: 'Open Mouth', title: 'Actor keeps their mouth open during this stage.' },
+                { attr: 'silent', label: 'Silent', title: 'Actor makes no voice sounds during this stage.' },
+              ].map(({ attr, label, title }) => (
+                <Col key={attr}>
+                  <Tooltip className="tool-tip" title={title}>
+                    <div>
+                      <Checkbox
+                        checked={position[attr]}
+                        onChange={(e) => onChange({ ...position, [attr]: e.target.checked }, info)}
+                      >
+                        {label}
+                      </Checkbox>
+                    </div>
+                  </Tooltip>
+                </Col>
+              ))}
               <Select
                 mode="tags"
                 style={{ width: '100%' }}